@@ -1,14 +1,44 @@
 use std::env;
-use std::fs::File;
-use std::io::Write;
-use std::path::PathBuf;
+use std::fs::{File, read_dir};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 
 fn main() {
     let target = env::var("TARGET").unwrap();
     let mut out_path: PathBuf = env::var_os("OUT_DIR").unwrap().into();
     out_path.push("host.rs");
-    let mut file = File::create(out_path).unwrap();
+    let mut file = File::create(&out_path).unwrap();
     write!(file, r#"const HOST: &str = "{}";"#, target).unwrap();
 
+    out_path.set_file_name("templates.rs");
+    let mut file = File::create(&out_path).unwrap();
+    let templates_path = [&*env::var("CARGO_MANIFEST_DIR").unwrap(), "res", "templates"].iter().collect::<PathBuf>();
+    let mut files = Vec::new();
+    collect_files(&templates_path, &mut files).unwrap();
+    files.sort();
+    writeln!(file, "const TEMPLATE_FILES: &[(&str, &[u8])] = &[").unwrap();
+    for path in files {
+        let relative_path = path.strip_prefix(&templates_path).unwrap();
+        let name = relative_path.iter().map(|c| c.to_str().unwrap()).collect::<Vec<_>>().join("/");
+        writeln!(file, "    ({:?}, include_bytes!({:?})),", name, path).unwrap();
+        println!("cargo:rerun-if-changed={}", path.display());
+    }
+    writeln!(file, "];").unwrap();
+
     println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed={}", templates_path.display());
+}
+
+/// Recursively collects all files in the directory.
+fn collect_files(dir_path: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in read_dir(dir_path)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            println!("cargo:rerun-if-changed={}", path.display());
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
 }
@@ -0,0 +1,208 @@
+'use strict';
+
+/**
+ * Client-side filtering and grouping of the summary table, using the coverage data embedded in the page. The sorting is
+ * done by `sortable.js`.
+ */
+
+var coverageData = JSON.parse(document.getElementById('coverage-data').textContent);
+
+/**
+ * @param {HTMLElement} td
+ * @param {number} value
+ * @param {number} total
+ * @param {number} fair
+ * @param {number} good
+ */
+function setPercentCell(td, value, total, fair, good) {
+    var cls, percent;
+    if (total === 0) {
+        cls = 'none';
+        percent = '—';
+    } else if (value === total) {
+        cls = 'perfect';
+        percent = '100.00';
+    } else if (value === 0) {
+        cls = 'zero';
+        percent = '0.00';
+    } else {
+        var p = 100 * value / total;
+        percent = Math.min(Math.max(p, 0.01), 99.99).toFixed(2);
+        cls = p > good ? 'good' : p > fair ? 'fair' : 'bad';
+    }
+    td.className = 'ch-' + cls;
+    td.title = value + '/' + total;
+    td.textContent = percent + '%';
+}
+
+/**
+ * Sums the coverage of the visible file rows.
+ * @param {HTMLTableRowElement[]} rows
+ */
+function summarize(rows) {
+    var total = {
+        files: 0,
+        lines_count: 0,
+        lines_covered: 0,
+        branches_count: 0,
+        branches_taken: 0,
+        functions_count: 0,
+        functions_called: 0,
+    };
+    rows.forEach(function(row) {
+        if (row.hidden) {
+            return;
+        }
+        var file = coverageData[+row.getAttribute('data-index')];
+        total.files += 1;
+        for (var key in file.summary) {
+            if (key in total) {
+                total[key] += file.summary[key];
+            }
+        }
+    });
+    return total;
+}
+
+/**
+ * Fills the coverage columns of a row with `total`, in the same columns as the total row.
+ * @param {HTMLTableRowElement} row
+ * @param {Object} total
+ */
+function fillSummaryCells(row, total) {
+    var columns = document.getElementById('total').cells;
+    for (var i = 1; i < columns.length; ++ i) {
+        var td = row.cells[i];
+        switch (columns[i].id) {
+            case 'total-lines':
+                setPercentCell(td, total.lines_covered, total.lines_count, 75, 90);
+                break;
+            case 'total-missed':
+                td.textContent = total.lines_count - total.lines_covered;
+                break;
+            case 'total-branches':
+                setPercentCell(td, total.branches_taken, total.branches_count, 50, 75);
+                break;
+            case 'total-functions':
+                setPercentCell(td, total.functions_called, total.functions_count, 75, 90);
+                break;
+        }
+    }
+}
+
+/**
+ * The directory of a file row, from the simplified path shown in its first cell.
+ * @param {HTMLTableRowElement} row
+ */
+function directoryOf(row) {
+    var path = row.cells[0].textContent.trim();
+    var index = Math.max(path.lastIndexOf('/'), path.lastIndexOf('\\'));
+    return index < 0 ? '.' : path.substring(0, index);
+}
+
+var collapsedDirs = {};
+
+/**
+ * Groups the file rows under a header row of every directory if "Group by directory" is checked. The files keep their
+ * (sorted) order within a directory. The header shows the coverage of the visible files, and is clicked to collapse the
+ * directory.
+ */
+function regroup() {
+    var tbody = document.querySelector('#summary tbody');
+    var headers = tbody.querySelectorAll('tr.dir-row');
+    for (var i = 0; i < headers.length; ++ i) {
+        tbody.removeChild(headers[i]);
+    }
+    var rows = Array.prototype.slice.call(tbody.rows);
+    var grouped = document.getElementById('group-by-dir').checked;
+    if (!grouped) {
+        rows.forEach(function(row) {
+            row.classList.remove('collapsed');
+        });
+        return;
+    }
+
+    var groups = {};
+    var dirs = [];
+    rows.forEach(function(row) {
+        var dir = directoryOf(row);
+        if (!(dir in groups)) {
+            groups[dir] = [];
+            dirs.push(dir);
+        }
+        groups[dir].push(row);
+    });
+    dirs.sort();
+
+    var columnsCount = document.getElementById('total').cells.length;
+    dirs.forEach(function(dir) {
+        var header = document.createElement('tr');
+        header.className = 'dir-row';
+        for (var i = 0; i < columnsCount; ++ i) {
+            header.appendChild(document.createElement('td'));
+        }
+        var total = summarize(groups[dir]);
+        header.cells[0].textContent = (collapsedDirs[dir] ? '▸ ' : '▾ ') + dir + ' (' + total.files + ' files)';
+        fillSummaryCells(header, total);
+        header.hidden = total.files === 0;
+        header.onclick = function() {
+            collapsedDirs[dir] = !collapsedDirs[dir];
+            regroup();
+        };
+        tbody.appendChild(header);
+        groups[dir].forEach(function(row) {
+            row.classList.toggle('collapsed', !!collapsedDirs[dir]);
+            tbody.appendChild(row);
+        });
+    });
+}
+
+function applyFilters() {
+    var checked = {};
+    var checkboxes = document.querySelectorAll('#source-types input');
+    for (var i = 0; i < checkboxes.length; ++ i) {
+        checked[checkboxes[i].value] = checkboxes[i].checked;
+    }
+    var pattern = document.getElementById('path-filter').value.toLowerCase();
+
+    var rows = Array.prototype.slice.call(document.querySelectorAll('#summary tbody tr[data-index]'));
+    rows.forEach(function(row) {
+        var file = coverageData[+row.getAttribute('data-index')];
+        row.hidden = !(checked[file.source_type] && file.path.toLowerCase().indexOf(pattern) !== -1);
+    });
+
+    var total = summarize(rows);
+    var totalRow = document.getElementById('total');
+    totalRow.hidden = false;
+    totalRow.cells[0].textContent = 'Total (' + total.files + ' of ' + coverageData.length + ' files)';
+    fillSummaryCells(totalRow, total);
+    regroup();
+}
+
+function initFilters() {
+    var sourceTypes = document.getElementById('source-types');
+    var seen = {};
+    coverageData.forEach(function(file) {
+        if (seen[file.source_type]) {
+            return;
+        }
+        seen[file.source_type] = true;
+        var label = document.createElement('label');
+        var checkbox = document.createElement('input');
+        checkbox.type = 'checkbox';
+        checkbox.value = file.source_type;
+        checkbox.checked = true;
+        checkbox.onchange = applyFilters;
+        label.appendChild(checkbox);
+        label.appendChild(document.createTextNode(' ' + file.source_type));
+        sourceTypes.appendChild(label);
+    });
+    document.getElementById('path-filter').oninput = applyFilters;
+    document.getElementById('group-by-dir').onchange = regroup;
+    // Sorting moves the rows (including the directory headers) around, so they are grouped again afterwards.
+    document.querySelector('#summary thead').addEventListener('click', regroup);
+    document.getElementById('filters').hidden = false;
+    applyFilters();
+}
+
+initFilters();
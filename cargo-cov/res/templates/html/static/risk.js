@@ -0,0 +1,18 @@
+'use strict';
+
+/**
+ * Ranks the functions of the risk matrix, riskiest first.
+ */
+
+function rankFunctions() {
+    var tbody = document.querySelector('#summary tbody');
+    var rows = Array.prototype.slice.call(tbody.rows);
+    rows.sort(function(a, b) {
+        return b.getAttribute('data-risk') - a.getAttribute('data-risk');
+    });
+    rows.forEach(function(row) {
+        tbody.appendChild(row);
+    });
+}
+
+rankFunctions();
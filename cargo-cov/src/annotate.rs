@@ -0,0 +1,245 @@
+//! Annotation of the uncovered lines in the source files themselves.
+//!
+//! `cargo cov annotate` is meant for code reviews which happen outside web UIs, e.g. in an editor or on a patch sent
+//! by email. The coverage is collected the same way as `cargo cov report`, honoring `--include`, `--workspace`,
+//! `--gcno`, `--gcda`, `--import` and `--import-lcov`. By default it writes a sidecar file `<source>.cov` next to every
+//! source file with uncovered lines, listing them in the format of `gcov`:
+//!
+//! ```text
+//! #####:   12:    return Err(e);
+//! ```
+//!
+//! With `--in-place`, the comment `// COV: 0 hits` is appended to the uncovered lines of the source files instead.
+//! Only the files inside the workspace are changed, so the sources of dependencies stay intact even if they are reported
+//! with `--include crates` or `rustsrc`.
+//! Comments left by a previous run are replaced, so running it again after more tests are written updates them. Lines
+//! ending inside a string or a block comment, or continued by a backslash, are never annotated, so the comment cannot
+//! change the meaning of the code.
+//!
+//! `--strip` removes the comments (with `--in-place`) or the sidecar files again.
+
+use argparse::ReportConfig;
+use error::{Result, ResultExt};
+use highlight::Highlighter;
+use report;
+use sourcepath::{Language, SourceClassifier};
+
+use cov::Interner;
+
+use std::collections::BTreeSet;
+use std::fs::{File, remove_file};
+use std::io::{self, ErrorKind as IoErrorKind, Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+/// The comment appended to the uncovered lines with `--in-place`.
+const MARKER: &str = " // COV: 0 hits";
+
+/// The extension appended to the name of a source file to get its sidecar file.
+const SIDECAR_EXTENSION: &str = ".cov";
+
+/// Where the annotations are written.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Into `<source>.cov`.
+    Sidecar,
+    /// Into the source file, as trailing comments.
+    InPlace,
+}
+
+/// Removes the comments added by [`annotate_source()`] from `source`.
+///
+/// [`annotate_source()`]: ./fn.annotate_source.html
+fn strip_source(source: &str) -> String {
+    source
+        .split('\n')
+        .map(|line| {
+            let (content, cr) = split_cr(line);
+            if content.ends_with(MARKER) {
+                format!("{}{}", &content[..content.len() - MARKER.len()], cr)
+            } else {
+                line.to_owned()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Appends the comment to the `uncovered` lines of `source` (1-based), after removing the comments of the previous
+/// run. The line terminators are kept as is.
+fn annotate_source(source: &str, uncovered: &BTreeSet<u32>, language: Language) -> String {
+    let mut highlighter = Highlighter::new(language);
+    strip_source(source)
+        .split('\n')
+        .enumerate()
+        .map(|(i, line)| {
+            let (content, cr) = split_cr(line);
+            // The highlighter tells whether the line ends inside a string or a block comment.
+            highlighter.line(content);
+            let annotatable = !highlighter.is_continued() && !content.ends_with('\\') && !content.trim().is_empty();
+            if annotatable && uncovered.contains(&(i as u32 + 1)) {
+                format!("{}{}{}", content, MARKER, cr)
+            } else {
+                line.to_owned()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Lists the `uncovered` lines of `source` (1-based) in the format of `gcov`.
+fn sidecar(source: &str, uncovered: &BTreeSet<u32>) -> String {
+    let lines = source.lines().collect::<Vec<_>>();
+    let mut out = String::new();
+    for &line_number in uncovered {
+        // Line 0 appears in the records of compiler-generated code, which has no source line to show.
+        let index = match (line_number as usize).checked_sub(1) {
+            Some(index) => index,
+            None => continue,
+        };
+        let line = lines.get(index).map_or("/*EOF*/", |line| line);
+        out.push_str(&format!("#####:{:>5}:{}\n", line_number, line));
+    }
+    out
+}
+
+/// Splits the `\r` of a CRLF line terminator from the end of `line`.
+fn split_cr(line: &str) -> (&str, &str) {
+    if line.ends_with('\r') {
+        line.split_at(line.len() - 1)
+    } else {
+        (line, "")
+    }
+}
+
+/// Reads the whole file as a string.
+fn read_to_string(path: &Path) -> io::Result<String> {
+    let mut content = String::new();
+    File::open(path)?.read_to_string(&mut content)?;
+    Ok(content)
+}
+
+/// Writes `content` to the file.
+fn write(path: &Path, content: &str) -> Result<()> {
+    File::create(path)
+        .and_then(|mut file| file.write_all(content.as_bytes()))
+        .chain_err(|| format!("Cannot write `{}`", path.display()))
+}
+
+/// The path of the sidecar file of a source file.
+fn sidecar_path(path: &Path) -> PathBuf {
+    let mut sidecar_path = path.as_os_str().to_owned();
+    sidecar_path.push(SIDECAR_EXTENSION);
+    PathBuf::from(sidecar_path)
+}
+
+/// Checks whether `path` is inside `workspace_path`, without going through a `..` component.
+fn is_inside(path: &Path, workspace_path: &Path) -> bool {
+    path.starts_with(workspace_path) && !path.components().any(|c| c == Component::ParentDir)
+}
+
+/// Annotates the uncovered lines of the source files, or strips the annotations if `strip` is true.
+pub fn annotate(config: &ReportConfig, mode: Mode, strip: bool) -> Result<()> {
+    let mut interner = Interner::new();
+    let report = report::collect(config, &mut interner)?;
+
+    let mut classifier = SourceClassifier::new(config.language, config.workspace_path.to_string_lossy().into_owned());
+    let mut files = report
+        .files
+        .iter()
+        .filter(|&(&symbol, _)| config.allowed_source_types.contains(classifier.identify(symbol, &interner).0) && config.includes_path(&interner[symbol]))
+        .map(|(&symbol, file)| {
+            let uncovered = file.lines.iter().filter(|&(_, line)| line.count == 0).map(|(&line_number, _)| line_number);
+            (config.workspace_path.join(&interner[symbol]), uncovered.collect::<BTreeSet<_>>())
+        })
+        .collect::<Vec<_>>();
+    ensure!(!files.is_empty(), "no coverage found, run `cargo cov test` first");
+    files.sort();
+
+    let mut files_count = 0;
+    let mut lines_count = 0;
+    let mut outside_count = 0;
+    for (path, uncovered) in files {
+        if mode == Mode::InPlace && !is_inside(&path, &config.workspace_path) {
+            // The sources of the registry or the sysroot must never be changed, even if `--include` reports them.
+            outside_count += 1;
+            continue;
+        }
+        let source = match read_to_string(&path) {
+            Ok(source) => source,
+            Err(e) => {
+                warning!("cannot read source `{}`: {}", path.display(), e);
+                continue;
+            },
+        };
+        match (mode, strip) {
+            (Mode::Sidecar, true) => match remove_file(sidecar_path(&path)) {
+                Ok(()) => files_count += 1,
+                Err(ref e) if e.kind() == IoErrorKind::NotFound => {},
+                Err(e) => return Err(e.into()),
+            },
+            (Mode::Sidecar, false) => {
+                let sidecar_path = sidecar_path(&path);
+                if uncovered.is_empty() {
+                    // Remove the outdated sidecar file of a file which is now fully covered.
+                    remove_file(&sidecar_path).ok();
+                    continue;
+                }
+                write(&sidecar_path, &sidecar(&source, &uncovered))?;
+                files_count += 1;
+                lines_count += uncovered.len();
+            },
+            (Mode::InPlace, _) => {
+                let annotated = if strip { strip_source(&source) } else { annotate_source(&source, &uncovered, config.language) };
+                if annotated != source {
+                    write(&path, &annotated)?;
+                    files_count += 1;
+                }
+                if !strip {
+                    lines_count += annotated.split('\n').filter(|line| split_cr(line).0.ends_with(MARKER)).count();
+                }
+            },
+        }
+    }
+
+    if outside_count > 0 {
+        warning!("{} files outside the workspace are not changed in place", outside_count);
+    }
+    match (mode, strip) {
+        (Mode::Sidecar, true) => progress!("Removed", "{} sidecar files", files_count),
+        (Mode::Sidecar, false) => progress!("Annotated", "{} uncovered lines into {} sidecar files", lines_count, files_count),
+        (Mode::InPlace, true) => progress!("Stripped", "the annotations from {} files", files_count),
+        (Mode::InPlace, false) => progress!("Annotated", "{} uncovered lines, {} files changed", lines_count, files_count),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_annotate_source() {
+    let source = "fn f() {\r\n    g(); // COV: 0 hits\r\n    let s = \"a\n    b\";\n    h();\n}\n";
+    let uncovered = [3, 4, 5].iter().cloned().collect();
+    let annotated = annotate_source(source, &uncovered, Language::Rust);
+    assert_eq!(annotated, "fn f() {\r\n    g();\r\n    let s = \"a\n    b\"; // COV: 0 hits\n    h(); // COV: 0 hits\n}\n");
+    assert_eq!(annotate_source(&annotated, &uncovered, Language::Rust), annotated);
+    assert_eq!(strip_source(&annotated), "fn f() {\r\n    g();\r\n    let s = \"a\n    b\";\n    h();\n}\n");
+
+    let source = "#define X(a) \\\n    a\nint y;";
+    let uncovered = [1, 2, 3].iter().cloned().collect();
+    assert_eq!(annotate_source(source, &uncovered, Language::C), "#define X(a) \\\n    a // COV: 0 hits\nint y; // COV: 0 hits");
+}
+
+#[test]
+fn test_sidecar() {
+    let uncovered = [2, 5].iter().cloned().collect();
+    assert_eq!(sidecar("a\nb\nc\n", &uncovered), "#####:    2:b\n#####:    5:/*EOF*/\n");
+    let uncovered = [0, 1].iter().cloned().collect();
+    assert_eq!(sidecar("a\n", &uncovered), "#####:    1:a\n");
+}
+
+#[test]
+fn test_is_inside() {
+    let workspace = Path::new("/workspace");
+    assert!(is_inside(Path::new("/workspace/src/lib.rs"), workspace));
+    assert!(!is_inside(Path::new("/workspace2/src/lib.rs"), workspace));
+    assert!(!is_inside(Path::new("/workspace/../etc/passwd"), workspace));
+    assert!(!is_inside(Path::new("/home/user/.cargo/registry/src/foo/lib.rs"), workspace));
+}
@@ -0,0 +1,326 @@
+//! Reading GCNO and GCDA files from archives.
+//!
+//! `--gcno` and `--gcda` may point at a `.zip`, `.tar`, `.tar.gz` or `.tgz` archive instead of a directory, e.g. the
+//! artifact uploaded by every shard of a CI job. The members with the expected extension are parsed directly from the
+//! archive, without extracting them. Members in subdirectories of the archive are included.
+//!
+//! A member is named by the path of the archive joined with its path inside the archive, e.g.
+//! `shard-1.zip/gcda/foo-0123456789abcdef.gcda`, so the [crate hash] recorded in the file name still applies.
+//!
+//! [`list()`] only reads the headers of the archive, which record the size of every member. The content is streamed
+//! by [`for_each()`], which visits all members in a single pass over the archive (a gzipped tar cannot be read out of
+//! order), and is never read beyond the size recorded in the header. A member larger than [`MAX_MEMBER_SIZE`] is
+//! rejected, so a crafted archive cannot expand into an unbounded amount of data.
+//!
+//! [crate hash]: ../shim/fn.artifact_hash.html
+//! [`list()`]: ./fn.list.html
+//! [`for_each()`]: ./fn.for_each.html
+//! [`MAX_MEMBER_SIZE`]: ./constant.MAX_MEMBER_SIZE.html
+
+use flate2::read::GzDecoder;
+use tar::Archive;
+use zip::ZipArchive;
+
+use std::ffi::OsStr;
+use std::fs::{read_dir, File};
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+/// The maximum size of a member of an archive in bytes, far above any GCNO or GCDA file.
+pub const MAX_MEMBER_SIZE: u64 = 256 << 20;
+
+/// A GCNO or GCDA file, either in a directory or a member of an archive.
+#[derive(Clone, Debug)]
+pub struct GcovFile {
+    /// Path to the file. For a member of an archive, the path of the archive joined with the path of the member.
+    pub path: PathBuf,
+    /// Size of the (decompressed) file in bytes.
+    size: u64,
+}
+
+impl GcovFile {
+    /// Obtains the size of the (decompressed) file in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+/// Kinds of supported archives, detected from the file name.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum ArchiveKind {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+impl ArchiveKind {
+    fn of(path: &Path) -> Option<ArchiveKind> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        if name.ends_with(".zip") {
+            Some(ArchiveKind::Zip)
+        } else if name.ends_with(".tar") {
+            Some(ArchiveKind::Tar)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveKind::TarGz)
+        } else {
+            None
+        }
+    }
+}
+
+/// Checks whether `path` is an archive file which can be given to `--gcno` and `--gcda`.
+pub fn is_archive(path: &Path) -> bool {
+    ArchiveKind::of(path).is_some() && path.is_file()
+}
+
+/// Lists the files with the given `extension` in the directory `path`, sorted by path.
+fn list_dir(path: &Path, extension: &str) -> io::Result<Vec<GcovFile>> {
+    let mut files = Vec::new();
+    for entry in read_dir(path)? {
+        let entry = entry?;
+        let path = entry.path();
+        if path.extension() == Some(OsStr::new(extension)) {
+            files.push(GcovFile {
+                size: entry.metadata()?.len(),
+                path,
+            });
+        }
+    }
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(files)
+}
+
+/// Creates the error of a malformed archive.
+fn invalid(archive_path: &Path, message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("cannot read archive `{}`: {}", archive_path.display(), message))
+}
+
+/// Calls `f` with the name, size and content of every regular file in the archive whose name is `wanted`, in their
+/// order in the archive. The content is limited to the size in the header.
+fn visit_members(archive_path: &Path, wanted: &Fn(&str) -> bool, f: &mut FnMut(&str, u64, &mut Read) -> io::Result<()>) -> io::Result<()> {
+    let file = BufReader::new(File::open(archive_path)?);
+    let check_size = |name: &str, size: u64| {
+        if size > MAX_MEMBER_SIZE {
+            Err(invalid(archive_path, &format!("member `{}` of {} bytes exceeds the limit {}", name, size, MAX_MEMBER_SIZE)))
+        } else {
+            Ok(())
+        }
+    };
+
+    let tar_reader: Box<Read> = match ArchiveKind::of(archive_path) {
+        Some(ArchiveKind::Zip) => {
+            let mut zip = ZipArchive::new(file).map_err(|e| invalid(archive_path, &e.to_string()))?;
+            for index in 0..zip.len() {
+                let mut member = zip.by_index(index).map_err(|e| invalid(archive_path, &e.to_string()))?;
+                let name = member.name().to_owned();
+                if !member.is_file() || !wanted(&name) {
+                    continue;
+                }
+                let size = member.size();
+                check_size(&name, size)?;
+                f(&name, size, &mut (&mut member).take(size))?;
+            }
+            return Ok(());
+        },
+        Some(ArchiveKind::Tar) => Box::new(file),
+        Some(ArchiveKind::TarGz) => Box::new(GzDecoder::new(file)),
+        None => return Err(invalid(archive_path, "unsupported archive format")),
+    };
+
+    let mut tar = Archive::new(tar_reader);
+    for member in tar.entries().map_err(|e| invalid(archive_path, &e.to_string()))? {
+        let mut member = member.map_err(|e| invalid(archive_path, &e.to_string()))?;
+        if !member.header().entry_type().is_file() {
+            continue;
+        }
+        let name = member.path().map_err(|e| invalid(archive_path, &e.to_string()))?.to_string_lossy().into_owned();
+        if !wanted(&name) {
+            continue;
+        }
+        let size = member.size();
+        check_size(&name, size)?;
+        f(&name, size, &mut (&mut member).take(size))?;
+    }
+    Ok(())
+}
+
+/// Converts a member of an archive into a `GcovFile`.
+fn member_file(archive_path: &Path, name: &str, size: u64) -> GcovFile {
+    GcovFile {
+        path: archive_path.join(name.trim_start_matches('/')),
+        size,
+    }
+}
+
+/// Lists the files with the given `extension` in `path`, which is either a directory or an archive, without reading
+/// their content. The files in a directory are sorted by path, and those in an archive are in their order in it.
+pub fn list(path: &Path, extension: &str) -> io::Result<Vec<GcovFile>> {
+    if !is_archive(path) {
+        return list_dir(path, extension);
+    }
+    let wanted = |name: &str| Path::new(name).extension() == Some(OsStr::new(extension));
+    let mut files = Vec::new();
+    // The content of a zip member is only decompressed when read, and that of a tar member is skipped.
+    visit_members(path, &wanted, &mut |name, size, _| {
+        files.push(member_file(path, name, size));
+        Ok(())
+    })?;
+    Ok(files)
+}
+
+/// Calls `f` with every file with the given `extension` in `path` (a directory or an archive) and its content, in the
+/// same order as [`list()`]. An archive is only read once.
+///
+/// [`list()`]: ./fn.list.html
+pub fn for_each<E, F>(path: &Path, extension: &str, mut f: F) -> Result<(), E>
+where
+    E: From<io::Error>,
+    F: FnMut(&GcovFile, &mut Read) -> Result<(), E>,
+{
+    if !is_archive(path) {
+        for file in list_dir(path, extension)? {
+            let mut reader = BufReader::new(File::open(&file.path)?);
+            f(&file, &mut reader)?;
+        }
+        return Ok(());
+    }
+
+    let wanted = |name: &str| Path::new(name).extension() == Some(OsStr::new(extension));
+    // The error of `f` is kept aside, since the visitor can only return an I/O error.
+    let mut error = None;
+    visit_members(path, &wanted, &mut |name, size, reader| {
+        if let Err(e) = f(&member_file(path, name, size), reader) {
+            error = Some(e);
+            return Err(io::Error::new(io::ErrorKind::Other, "aborted"));
+        }
+        Ok(())
+    }).or_else(|e| if error.is_some() { Ok(()) } else { Err(e) })?;
+    match error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+#[test]
+fn test_list_archives() {
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::fs::write;
+    use std::io::Write;
+    use tar::{Builder, EntryType, Header};
+    use zip::write::{SimpleFileOptions, ZipWriter};
+    use zip::CompressionMethod;
+
+    let gcno = ::std::fs::read("../cov/test-data/branches.clang/x.gcno").unwrap();
+    let gcda = ::std::fs::read("../cov/test-data/branches.clang/x.gcda").unwrap();
+    let long_name = "gcov/a-very-long-directory-name-which-does-not-fit-into-the-hundred-bytes-of-a-tar-header/x.gcda";
+    let dir = ::tempfile::TempDir::new().unwrap();
+
+    let mut tar = Builder::new(Vec::new());
+    let append = |tar: &mut Builder<Vec<u8>>, name: &str, content: &[u8], entry_type: EntryType| {
+        let mut header = Header::new_gnu();
+        header.set_entry_type(entry_type);
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        tar.append_data(&mut header, name, content).unwrap();
+    };
+    append(&mut tar, "gcov/", b"", EntryType::Directory);
+    append(&mut tar, "gcov/x.gcno", &gcno, EntryType::Regular);
+    append(&mut tar, long_name, &gcda, EntryType::Regular);
+    append(&mut tar, "README", b"not coverage", EntryType::Regular);
+    let tar = tar.into_inner().unwrap();
+    let tar_path = dir.path().join("shard.tar");
+    write(&tar_path, &tar).unwrap();
+
+    let mut tar_gz = GzEncoder::new(Vec::new(), Compression::default());
+    tar_gz.write_all(&tar).unwrap();
+    let tar_gz_path = dir.path().join("shard.tar.gz");
+    write(&tar_gz_path, tar_gz.finish().unwrap()).unwrap();
+
+    let zip_path = dir.path().join("shard.zip");
+    let mut zip = ZipWriter::new(File::create(&zip_path).unwrap());
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    zip.add_directory("gcov/", deflated).unwrap();
+    zip.start_file("gcov/x.gcno", deflated).unwrap();
+    zip.write_all(&gcno).unwrap();
+    zip.start_file(long_name, deflated.compression_method(CompressionMethod::Stored)).unwrap();
+    zip.write_all(&gcda).unwrap();
+    zip.start_file("README", deflated).unwrap();
+    zip.write_all(b"not coverage").unwrap();
+    zip.finish().unwrap();
+
+    for archive_path in &[tar_path, tar_gz_path, zip_path] {
+        assert!(is_archive(archive_path));
+        let gcnos = list(archive_path, "gcno").unwrap();
+        assert_eq!(gcnos.len(), 1);
+        assert_eq!(gcnos[0].path, archive_path.join("gcov/x.gcno"));
+        assert_eq!(gcnos[0].size(), gcno.len() as u64);
+        let gcdas = list(archive_path, "gcda").unwrap();
+        assert_eq!(gcdas.len(), 1);
+        assert_eq!(gcdas[0].path, archive_path.join(long_name));
+        let mut visited = Vec::new();
+        for_each(archive_path, "gcda", |file, reader| -> io::Result<()> {
+            let mut content = Vec::new();
+            reader.read_to_end(&mut content)?;
+            visited.push((file.path.clone(), content));
+            Ok(())
+        }).unwrap();
+        assert_eq!(visited, vec![(gcdas[0].path.clone(), gcda.clone())]);
+        let aborted = for_each(archive_path, "gcno", |_, _| Err(io::Error::new(io::ErrorKind::Other, "stop")));
+        assert_eq!(aborted.unwrap_err().to_string(), "stop");
+    }
+
+    assert!(!is_archive(dir.path()));
+    assert!(list(&dir.path().join("missing.zip"), "gcno").is_err());
+    write(dir.path().join("broken.zip"), b"PK").unwrap();
+    assert!(list(&dir.path().join("broken.zip"), "gcno").is_err());
+    write(dir.path().join("broken.tar.gz"), b"\x1f\x8b\x08\0").unwrap();
+    assert!(list(&dir.path().join("broken.tar.gz"), "gcno").is_err());
+}
+
+#[test]
+fn test_member_size_limit() {
+    use tar::{EntryType, Header};
+
+    // A header claiming a huge member, as in a crafted archive, is rejected before reading the content.
+    let mut header = Header::new_gnu();
+    header.set_entry_type(EntryType::Regular);
+    header.set_path("x.gcda").unwrap();
+    header.set_size(MAX_MEMBER_SIZE + 1);
+    header.set_mode(0o644);
+    header.set_cksum();
+    let mut tar = header.as_bytes().to_vec();
+    tar.extend(vec![0; 1024]);
+
+    let dir = ::tempfile::TempDir::new().unwrap();
+    let tar_path = dir.path().join("bomb.tar");
+    ::std::fs::write(&tar_path, &tar).unwrap();
+    let error = list(&tar_path, "gcda").unwrap_err();
+    assert!(error.to_string().contains("exceeds the limit"), "{}", error);
+}
+
+#[test]
+fn test_malformed_pax_path() {
+    use tar::{EntryType, Header};
+
+    // An extended header with an empty `path` record, followed by a regular member.
+    let mut tar = Vec::new();
+    for &(entry_type, name, content) in &[(EntryType::XHeader, "pax", &b"8 path=\n"[..]), (EntryType::Regular, "x.gcda", &b""[..])] {
+        let mut header = Header::new_ustar();
+        header.set_entry_type(entry_type);
+        header.set_path(name).unwrap();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        tar.extend_from_slice(header.as_bytes());
+        tar.extend_from_slice(content);
+        tar.extend(vec![0; (512 - content.len() % 512) % 512]);
+    }
+    tar.extend(vec![0; 1024]);
+
+    let dir = ::tempfile::TempDir::new().unwrap();
+    let tar_path = dir.path().join("pax.tar");
+    ::std::fs::write(&tar_path, &tar).unwrap();
+    // Either the member is skipped or the archive is rejected, but it must not panic.
+    let _ = list(&tar_path, "gcda");
+}
@@ -1,13 +1,24 @@
 //! Extra functions for command line argument parsing.
 
-use error::Result;
-use sourcepath::SourceType;
+use assets;
+use config::Config;
+use error::{ErrorKind, Result, ResultExt};
+use history::DEFAULT_HISTORY_LIMIT;
+use outcome::DEFAULT_COVERAGE_LINE;
+use owners;
+use sourcepath::{GroupBy, Language, SourceType};
+use rounding::{DEFAULT_PRECISION, Rounding};
 use utils::{join_3, parent_3};
+use waivers::Waiver;
 
 use clap::ArgMatches;
+use cov::report::FileSummary;
+use regex::Regex;
+use rustc_demangle::demangle;
+use serde_json::{Map, Value, from_str};
 
 use std::borrow::Cow;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::ffi::OsStr;
 use std::path::{Path, PathBuf};
 
@@ -19,6 +30,20 @@ lazy_static! {
         "manifest-path",
         "target",
         "profiler",
+        "runner",
+        "changed-since",
+        "only-path",
+    ].iter().cloned().collect();
+
+    /// The list of special arguments which are flags without values. See [`update_from_clap()`] for detail.
+    ///
+    /// [`update_from_clap()`]: ./fn.update_from_clap.html
+    static ref SPECIAL_FLAGS: HashSet<&'static str> = [
+        "wait",
+        "no-lock",
+        "link-dead-code",
+        "no-link-dead-code",
+        "changed-first",
     ].iter().cloned().collect();
 
     /// The list of `rustc` flags that take a value (i.e. of the form `--foo bar`).
@@ -79,12 +104,23 @@ pub type SpecialMap<'a> = HashMap<&'static str, &'a OsStr>;
 /// * `--manifest-path`
 /// * `--target`
 /// * `--profiler`
+/// * `--runner`
+/// * `--changed-since`
+/// * `--only-path`
+///
+/// Special flags (`--wait`, `--no-lock`, `--link-dead-code`, `--no-link-dead-code` and `--changed-first`) are handled
+/// similarly, and are inserted with an empty value when present.
 pub fn update_from_clap<'a>(matches: &'a ArgMatches, specialized: &mut SpecialMap<'a>) {
     for name in SPECIALS.iter() {
         if let Some(value) = matches.value_of_os(name) {
             specialized.insert(name, value);
         }
     }
+    for name in SPECIAL_FLAGS.iter() {
+        if matches.is_present(name) {
+            specialized.insert(name, OsStr::new(""));
+        }
+    }
 }
 
 /// Finds out the path to the crate `rustc` is building from its arguments. If the path is a descendant of
@@ -114,6 +150,65 @@ pub fn is_rustc_compiling_local_crate<'a, I: IntoIterator<Item = &'a OsStr>>(arg
     false
 }
 
+/// Finds the value of the option `name` (e.g. `--crate-name`), given either as `--crate-name foo` or as
+/// `--crate-name=foo`. Arguments after `--` are not searched.
+pub fn find_option_value<'a, I: IntoIterator<Item = &'a OsStr>>(args: I, name: &str) -> Option<&'a str> {
+    let mut is_value = false;
+    for arg in args {
+        let s = match arg.to_str() {
+            Some(s) => s,
+            None => continue,
+        };
+        if is_value {
+            return Some(s);
+        } else if s == "--" {
+            break;
+        } else if s == name {
+            is_value = true;
+        } else if s.starts_with(name) && s[name.len()..].starts_with('=') {
+            return Some(&s[name.len() + 1..]);
+        }
+    }
+    None
+}
+
+/// Finds out the hash which distinguishes the crate `rustc` is building from other crates with the same name.
+///
+/// This is the value of `-C extra-filename` without the leading `-`, or the value of `-C metadata` if the former is
+/// absent. Cargo sets both to the same hash.
+pub fn extract_crate_hash<'a, I: IntoIterator<Item = &'a OsStr>>(args: I) -> Option<&'a str> {
+    let mut metadata = None;
+    let mut is_codegen_value = false;
+    for arg in args {
+        let s = match arg.to_str() {
+            Some(s) => s,
+            None => continue,
+        };
+        let codegen = if is_codegen_value {
+            is_codegen_value = false;
+            s
+        } else if s == "-C" || s == "--codegen" {
+            is_codegen_value = true;
+            continue;
+        } else if s.starts_with("-C") {
+            &s[2..]
+        } else if s.starts_with("--codegen=") {
+            &s[10..]
+        } else {
+            continue;
+        };
+        if codegen.starts_with("extra-filename=") {
+            let extra_filename = codegen[15..].trim_start_matches('-');
+            if !extra_filename.is_empty() {
+                return Some(extra_filename);
+            }
+        } else if codegen.starts_with("metadata=") {
+            metadata = Some(&codegen[9..]);
+        }
+    }
+    metadata
+}
+
 /// Extracts *special arguments* from the iterator of arguments.
 ///
 /// The values will be inserted to the `specialized` map. Remaining arguments are returned as a vector.
@@ -144,6 +239,9 @@ pub fn normalize<'a, I: IntoIterator<Item = &'a OsStr>>(args: I, specialized: &m
                     } else if let Some(name) = SPECIALS.get(s) {
                         current_name = Some(name);
                         continue;
+                    } else if let Some(name) = SPECIAL_FLAGS.get(s) {
+                        specialized.insert(name, OsStr::new(""));
+                        continue;
                     } else if let Some(eq_index) = s.find('=') {
                         if let Some(name) = SPECIALS.get(&s[..eq_index]) {
                             let value = OsStr::new(&s[(eq_index + 1)..]);
@@ -163,44 +261,190 @@ pub fn normalize<'a, I: IntoIterator<Item = &'a OsStr>>(args: I, specialized: &m
 
 
 /// Parsed command-line configuration for the `report` subcommand.
+#[derive(Clone)]
 pub struct ReportConfig<'a> {
     pub workspace_path: Cow<'a, Path>,
     pub gcno_path: Cow<'a, Path>,
     pub gcda_path: Cow<'a, Path>,
     pub output_path: Cow<'a, Path>,
     pub template_name: &'a OsStr,
+    /// Directory containing templates and static files overriding those of the built-in template.
+    pub template_override_path: Option<Cow<'a, Path>>,
+    /// Whether the template override may read files outside its directory.
+    pub allow_template_fs: bool,
+    /// Name of the color theme, for the templates providing `static/themes/`.
+    pub theme: Option<&'a str>,
     pub allowed_source_types: SourceType,
+    /// Only the source files inside this directory are reported.
+    pub only_path: Option<PathBuf>,
+    /// Path to the `CODEOWNERS` file, if coverage should be aggregated per owner.
+    pub codeowners_path: Option<Cow<'a, Path>>,
+    /// Minimum line coverage required for each owner.
+    pub owner_thresholds: Vec<(&'a str, f64)>,
+    /// Template options overriding the defaults in the template's `config.toml`.
+    pub template_options: Vec<(&'a str, Value)>,
+    /// Path to the coverage history, if it should be recorded.
+    pub history_path: Option<PathBuf>,
+    /// Number of snapshots kept in the coverage history.
+    pub history_limit: usize,
+    /// Whether to remove functions from the sysroot before constructing the graph.
+    pub prune_sysroot: bool,
+    /// Whether to print the time spent in every phase.
+    pub timings: bool,
+    /// Path to write the Chrome trace of the phases.
+    pub timings_trace_path: Option<Cow<'a, Path>>,
+    /// Estimated memory usage of the graph in megabytes, above which the optional features are disabled.
+    pub max_memory: Option<u64>,
+    /// Files produced by `cargo cov export` to be merged together with the local GCNO and GCDA files.
+    pub import_paths: &'a [PathBuf],
+    /// LCOV tracefiles produced by other tools to be merged into the report.
+    pub import_lcov_paths: &'a [PathBuf],
+    /// The public key to verify the signature of every imported file with.
+    pub verify_key_path: Option<&'a Path>,
+    /// Language of the profiled project.
+    pub language: Language,
+    /// How the files are grouped in the summary, default to the template's preference.
+    pub group_by: Option<GroupBy>,
+    /// How the percentages are rounded.
+    pub rounding: Rounding,
+    /// Maximum number of files shown in the summary, the worst first.
+    pub max_files: Option<usize>,
+    /// Whether to rank the files in the summary by the number of uncovered lines.
+    pub top_uncovered: bool,
+    /// Only the functions whose demangled name matches this are reported.
+    pub functions_regex: Option<Regex>,
+    /// The functions whose demangled name matches this are left out.
+    pub exclude_functions_regex: Option<Regex>,
+    /// Revision to find the changed lines since.
+    pub diff_base: Option<&'a str>,
+    /// Whether to find the uncovered functions unreachable from the code run by the tests.
+    pub reachability: bool,
+    /// Web URL of the repository (or `auto`) which the lines of the file pages link to.
+    pub permalink_base: Option<&'a str>,
+    /// Whether to find the uncovered lines of the changed files for GitHub Actions annotations.
+    pub github_annotations: bool,
+    /// Whether to keep the previous report and only render the pages of files which changed.
+    pub changed_only: bool,
+    /// Summary of every file in the previous report kept in memory by `cargo cov serve`, used with `changed_only` instead
+    /// of the one recorded in the output directory.
+    pub previous_summaries: Option<BTreeMap<String, FileSummary>>,
+    /// Code expected to be uncovered.
+    pub waivers: &'a [Waiver],
+    /// Whether to fail if any uncovered line is not waived.
+    pub fail_on_unwaived_uncovered: bool,
+    /// Whether to fail if any warning is printed while generating the report.
+    pub strict: bool,
+    /// Whether to print the recoverable anomalies found in the GCNO/GCDA files, implied by `strict`.
+    pub verbose: bool,
+    /// Whether to attribute the code inlined from other crates to the local functions calling it.
+    pub attribute_inlined: bool,
+    /// Whether to also write the annotated sources in the format of `gcov` into `<output>/gcov/`.
+    pub gcov_files: bool,
+    /// Whether to also bundle the HTML pages into the standalone `<output>/report.html`.
+    pub single_file: bool,
+    /// Whether to also copy the HTML pages with inlined resources into `<output>/azure/` for Azure Pipelines.
+    pub azure: bool,
+    /// The other templates rendered into the output directory after `template_name`, sharing the same coverage.
+    pub emit: Vec<&'a str>,
 }
 
 impl<'a> ReportConfig<'a> {
-    /// Parses the command-line arguments for the `report` subcommand.
+    /// Resolves the configuration for the `report` subcommand, filling in the default paths.
     ///
-    /// Returns None if
-    pub fn parse(matches: &'a ArgMatches<'a>, cov_build_path: Result<PathBuf>) -> Result<ReportConfig<'a>> {
-        fn match_or_else<'a, F: FnOnce() -> PathBuf>(matches: &'a ArgMatches<'a>, name: &str, default: F) -> Cow<'a, Path> {
-            match matches.value_of_os(name) {
-                Some(path) => Cow::Borrowed(Path::new(path)),
+    /// # Errors
+    ///
+    /// Returns [`UnsupportedSourceType`] if `config.include` contains an unknown source type.
+    ///
+    /// [`UnsupportedSourceType`]: ../error/enum.ErrorKind.html#variant.UnsupportedSourceType
+    pub fn resolve(config: &'a Config, cov_build_path: Result<PathBuf>) -> Result<ReportConfig<'a>> {
+        fn path_or_else<'a, F: FnOnce() -> PathBuf>(path: &'a Option<PathBuf>, default: F) -> Cow<'a, Path> {
+            match *path {
+                Some(ref path) => Cow::Borrowed(path),
                 None => Cow::Owned(default()),
             }
         }
 
-
-        let (workspace_path, cov_build_path) = match (matches.value_of_os("workspace"), cov_build_path) {
-            (Some(workspace_path), _) => {
-                let workspace_path = Path::new(workspace_path);
+        let (workspace_path, cov_build_path) = match (&config.workspace, cov_build_path) {
+            (&Some(ref workspace_path), _) => {
                 let cov_build_path = join_3(workspace_path, "target", "cov", "build");
-                (Cow::Borrowed(workspace_path), cov_build_path)
+                (Cow::Borrowed(&**workspace_path), cov_build_path)
             },
             (_, Ok(cov_build_path)) => (Cow::Owned(parent_3(&cov_build_path).to_owned()), cov_build_path),
-            (None, Err(e)) => return Err(e),
+            (&None, Err(e)) => return Err(e).chain_err(|| "Cannot find the Cargo workspace, use --workspace to generate the report without one"),
+        };
+
+        let gcno_path = path_or_else(&config.gcno, || cov_build_path.join("gcno"));
+        let gcda_path = path_or_else(&config.gcda, || cov_build_path.join("gcda"));
+        let output_path = path_or_else(&config.output, || join_3(&workspace_path, "target", "cov", "report"));
+
+        let history_path = if config.history {
+            Some(cov_build_path.with_file_name("history.json"))
+        } else {
+            None
+        };
+
+        let language = config.language.unwrap_or_default();
+        let mut template_names = match config.emit {
+            Some(ref names) if names.iter().any(|name| name == "all") => assets::template_names(),
+            Some(ref names) if !names.is_empty() => names.iter().map(|name| &**name).collect(),
+            _ => vec![config.template.as_ref().map_or("html", |s| &**s)],
+        };
+        // The Coverage tab of Azure Pipelines needs both the Cobertura summary and the HTML report.
+        if config.azure {
+            template_names.extend(&["html", "cobertura"]);
+        }
+        // The `html` template comes first, so `--open` and `--changed-only` refer to it. The `gcov` template writes a
+        // page per source file, which are put aside in `<output>/gcov/` like `--gcov-files`.
+        let mut seen = HashSet::new();
+        template_names.retain(|&name| seen.insert(name));
+        template_names.sort_by_key(|&name| name != "html");
+        let mut gcov_files = config.gcov_files;
+        if template_names.len() > 1 && template_names.contains(&"gcov") {
+            template_names.retain(|&name| name != "gcov");
+            gcov_files = true;
+        }
+        let template_name = OsStr::new(template_names.remove(0));
+        let allowed_source_types = match config.include {
+            Some(ref names) => {
+                let mut source_types = SourceType::empty();
+                for name in names {
+                    source_types |= name.parse().map_err(|_| ErrorKind::UnsupportedSourceType(name.clone()))?;
+                }
+                source_types
+            },
+            None => language.default_source_types(),
         };
 
-        let gcno_path = match_or_else(matches, "gcno", || cov_build_path.join("gcno"));
-        let gcda_path = match_or_else(matches, "gcda", || cov_build_path.join("gcda"));
-        let output_path = match_or_else(matches, "output", || join_3(&workspace_path, "target", "cov", "report"));
+        let owner_thresholds = config.owner_thresholds.iter().map(|(owner, &threshold)| (&**owner, threshold)).collect::<Vec<_>>();
+        let template_options = config.template_options.iter().map(|(name, value)| (&**name, value.clone())).collect();
+        let codeowners_path = match config.codeowners {
+            Some(ref path) => Some(Cow::Borrowed(&**path)),
+            None if config.owners || !owner_thresholds.is_empty() => {
+                owners::DEFAULT_PATHS.iter().map(|p| workspace_path.join(p)).find(|p| p.is_file()).map(Cow::Owned)
+            },
+            None => None,
+        };
 
-        let template_name = matches.value_of_os("template").unwrap_or_else(|| OsStr::new("html"));
-        let allowed_source_types = matches.values_of("include").map_or(SourceType::DEFAULT, |it| SourceType::from_multi_str(it).expect("SourceType"));
+        let only_path = config.only_path.as_ref().map(|path| workspace_path.join(path));
+        let compile = |option: &str, regex: &Option<String>| -> Result<Option<Regex>> {
+            match *regex {
+                Some(ref regex) => Ok(Some(Regex::new(regex).chain_err(|| format!("Invalid regular expression for --{}", option))?)),
+                None => Ok(None),
+            }
+        };
+        // The sandbox of the template override would be pointless if a configuration file could point it at e.g. `/`.
+        let template_override_path = match config.template_override {
+            Some(ref path) if !config.template_override_from_cli => {
+                let canonical_path = path.canonicalize().chain_err(|| format!("Cannot find the template override `{}`", path.display()))?;
+                let canonical_workspace_path = workspace_path.canonicalize().chain_err(|| format!("Cannot find the workspace `{}`", workspace_path.display()))?;
+                ensure!(canonical_path.starts_with(&canonical_workspace_path), ErrorKind::TemplateOverrideOutsideWorkspace(path.clone()));
+                Some(Cow::Owned(canonical_path))
+            },
+            Some(ref path) => Some(Cow::Borrowed(&**path)),
+            None => None,
+        };
+        let functions_regex = compile("functions-regex", &config.functions_regex)?;
+        let exclude_functions_regex = compile("exclude-functions-regex", &config.exclude_functions_regex)?;
 
         Ok(ReportConfig {
             workspace_path,
@@ -208,7 +452,276 @@ impl<'a> ReportConfig<'a> {
             gcda_path,
             output_path,
             template_name,
+            template_override_path,
+            allow_template_fs: config.allow_template_fs,
+            theme: config.theme.as_ref().map(|theme| &**theme),
             allowed_source_types,
+            only_path,
+            codeowners_path,
+            owner_thresholds,
+            template_options,
+            history_path,
+            history_limit: config.history_limit.unwrap_or(DEFAULT_HISTORY_LIMIT),
+            prune_sysroot: config.prune_sysroot,
+            timings: config.timings || config.timings_trace.is_some(),
+            timings_trace_path: config.timings_trace.as_ref().map(|path| Cow::Borrowed(&**path)),
+            max_memory: config.max_memory,
+            import_paths: &config.import,
+            import_lcov_paths: &config.import_lcov,
+            verify_key_path: config.verify_key.as_ref().map(|path| &**path),
+            language,
+            group_by: config.group_by,
+            rounding: Rounding {
+                mode: config.rounding.unwrap_or_default(),
+                precision: config.precision.unwrap_or(DEFAULT_PRECISION),
+            },
+            max_files: config.max_files,
+            top_uncovered: config.top_uncovered,
+            functions_regex,
+            exclude_functions_regex,
+            diff_base: config.diff_base.as_ref().map(|base| &**base),
+            reachability: config.reachability,
+            permalink_base: config.permalink_base.as_ref().map(|base| &**base),
+            github_annotations: config.github_annotations,
+            changed_only: config.changed_only,
+            previous_summaries: None,
+            waivers: &config.waivers,
+            fail_on_unwaived_uncovered: config.fail_on_unwaived_uncovered,
+            strict: config.strict,
+            verbose: config.verbose || config.strict,
+            attribute_inlined: config.attribute_inlined,
+            gcov_files,
+            single_file: config.single_file,
+            azure: config.azure,
+            emit: template_names,
         })
     }
+
+    /// Checks whether a source file, given by its path in the GCNO files (absolute or relative to the workspace), is
+    /// inside `--only-path`.
+    pub fn includes_path(&self, path: &str) -> bool {
+        match self.only_path {
+            Some(ref only_path) => self.workspace_path.join(path).starts_with(only_path),
+            None => true,
+        }
+    }
+
+    /// Checks whether a function, given by its symbol name, matches `--functions-regex` and not
+    /// `--exclude-functions-regex`. Rust symbols are demangled without the hash before matching.
+    pub fn includes_function(&self, name: &str) -> bool {
+        if self.functions_regex.is_none() && self.exclude_functions_regex.is_none() {
+            return true;
+        }
+        let demangled = match self.language {
+            Language::Rust => Cow::Owned(format!("{:#}", demangle(name))),
+            Language::C => Cow::Borrowed(name),
+        };
+        self.functions_regex.as_ref().map_or(true, |r| r.is_match(&demangled)) && !self.exclude_functions_regex.as_ref().map_or(false, |r| r.is_match(&demangled))
+    }
+}
+
+/// Reads the configuration from the matches of a report subcommand, on top of the configuration file given by `file`
+/// or the `--config` option. Only the options present in the command line override the file.
+pub fn parse_config(matches: &ArgMatches, file: Option<&Path>) -> Result<Config> {
+    let mut config = match file.or_else(|| matches.value_of_os("config").map(Path::new)) {
+        Some(path) => Config::from_file(path)?,
+        None => Config::default(),
+    };
+
+    let path = |name| matches.value_of_os(name).map(PathBuf::from);
+    let mut template_options = Map::new();
+    for spec in matches.values_of("template_option").into_iter().flat_map(|it| it) {
+        let (name, value) = parse_template_option(spec)?;
+        template_options.insert(name.to_owned(), value);
+    }
+    let mut owner_thresholds = BTreeMap::new();
+    for spec in matches.values_of("owner_threshold").into_iter().flat_map(|it| it) {
+        let (owner, threshold) = parse_threshold(spec)?;
+        owner_thresholds.insert(owner.to_owned(), threshold);
+    }
+    let max_memory = match matches.value_of("max_memory") {
+        Some(mb) => Some(mb.parse().chain_err(|| format!("Invalid memory limit `{}`", mb))?),
+        None => None,
+    };
+    let max_files = match matches.value_of("max_files") {
+        Some(n) => Some(n.parse().chain_err(|| format!("Invalid number of files `{}`", n))?),
+        None => None,
+    };
+    let history_limit = match matches.value_of("history_limit") {
+        Some(n) => Some(n.parse().ok().filter(|&n| n > 0).ok_or_else(|| format!("Invalid number of snapshots `{}`", n))?),
+        None => None,
+    };
+    let precision = match matches.value_of("precision") {
+        Some(digits) => Some(digits.parse().chain_err(|| format!("Invalid precision `{}`", digits))?),
+        None => None,
+    };
+
+    config.merge(Config {
+        workspace: path("workspace"),
+        gcno: path("gcno"),
+        gcda: path("gcda"),
+        output: path("output"),
+        template: matches.value_of("template").map(str::to_owned),
+        emit: matches.values_of("emit").map(|it| it.map(str::to_owned).collect()),
+        template_override: path("template_override"),
+        template_override_from_cli: matches.is_present("template_override"),
+        theme: matches.value_of("theme").map(str::to_owned),
+        template_options,
+        include: matches.values_of("include").map(|it| it.map(str::to_owned).collect()),
+        only_path: path("only-path"),
+        owners: matches.is_present("owners"),
+        codeowners: path("codeowners"),
+        owner_thresholds,
+        history: matches.is_present("history"),
+        history_limit,
+        prune_sysroot: matches.is_present("prune_sysroot"),
+        timings: matches.is_present("timings"),
+        timings_trace: path("timings"),
+        max_memory,
+        import: matches.values_of_os("import").map_or_else(Vec::new, |it| it.map(PathBuf::from).collect()),
+        import_lcov: matches.values_of_os("import_lcov").map_or_else(Vec::new, |it| it.map(PathBuf::from).collect()),
+        verify_key: path("verify_key"),
+        language: matches.value_of("language").map(|name| name.parse().expect("validated by clap")),
+        group_by: matches.value_of("group_by").map(|name| name.parse().expect("validated by clap")),
+        rounding: matches.value_of("rounding").map(|name| name.parse().expect("validated by clap")),
+        precision,
+        max_files,
+        top_uncovered: matches.is_present("top_uncovered"),
+        functions_regex: matches.value_of("functions_regex").map(str::to_owned),
+        exclude_functions_regex: matches.value_of("exclude_functions_regex").map(str::to_owned),
+        coverage_line: match matches.value_of("coverage_line") {
+            Some(format) => Some(format.to_owned()),
+            None if matches.is_present("coverage_line") => Some(DEFAULT_COVERAGE_LINE.to_owned()),
+            None => None,
+        },
+        teamcity: matches.is_present("teamcity"),
+        github_annotations: matches.is_present("github_annotations"),
+        azure: matches.is_present("azure"),
+        diff_base: matches.value_of("diff_base").map(str::to_owned),
+        reachability: matches.is_present("reachability"),
+        permalink_base: matches.value_of("permalink_base").map(str::to_owned),
+        changed_only: matches.is_present("changed_only"),
+        waivers: Vec::new(),
+        fail_on_unwaived_uncovered: matches.is_present("fail_on_unwaived_uncovered"),
+        strict: matches.is_present("strict"),
+        verbose: matches.is_present("verbose"),
+        attribute_inlined: matches.is_present("attribute_inlined"),
+        gcov_files: matches.is_present("gcov_files"),
+        single_file: matches.is_present("single_file"),
+        allow_template_fs: matches.is_present("allow_template_fs"),
+        upload_token: matches.value_of("token").map(str::to_owned),
+    });
+    Ok(config)
+}
+
+/// Parses a threshold of the form `NAME=PERCENT`.
+///
+/// # Errors
+///
+/// Returns [`InvalidThreshold`] if the string is not of the expected form.
+///
+/// [`InvalidThreshold`]: ../error/enum.ErrorKind.html#variant.InvalidThreshold
+pub fn parse_threshold(spec: &str) -> Result<(&str, f64)> {
+    let eq_index = spec.rfind('=').ok_or_else(|| ErrorKind::InvalidThreshold(spec.to_owned()))?;
+    let percent = spec[(eq_index + 1)..].trim_end_matches('%').parse().map_err(|_| ErrorKind::InvalidThreshold(spec.to_owned()))?;
+    Ok((&spec[..eq_index], percent))
+}
+
+/// Parses a template option of the form `NAME=VALUE`.
+///
+/// The value is interpreted as JSON if possible (so `true`, `12` and `[1, 2]` have their natural types), otherwise it is
+/// treated as a plain string.
+///
+/// # Errors
+///
+/// Returns [`InvalidTemplateOption`] if the string does not contain `=`.
+///
+/// [`InvalidTemplateOption`]: ../error/enum.ErrorKind.html#variant.InvalidTemplateOption
+pub fn parse_template_option(spec: &str) -> Result<(&str, Value)> {
+    let eq_index = spec.find('=').ok_or_else(|| ErrorKind::InvalidTemplateOption(spec.to_owned()))?;
+    let value = &spec[(eq_index + 1)..];
+    let value = from_str(value).unwrap_or_else(|_| Value::String(value.to_owned()));
+    Ok((&spec[..eq_index], value))
+}
+
+#[test]
+fn test_extract_crate_hash() {
+    fn extract(args: &[&'static str]) -> Option<&'static str> {
+        extract_crate_hash(args.iter().map(|a| OsStr::new(*a)))
+    }
+    assert_eq!(extract(&["--crate-name", "foo", "src/lib.rs"]), None);
+    assert_eq!(extract(&["-C", "metadata=0123abcd", "-C", "extra-filename=-0123abcd"]), Some("0123abcd"));
+    assert_eq!(extract(&["-Cmetadata=0123abcd"]), Some("0123abcd"));
+    assert_eq!(extract(&["--codegen=extra-filename=-4567", "-C", "metadata=0123"]), Some("4567"));
+}
+
+#[test]
+fn test_find_option_value() {
+    fn find(args: &[&'static str], name: &str) -> Option<&'static str> {
+        find_option_value(args.iter().cloned().map(OsStr::new), name)
+    }
+    assert_eq!(find(&["--crate-name", "foo", "src/lib.rs"], "--crate-name"), Some("foo"));
+    assert_eq!(find(&["--lib", "--message-format=json-render-diagnostics"], "--message-format"), Some("json-render-diagnostics"));
+    assert_eq!(find(&["--message-formats=json"], "--message-format"), None);
+    assert_eq!(find(&["--lib", "--", "--message-format", "json"], "--message-format"), None);
+}
+
+#[test]
+fn test_parse_threshold() {
+    assert_eq!(parse_threshold("@org/core=80").unwrap(), ("@org/core", 80.0));
+    assert_eq!(parse_threshold("a=b=72.5%").unwrap(), ("a=b", 72.5));
+    assert!(parse_threshold("@org/core").is_err());
+    assert!(parse_threshold("@org/core=high").is_err());
+}
+
+#[test]
+fn test_normalize() {
+    let args = ["--lib", "--target", "x86_64-unknown-linux-gnu", "--wait", "--", "--no-lock"];
+    let mut specialized = SpecialMap::new();
+    let normalized = normalize(args.iter().map(OsStr::new), &mut specialized);
+    assert_eq!(normalized, ["--lib", "--", "--no-lock"]);
+    assert_eq!(specialized.get("target"), Some(&OsStr::new("x86_64-unknown-linux-gnu")));
+    assert!(specialized.contains_key("wait"));
+    assert!(!specialized.contains_key("no-lock"));
+
+    // Everything after `--` belongs to the test harness, even if it looks like a special argument.
+    let args = ["foo", "--", "--include-ignored", "--target=x", "--exact"];
+    let normalized = normalize(args.iter().map(OsStr::new), &mut specialized);
+    assert_eq!(normalized, args);
+}
+
+#[test]
+fn test_includes_function() {
+    let config = Config::from_cli_and_file(vec!["--workspace", ".", "--exclude-functions-regex", r"\bfmt::Debug\b"], None).unwrap();
+    let report_config = ReportConfig::resolve(&config, Ok(PathBuf::new())).unwrap();
+    assert!(report_config.includes_function("_ZN3foo5parse17h0123456789abcdefE"));
+    assert!(!report_config.includes_function("_ZN45_$LT$foo..Bar$u20$as$u20$core..fmt..Debug$GT$3fmt17h0123456789abcdefE"));
+
+    let config = Config::from_cli_and_file(vec!["--workspace", ".", "--functions-regex", "("], None).unwrap();
+    assert!(ReportConfig::resolve(&config, Ok(PathBuf::new())).is_err());
+}
+
+#[test]
+fn test_template_override_from_file() {
+    let workspace = ::tempfile::TempDir::new().unwrap();
+    let override_path = workspace.path().join("overrides");
+    ::std::fs::create_dir(&override_path).unwrap();
+    let resolve = |template_override: &Path, from_cli| {
+        let config = Config {
+            workspace: Some(workspace.path().to_owned()),
+            template_override: Some(template_override.to_owned()),
+            template_override_from_cli: from_cli,
+            ..Config::default()
+        };
+        ReportConfig::resolve(&config, Err("no cargo".into())).map(|c| c.template_override_path.map(Cow::into_owned))
+    };
+
+    assert_eq!(resolve(&override_path, false).unwrap(), Some(override_path.canonicalize().unwrap()));
+    match *resolve(Path::new("/"), false).unwrap_err().kind() {
+        ErrorKind::TemplateOverrideOutsideWorkspace(ref path) => assert_eq!(path, Path::new("/")),
+        ref kind => panic!("unexpected error {:?}", kind),
+    }
+    assert!(resolve(&override_path.join(".."), false).is_ok());
+    assert!(resolve(&override_path.join("../.."), false).is_err());
+    assert_eq!(resolve(Path::new("/"), true).unwrap(), Some(PathBuf::from("/")));
 }
@@ -0,0 +1,144 @@
+//! Listing of the collected GCNO and GCDA files.
+//!
+//! `cargo cov ls` shows every file under `target/cov/build/{gcno,gcda}`, which crate it belongs to, and whether it can
+//! be matched with a counterpart. A GCNO and a GCDA match when their headers carry the same stamp. A GCNO without any
+//! GCDA simply means the code was never run, while a GCDA without a GCNO cannot contribute to the report at all.
+
+use archive;
+use error::Result;
+use shim::{GcnoHashes, artifact_crate_name, artifact_hash};
+
+use cov::Interner;
+use cov::raw::Type;
+use cov::reader::Reader;
+
+use std::collections::HashSet;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Whether a GCNO/GCDA file has a counterpart.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MatchStatus {
+    /// The file has a counterpart with the same stamp.
+    Matched,
+    /// The GCNO has no GCDA, i.e. the code was never run.
+    NoGcda,
+    /// The GCDA has no GCNO, i.e. it is stale and will be ignored.
+    Orphan,
+    /// The GCDA is recorded with a crate hash not matching any GCNO, and will be skipped, see [`GcnoHashes`].
+    ///
+    /// [`GcnoHashes`]: ../shim/struct.GcnoHashes.html
+    Stale,
+    /// The file cannot be parsed as GCNO/GCDA.
+    Invalid,
+}
+
+/// A collected GCNO or GCDA file.
+#[derive(Debug, Serialize)]
+pub struct Artifact {
+    /// Path to the file.
+    pub path: PathBuf,
+    /// The file extension, either `gcno` or `gcda`.
+    pub kind: &'static str,
+    /// The crate name, if it can be recovered from the file name.
+    #[serde(rename = "crate")]
+    pub crate_name: Option<String>,
+    /// The crate hash, if recorded in the file name.
+    pub crate_hash: Option<String>,
+    /// Size of the file in bytes.
+    pub size: u64,
+    /// Last modification time in seconds since the Unix epoch.
+    pub modified: u64,
+    /// The stamp in the file header.
+    pub stamp: Option<u32>,
+    /// Whether the file has a counterpart.
+    pub status: MatchStatus,
+}
+
+/// Lists all GCNO files in `gcno_path` and GCDA files in `gcda_path` (directories or archives), sorted by kind and path.
+pub fn list(gcno_path: &Path, gcda_path: &Path) -> Result<Vec<Artifact>> {
+    let mut interner = Interner::new();
+    let mut artifacts = Vec::new();
+    for &(kind, ty, dir_path) in &[("gcno", Type::Gcno, gcno_path), ("gcda", Type::Gcda, gcda_path)] {
+        let in_archive = archive::is_archive(dir_path);
+        if !dir_path.is_dir() && !in_archive {
+            continue;
+        }
+        let start = artifacts.len();
+        archive::for_each(dir_path, kind, |file, reader| -> Result<()> {
+            // The members of an archive have the modification time of the archive.
+            let modified_path = if in_archive { dir_path } else { &file.path };
+            let modified = modified_path.metadata()?.modified()?.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+            let stamp = read_stamp(reader, ty, &mut interner);
+            artifacts.push(Artifact {
+                crate_name: artifact_crate_name(&file.path).map(str::to_owned),
+                crate_hash: artifact_hash(&file.path).map(str::to_owned),
+                kind,
+                size: file.size(),
+                modified,
+                stamp,
+                status: if stamp.is_some() { MatchStatus::Matched } else { MatchStatus::Invalid },
+                path: file.path.clone(),
+            });
+            Ok(())
+        })?;
+        artifacts[start..].sort_by(|a, b| a.path.cmp(&b.path));
+    }
+
+    let stamps = |kind| artifacts.iter().filter(|a| a.kind == kind).filter_map(|a| a.stamp).collect::<HashSet<_>>();
+    let gcno_stamps = stamps("gcno");
+    let gcda_stamps = stamps("gcda");
+    let mut gcno_hashes = GcnoHashes::default();
+    for artifact in artifacts.iter().filter(|a| a.kind == "gcno") {
+        gcno_hashes.insert(&artifact.path);
+    }
+    for artifact in &mut artifacts {
+        if let Some(stamp) = artifact.stamp {
+            if artifact.kind == "gcda" && gcno_hashes.stale_hash(&artifact.path).is_some() {
+                artifact.status = MatchStatus::Stale;
+            } else if artifact.kind == "gcno" && !gcda_stamps.contains(&stamp) {
+                artifact.status = MatchStatus::NoGcda;
+            } else if artifact.kind == "gcda" && !gcno_stamps.contains(&stamp) {
+                artifact.status = MatchStatus::Orphan;
+            }
+        }
+    }
+
+    Ok(artifacts)
+}
+
+/// Reads the stamp from the header of a GCNO/GCDA file, without parsing the rest. Returns `None` if the file is not of
+/// the expected type.
+fn read_stamp(reader: &mut Read, ty: Type, interner: &mut Interner) -> Option<u32> {
+    let reader = Reader::new(reader, interner).ok()?;
+    if reader.file_type() == ty {
+        Some(reader.stamp())
+    } else {
+        None
+    }
+}
+
+#[test]
+fn test_list_stale() {
+    use std::fs::{copy, create_dir};
+
+    let dir = ::tempfile::TempDir::new().unwrap();
+    let (gcno_path, gcda_path) = (dir.path().join("gcno"), dir.path().join("gcda"));
+    create_dir(&gcno_path).unwrap();
+    create_dir(&gcda_path).unwrap();
+    copy("../cov/test-data/branches.clang/x.gcno", gcno_path.join("0000000000000001.@0123456789abcdef.x.gcno")).unwrap();
+    copy("../cov/test-data/branches.clang/x.gcda", gcda_path.join("0000000000000002.@0123456789abcdef.x.gcda")).unwrap();
+    copy("../cov/test-data/branches.clang/x.gcda", gcda_path.join("0000000000000003.@fedcba9876543210.x.gcda")).unwrap();
+
+    let statuses = list(&gcno_path, &gcda_path).unwrap().into_iter().map(|a| (a.kind, a.crate_hash, a.status)).collect::<Vec<_>>();
+    assert_eq!(
+        statuses,
+        vec![
+            ("gcno", Some("0123456789abcdef".to_owned()), MatchStatus::Matched),
+            ("gcda", Some("0123456789abcdef".to_owned()), MatchStatus::Matched),
+            ("gcda", Some("fedcba9876543210".to_owned()), MatchStatus::Stale),
+        ]
+    );
+}
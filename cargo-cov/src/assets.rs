@@ -0,0 +1,56 @@
+//! Built-in templates embedded in the binary.
+//!
+//! All files of `cargo-cov/res/templates/` are included at compile time, so an installed `cargo-cov` does not depend on
+//! the source directory it was built from. The files of the template in use are written to a temporary directory when
+//! generating a report.
+
+use error::{ErrorKind, Result};
+
+use std::fs::{File, create_dir_all};
+use std::io::Write;
+use std::path::Path;
+
+include!(concat!(env!("OUT_DIR"), "/templates.rs"));
+
+/// Lists the names of all built-in templates.
+pub fn template_names() -> Vec<&'static str> {
+    let mut names = TEMPLATE_FILES.iter().filter_map(|&(path, _)| path.split('/').next()).collect::<Vec<_>>();
+    names.dedup();
+    names
+}
+
+/// Writes all files of the built-in template `name` into `dest_path`.
+///
+/// # Errors
+///
+/// Returns [`UnknownTemplate`] if there is no built-in template called `name`.
+///
+/// [`UnknownTemplate`]: ../error/enum.ErrorKind.html#variant.UnknownTemplate
+pub fn materialize(name: &str, dest_path: &Path) -> Result<()> {
+    let mut found = false;
+    for &(path, content) in TEMPLATE_FILES {
+        let mut components = path.splitn(2, '/');
+        if components.next() != Some(name) {
+            continue;
+        }
+        let relative_path = components.next().expect("file inside a template");
+        let path = relative_path.split('/').fold(dest_path.to_owned(), |p, c| p.join(c));
+        create_dir_all(path.parent().expect("parent directory"))?;
+        File::create(&path)?.write_all(content)?;
+        found = true;
+    }
+    ensure!(found, ErrorKind::UnknownTemplate(name.to_owned(), template_names().join(", ")));
+    Ok(())
+}
+
+#[test]
+fn test_materialize() {
+    use tempfile::TempDir;
+
+    assert_eq!(template_names(), vec!["badge", "clover", "cobertura", "codecov", "coveralls", "csv", "gcov", "gcov-json", "gerrit", "html", "istanbul", "json", "lcov", "markdown", "quickfix", "snapshot"]);
+    let dir = TempDir::new().unwrap();
+    materialize("lcov", dir.path()).unwrap();
+    assert!(dir.path().join("config.toml").is_file());
+    assert!(dir.path().join("tera").join("lcov.info").is_file());
+    assert!(materialize("nothing", dir.path()).is_err());
+}
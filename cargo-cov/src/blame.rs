@@ -0,0 +1,172 @@
+//! Provenance of the coverage of a single source line.
+//!
+//! `cargo cov blame-report src/lib.rs:123` finds out which GCNO objects and functions the line belongs to, how many
+//! counts each GCDA file contributed, and how the counts of the basic blocks covering the line are computed from the
+//! arcs. This is a debugging tool for answering "why does this line claim 0 hits".
+
+use archive::{self, GcovFile};
+use argparse::ReportConfig;
+use budget;
+use error::{ErrorKind, Result, ResultExt};
+use shim::GcnoHashes;
+
+use cov::{Gcov, Graph, Interner, Symbol};
+use cov::graph::BlockExplanation;
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Contribution of a single GCDA file to the line.
+#[derive(Debug)]
+pub struct GcdaContribution {
+    /// Path to the GCDA file.
+    pub path: PathBuf,
+    /// The line count when only this GCDA file is merged.
+    pub count: u64,
+}
+
+/// Provenance of a source line.
+#[derive(Debug)]
+pub struct Blame {
+    /// The matched source path.
+    pub path: String,
+    /// The line number.
+    pub line: u32,
+    /// The total line count.
+    pub count: Option<u64>,
+    /// GCNO files containing the line.
+    pub gcno_paths: Vec<PathBuf>,
+    /// Contributions from every GCDA file with a non-zero count for the line.
+    pub gcda_contributions: Vec<GcdaContribution>,
+    /// The basic blocks covering the line, paired with the name of the function containing it.
+    pub blocks: Vec<(String, BlockExplanation)>,
+}
+
+/// Parses a location of the form `FILE:LINE`.
+///
+/// # Errors
+///
+/// Returns [`InvalidLocation`] if the string is not of the expected form.
+///
+/// [`InvalidLocation`]: ../error/enum.ErrorKind.html#variant.InvalidLocation
+pub fn parse_location(location: &str) -> Result<(&str, u32)> {
+    let colon_index = location.rfind(':').ok_or_else(|| ErrorKind::InvalidLocation(location.to_owned()))?;
+    let line = location[(colon_index + 1)..].parse().map_err(|_| ErrorKind::InvalidLocation(location.to_owned()))?;
+    Ok((&location[..colon_index], line))
+}
+
+/// Finds out the provenance of the line at `file:line`.
+///
+/// The `file` may be the full source path, or any suffix of it, e.g. `src/lib.rs`.
+///
+/// If the graph would exceed the memory limit of the `config`, the contributing GCDAs are not collected.
+pub fn blame(config: &ReportConfig, file: &str, line: u32) -> Result<Blame> {
+    let track_provenance = !budget::exceeds(
+        &config.gcno_path,
+        &config.gcda_path,
+        &[],
+        config.max_memory,
+        "not collecting the contributing *.gcda files",
+        None,
+    )?;
+    let mut interner = Interner::new();
+
+    // Merge all GCNOs first, the GCDAs are merged into clones of this graph.
+    let mut base_graph = Graph::default();
+    let mut gcno_files = Vec::new();
+    let mut gcno_symbols = Vec::new();
+    let mut gcno_hashes = GcnoHashes::default();
+    for_each_file(&config.gcno_path, "gcno", |file, reader| {
+        gcno_hashes.insert(&file.path);
+        let gcno = Gcov::read(reader, file.path.clone(), &mut interner).chain_err(|| format!("Cannot parse `{}`", file.path.display()))?;
+        let mut single_graph = Graph::default();
+        single_graph.merge(gcno.clone())?;
+        single_graph.analyze();
+        gcno_symbols.push(single_graph.report().files.into_iter().filter(|(_, f)| f.lines.contains_key(&line)).map(|(s, _)| s).collect::<Vec<_>>());
+        base_graph.merge(gcno)?;
+        gcno_files.push(file.clone());
+        Ok(())
+    })?;
+
+    let symbol = find_symbol(&interner, base_graph.report().files.keys().cloned(), file).ok_or_else(|| ErrorKind::SourceNotFound(file.to_owned()))?;
+
+    let mut graph = base_graph.clone();
+    if track_provenance {
+        graph.enable_provenance();
+    }
+    // The content of every GCDA is kept while tracking the provenance, so the contributing ones can be parsed again
+    // without reopening the archive for each of them.
+    let mut gcda_contents = Vec::new();
+    for_each_file(&config.gcda_path, "gcda", |file, reader| {
+        if gcno_hashes.skips(&file.path) {
+            return Ok(());
+        }
+        let mut content = Vec::new();
+        reader.read_to_end(&mut content)?;
+        graph.merge(Gcov::read(&mut &*content, file.path.clone(), &mut interner).chain_err(|| format!("Cannot parse `{}`", file.path.display()))?)?;
+        if track_provenance {
+            gcda_contents.push((file.path.clone(), content));
+        }
+        Ok(())
+    })?;
+    graph.analyze();
+
+    // Only the GCDAs which contributed to the file need to be analyzed individually.
+    let mut gcda_contributions = Vec::new();
+    for (path, _) in graph.provenance(symbol) {
+        let &(_, ref content) = gcda_contents.iter().find(|&&(ref p, _)| p == path).expect("provenance is one of the merged GCDAs");
+        let mut single_graph = base_graph.clone();
+        single_graph.merge(Gcov::read(&mut &**content, path.to_owned(), &mut interner)?)?;
+        single_graph.analyze();
+        let count = line_count(&single_graph, symbol, line).unwrap_or(0);
+        if count > 0 {
+            gcda_contributions.push(GcdaContribution {
+                path: path.to_owned(),
+                count,
+            });
+        }
+    }
+
+    let gcno_paths = gcno_files.into_iter().zip(gcno_symbols).filter(|&(_, ref symbols)| symbols.contains(&symbol)).map(|(f, _)| f.path).collect();
+
+    Ok(Blame {
+        path: interner[symbol].to_owned(),
+        line,
+        count: line_count(&graph, symbol, line),
+        gcno_paths,
+        gcda_contributions,
+        blocks: graph.explain_line(symbol, line).into_iter().map(|b| (interner[b.function].to_owned(), b)).collect(),
+    })
+}
+
+/// Calls `f` with all files with the given extension in the directory or archive, and their content.
+fn for_each_file<F>(dir_path: &Path, extension: &str, f: F) -> Result<()>
+where
+    F: FnMut(&GcovFile, &mut Read) -> Result<()>,
+{
+    archive::for_each(dir_path, extension, f).chain_err(|| format!("Cannot read `{}`", dir_path.display()))
+}
+
+/// Finds the symbol among `candidates` whose source path is equal to `file`, or ends with `file` as path components.
+pub fn find_symbol<I: IntoIterator<Item = Symbol>>(interner: &Interner, candidates: I, file: &str) -> Option<Symbol> {
+    let file_path = Path::new(file);
+    let candidates = candidates.into_iter().collect::<Vec<_>>();
+    candidates
+        .iter()
+        .find(|&&s| &interner[s] == file)
+        .or_else(|| candidates.iter().find(|&&s| Path::new(&interner[s]).ends_with(file_path)))
+        .cloned()
+}
+
+/// Obtains the count of a line in the report of the graph.
+fn line_count(graph: &Graph, symbol: Symbol, line: u32) -> Option<u64> {
+    graph.report().files.get(&symbol).and_then(|f| f.lines.get(&line)).map(|l| l.count)
+}
+
+#[test]
+fn test_parse_location() {
+    assert_eq!(parse_location("src/lib.rs:12").unwrap(), ("src/lib.rs", 12));
+    assert_eq!(parse_location(r"C:\src\lib.rs:3").unwrap(), (r"C:\src\lib.rs", 3));
+    assert!(parse_location("src/lib.rs").is_err());
+    assert!(parse_location("src/lib.rs:x").is_err());
+}
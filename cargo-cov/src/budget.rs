@@ -0,0 +1,119 @@
+//! Memory budget of the report subcommands.
+//!
+//! The graph of a large workspace (especially with the standard library instantiations included) can take several
+//! gigabytes, which gets the process OOM-killed on small CI runners. With `--max-memory <MB>`, the size of the graph is
+//! estimated from the GCNO, GCDA and imported files before parsing them. If the estimate exceeds the limit, the report
+//! degrades gracefully where it can:
+//!
+//! * `cargo cov report` prunes the sysroot functions right after parsing (as if `--prune-sysroot` is given). This only
+//!   drops data the report would not show anyway, so it fails instead if the `rustsrc` sources are requested by
+//!   `--include`, or the project is not written in Rust, where nothing can be pruned.
+//! * `cargo cov blame-report` does not track which GCDA files contributed to the line, which would otherwise require a
+//!   copy of the graph per GCDA.
+
+use archive;
+use error::{ErrorKind, Result, ResultExt};
+
+use std::fs::metadata;
+use std::path::{Path, PathBuf};
+
+/// Number of bytes in a megabyte.
+const BYTES_PER_MB: u64 = 1 << 20;
+
+/// Approximate number of bytes the graph takes for every byte of GCNO. The blocks, arcs and lines are expanded into
+/// separate nodes, edges and map entries, each much larger than their encoded form.
+const GCNO_EXPANSION: u64 = 12;
+
+/// Approximate number of bytes the graph takes for every byte of GCDA. The counts are added into existing arcs, only
+/// the function lookup table grows.
+const GCDA_EXPANSION: u64 = 1;
+
+/// Approximate number of bytes the graph takes for every byte of a file produced by `cargo cov export`. The records are
+/// written as JSON, which is several times larger than their binary form, and the whole file is parsed in memory too.
+const IMPORT_EXPANSION: u64 = 4;
+
+/// Sums up the size of all files with the given extension in the directory. A missing directory counts as empty.
+fn total_size(dir_path: &Path, extension: &str) -> Result<u64> {
+    if !dir_path.is_dir() && !archive::is_archive(dir_path) {
+        return Ok(0);
+    }
+    let mut size = 0;
+    for file in archive::list(dir_path, extension).chain_err(|| format!("Cannot read `{}`", dir_path.display()))? {
+        size += file.size();
+    }
+    Ok(size)
+}
+
+/// Estimates the memory needed to construct the graph from all GCNO and GCDA files in the given folders, and the files
+/// given to `--import`, in bytes.
+pub fn estimate_graph_size(gcno_path: &Path, gcda_path: &Path, import_paths: &[PathBuf]) -> Result<u64> {
+    let gcno_size = total_size(gcno_path, "gcno")?;
+    let gcda_size = total_size(gcda_path, "gcda")?;
+    let mut import_size = 0;
+    for import_path in import_paths {
+        import_size += metadata(import_path).chain_err(|| format!("Cannot read `{}`", import_path.display()))?.len();
+    }
+    Ok(gcno_size * GCNO_EXPANSION + gcda_size * GCDA_EXPANSION + import_size * IMPORT_EXPANSION)
+}
+
+/// Checks whether the graph would exceed `max_memory` megabytes. If so, warns that the `reduced_features` are disabled
+/// and returns `true`.
+///
+/// # Errors
+///
+/// Returns [`MemoryBudgetExceeded`] if the graph would exceed the limit but cannot be reduced, for the reason given in
+/// `obstacle`.
+///
+/// [`MemoryBudgetExceeded`]: ../error/enum.ErrorKind.html#variant.MemoryBudgetExceeded
+pub fn exceeds(
+    gcno_path: &Path,
+    gcda_path: &Path,
+    import_paths: &[PathBuf],
+    max_memory: Option<u64>,
+    reduced_features: &str,
+    obstacle: Option<&str>,
+) -> Result<bool> {
+    let max_memory = match max_memory {
+        Some(max_memory) => max_memory,
+        None => return Ok(false),
+    };
+    let estimate = estimate_graph_size(gcno_path, gcda_path, import_paths)?;
+    if estimate <= max_memory.saturating_mul(BYTES_PER_MB) {
+        return Ok(false);
+    }
+    let estimate = (estimate + BYTES_PER_MB - 1) / BYTES_PER_MB;
+    if let Some(obstacle) = obstacle {
+        bail!(ErrorKind::MemoryBudgetExceeded(estimate, max_memory, obstacle.to_owned()));
+    }
+    warning!("the graph is estimated to take {} MB, exceeding --max-memory {} MB; {}", estimate, max_memory, reduced_features);
+    Ok(true)
+}
+
+#[test]
+fn test_estimate_graph_size() {
+    let test_data = Path::new("../cov/test-data/branches.gcc12");
+    let gcno_size = metadata(test_data.join("x.gcno")).unwrap().len();
+    let gcda_size = metadata(test_data.join("x.gcda")).unwrap().len();
+    let import_path = test_data.join("x.json");
+    let import_size = metadata(&import_path).unwrap().len();
+
+    assert_eq!(estimate_graph_size(test_data, test_data, &[]).unwrap(), gcno_size * GCNO_EXPANSION + gcda_size * GCDA_EXPANSION);
+    assert_eq!(estimate_graph_size(test_data, Path::new("../cov/test-data/missing"), &[]).unwrap(), gcno_size * GCNO_EXPANSION);
+    assert_eq!(
+        estimate_graph_size(test_data, test_data, &[import_path.clone()]).unwrap(),
+        gcno_size * GCNO_EXPANSION + gcda_size * GCDA_EXPANSION + import_size * IMPORT_EXPANSION
+    );
+    assert!(estimate_graph_size(test_data, test_data, &[test_data.join("missing.json")]).is_err());
+}
+
+#[test]
+fn test_exceeds() {
+    let test_data = Path::new("../cov/test-data/branches.gcc12");
+    assert!(!exceeds(test_data, test_data, &[], None, "nothing", Some("cannot reduce")).unwrap());
+    assert!(!exceeds(test_data, test_data, &[], Some(1), "nothing", Some("cannot reduce")).unwrap());
+    assert!(exceeds(test_data, test_data, &[], Some(0), "nothing", None).unwrap());
+    match exceeds(test_data, test_data, &[], Some(0), "nothing", Some("cannot reduce")).map_err(|e| e.0) {
+        Err(ErrorKind::MemoryBudgetExceeded(1, 0, ref obstacle)) => assert_eq!(obstacle, "cannot reduce"),
+        r => panic!("unexpected result {:?}", r),
+    }
+}
@@ -0,0 +1,274 @@
+//! Bundling an HTML report into a single file.
+//!
+//! With `--single-file`, every HTML page written by the template is bundled into `report.html` after rendering, so the
+//! report can be attached to CI artifacts or mailed around. The stylesheets and scripts under the output directory
+//! are inlined into every page, and the pages themselves are embedded as JSON. The bundle shows one page at a time in an
+//! `<iframe>`, and a click on a link to another page is turned into the fragment of the bundle, e.g. `report.html#12.html#34`
+//! for line 34 of the page `12.html`.
+//!
+//! The pages still refer to each other by their file names, so the templates need no change.
+//!
+//! The Coverage tab of Azure Pipelines shows the `index.html` of the published report directory, but does not load the
+//! stylesheets beside it. With `--azure`, the pages are instead copied into `azure/` with their stylesheets and scripts
+//! inlined one by one, see [`inline_pages()`].
+//!
+//! [`inline_pages()`]: ./fn.inline_pages.html
+
+use error::{Result, ResultExt};
+
+use serde_json::to_string;
+
+use std::collections::BTreeMap;
+use std::fs::{create_dir_all, read_dir, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Name of the bundle inside the output directory.
+pub const BUNDLE_FILE_NAME: &str = "report.html";
+
+/// Name of the directory inside the output directory with the pages for Azure Pipelines.
+pub const AZURE_DIR_NAME: &str = "azure";
+
+/// Script added to every page, which sends the links to other pages to the bundle.
+const PAGE_SCRIPT: &str = r#"'use strict';
+document.addEventListener('click', function (e) {
+    var a = e.target.closest ? e.target.closest('a') : null;
+    var href = a && a.getAttribute('href');
+    if (!href || href.charAt(0) === '#' || /^[a-z][a-z0-9+.-]*:/i.test(href)) {
+        return;
+    }
+    e.preventDefault();
+    e.stopPropagation();
+    parent.location.hash = href;
+}, true);
+"#;
+
+/// Script of the bundle, which shows the page named by the fragment.
+const BUNDLE_SCRIPT: &str = r#"'use strict';
+var pages = JSON.parse(document.getElementById('pages').textContent);
+var frame = document.getElementById('page');
+var current = null;
+
+function jump(anchor) {
+    if (anchor) {
+        frame.contentWindow.location.hash = anchor;
+    }
+}
+
+function show() {
+    var hash = location.hash.substr(1);
+    var split = hash.indexOf('#');
+    var page = split < 0 ? hash : hash.substr(0, split);
+    var anchor = split < 0 ? '' : hash.substr(split + 1);
+    if (!pages.hasOwnProperty(page)) {
+        page = INDEX;
+    }
+    if (page === current) {
+        jump(anchor);
+        return;
+    }
+    current = page;
+    frame.onload = function () {
+        document.title = frame.contentDocument.title;
+        jump(anchor);
+    };
+    frame.srcdoc = pages[page];
+}
+
+window.onhashchange = show;
+show();
+"#;
+
+/// Bundles the HTML pages in `output_path` into a single file, showing `index_path` first. Returns the path of the bundle.
+///
+/// The whole directory is read instead of the files written by this run, since `--changed-only` reuses the pages of
+/// the previous report.
+pub fn bundle(output_path: &Path, index_path: &Path) -> Result<PathBuf> {
+    let bundle_path = output_path.join(BUNDLE_FILE_NAME);
+    let mut pages = BTreeMap::new();
+    for entry in read_dir(output_path)? {
+        let path = entry?.path();
+        if path == bundle_path || path.extension().map_or(true, |e| e != "html") || !path.is_file() {
+            continue;
+        }
+        let name = path.file_name().expect("file in the output directory").to_string_lossy().into_owned();
+        let html = read_to_string(&path)?;
+        let mut html = inline_resources(&html, output_path)?;
+        let script = format!("<script>{}</script>", PAGE_SCRIPT);
+        match html.rfind("</body>") {
+            Some(i) => html.insert_str(i, &script),
+            None => html.push_str(&script),
+        }
+        pages.insert(name, html);
+    }
+
+    let index = index_path.file_name().map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+    let title = pages.get(&index).and_then(|html| extract_title(html)).unwrap_or("Coverage report").to_owned();
+    let pages_json = to_string(&pages)?.replace("</", "<\\/");
+    let script = BUNDLE_SCRIPT.replace("INDEX", &to_string(&index)?);
+
+    let mut file = File::create(&bundle_path).chain_err(|| format!("Cannot write `{}`", bundle_path.display()))?;
+    write!(
+        file,
+        r#"<!DOCTYPE html>
+<html lang="en">
+    <head>
+        <meta charset="utf-8" />
+        <title>{}</title>
+        <style>html, body, iframe {{ display: block; width: 100%; height: 100%; margin: 0; border: 0; }}</style>
+    </head>
+    <body>
+        <iframe id="page"></iframe>
+        <script type="application/json" id="pages">{}</script>
+        <script>{}</script>
+    </body>
+</html>
+"#,
+        title, pages_json, script
+    )?;
+    Ok(bundle_path)
+}
+
+/// Copies the HTML pages in `output_path` into `target_path`, each with its stylesheets and scripts inlined, so the
+/// pages can be shown without the `static/` directory. Returns the paths of the copied pages.
+pub fn inline_pages(output_path: &Path, target_path: &Path) -> Result<Vec<PathBuf>> {
+    create_dir_all(target_path).chain_err(|| format!("Cannot create `{}`", target_path.display()))?;
+    let mut written = Vec::new();
+    for entry in read_dir(output_path)? {
+        let path = entry?.path();
+        if path.file_name().map_or(true, |name| name == BUNDLE_FILE_NAME) || path.extension().map_or(true, |e| e != "html") || !path.is_file() {
+            continue;
+        }
+        let html = inline_resources(&read_to_string(&path)?, output_path)?;
+        let page_path = target_path.join(path.file_name().expect("file in the output directory"));
+        File::create(&page_path)
+            .and_then(|mut file| file.write_all(html.as_bytes()))
+            .chain_err(|| format!("Cannot write `{}`", page_path.display()))?;
+        written.push(page_path);
+    }
+    written.sort();
+    Ok(written)
+}
+
+/// Reads the whole file at `path`.
+fn read_to_string(path: &Path) -> Result<String> {
+    let mut content = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut content))
+        .chain_err(|| format!("Cannot read `{}`", path.display()))?;
+    Ok(content)
+}
+
+/// Extracts the content of the `<title>` element, which is already escaped.
+fn extract_title(html: &str) -> Option<&str> {
+    let start = html.find("<title>")? + "<title>".len();
+    let end = html[start..].find("</title>")?;
+    Some(&html[start..start + end])
+}
+
+/// Extracts the value of the attribute `name` of a start tag, e.g. `href` of `<link rel="stylesheet" href="a.css" />`.
+fn extract_attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let pattern = format!(" {}=\"", name);
+    let start = tag.find(&pattern)? + pattern.len();
+    let end = tag[start..].find('"')?;
+    Some(&tag[start..start + end])
+}
+
+/// Returns the path of a relative URL inside `output_path`, or `None` if the URL is absolute.
+fn local_path(url: &str, output_path: &Path) -> Option<PathBuf> {
+    if url.is_empty() || url.starts_with('/') || url.contains(':') {
+        None
+    } else {
+        Some(output_path.join(url))
+    }
+}
+
+/// Replaces the stylesheets and scripts linked from the `html` page by their content.
+fn inline_resources(html: &str, output_path: &Path) -> Result<String> {
+    const SCRIPT_END: &str = "</script>";
+
+    let mut result = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let tag_len = match rest.find('>') {
+            Some(i) => i + 1,
+            None => break,
+        };
+        let tag = &rest[..tag_len];
+        rest = &rest[tag_len..];
+
+        if tag.starts_with("<link ") && tag.contains(" rel=\"stylesheet\"") {
+            if let Some(path) = extract_attribute(tag, "href").and_then(|href| local_path(href, output_path)) {
+                let css = read_to_string(&path)?;
+                result.push_str("<style>");
+                result.push_str(&css.replace("</", "<\\/"));
+                result.push_str("</style>");
+                continue;
+            }
+        } else if tag.starts_with("<script ") && rest.starts_with(SCRIPT_END) {
+            if let Some(path) = extract_attribute(tag, "src").and_then(|src| local_path(src, output_path)) {
+                let js = read_to_string(&path)?;
+                result.push_str("<script>");
+                result.push_str(&js.replace("</script", "<\\/script"));
+                result.push_str(SCRIPT_END);
+                rest = &rest[SCRIPT_END.len()..];
+                continue;
+            }
+        }
+        result.push_str(tag);
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+#[test]
+fn test_bundle() {
+    use std::fs::{create_dir, write};
+
+    let dir = ::tempfile::TempDir::new().unwrap();
+    create_dir(dir.path().join("static")).unwrap();
+    write(dir.path().join("static").join("common.css"), "body { color: red; }").unwrap();
+    write(dir.path().join("static").join("index.js"), "if (a </script> b) {}").unwrap();
+
+    let page = r#"<html><head><title>Coverage &amp; more</title><link rel="stylesheet" href="static/common.css" /><link rel="stylesheet" href="https://example.com/a.css" /></head><body><a href="1.html#3">x</a><script src="static/index.js"></script><script>var x = 1 < 2;</script></body></html>"#;
+    let inlined = inline_resources(page, dir.path()).unwrap();
+    assert_eq!(
+        inlined,
+        r#"<html><head><title>Coverage &amp; more</title><style>body { color: red; }</style><link rel="stylesheet" href="https://example.com/a.css" /></head><body><a href="1.html#3">x</a><script>if (a <\/script> b) {}</script><script>var x = 1 < 2;</script></body></html>"#
+    );
+
+    let index_path = dir.path().join("index.html");
+    write(&index_path, page).unwrap();
+    write(dir.path().join("1.html"), "<html><body><p>1</p></body></html>").unwrap();
+    write(dir.path().join("lcov.info"), "TN:").unwrap();
+    let bundle_path = bundle(dir.path(), &index_path).unwrap();
+    let bundled = read_to_string(&bundle_path).unwrap();
+    assert!(bundled.contains("<title>Coverage &amp; more</title>"));
+    assert!(bundled.contains(r#"{"1.html":"<html><body><p>1<\/p><script>'use strict';"#));
+    assert!(bundled.contains(r#""index.html":"#));
+    assert!(bundled.contains(r#"page = "index.html";"#));
+    assert!(!bundled.contains("lcov.info"));
+
+    // Bundling again does not embed the previous bundle.
+    let bundled_again = read_to_string(&bundle(dir.path(), &index_path).unwrap()).unwrap();
+    assert_eq!(bundled, bundled_again);
+}
+
+#[test]
+fn test_inline_pages() {
+    use std::fs::{create_dir, write};
+
+    let dir = ::tempfile::TempDir::new().unwrap();
+    create_dir(dir.path().join("static")).unwrap();
+    write(dir.path().join("static").join("common.css"), "body { color: red; }").unwrap();
+    write(dir.path().join("index.html"), r#"<head><link rel="stylesheet" href="static/common.css" /></head><a href="1.html">1</a>"#).unwrap();
+    write(dir.path().join(BUNDLE_FILE_NAME), "<iframe></iframe>").unwrap();
+    write(dir.path().join("cobertura.xml"), "<coverage/>").unwrap();
+
+    let target_path = dir.path().join(AZURE_DIR_NAME);
+    let written = inline_pages(dir.path(), &target_path).unwrap();
+    assert_eq!(written, vec![target_path.join("index.html")]);
+    assert_eq!(read_to_string(&written[0]).unwrap(), r#"<head><style>body { color: red; }</style></head><a href="1.html">1</a>"#);
+}
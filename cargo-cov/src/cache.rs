@@ -0,0 +1,242 @@
+//! Cache of the rendered file pages.
+//!
+//! Rendering the page of every source file is the slowest part of `cargo cov report` on a large workspace, although
+//! usually only a handful of files changed since the previous report. After rendering, a fingerprint of the data given
+//! to every file page is recorded in `.cov-cache.json` inside the output directory. With `--changed-only`, the output
+//! directory is not cleaned, and a file page is rendered again only if its fingerprint differs from the recorded one
+//! (or the page is missing), which keeps a watch-and-serve loop responsive.
+//!
+//! The summaries of all files are recorded as well, so the summary page can show how the coverage of every file changed
+//! since the previous report while iterating on the tests.
+//!
+//! The summary page is always rendered. All pages are rendered if the template, the template options or the version of
+//! `cargo cov` changed. Changes inside the `--template-override` directory are *not* detected; run the report without
+//! `--changed-only` after editing it.
+//!
+//! # Report cache
+//!
+//! Parsing the GCNO and GCDA files and analyzing the graph is repeated by every `cargo cov report`, although rendering
+//! another template or re-running with `--open` uses exactly the same coverage. The coverage is thus recorded in
+//! `.cov-report.json` inside the output directory as well, keyed by a digest of the content of all GCNO, GCDA and
+//! imported files, the workspace path, the language and whether the sysroot is pruned. If the digest is unchanged, the
+//! next report reuses the recorded coverage (and repeats the warnings printed while parsing) instead of parsing the
+//! files again.
+//!
+//! The report cache is not used with `--attribute-inlined` and waivers of functions, which need the whole graph.
+
+use archive;
+use error::{Result, ResultExt};
+use sourcepath::Language;
+use utils::write_json_atomically;
+
+use cov::Interner;
+use cov::report::{FileSummary, Report};
+use serde_json::from_reader;
+
+use std::collections::BTreeMap;
+use std::collections::hash_map::DefaultHasher;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{self, ErrorKind as IoErrorKind, Read};
+use std::path::{Path, PathBuf};
+
+/// Name of the cache file inside the output directory.
+pub const CACHE_FILE_NAME: &str = ".cov-cache.json";
+
+/// Name of the report cache file inside the output directory.
+pub const REPORT_CACHE_FILE_NAME: &str = ".cov-report.json";
+
+/// A rendered file page.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CachedPage {
+    /// Fingerprint of the context the page was rendered with.
+    pub fingerprint: u64,
+    /// Output filename of the page, relative to the output directory.
+    pub output: String,
+}
+
+/// The recorded fingerprints of the previous report.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PageCache {
+    /// Fingerprint of the template and options all pages were rendered with.
+    pub template: u64,
+    /// The rendered pages, keyed by their source paths.
+    pub pages: BTreeMap<String, CachedPage>,
+    /// Summary of every reported file, keyed by their source paths.
+    #[serde(default)]
+    pub summaries: BTreeMap<String, FileSummary>,
+}
+
+impl PageCache {
+    /// Reads the cache from `path`. Returns an empty cache if the file does not exist yet.
+    pub fn open(path: &Path) -> Result<PageCache> {
+        match File::open(path) {
+            Ok(file) => Ok(from_reader(file).chain_err(|| format!("Cannot parse cache at `{}`", path.display()))?),
+            Err(ref e) if e.kind() == IoErrorKind::NotFound => Ok(PageCache::default()),
+            Err(e) => Err(e).chain_err(|| format!("Cannot read cache at `{}`", path.display())),
+        }
+    }
+
+    /// Writes the cache to `path`, replacing the file atomically.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        write_json_atomically(path, self).chain_err(|| format!("Cannot write cache at `{}`", path.display()))
+    }
+}
+
+/// The coverage of the previous report.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReportCache {
+    /// Digest of the inputs, see [`digest_inputs()`](./fn.digest_inputs.html).
+    pub digest: u64,
+    /// Content of the interner, in the order of the symbols.
+    pub symbols: Vec<String>,
+    /// The coverage, with symbols referring to `symbols`.
+    pub report: Report,
+    /// The warnings printed while creating the graph.
+    pub warnings: Vec<String>,
+}
+
+impl ReportCache {
+    /// Records the `report` whose symbols are resolved by `interner`.
+    pub fn new(digest: u64, interner: &Interner, report: Report, warnings: Vec<String>) -> ReportCache {
+        ReportCache {
+            digest,
+            symbols: interner.iter().map(|(_, s)| s.to_owned()).collect(),
+            report,
+            warnings,
+        }
+    }
+
+    /// Reads the cache from `path`. Returns `None` if the file does not exist or cannot be parsed, e.g. written by
+    /// another version of `cargo cov`.
+    pub fn open(path: &Path) -> Option<ReportCache> {
+        let file = File::open(path).ok()?;
+        from_reader(file).ok()
+    }
+
+    /// Writes the cache to `path`, replacing the file atomically.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        write_json_atomically(path, self).chain_err(|| format!("Cannot write cache at `{}`", path.display()))
+    }
+
+    /// Interns the recorded symbols into the fresh `interner`, and returns the report referring to them. Returns `None`
+    /// if the symbols cannot be restored, i.e. the `interner` is not fresh.
+    pub fn restore(self, interner: &mut Interner) -> Option<Report> {
+        for (i, symbol) in self.symbols.into_iter().enumerate() {
+            if usize::from(interner.intern(symbol)) != i + 1 {
+                return None;
+            }
+        }
+        Some(self.report)
+    }
+}
+
+/// Computes the digest of the GCNO files in `gcno_path`, the GCDA files in `gcda_path` (directories or archives) and the
+/// `import_paths`, together with the settings the coverage is computed with: the `workspace_path` (which the imported
+/// paths are resolved against and the pruned sources are classified by), the `language`, whether the sysroot is
+/// `pruned`, and whether the anomalies of the files are printed (`verbose`), since the cached warnings depend on it.
+/// Returns `None` if any file cannot be read.
+pub fn digest_inputs(gcno_path: &Path, gcda_path: &Path, import_paths: &[PathBuf], workspace_path: &Path, language: Language, pruned: bool, verbose: bool) -> Option<u64> {
+    let mut hasher = DefaultHasher::new();
+    env!("CARGO_PKG_VERSION").hash(&mut hasher);
+    workspace_path.hash(&mut hasher);
+    language.hash(&mut hasher);
+    pruned.hash(&mut hasher);
+    verbose.hash(&mut hasher);
+    for &(extension, dir_path) in &[("gcno", gcno_path), ("gcda", gcda_path)] {
+        // The files of a directory are visited in the order of their paths, and an archive in its own order.
+        archive::for_each(dir_path, extension, |file, reader| -> io::Result<()> {
+            file.path.hash(&mut hasher);
+            let mut buffer = [0; 8192];
+            loop {
+                let len = reader.read(&mut buffer)?;
+                if len == 0 {
+                    return Ok(());
+                }
+                hasher.write(&buffer[..len]);
+            }
+        }).ok()?;
+    }
+    let mut content = Vec::new();
+    for path in import_paths {
+        content.clear();
+        File::open(path).and_then(|mut file| file.read_to_end(&mut content)).ok()?;
+        path.hash(&mut hasher);
+        content.hash(&mut hasher);
+    }
+    Some(hasher.finish())
+}
+
+/// Computes the fingerprint of a value. The fingerprint is only meaningful for the same version of `cargo cov`.
+pub fn fingerprint<T: Hash + ?Sized>(value: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    env!("CARGO_PKG_VERSION").hash(&mut hasher);
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[test]
+fn test_open_save() {
+    let dir = ::tempfile::TempDir::new().unwrap();
+    let path = dir.path().join(CACHE_FILE_NAME);
+    assert!(PageCache::open(&path).unwrap().pages.is_empty());
+
+    let mut cache = PageCache::default();
+    cache.template = fingerprint("html");
+    cache.pages.insert(
+        "src/lib.rs".to_owned(),
+        CachedPage {
+            fingerprint: fingerprint("{}"),
+            output: "1.html".to_owned(),
+        },
+    );
+    let mut summary = FileSummary::default();
+    summary.lines_count = 10;
+    summary.lines_covered = 8;
+    cache.summaries.insert("src/lib.rs".to_owned(), summary);
+    cache.save(&path).unwrap();
+
+    let cache = PageCache::open(&path).unwrap();
+    assert_eq!(cache.template, fingerprint("html"));
+    assert_ne!(cache.template, fingerprint("lcov"));
+    assert_eq!(cache.pages["src/lib.rs"].output, "1.html");
+    assert_eq!(cache.summaries["src/lib.rs"].lines_covered, 8);
+}
+
+#[test]
+fn test_report_cache() {
+    use cov::report::File as ReportFile;
+
+    let dir = ::tempfile::TempDir::new().unwrap();
+    let gcov_path = dir.path().join("gcov");
+    ::std::fs::create_dir(&gcov_path).unwrap();
+    ::std::fs::write(gcov_path.join("a.gcno"), b"gcno").unwrap();
+    ::std::fs::write(gcov_path.join("a.gcda"), b"gcda").unwrap();
+    ::std::fs::write(gcov_path.join("ignored.txt"), b"text").unwrap();
+    let workspace_path = dir.path();
+    let digest_with = |gcno_path: &Path, workspace_path: &Path, language, pruned, verbose| digest_inputs(gcno_path, &gcov_path, &[], workspace_path, language, pruned, verbose);
+    let digest = digest_with(&gcov_path, workspace_path, Language::Rust, false, false).unwrap();
+    assert_ne!(digest_with(&gcov_path, workspace_path, Language::Rust, true, false), Some(digest));
+    assert_ne!(digest_with(&gcov_path, workspace_path, Language::Rust, false, true), Some(digest));
+    assert_ne!(digest_with(&gcov_path, &workspace_path.join("other"), Language::Rust, false, false), Some(digest));
+    assert_ne!(digest_with(&gcov_path, workspace_path, Language::C, false, false), Some(digest));
+    ::std::fs::write(gcov_path.join("ignored.txt"), b"changed").unwrap();
+    assert_eq!(digest_with(&gcov_path, workspace_path, Language::Rust, false, false), Some(digest));
+    ::std::fs::write(gcov_path.join("a.gcda"), b"changed").unwrap();
+    assert_ne!(digest_with(&gcov_path, workspace_path, Language::Rust, false, false), Some(digest));
+    assert_eq!(digest_with(&dir.path().join("missing"), workspace_path, Language::Rust, false, false), None);
+
+    let mut interner = Interner::new();
+    let mut report = Report::default();
+    report.files.insert(interner.intern("src/lib.rs"), ReportFile::default());
+    let path = dir.path().join(REPORT_CACHE_FILE_NAME);
+    ReportCache::new(digest, &interner, report.clone(), vec!["stale".to_owned()]).save(&path).unwrap();
+
+    let cache = ReportCache::open(&path).unwrap();
+    assert_eq!(cache.digest, digest);
+    assert_eq!(cache.warnings, vec!["stale".to_owned()]);
+    let mut restored_interner = Interner::new();
+    assert_eq!(cache.restore(&mut restored_interner), Some(report));
+    assert_eq!(restored_interner.iter().map(|(_, s)| s).collect::<Vec<_>>(), vec!["src/lib.rs"]);
+    assert!(ReportCache::open(&dir.path().join("missing.json")).is_none());
+}
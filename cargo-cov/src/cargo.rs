@@ -1,25 +1,31 @@
 //! Build environment information for `cargo cov`.
 
-use argparse::SpecialMap;
+use argparse::{SpecialMap, find_option_value};
 use error::{ErrorKind, Result, ResultExt};
+use hooks::Hooks;
 use lookup::*;
-use shim::move_gcov_files;
-use utils::{CommandExt, clean_dir, set_executable};
+use ordering;
+use repair::{self, Journal};
+use retention::{RetentionPolicy, apply as apply_retention, record_session};
+use shim::{RUNNERS, TargetFlags, is_static_target, move_gcov_files, print_artifact_messages};
+use timings::{self, RunEvent, new_run_id};
+use utils::{CommandExt, clean_dir, parent_3, set_executable};
 
 use cov::IntoStringLossy;
-use serde_json::from_reader;
+use serde_json::{Value, from_reader, from_str, to_string};
 use shell_escape::escape;
 use tempfile::TempDir;
 
 use std::borrow::Cow;
-use std::collections::HashMap;
-use std::env::current_exe;
+use std::collections::{BTreeMap, HashMap};
+use std::env::{current_dir, current_exe};
 use std::ffi::{OsStr, OsString};
 use std::fs::{File, canonicalize, create_dir, create_dir_all};
 use std::io::{self, Write};
 use std::iter::once;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
 
 include!(concat!(env!("OUT_DIR"), "/host.rs"));
 
@@ -38,15 +44,30 @@ pub struct Cargo<'a> {
     cov_build_path: PathBuf,
     /// Build target triples.
     target: &'a str,
-    /// Canonical path to the folder containing the compiler-rt profiler library, or the string `"@native"` if building
-    /// for nightly Rust.
-    profiler_lib_path: Cow<'static, str>,
-    /// Name of the compiler-rt profiler library, or the string `"@native"` if building for nightly Rust.
-    profiler_lib_name: Cow<'a, str>,
+    /// How the test runner shim executes the programs, see [`shim::run()`](../shim/fn.run.html).
+    runner: &'a str,
+    /// Path to the compiler-rt profiler library given by `--profiler`, see [`profiler_lib()`](#method.profiler_lib).
+    profiler: Option<&'a OsStr>,
     /// Arguments to be forwarded to `cargo`.
     forward_args: Vec<&'a OsStr>,
     /// List of packages in this workspace.
     workspace_packages: Vec<String>,
+    /// Retention policy of the GCDA files configured in `Cargo.toml`.
+    retention: RetentionPolicy,
+    /// Hooks configured in `Cargo.toml`.
+    hooks: Hooks,
+    /// Adjustments of the coverage flags configured in `Cargo.toml`, keyed by the target pattern.
+    target_flags: BTreeMap<String, TargetFlags>,
+    /// Whether to force `-Clink-dead-code` on or off, from the command line or `Cargo.toml`.
+    link_dead_code: Option<bool>,
+    /// The revision to find the changed lines from with `--changed-first` or `--changed-since`, see the [`ordering`
+    /// module](../ordering/index.html).
+    changed_since: Option<&'a str>,
+    /// Only the local crates which may contain the sources inside this directory (relative to the workspace) are
+    /// instrumented, see [`shim::rustc()`](../shim/fn.rustc.html).
+    only_path: Option<&'a OsStr>,
+    /// Identifies this command in the run log, see the [`timings` module](../timings/index.html#run-log).
+    run_id: u64,
 }
 
 impl<'a> Cargo<'a> {
@@ -71,6 +92,27 @@ impl<'a> Cargo<'a> {
         cov_build_path.push("build");
         create_dir_all(&cov_build_path).chain_err(|| "Cannot prepare coverage build directory")?;
 
+        let (retention, hooks, target_flags, configured_link_dead_code) = metadata
+            .packages
+            .into_iter()
+            .find(|p| p.manifest_path == manifest_path)
+            .and_then(|p| p.metadata)
+            .or(metadata.metadata)
+            .and_then(|m| m.cov)
+            .map_or_else(Default::default, |c| (c.retention, c.hooks, c.target_flags, c.link_dead_code));
+        let link_dead_code = if special_args.contains_key("link-dead-code") {
+            Some(true)
+        } else if special_args.contains_key("no-link-dead-code") {
+            Some(false)
+        } else {
+            configured_link_dead_code
+        };
+        let changed_since = match special_args.get("changed-since") {
+            Some(rev) => rev.to_str(),
+            None if special_args.contains_key("changed-first") => Some("HEAD"),
+            None => None,
+        };
+
         let mut workspace_packages = metadata.workspace_members;
         for pkg_id in &mut workspace_packages {
             let space_index = pkg_id.find(' ').unwrap_or_else(|| pkg_id.len());
@@ -78,22 +120,8 @@ impl<'a> Cargo<'a> {
         }
 
         let target = special_args.get("target").and_then(|s| s.to_str()).unwrap_or(HOST);
-        let (profiler_lib_path, profiler_lib_name) = match special_args.get("profiler") {
-            Some(&path) => {
-                let (p, n) = split_profiler_lib(Path::new(path)).chain_err(|| "Cannot parse user-provided profiler library")?;
-                (Cow::Owned(canonicalize(p)?.into_string_lossy()), Cow::Borrowed(n))
-            },
-            None => {
-                if supports_built_in_profiler(&rustc_path, target) {
-                    (Cow::Borrowed("@native"), Cow::Borrowed("@native"))
-                } else {
-                    let (p, n) = find_native_profiler_lib(target).chain_err(|| "Native profiler library not found")?;
-                    (Cow::Owned(canonicalize(p)?.into_string_lossy()), Cow::Owned(n))
-                }
-            },
-        };
-        debug!("Profiler: -L {} -l {}", profiler_lib_path, profiler_lib_name);
-
+        let runner = special_args.get("runner").map_or(Some("native"), |s| s.to_str()).unwrap_or("");
+        ensure!(RUNNERS.contains(&runner), ErrorKind::UnknownRunner(runner.to_owned()));
         Ok(Cargo {
             cargo_path,
             rustc_path,
@@ -101,13 +129,41 @@ impl<'a> Cargo<'a> {
             manifest_path,
             cov_build_path,
             target,
-            profiler_lib_path,
-            profiler_lib_name,
+            runner,
+            profiler: special_args.get("profiler").cloned(),
             forward_args,
             workspace_packages,
+            retention,
+            hooks,
+            target_flags,
+            link_dead_code,
+            changed_since,
+            only_path: special_args.get("only-path").cloned(),
+            run_id: new_run_id(),
         })
     }
 
+    /// Obtains the `target/cov/build` path.
+    pub fn cov_build_path(&self) -> &Path {
+        &self.cov_build_path
+    }
+
+    /// Obtains the retention policy configured in `Cargo.toml`.
+    pub fn retention(&self) -> RetentionPolicy {
+        self.retention
+    }
+
+    /// Runs a hook configured in `Cargo.toml`, see the [`hooks` module](../hooks/index.html).
+    ///
+    /// `context` should be a JSON object, to which the paths of this build environment are added.
+    pub fn run_hook(&self, hook: &str, mut context: Value) -> Result<()> {
+        if let Value::Object(ref mut map) = context {
+            map.insert("manifest_path".to_owned(), json!(self.manifest_path));
+            map.insert("cov_build_path".to_owned(), json!(self.cov_build_path));
+        }
+        self.hooks.run(hook, &self.manifest_path, context)
+    }
+
     /// Obtains the `target/cov/build` path and transfers ownership.
     pub fn into_cov_build_path(self) -> PathBuf {
         self.cov_build_path
@@ -206,31 +262,213 @@ bitflags! {
 
 impl<'a> Cargo<'a> {
     /// Runs the real cargo subcommand (build, test, run).
-    pub fn forward(self, subcommand: &str) -> Result<()> {
+    pub fn forward(&self, subcommand: &str) -> Result<()> {
+        let hook_context = json!({
+            "subcommand": subcommand,
+            "target": self.target,
+            "runner": self.runner,
+        });
+        self.run_hook(&format!("pre-{}", subcommand), hook_context.clone())?;
+        let started = Instant::now();
         self.prepare_cov_build_path()?;
-        let mut cmd = Command::new(self.cargo_path);
+        repair::recover(&self.cov_build_path)?;
+        let _journal = Journal::begin(&self.cov_build_path, subcommand)?;
+        let is_session = subcommand == "test" || subcommand == "run";
+        if is_session {
+            record_session(&self.cov_build_path.join("gcda"), subcommand, &self.forward_args)?;
+        }
+
+        let mut cmd = self.command(subcommand)?;
+        cmd.args(&self.forward_args);
+
+        // Let the shims interleave their own messages with those of cargo, see the `shim` module.
+        let json_messages = find_option_value(self.forward_args.iter().cloned(), "--message-format").map_or(false, |f| f.starts_with("json"));
+        if json_messages {
+            cmd.env("COV_MESSAGE_FORMAT", "json");
+        }
+
+        match self.changed_since {
+            Some(base) if subcommand == "test" => self.test_changed_first(base, json_messages)?,
+            _ => {
+                progress!("Delegate", "{:?}", cmd);
+                cmd.ensure_success("cargo")?;
+            },
+        }
+        if is_session {
+            // Before 1.19, the test-runner is absent, so we need to move them outside of the shim.
+            let gcda_paths = move_gcov_files(&self.cov_build_path, OsStr::new("gcda"), None)?;
+            if json_messages {
+                print_artifact_messages(OsStr::new("gcda"), &gcda_paths);
+            }
+        }
+
+        self.gc(self.retention)?;
+        self.record_run(&[("command", subcommand, started.elapsed())])?;
+        self.run_hook(&format!("post-{}", subcommand), hook_context)
+    }
+
+    /// Runs `cargo test`, but runs the test programs covering the lines changed since `base` first, see the [`ordering`
+    /// module](../ordering/index.html).
+    ///
+    /// The test programs are built with `cargo test --no-run`, and run one by one through the test runner shim. The
+    /// arguments after `--` are passed to every test program. The doc tests are run at last unless specific targets are
+    /// selected.
+    fn test_changed_first(&self, base: &str, json_messages: bool) -> Result<()> {
+        let split_index = self.forward_args.iter().position(|&arg| arg == "--").unwrap_or_else(|| self.forward_args.len());
+        let cargo_args = &self.forward_args[..split_index];
+        let harness_args = self.forward_args.get(split_index + 1..).unwrap_or(&[]);
+        let no_fail_fast = cargo_args.contains(&OsStr::new("--no-fail-fast"));
+
+        let mut cmd = self.command("test")?;
+        cmd.args(&["--no-run", "--message-format=json"]).args(cargo_args).stdout(Stdio::piped());
+        if json_messages {
+            cmd.env("COV_MESSAGE_FORMAT", "json");
+        }
+        progress!("Delegate", "{:?}", cmd);
+        let output = cmd.spawn()?.wait_with_output()?;
+        ensure!(output.status.success(), ErrorKind::ForwardFailed("cargo", output.status));
+
+        let mut programs = Vec::new();
+        let mut manifest_dirs = HashMap::new();
+        let mut has_lib = false;
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            if json_messages {
+                println!("{}", line);
+            }
+            let message = match from_str::<ArtifactMessage>(line) {
+                Ok(ref message) if message.reason != "compiler-artifact" || !message.profile.test => continue,
+                Ok(message) => message,
+                Err(_) => continue,
+            };
+            has_lib |= message.target.kind.iter().any(|kind| kind.ends_with("lib"));
+            if let Some(executable) = message.executable {
+                if let Some(manifest_dir) = message.manifest_path.as_ref().and_then(|p| p.parent()) {
+                    manifest_dirs.insert(executable.clone(), manifest_dir.to_owned());
+                }
+                programs.push(executable);
+            }
+        }
+
+        ordering::order_by_changes(&mut programs, &self.cov_build_path, parent_3(&self.cov_build_path), base)?;
+
+        let test_runner = self.cov_build_path.join("test-runner.bat");
+        let mut first_failure = None;
+        for program in &programs {
+            let mut cmd = Command::new(&test_runner);
+            cmd.env("COV_BUILD_PATH", &self.cov_build_path)
+                .env("COV_RUNNER", self.runner)
+                .env("COV_RUN_ID", self.run_id.to_string())
+                .arg(program)
+                .args(harness_args);
+            if let Some(manifest_dir) = manifest_dirs.get(program) {
+                cmd.current_dir(manifest_dir).env("CARGO_MANIFEST_DIR", manifest_dir);
+            }
+            if json_messages {
+                cmd.env("COV_MESSAGE_FORMAT", "json");
+            }
+            progress!("Running", "{}", program.display());
+            let status = cmd.status()?;
+            if !status.success() {
+                ensure!(no_fail_fast, ErrorKind::ForwardFailed("test", status));
+                first_failure = first_failure.or(Some(status));
+            }
+        }
+
+        let selects_targets = cargo_args.iter().any(|arg| TARGET_SELECTION_FLAGS.iter().any(|flag| arg == flag || arg.to_str().map_or(false, |a| a.starts_with(&format!("{}=", flag)))));
+        if has_lib && !selects_targets {
+            let mut cmd = self.command("test")?;
+            cmd.arg("--doc").args(&self.forward_args);
+            progress!("Delegate", "{:?}", cmd);
+            let status = cmd.status()?;
+            if !status.success() {
+                ensure!(no_fail_fast, ErrorKind::ForwardFailed("cargo", status));
+                first_failure = first_failure.or(Some(status));
+            }
+        }
+
+        match first_failure {
+            Some(status) => Err(ErrorKind::ForwardFailed("test", status).into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Finds the folder containing the compiler-rt profiler library and its name, or the string `"@native"` for both if
+    /// building for nightly Rust.
+    ///
+    /// This is only looked up when running a cargo subcommand, so the subcommands only reading the coverage data work
+    /// without a profiler.
+    fn profiler_lib(&self) -> Result<(Cow<'static, str>, Cow<'a, str>)> {
+        Ok(match self.profiler {
+            Some(path) => {
+                // A static executable can only link to the static archive of the profiler runtime.
+                ensure!(!is_static_target(self.target) || Path::new(path).extension() == Some(OsStr::new("a")), ErrorKind::InvalidProfilerLibraryPath);
+                let (p, n) = split_profiler_lib(Path::new(path)).chain_err(|| "Cannot parse user-provided profiler library")?;
+                (Cow::Owned(canonicalize(p)?.into_string_lossy()), Cow::Borrowed(n))
+            },
+            None => {
+                if supports_built_in_profiler(&self.rustc_path, self.target) {
+                    (Cow::Borrowed("@native"), Cow::Borrowed("@native"))
+                } else {
+                    let (p, n) = find_native_profiler_lib(self.target).chain_err(|| "Native profiler library not found")?;
+                    (Cow::Owned(canonicalize(p)?.into_string_lossy()), Cow::Owned(n))
+                }
+            },
+        })
+    }
+
+    /// Creates the command running the real cargo subcommand with the shims, without the forwarded arguments.
+    fn command(&self, subcommand: &str) -> Result<Command> {
+        let (profiler_lib_path, profiler_lib_name) = self.profiler_lib()?;
+        debug!("Profiler: -L {} -l {}", profiler_lib_path, profiler_lib_name);
+        let mut cmd = Command::new(&self.cargo_path);
         cmd.current_dir(&self.cov_build_path)
-            .env("COV_RUSTC", self.rustc_path)
-            .env("COV_RUSTDOC", self.rustdoc_path)
+            .env("COV_RUSTC", &self.rustc_path)
+            .env("COV_RUSTDOC", &self.rustdoc_path)
             .env("COV_BUILD_PATH", &self.cov_build_path)
-            .env("COV_PROFILER_LIB_PATH", &*self.profiler_lib_path)
-            .env("COV_PROFILER_LIB_NAME", &*self.profiler_lib_name)
+            .env("COV_PROFILER_LIB_PATH", &*profiler_lib_path)
+            .env("COV_PROFILER_LIB_NAME", &*profiler_lib_name)
+            .env("COV_TARGET", self.target)
+            .env("COV_RUNNER", self.runner)
+            .env("COV_RUN_ID", self.run_id.to_string())
             .arg(subcommand)
             .arg("--manifest-path")
-            .arg(self.manifest_path);
+            .arg(&self.manifest_path);
         if self.target != HOST {
             cmd.args(&["--target", self.target]);
         }
-        cmd.args(self.forward_args);
-
-        progress!("Delegate", "{:?}", cmd);
+        if !self.target_flags.is_empty() {
+            cmd.env("COV_TARGET_FLAGS", to_string(&self.target_flags)?);
+        }
+        if let Some(link_dead_code) = self.link_dead_code {
+            cmd.env("COV_LINK_DEAD_CODE", if link_dead_code { "on" } else { "off" });
+        }
+        if let Some(only_path) = self.only_path {
+            cmd.env("COV_ONLY_PATH", parent_3(&self.cov_build_path).join(only_path));
+        }
+        Ok(cmd)
+    }
 
-        cmd.ensure_success("cargo")?;
-        if subcommand == "test" || subcommand == "run" {
-            // Before 1.19, the test-runner is absent, so we need to move them outside of the shim.
-            move_gcov_files(&self.cov_build_path, OsStr::new("gcda"))?;
+    /// Appends the `(kind, name, duration)` events of this command to the run log, and updates
+    /// `target/cov/timings.html`, see the [`timings` module](../timings/index.html#run-log).
+    ///
+    /// Nothing is recorded if `target/cov/build` does not exist, e.g. when only reporting imported files.
+    pub fn record_run(&self, events: &[(&str, &str, Duration)]) -> Result<()> {
+        if !self.cov_build_path.is_dir() {
+            return Ok(());
         }
+        let events = events.iter().map(|&(kind, name, duration)| RunEvent::new(self.run_id, kind, name, duration)).collect::<Vec<_>>();
+        timings::record_events(&self.cov_build_path, &events)?;
+        let path = timings::write_page(&self.cov_build_path)?;
+        debug!("Updated {}", path.display());
+        Ok(())
+    }
 
+    /// Removes the GCDA files which are not kept by the retention policy.
+    pub fn gc(&self, policy: RetentionPolicy) -> Result<()> {
+        let outcome = apply_retention(&self.cov_build_path.join("gcda"), policy)?;
+        if outcome.files_removed > 0 {
+            progress!("Remove", "{} stale *.gcda files ({} bytes)", outcome.files_removed, outcome.bytes_removed);
+        }
         Ok(())
     }
 
@@ -273,6 +511,12 @@ impl<'a> Cargo<'a> {
     }
 }
 
+/// Checks whether `cargo locate-project` would find a `Cargo.toml`, i.e. whether the current directory or any of its
+/// ancestors contains one.
+pub fn manifest_exists() -> bool {
+    current_dir().ok().map_or(false, |dir| dir.ancestors().any(|d| d.join("Cargo.toml").is_file()))
+}
+
 /// Locates the path to `Cargo.toml` if it is not specified in the command line.
 fn locate_project(cargo_path: &OsStr) -> Result<PathBuf> {
     let child = Command::new(cargo_path) // @rustfmt-force-break
@@ -285,6 +529,35 @@ fn locate_project(cargo_path: &OsStr) -> Result<PathBuf> {
     Ok(project_location.root)
 }
 
+/// The `cargo test` flags which select the targets to test, in which case the doc tests are not run by
+/// [`Cargo::test_changed_first()`](./struct.Cargo.html#method.test_changed_first).
+const TARGET_SELECTION_FLAGS: &[&str] = &["--lib", "--bin", "--bins", "--example", "--examples", "--test", "--tests", "--bench", "--benches", "--all-targets", "--doc"];
+
+/// A message printed by `cargo test --no-run --message-format=json`. Only the fields of a `compiler-artifact` message
+/// needed to run the test programs are parsed.
+#[derive(Debug, Deserialize)]
+struct ArtifactMessage {
+    reason: String,
+    #[serde(default)]
+    manifest_path: Option<PathBuf>,
+    #[serde(default)]
+    target: ArtifactTarget,
+    #[serde(default)]
+    profile: ArtifactProfile,
+    #[serde(default)]
+    executable: Option<PathBuf>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ArtifactTarget {
+    kind: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ArtifactProfile {
+    test: bool,
+}
+
 #[derive(Debug, Deserialize)]
 struct ProjectLocation {
     root: PathBuf,
@@ -294,6 +567,34 @@ struct ProjectLocation {
 struct Metadata {
     workspace_members: Vec<String>,
     target_directory: Option<PathBuf>,
+    #[serde(default)]
+    packages: Vec<Package>,
+    /// The `[workspace.metadata]` table.
+    metadata: Option<CustomMetadata>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Package {
+    manifest_path: PathBuf,
+    /// The `[package.metadata]` table.
+    metadata: Option<CustomMetadata>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CustomMetadata {
+    cov: Option<CovMetadata>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CovMetadata {
+    #[serde(default)]
+    retention: RetentionPolicy,
+    #[serde(default)]
+    hooks: Hooks,
+    #[serde(default, rename = "target-flags")]
+    target_flags: BTreeMap<String, TargetFlags>,
+    #[serde(rename = "link-dead-code")]
+    link_dead_code: Option<bool>,
 }
 
 /// Obtains the `target/` directory for a crate using `cargo metadata`.
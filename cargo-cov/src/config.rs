@@ -0,0 +1,337 @@
+//! Typed configuration of the report subcommands.
+//!
+//! The settings of `cargo cov report` (paths, source filters, thresholds and template options) can be written in a
+//! TOML file and passed using `--config`. Options given on the command line take precedence over the file:
+//!
+//! ```toml
+//! workspace = "/path/to/workspace"
+//! output = "/path/to/workspace/target/cov/report"
+//! template = "html"
+//! include = ["local", "macros"]
+//! only-path = "src/engine"
+//! group-by = "crate"
+//! rounding = "floor"
+//! precision = 1
+//! owners = true
+//! history = true
+//! history-limit = 20
+//! prune-sysroot = true
+//! import = ["/path/to/coverage-from-ci.json"]
+//! import-lcov = ["/path/to/vendor/coverage.info"]
+//!
+//! [template-options]
+//! branches = false
+//!
+//! [owner-thresholds]
+//! "@org/core" = 80.0
+//!
+//! [[waivers]]
+//! path = "src/generated/**"
+//! justification = "Generated code"
+//! ```
+//!
+//! Wrappers (editor plugins, `xtask` scripts, etc.) can use [`Config::from_cli_and_file()`] to obtain the exact same
+//! configuration as `cargo cov report` would, given the same arguments.
+//!
+//! [`Config::from_cli_and_file()`]: ./struct.Config.html#method.from_cli_and_file
+
+use argparse::parse_config;
+use error::{Result, ResultExt};
+use rounding::RoundingMode;
+use sourcepath::{GroupBy, Language};
+use waivers::Waiver;
+
+use serde_json::{Map, Value};
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Configuration of `cargo cov report`.
+///
+/// Every path which is `None` is resolved relative to the current Cargo workspace, as described in the help message
+/// of `cargo cov report`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Config {
+    /// The directory to find the source code.
+    pub workspace: Option<PathBuf>,
+    /// The directory that contains all `*.gcno` files.
+    pub gcno: Option<PathBuf>,
+    /// The directory that contains all `*.gcda` files.
+    pub gcda: Option<PathBuf>,
+    /// The directory to store the generated report.
+    pub output: Option<PathBuf>,
+    /// Name of the report template.
+    pub template: Option<String>,
+    /// Directory with templates and static files replacing parts of the built-in template.
+    pub template_override: Option<PathBuf>,
+    /// Name of the color theme of the HTML report, e.g. `light`, `dark` or `auto`.
+    pub theme: Option<String>,
+    /// Names of the templates to render in one pass instead of `template`, or `all` for every built-in template.
+    pub emit: Option<Vec<String>>,
+    /// Template options overriding the defaults in the template's `config.toml`.
+    pub template_options: Map<String, Value>,
+    /// Names of the source types to include in the report, e.g. `local` or `rustsrc`.
+    pub include: Option<Vec<String>>,
+    /// Only report the source files inside this directory, relative to the workspace.
+    pub only_path: Option<PathBuf>,
+    /// Whether to summarize coverage per owner.
+    pub owners: bool,
+    /// Path to the `CODEOWNERS` file.
+    pub codeowners: Option<PathBuf>,
+    /// Minimum line coverage percentage required for each owner.
+    pub owner_thresholds: BTreeMap<String, f64>,
+    /// Whether to record the coverage history.
+    pub history: bool,
+    /// Number of snapshots kept in the coverage history, default to 100.
+    pub history_limit: Option<usize>,
+    /// Whether to remove functions from the sysroot before constructing the graph.
+    pub prune_sysroot: bool,
+    /// Whether to print the time spent in every phase.
+    pub timings: bool,
+    /// Path to write the Chrome trace of the phases.
+    pub timings_trace: Option<PathBuf>,
+    /// Estimated memory usage of the graph in megabytes, above which the optional features are disabled.
+    pub max_memory: Option<u64>,
+    /// Files produced by `cargo cov export` to be merged together with the local GCNO and GCDA files.
+    pub import: Vec<PathBuf>,
+    /// LCOV tracefiles produced by other tools (e.g. `lcov` or `kcov`) to be merged into the report, see the [`lcov`
+    /// module].
+    ///
+    /// [`lcov` module]: ../lcov/index.html
+    pub import_lcov: Vec<PathBuf>,
+    /// The public key to verify the signature of every imported file with, see the [`signing` module].
+    ///
+    /// [`signing` module]: ../signing/index.html
+    pub verify_key: Option<PathBuf>,
+    /// Language of the profiled project, default to Rust.
+    pub language: Option<Language>,
+    /// How the files are grouped in the summary, default to by source type.
+    pub group_by: Option<GroupBy>,
+    /// How the percentages are rounded, default to round half away from zero.
+    pub rounding: Option<RoundingMode>,
+    /// Number of decimal places of the percentages, default to 2.
+    pub precision: Option<u32>,
+    /// Maximum number of files shown in the summary, the worst first.
+    pub max_files: Option<usize>,
+    /// Whether to rank the files in the summary by the number of uncovered lines.
+    pub top_uncovered: bool,
+    /// Only the functions whose demangled name matches this regular expression are reported.
+    pub functions_regex: Option<String>,
+    /// The functions whose demangled name matches this regular expression are left out.
+    pub exclude_functions_regex: Option<String>,
+    /// Format of the total line coverage printed at the end, with `{}` replaced by the percentage.
+    pub coverage_line: Option<String>,
+    /// Whether to print the total coverage as TeamCity service messages.
+    pub teamcity: bool,
+    /// Whether to print the uncovered lines of the changed files as GitHub Actions annotations.
+    pub github_annotations: bool,
+    /// Whether to publish the Cobertura summary and the HTML report to Azure Pipelines.
+    pub azure: bool,
+    /// Revision to find the changed lines since, e.g. the target branch of a code review.
+    pub diff_base: Option<String>,
+    /// Whether to find the uncovered functions unreachable from the code run by the tests.
+    pub reachability: bool,
+    /// Web URL of the repository, or `auto`, to link every line of the HTML report to the repository host.
+    pub permalink_base: Option<String>,
+    /// Whether to keep the previous report and only render the pages of files which changed.
+    pub changed_only: bool,
+    /// Code expected to be uncovered, see the [`waivers` module](../waivers/index.html).
+    pub waivers: Vec<Waiver>,
+    /// Whether to fail if any uncovered line is not waived.
+    pub fail_on_unwaived_uncovered: bool,
+    /// Whether to fail if any warning is printed while generating the report.
+    pub strict: bool,
+    /// Whether to print the recoverable anomalies found in the GCNO/GCDA files.
+    pub verbose: bool,
+    /// Whether to attribute the code inlined from other crates to the local functions calling it.
+    pub attribute_inlined: bool,
+    /// Whether to also write the annotated sources in the format of `gcov` into `<output>/gcov/`.
+    pub gcov_files: bool,
+    /// Whether to also bundle the HTML pages into the standalone `<output>/report.html`.
+    pub single_file: bool,
+    /// Whether the template override may read files outside its directory, e.g. through symbolic links. Never
+    /// deserialized, so an untrusted configuration file cannot lift the sandbox.
+    #[serde(skip)]
+    pub allow_template_fs: bool,
+    /// Whether `template_override` is given in the command line rather than by a configuration file. Never
+    /// deserialized, so an untrusted configuration file can only use an override inside the workspace.
+    #[serde(skip)]
+    pub template_override_from_cli: bool,
+    /// The repository token of the coverage service for `cargo cov upload`. Never serialized, so it is not leaked to
+    /// the hooks.
+    #[serde(skip_serializing)]
+    pub upload_token: Option<String>,
+}
+
+impl Config {
+    /// Reads the configuration from a TOML file.
+    pub fn from_file(path: &Path) -> Result<Config> {
+        let mut bytes = Vec::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_end(&mut bytes))
+            .chain_err(|| format!("Cannot read configuration at `{}`", path.display()))?;
+        Ok(::toml::from_slice(&bytes).chain_err(|| format!("Cannot parse configuration at `{}`", path.display()))?)
+    }
+
+    /// Resolves the configuration in the same way as `cargo cov report`.
+    ///
+    /// `args` are the arguments after `cargo cov report`, e.g. `["--include", "local,crates"]`. The options in `args`
+    /// take precedence over those in `file`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Clap`] if `args` are not valid arguments of `cargo cov report`.
+    ///
+    /// [`Clap`]: ../error/enum.ErrorKind.html#variant.Clap
+    pub fn from_cli_and_file<I, T>(args: I, file: Option<&Path>) -> Result<Config>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let prefix = ["cargo", "cov", "report"].iter().map(OsString::from);
+        let matches = ::app().get_matches_from_safe(prefix.chain(args.into_iter().map(Into::into)))?;
+        let matches = matches.subcommand_matches("cov").and_then(|m| m.subcommand_matches("report")).expect("report");
+        parse_config(matches, file)
+    }
+
+    /// Overrides this configuration by the settings in `other`.
+    ///
+    /// Options set in `other` replace those in `self`, flags are enabled if set in either, and the maps are merged.
+    pub fn merge(&mut self, other: Config) {
+        fn replace<T>(this: &mut Option<T>, other: Option<T>) {
+            if other.is_some() {
+                *this = other;
+            }
+        }
+
+        replace(&mut self.workspace, other.workspace);
+        replace(&mut self.gcno, other.gcno);
+        replace(&mut self.gcda, other.gcda);
+        replace(&mut self.output, other.output);
+        replace(&mut self.verify_key, other.verify_key);
+        // A template chosen in the command line replaces the templates to emit given in the file, and vice versa.
+        if other.template.is_some() && other.emit.is_none() {
+            self.emit = None;
+        }
+        replace(&mut self.template, other.template);
+        replace(&mut self.emit, other.emit);
+        if other.template_override.is_some() {
+            self.template_override_from_cli = other.template_override_from_cli;
+        }
+        replace(&mut self.template_override, other.template_override);
+        replace(&mut self.theme, other.theme);
+        replace(&mut self.include, other.include);
+        replace(&mut self.only_path, other.only_path);
+        replace(&mut self.codeowners, other.codeowners);
+        replace(&mut self.timings_trace, other.timings_trace);
+        replace(&mut self.max_memory, other.max_memory);
+        replace(&mut self.language, other.language);
+        replace(&mut self.group_by, other.group_by);
+        replace(&mut self.rounding, other.rounding);
+        replace(&mut self.precision, other.precision);
+        replace(&mut self.upload_token, other.upload_token);
+        replace(&mut self.max_files, other.max_files);
+        replace(&mut self.history_limit, other.history_limit);
+        replace(&mut self.functions_regex, other.functions_regex);
+        replace(&mut self.exclude_functions_regex, other.exclude_functions_regex);
+        replace(&mut self.coverage_line, other.coverage_line);
+        replace(&mut self.diff_base, other.diff_base);
+        replace(&mut self.permalink_base, other.permalink_base);
+        self.template_options.extend(other.template_options);
+        self.import.extend(other.import);
+        self.import_lcov.extend(other.import_lcov);
+        self.waivers.extend(other.waivers);
+        self.owner_thresholds.extend(other.owner_thresholds);
+        self.owners |= other.owners;
+        self.history |= other.history;
+        self.prune_sysroot |= other.prune_sysroot;
+        self.timings |= other.timings;
+        self.changed_only |= other.changed_only;
+        self.fail_on_unwaived_uncovered |= other.fail_on_unwaived_uncovered;
+        self.strict |= other.strict;
+        self.verbose |= other.verbose;
+        self.top_uncovered |= other.top_uncovered;
+        self.teamcity |= other.teamcity;
+        self.github_annotations |= other.github_annotations;
+        self.azure |= other.azure;
+        self.reachability |= other.reachability;
+        self.attribute_inlined |= other.attribute_inlined;
+        self.gcov_files |= other.gcov_files;
+        self.single_file |= other.single_file;
+        self.allow_template_fs |= other.allow_template_fs;
+    }
+}
+
+#[test]
+fn test_from_cli_and_file() {
+    let args = vec![
+        "--include",
+        "local,crates",
+        "--owner-threshold",
+        "*=80",
+        "--history",
+        "--history-limit",
+        "20",
+        "--group-by",
+        "crate",
+        "--rounding",
+        "floor",
+        "--max-files",
+        "3",
+        "--diff-base",
+        "origin/master",
+        "--reachability",
+        "--exclude-functions-regex",
+        r"\bfmt::Debug\b",
+        "--emit",
+        "html,lcov",
+        "--only-path",
+        "src/engine",
+        "--import-lcov",
+        "vendor/zlib.info",
+    ];
+    let config = Config::from_cli_and_file(args, None).unwrap();
+    assert_eq!(config.include, Some(vec!["local".to_owned(), "crates".to_owned()]));
+    assert_eq!(config.owner_thresholds.get("*"), Some(&80.0));
+    assert!(config.history);
+    assert_eq!(config.history_limit, Some(20));
+    assert_eq!(config.group_by, Some(GroupBy::Crate));
+    assert_eq!(config.rounding, Some(RoundingMode::Floor));
+    assert_eq!(config.max_files, Some(3));
+    assert_eq!(config.diff_base, Some("origin/master".to_owned()));
+    assert!(config.reachability);
+    assert_eq!(config.exclude_functions_regex, Some(r"\bfmt::Debug\b".to_owned()));
+    assert_eq!(config.emit, Some(vec!["html".to_owned(), "lcov".to_owned()]));
+    assert_eq!(config.only_path, Some(PathBuf::from("src/engine")));
+    assert_eq!(config.import_lcov, vec![PathBuf::from("vendor/zlib.info")]);
+    assert_eq!(config.workspace, None);
+    assert!(Config::from_cli_and_file(vec!["--include", "nothing"], None).is_err());
+
+    let mut file_config: Config = ::toml::from_str("template = \"lcov\"\ninclude = [\"all\"]\nprune-sysroot = true\nprecision = 1\n").unwrap();
+    file_config.merge(config);
+    assert_eq!(file_config.template, Some("lcov".to_owned()));
+    assert_eq!(file_config.include, Some(vec!["local".to_owned(), "crates".to_owned()]));
+    assert!(file_config.prune_sysroot);
+    assert!(file_config.history);
+    assert_eq!(file_config.precision, Some(1));
+
+    let mut file_config: Config = ::toml::from_str("emit = [\"html\", \"json\"]\n").unwrap();
+    file_config.merge(Config::from_cli_and_file(vec!["--template", "lcov"], None).unwrap());
+    assert_eq!(file_config.template, Some("lcov".to_owned()));
+    assert_eq!(file_config.emit, None);
+
+    let mut file_config: Config = ::toml::from_str("template-override = \"overrides\"\nallow-template-fs = true\ntemplate-override-from-cli = true\n").unwrap();
+    assert!(!file_config.allow_template_fs);
+    assert!(!file_config.template_override_from_cli);
+    file_config.merge(Config::from_cli_and_file(vec!["--template-override", "/elsewhere"], None).unwrap());
+    assert!(file_config.template_override_from_cli);
+    assert!(Config::from_cli_and_file(vec!["--allow-template-fs"], None).unwrap().allow_template_fs);
+
+    let mut file_config: Config = ::toml::from_str("theme = \"dark\"\n").unwrap();
+    file_config.merge(Config::from_cli_and_file(vec!["--theme", "auto"], None).unwrap());
+    assert_eq!(file_config.theme, Some("auto".to_owned()));
+}
@@ -0,0 +1,176 @@
+//! Programmatic drivers of the `cargo cov` subcommands.
+//!
+//! Build systems (e.g. `xtask` scripts or Bazel rules) can run the whole pipeline in-process instead of spawning
+//! `cargo cov` and scraping its output:
+//!
+//! ```no_run
+//! extern crate cargo_cov;
+//!
+//! use cargo_cov::config::Config;
+//! use cargo_cov::driver::{CargoOptions, run_report, run_test};
+//!
+//! # fn main() { run().unwrap(); }
+//! # fn run() -> cargo_cov::error::Result<()> {
+//! let options = CargoOptions {
+//!     args: vec!["--lib".into()],
+//!     ..CargoOptions::default()
+//! };
+//! let outcome = run_test(&options)?;
+//! println!("{} artifacts in {}", outcome.artifacts.len(), outcome.cov_build_path.display());
+//!
+//! let config = Config::from_cli_and_file(vec!["--template", "lcov"], None)?;
+//! let outcome = run_report(&options, &config)?;
+//! println!("{} of {} lines covered", outcome.totals.lines_covered, outcome.totals.lines_count);
+//! outcome.check()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Every driver locks `target/cov` in the same way as the command line program, and still prints the progress to
+//! `stderr`.
+
+use argparse::{ReportConfig, SpecialMap};
+use artifacts::{Artifact, list};
+use cargo::{Cargo, manifest_exists};
+use config::Config;
+use error::Result;
+use lock::CovLock;
+use outcome::ReportOutcome;
+use report;
+use utils::join_2;
+
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+
+/// The options common to all subcommands, equivalent to the global options of `cargo cov`.
+#[derive(Clone, Debug, Default)]
+pub struct CargoOptions {
+    /// Path to the manifest of the package, default to the package in the current directory.
+    pub manifest_path: Option<PathBuf>,
+    /// Target triple which the covered program will run in, default to the host.
+    pub target: Option<String>,
+    /// Path to `libclang_rt.profile_*.a`, default to searching the known locations.
+    pub profiler: Option<PathBuf>,
+    /// How to run the tests, one of `native` (default), `adb` or `simctl`.
+    pub runner: Option<String>,
+    /// Wait for other `cargo cov` processes in the same workspace to finish.
+    pub wait: bool,
+    /// Do not lock `target/cov`.
+    pub no_lock: bool,
+    /// Whether to link functions never called, default to the setting in `Cargo.toml` and the target presets.
+    pub link_dead_code: Option<bool>,
+    /// Arguments forwarded to `cargo build`, `cargo test` or `cargo run`.
+    pub args: Vec<OsString>,
+}
+
+impl CargoOptions {
+    /// Creates the build environment from these options.
+    fn cargo(&self) -> Result<Cargo> {
+        let mut special_args = SpecialMap::with_capacity(4);
+        if let Some(ref path) = self.manifest_path {
+            special_args.insert("manifest-path", path.as_os_str());
+        }
+        if let Some(ref target) = self.target {
+            special_args.insert("target", OsStr::new(target));
+        }
+        if let Some(ref path) = self.profiler {
+            special_args.insert("profiler", path.as_os_str());
+        }
+        if let Some(ref runner) = self.runner {
+            special_args.insert("runner", OsStr::new(runner));
+        }
+        if let Some(link_dead_code) = self.link_dead_code {
+            special_args.insert(if link_dead_code { "link-dead-code" } else { "no-link-dead-code" }, OsStr::new(""));
+        }
+        Cargo::new(special_args, self.args.iter().map(|a| &**a).collect())
+    }
+
+    /// Locks the `target/cov` directory, unless `no_lock` is set.
+    fn lock(&self, cov_path: &Path, exclusive: bool) -> Result<Option<CovLock>> {
+        if self.no_lock || (!exclusive && !cov_path.is_dir()) {
+            Ok(None)
+        } else {
+            Ok(Some(CovLock::acquire(cov_path, exclusive, self.wait)?))
+        }
+    }
+}
+
+/// The result of [`run_build()`] and [`run_test()`].
+///
+/// [`run_build()`]: ./fn.run_build.html
+/// [`run_test()`]: ./fn.run_test.html
+#[derive(Debug)]
+pub struct BuildOutcome {
+    /// Path to `target/cov/build`.
+    pub cov_build_path: PathBuf,
+    /// All GCNO and GCDA files collected so far, including those from previous runs.
+    pub artifacts: Vec<Artifact>,
+}
+
+/// Runs `subcommand` through cargo, and lists the collected artifacts afterwards.
+fn forward(options: &CargoOptions, subcommand: &str) -> Result<BuildOutcome> {
+    let cargo = options.cargo()?;
+    let cov_build_path = cargo.cov_build_path().to_owned();
+    let _lock = match cov_build_path.parent() {
+        Some(cov_path) => options.lock(cov_path, true)?,
+        None => None,
+    };
+    cargo.forward(subcommand)?;
+    let artifacts = list(&cov_build_path.join("gcno"), &cov_build_path.join("gcda"))?;
+    Ok(BuildOutcome { cov_build_path, artifacts })
+}
+
+/// Compiles the crate and produces the coverage data (`*.gcno`), equivalent to `cargo cov build`.
+pub fn run_build(options: &CargoOptions) -> Result<BuildOutcome> {
+    forward(options, "build")
+}
+
+/// Tests the crate and produces the profile data (`*.gcda`), equivalent to `cargo cov test`.
+pub fn run_test(options: &CargoOptions) -> Result<BuildOutcome> {
+    forward(options, "test")
+}
+
+/// Runs a program and produces the profile data (`*.gcda`), equivalent to `cargo cov run`.
+pub fn run_run(options: &CargoOptions) -> Result<BuildOutcome> {
+    forward(options, "run")
+}
+
+/// Generates a coverage report, equivalent to `cargo cov report`.
+///
+/// `config` can be obtained from [`Config::from_cli_and_file()`]. If `config.workspace` is set, the coverage data is
+/// read from there, and the Cargo workspace described by `options` is only probed if a `Cargo.toml` can be found. The
+/// forwarded arguments in `options` are ignored.
+///
+/// Failed thresholds are recorded in the outcome instead of returned as errors, see [`ReportOutcome::check()`].
+///
+/// The `pre-report` and `post-report` hooks configured in `Cargo.toml` run only if the Cargo workspace is probed.
+///
+/// [`Config::from_cli_and_file()`]: ../config/struct.Config.html#method.from_cli_and_file
+/// [`ReportOutcome::check()`]: ../outcome/struct.ReportOutcome.html#method.check
+pub fn run_report(options: &CargoOptions, config: &Config) -> Result<ReportOutcome> {
+    let (cov_path, cargo, cov_build_path) = match config.workspace {
+        Some(ref workspace) if options.manifest_path.is_none() && !manifest_exists() => {
+            (join_2(workspace, "target", "cov"), None, Err("running without a Cargo workspace".into()))
+        },
+        Some(ref workspace) => {
+            let cargo = options.cargo()?;
+            let cov_build_path = cargo.cov_build_path().to_owned();
+            (join_2(workspace, "target", "cov"), Some(cargo), Ok(cov_build_path))
+        },
+        None => {
+            let cargo = options.cargo()?;
+            let cov_build_path = cargo.cov_build_path().to_owned();
+            (cov_build_path.parent().unwrap_or(&cov_build_path).to_owned(), Some(cargo), Ok(cov_build_path))
+        },
+    };
+    let _lock = options.lock(&cov_path, false)?;
+    let report_config = ReportConfig::resolve(config, cov_build_path)?;
+    if let Some(ref cargo) = cargo {
+        cargo.run_hook("pre-report", json!({ "config": config }))?;
+    }
+    let outcome = report::generate(&report_config, false)?;
+    if let Some(ref cargo) = cargo {
+        cargo.run_hook("post-report", json!({ "outcome": outcome }))?;
+    }
+    Ok(outcome)
+}
@@ -21,6 +21,8 @@ error_chain! {
         Json(::serde_json::Error);
         WalkDir(::walkdir::Error);
         FsExtra(::fs_extra::error::Error);
+        Clap(::clap::Error);
+        Regex(::regex::Error);
     }
 
     errors {
@@ -44,5 +46,120 @@ error_chain! {
             description("command failed")
             display("{} exited with {}", command, status)
         }
+
+        HookFailed(hook: String, status: ExitStatus) {
+            description("hook failed")
+            display("the {} hook exited with {}", hook, status)
+        }
+
+        InvalidThreshold(spec: String) {
+            description("invalid coverage threshold")
+            display("invalid coverage threshold `{}`, expecting the form `NAME=PERCENT`", spec)
+        }
+
+        InvalidLocation(location: String) {
+            description("invalid source location")
+            display("invalid source location `{}`, expecting the form `FILE:LINE`", location)
+        }
+
+        SourceNotFound(file: String) {
+            description("source file not found in coverage data")
+            display("source file `{}` is not found in any *.gcno file", file)
+        }
+
+        InvalidRuns(runs: usize) {
+            description("invalid number of runs")
+            display("the tests must be run at least 2 times to compare the coverage, but only {} requested", runs)
+        }
+
+        InvalidTemplateOption(spec: String) {
+            description("invalid template option")
+            display("invalid template option `{}`, expecting the form `NAME=VALUE`", spec)
+        }
+
+        UnsupportedSourceType(name: String) {
+            description("unsupported source type")
+            display("unsupported source type `{}`, expecting one of local, macros, rustsrc, crates, unknown or all", name)
+        }
+
+        Locked(path: ::std::path::PathBuf) {
+            description("another cargo cov process is running")
+            display("another `cargo cov` process is holding the lock `{}`, use --wait to wait for it, or --no-lock to ignore the lock", path.display())
+        }
+
+        OwnerCoverageTooLow(owner: String, coverage: f64, threshold: f64) {
+            description("coverage of an owner is below the threshold")
+            display("line coverage of {} is {:.2}%, below the required {}%", owner, coverage, threshold)
+        }
+
+        UnknownTemplate(name: String, available: String) {
+            description("unknown template")
+            display("unknown template `{}`, expecting one of {}", name, available)
+        }
+
+        UnknownTheme(name: String, available: String) {
+            description("unknown theme")
+            display("unknown theme `{}`, expecting one of {}", name, available)
+        }
+
+        TemplateOutsideSandbox(path: ::std::path::PathBuf) {
+            description("template file outside the sandbox")
+            display("template file `{}` resolves outside the template override directory, pass --allow-template-fs to allow it", path.display())
+        }
+
+        TemplateOverrideOutsideWorkspace(path: ::std::path::PathBuf) {
+            description("template override outside the workspace")
+            display("the template override `{}` from the configuration file is outside the workspace, pass it with --template-override instead", path.display())
+        }
+
+        IncompatibleTemplate(path: String, schema: u32, min_schema: u32, max_schema: u32) {
+            description("incompatible template")
+            display("template `{}` is written for context schema version {}, but this version of `cargo cov` only supports {} to {}, please update the template", path, schema, min_schema, max_schema)
+        }
+
+        UnsupportedExportFormat(format: u32) {
+            description("unsupported export format")
+            display("unsupported export format {}, the file should be exported again by this version of `cargo cov`", format)
+        }
+
+        InvalidSignature(path: ::std::path::PathBuf, reason: &'static str) {
+            description("invalid signature")
+            display("cannot verify the signature of `{}`: {}", path.display(), reason)
+        }
+
+        MemoryBudgetExceeded(estimate: u64, max_memory: u64, obstacle: String) {
+            description("memory budget exceeded")
+            display("the graph is estimated to take {} MB, exceeding --max-memory {} MB, and cannot be reduced: {}", estimate, max_memory, obstacle)
+        }
+
+        InvalidLcov(line_number: usize, line: String) {
+            description("invalid LCOV tracefile")
+            display("invalid LCOV record `{}` on line {}", line, line_number)
+        }
+
+        InvalidWaiver(reason: String) {
+            description("invalid waiver")
+            display("invalid waiver: {}", reason)
+        }
+
+        UnwaivedUncoveredLines(count: usize, first: String) {
+            description("uncovered lines are not waived")
+            display("{} uncovered lines are not waived, the first one is {}", count, first)
+        }
+
+        StrictWarnings(count: usize, first: String) {
+            description("warnings are treated as errors")
+            display("{} warnings are treated as errors because of --strict, the first one is: {}", count, first)
+        }
+
+        UnknownRunner(name: String) {
+            description("unknown test runner")
+            display("unknown test runner `{}`, expecting one of: native, adb, simctl", name)
+        }
+
+        UploadFailed(service: &'static str, status: u32, response: String) {
+            description("upload failed")
+            display("{} rejected the upload with HTTP status {}: {}", service, status, response.trim())
+        }
     }
 }
@@ -0,0 +1,221 @@
+//! Exporting and importing the coverage data.
+//!
+//! Collecting the coverage (building and running the tests) and reporting it do not need to happen on the same machine.
+//! `cargo cov export` parses all GCNO and GCDA files that make up the graph, and writes them into a single JSON file
+//! together with some metadata:
+//!
+//! ```json
+//! {
+//!     "metadata": {
+//!         "format": 1,
+//!         "generator": "cargo-cov 0.0.5",
+//!         "workspace": "/path/to/workspace",
+//!         "created": 1526300000
+//!     },
+//!     "files": [
+//!         {"ty": "Gcno", "version": "...", "stamp": 123, "records": [...]},
+//!         {"ty": "Gcda", "version": "...", "stamp": 123, "records": [...]},
+//!         ...
+//!     ]
+//! }
+//! ```
+//!
+//! The records are the same as the output of the `dump` example of the `cov` crate, with file names and function names
+//! written as strings.
+//!
+//! The file can be given to `cargo cov report --import` on another machine, where it is merged together with the local
+//! GCNO and GCDA files (if any). Source paths inside the exporting workspace are rebased onto the importing workspace,
+//! so the report finds the local checkout of the source code.
+
+use argparse::ReportConfig;
+use error::{ErrorKind, Result, ResultExt};
+use report::for_each_gcov;
+use sourcepath::{is_separator, path_starts_with};
+use timings::Timings;
+
+use cov::{Gcov, Interner, SerializeWithInterner};
+use cov::raw::{Line, Record, Type};
+use serde::Deserialize;
+use serde_json::{Value, from_slice};
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::mem::replace;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Version of the export format. Files with a different version are rejected on import.
+const FORMAT_VERSION: u32 = 1;
+
+/// Description of an exported file.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ExportMetadata {
+    /// Version of the export format.
+    pub format: u32,
+    /// Name and version of the program which produced the file.
+    pub generator: String,
+    /// The workspace in which the coverage was collected.
+    pub workspace: String,
+    /// Time of export in seconds since the Unix epoch.
+    pub created: u64,
+}
+
+
+/// Statistics of the exported files.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ExportOutcome {
+    /// Number of GCNO files exported.
+    pub gcno_count: usize,
+    /// Number of GCDA files exported.
+    pub gcda_count: usize,
+}
+
+/// Writes all GCNO and GCDA files described by the `config` into `output_path`.
+pub fn export(config: &ReportConfig, output_path: &Path) -> Result<ExportOutcome> {
+    let mut interner = Interner::new();
+    let mut files = Vec::new();
+    let mut outcome = ExportOutcome::default();
+    for_each_gcov(&config.gcno_path, &config.gcda_path, &mut interner, &mut Timings::new(), |gcov, _, _| {
+        match gcov.ty {
+            Type::Gcno => outcome.gcno_count += 1,
+            Type::Gcda => outcome.gcda_count += 1,
+        }
+        files.push(gcov);
+        Ok(())
+    })?;
+
+    let metadata = ExportMetadata {
+        format: FORMAT_VERSION,
+        generator: concat!("cargo-cov ", env!("CARGO_PKG_VERSION")).to_owned(),
+        workspace: config.workspace_path.to_string_lossy().into_owned(),
+        created: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+    };
+    let file = File::create(output_path).chain_err(|| format!("Cannot create `{}`", output_path.display()))?;
+    ::serde_json::to_writer(
+        BufWriter::new(file),
+        &json!({
+            "metadata": metadata,
+            "files": files.with_interner(&interner),
+        }),
+    )?;
+    Ok(outcome)
+}
+
+/// Rebases `path` inside the exporting workspace `from` onto the importing workspace `to`. Returns `None` if `path` is
+/// outside `from`, including a sibling sharing its prefix, e.g. `/workspace2/src/lib.rs` outside `/workspace`.
+fn rebase(path: &str, from: &str, to: &str) -> Option<String> {
+    if from.is_empty() || !path_starts_with(path, from) {
+        return None;
+    }
+    let rest = &path[from.len()..];
+    if from.ends_with(is_separator) || rest.is_empty() || rest.starts_with(is_separator) {
+        Some(format!("{}{}", to, rest))
+    } else {
+        None
+    }
+}
+
+/// Parses the `content` of an exported file at `path`, interning the strings into `interner`. The source paths are
+/// rebased from the exporting workspace onto `workspace_path`.
+///
+/// The content is read by [`signing::read()`], so it can be verified before it is parsed.
+///
+/// # Errors
+///
+/// Returns [`UnsupportedExportFormat`] if the file is produced by an incompatible version of `cargo cov export`.
+///
+/// [`UnsupportedExportFormat`]: ../error/enum.ErrorKind.html#variant.UnsupportedExportFormat
+/// [`signing::read()`]: ../signing/fn.read.html
+pub fn import(path: &Path, content: &[u8], workspace_path: &Path, interner: &mut Interner) -> Result<Vec<Gcov>> {
+    let mut export: Value = from_slice(content).chain_err(|| format!("Cannot parse `{}`", path.display()))?;
+    let metadata = ExportMetadata::deserialize(&export["metadata"]).chain_err(|| format!("Cannot parse `{}`", path.display()))?;
+    ensure!(metadata.format == FORMAT_VERSION, ErrorKind::UnsupportedExportFormat(metadata.format));
+
+    let workspace = workspace_path.to_string_lossy();
+    let mut intern = |s: &str| match rebase(s, &metadata.workspace, &workspace) {
+        Some(rebased) => interner.intern(rebased),
+        None => interner.intern(s),
+    };
+
+    let mut files = Vec::new();
+    for file in export["files"].as_array_mut().into_iter().flat_map(|files| files) {
+        // The symbols are written as strings, which need to be interned before deserializing the records. The lines of
+        // a block cannot be deserialized at all, since a file name cannot be distinguished from a line number once
+        // interned, so they are taken out and converted separately.
+        let mut lines_of_blocks = Vec::new();
+        for record in file["records"].as_array_mut().into_iter().flat_map(|records| records) {
+            if let Some(source) = record.pointer_mut("/Function/1/source") {
+                for key in &["name", "filename"] {
+                    let symbol = source[key].as_str().map(&mut intern);
+                    if let Some(symbol) = symbol {
+                        source[key] = json!(usize::from(symbol));
+                    }
+                }
+            }
+            if let Some(lines) = record.pointer_mut("/Lines/lines") {
+                let lines = replace(lines, json!([]));
+                lines_of_blocks.push(
+                    lines
+                        .as_array()
+                        .into_iter()
+                        .flat_map(|lines| lines)
+                        .filter_map(|line| match *line {
+                            Value::Number(ref n) => n.as_u64().map(|n| Line::LineNumber(n as u32)),
+                            Value::String(ref s) => Some(Line::FileName(intern(s))),
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>(),
+                );
+            }
+        }
+
+        let mut gcov = Gcov::deserialize(&*file).chain_err(|| format!("Cannot parse `{}`", path.display()))?;
+        let mut lines_of_blocks = lines_of_blocks.into_iter();
+        for record in &mut gcov.records {
+            if let Record::Lines(ref mut lines) = *record {
+                lines.lines = lines_of_blocks.next().unwrap_or_default();
+            }
+        }
+        gcov.src = Some(path.to_owned());
+        files.push(gcov);
+    }
+    Ok(files)
+}
+
+#[test]
+fn test_export_import() {
+    use config::Config;
+    use std::path::PathBuf;
+
+    let test_data = PathBuf::from("../cov/test-data/branches.clang");
+    let config = Config {
+        workspace: Some(PathBuf::from("/exporter")),
+        gcno: Some(test_data.clone()),
+        gcda: Some(test_data),
+        ..Config::default()
+    };
+    let report_config = ReportConfig::resolve(&config, Err("no cargo".into())).unwrap();
+    let output = ::tempfile::NamedTempFile::new().unwrap();
+    let outcome = export(&report_config, output.path()).unwrap();
+    assert_eq!((outcome.gcno_count, outcome.gcda_count), (1, 1));
+
+    let mut interner = Interner::new();
+    let content = ::std::fs::read(output.path()).unwrap();
+    let files = import(output.path(), &content, Path::new("/importer"), &mut interner).unwrap();
+    assert_eq!(files.iter().map(|f| f.ty).collect::<Vec<_>>(), vec![Type::Gcno, Type::Gcda]);
+    let mut expected_interner = Interner::new();
+    let expected = Gcov::open("../cov/test-data/branches.clang/x.gcno", &mut expected_interner).unwrap();
+    assert_eq!(files[0].records.len(), expected.records.len());
+    assert!(interner.iter().any(|(_, s)| s == "x.cpp"));
+}
+
+#[test]
+fn test_rebase() {
+    assert_eq!(rebase("/work/src/lib.rs", "/work", "/home/ci"), Some("/home/ci/src/lib.rs".to_owned()));
+    assert_eq!(rebase("/work/src/lib.rs", "/work/", "/home/ci/"), Some("/home/ci/src/lib.rs".to_owned()));
+    assert_eq!(rebase(r"C:\work\src\lib.rs", "C:/work", "/home/ci"), Some(r"/home/ci\src\lib.rs".to_owned()));
+    assert_eq!(rebase("/work", "/work", "/home/ci"), Some("/home/ci".to_owned()));
+    assert_eq!(rebase("/workspace2/src/x.rs", "/work", "/home/ci"), None);
+    assert_eq!(rebase("/usr/include/stdio.h", "/work", "/home/ci"), None);
+    assert_eq!(rebase("x.cpp", "", "/home/ci"), None);
+}
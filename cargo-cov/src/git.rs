@@ -0,0 +1,391 @@
+//! Git metadata of the workspace.
+//!
+//! Coverage services (e.g. Coveralls) show which commit and branch a report was generated from. The metadata is read
+//! by running the `git` executable in the workspace, so a workspace which is not a Git repository (or a system without
+//! Git) simply has no metadata.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// The Git metadata of a workspace, in the same shape as the `git` object of the Coveralls API.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct GitInfo {
+    /// The commit checked out.
+    pub head: Commit,
+    /// The branch checked out, or `HEAD` if detached.
+    pub branch: String,
+    /// The configured remotes.
+    pub remotes: Vec<Remote>,
+}
+
+/// A Git commit.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Commit {
+    /// The full commit hash.
+    pub id: String,
+    pub author_name: String,
+    pub author_email: String,
+    pub committer_name: String,
+    pub committer_email: String,
+    /// The first line of the commit message.
+    pub message: String,
+}
+
+/// A Git remote.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Remote {
+    pub name: String,
+    pub url: String,
+}
+
+/// Runs `git` with `args` inside `workspace`, and returns the standard output if successful.
+fn git(workspace: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .current_dir(workspace)
+        .args(args)
+        .output()
+        .ok()?;
+    if output.status.success() {
+        String::from_utf8(output.stdout).ok()
+    } else {
+        debug!("git {:?} failed in {:?} with {}", args, workspace, output.status);
+        None
+    }
+}
+
+/// Reads the Git metadata of `workspace`. Returns `None` if it is not inside a Git repository.
+pub fn describe(workspace: &Path) -> Option<GitInfo> {
+    let head = parse_head(&git(workspace, &["log", "-1", "--format=%H%n%an%n%ae%n%cn%n%ce%n%s"])?)?;
+    let branch = git(workspace, &["rev-parse", "--abbrev-ref", "HEAD"]).map_or_else(String::new, |b| b.trim().to_owned());
+    // `git config` fails if there is no remote at all.
+    let remotes = git(workspace, &["config", "--get-regexp", r"^remote\..*\.url$"]).map_or_else(Vec::new, |r| parse_remotes(&r));
+    Some(GitInfo { head, branch, remotes })
+}
+
+/// Finds the lines added or modified since the merge base of `base` and `HEAD`, keyed by the path relative to
+/// `workspace`. Files outside `workspace` are ignored. Returns `None` if the diff cannot be computed, e.g. `base` does not
+/// exist or the clone is too shallow to find the merge base.
+pub fn changed_lines(workspace: &Path, base: &str) -> Option<BTreeMap<String, BTreeSet<u32>>> {
+    let range = format!("{}...HEAD", base);
+    let output = git(workspace, &["diff", "--unified=0", "--no-color", "--no-ext-diff", "--no-renames", "--relative", &range])?;
+    Some(parse_diff(&output))
+}
+
+/// A file changed in the output of `git diff`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct DiffFile {
+    /// Path of the file after the change, relative to the workspace.
+    pub path: String,
+    /// The changed regions with their context.
+    pub hunks: Vec<Hunk>,
+}
+
+/// A changed region of a file, e.g. `@@ -12,3 +14,5 @@ fn main() {` and its lines.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Hunk {
+    /// The `@@` line introducing the hunk.
+    pub header: String,
+    pub lines: Vec<DiffLine>,
+}
+
+/// Whether a line of a hunk is added, removed or unchanged.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffLineKind {
+    Added,
+    Removed,
+    Context,
+}
+
+/// A line of a hunk.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct DiffLine {
+    pub kind: DiffLineKind,
+    /// Line number before the change, absent for added lines.
+    pub old: Option<u32>,
+    /// Line number after the change, absent for removed lines.
+    pub new: Option<u32>,
+    /// The content of the line, without the leading `+`, `-` or space.
+    pub text: String,
+}
+
+/// Finds the changes since the merge base of `base` and `HEAD` with 3 lines of context, for review. Files outside
+/// `workspace`, deleted files and binary files are ignored. Returns `None` if the diff cannot be computed.
+pub fn diff(workspace: &Path, base: &str) -> Option<Vec<DiffFile>> {
+    let range = format!("{}...HEAD", base);
+    let output = git(workspace, &["diff", "--unified=3", "--no-color", "--no-ext-diff", "--no-renames", "--relative", &range])?;
+    Some(parse_hunks(&output))
+}
+
+/// Finds the lines added or modified in the working tree since the merge base of `base` and `HEAD`, including the
+/// uncommitted changes, keyed by the path relative to `workspace`. With `HEAD` as `base`, these are only the uncommitted
+/// changes. Returns `None` if the diff cannot be computed.
+pub fn working_tree_changed_lines(workspace: &Path, base: &str) -> Option<BTreeMap<String, BTreeSet<u32>>> {
+    let merge_base = git(workspace, &["merge-base", base, "HEAD"])?;
+    let output = git(workspace, &["diff", "--unified=0", "--no-color", "--no-ext-diff", "--no-renames", "--relative", merge_base.trim()])?;
+    Some(parse_diff(&output))
+}
+
+/// Finds the path of `workspace` relative to the root of its repository, e.g. `cov/` or the empty string at the root.
+/// Returns `None` if it is not inside a Git repository.
+pub fn prefix(workspace: &Path) -> Option<String> {
+    git(workspace, &["rev-parse", "--show-prefix"]).map(|prefix| prefix.trim().to_owned())
+}
+
+/// Finds the URL linking to the files of `workspace` at the commit checked out on the repository host, which the path of
+/// a file relative to `workspace` is appended to. `base` is the web URL of the repository, e.g.
+/// `https://github.com/kennytm/cov`, or `auto` to derive it from the `origin` remote. Returns `None` if it is not
+/// inside a Git repository, or the URL of `origin` is not recognized.
+pub fn permalink_prefix(workspace: &Path, base: &str) -> Option<String> {
+    let base = if base == "auto" {
+        web_url(&git(workspace, &["config", "--get", "remote.origin.url"])?)?
+    } else {
+        base.trim_end_matches('/').to_owned()
+    };
+    let commit = git(workspace, &["rev-parse", "HEAD"])?;
+    Some(blob_url(&base, commit.trim(), &prefix(workspace)?))
+}
+
+/// Converts the URL of a remote to the web URL of the repository, e.g. `git@github.com:kennytm/cov.git` to
+/// `https://github.com/kennytm/cov`. Returns `None` if the remote is a local path.
+fn web_url(remote_url: &str) -> Option<String> {
+    let url = remote_url.trim().trim_end_matches('/');
+    let url = url.trim_end_matches(".git");
+    let (authority, path) = match url.find("://") {
+        Some(index) => {
+            let mut parts = url[index + 3..].splitn(2, '/');
+            (parts.next()?, parts.next()?)
+        },
+        // The scp-like syntax `user@host:path`.
+        None => {
+            let mut parts = url.splitn(2, ':');
+            (parts.next()?, parts.next()?)
+        },
+    };
+    // Drop the user and the port, which the web host does not use. A single letter is a Windows drive, e.g. `C:\repo`.
+    let host = authority.rsplit('@').next()?.split(':').next()?;
+    if host.len() <= 1 || path.is_empty() || url.starts_with("file://") {
+        return None;
+    }
+    Some(format!("https://{}/{}", host, path.trim_start_matches('/')))
+}
+
+/// Forms the URL of the directory `prefix` (empty or ending with `/`) at `commit` in the repository at `base`.
+fn blob_url(base: &str, commit: &str, prefix: &str) -> String {
+    // GitLab serves the files under `/-/blob/`, GitHub, Gitea and most others under `/blob/`.
+    let blob = if base.contains("gitlab") { "-/blob" } else { "blob" };
+    format!("{}/{}/{}/{}", base, blob, commit, prefix)
+}
+
+/// Parses the new line numbers of every hunk of a `git diff --unified=0` output.
+fn parse_diff(output: &str) -> BTreeMap<String, BTreeSet<u32>> {
+    let mut changes = BTreeMap::new();
+    let mut current = None;
+    for line in output.lines() {
+        if line.starts_with("+++ ") {
+            current = line.get(4..).and_then(|path| if path.starts_with("b/") { Some(path[2..].to_owned()) } else { None });
+        } else if line.starts_with("@@ ") {
+            let path = match current {
+                Some(ref path) => path,
+                None => continue,
+            };
+            // `@@ -12,3 +14,5 @@ context`, where the count defaults to 1 if omitted.
+            let new_range = line.split(' ').nth(2).filter(|r| r.starts_with('+')).map(|r| &r[1..]);
+            if let Some(new_range) = new_range {
+                let mut parts = new_range.splitn(2, ',');
+                let start = parts.next().and_then(|s| s.parse::<u32>().ok()).unwrap_or(0);
+                let count = parts.next().map_or(Some(1), |s| s.parse::<u32>().ok()).unwrap_or(0);
+                changes.entry(path.clone()).or_insert_with(BTreeSet::new).extend(start..start + count);
+            }
+        }
+    }
+    changes
+}
+
+/// Parses the hunks of every file of a `git diff` output.
+fn parse_hunks(output: &str) -> Vec<DiffFile> {
+    fn parse_start(range: Option<&str>) -> u32 {
+        range.and_then(|r| r[1..].split(',').next()).and_then(|s| s.parse().ok()).unwrap_or(0)
+    }
+
+    let mut files = Vec::<DiffFile>::new();
+    // The file the hunks belong to (`None` if deleted), and the next old and new line numbers.
+    let mut current = None;
+    let (mut old, mut new) = (0, 0);
+    for line in output.lines() {
+        if line.starts_with("diff --git ") {
+            current = None;
+        } else if line.starts_with("+++ ") && current.is_none() {
+            if let Some(path) = line.get(4..).and_then(|path| if path.starts_with("b/") { Some(&path[2..]) } else { None }) {
+                files.push(DiffFile { path: path.to_owned(), hunks: Vec::new() });
+                current = Some(files.len() - 1);
+            }
+        } else if line.starts_with("@@ ") {
+            let file = match current {
+                Some(i) => &mut files[i],
+                None => continue,
+            };
+            let mut ranges = line.split(' ').skip(1);
+            old = parse_start(ranges.next().filter(|r| r.starts_with('-')));
+            new = parse_start(ranges.next().filter(|r| r.starts_with('+')));
+            file.hunks.push(Hunk { header: line.to_owned(), lines: Vec::new() });
+        } else if let Some(hunk) = current.and_then(|i| files[i].hunks.last_mut()) {
+            let (kind, old_line, new_line) = match line.chars().next() {
+                Some('+') => (DiffLineKind::Added, None, Some(new)),
+                Some('-') => (DiffLineKind::Removed, Some(old), None),
+                Some(' ') => (DiffLineKind::Context, Some(old), Some(new)),
+                // `\ No newline at end of file`.
+                _ => continue,
+            };
+            old += old_line.is_some() as u32;
+            new += new_line.is_some() as u32;
+            hunk.lines.push(DiffLine {
+                kind,
+                old: old_line,
+                new: new_line,
+                text: line[1..].to_owned(),
+            });
+        }
+    }
+    files.retain(|file| !file.hunks.is_empty());
+    files
+}
+
+/// Parses the output of `git log -1 --format=%H%n%an%n%ae%n%cn%n%ce%n%s`.
+fn parse_head(output: &str) -> Option<Commit> {
+    let mut lines = output.lines();
+    let mut next = || lines.next().map(str::to_owned);
+    Some(Commit {
+        id: next().filter(|id| !id.is_empty())?,
+        author_name: next()?,
+        author_email: next()?,
+        committer_name: next()?,
+        committer_email: next()?,
+        message: next().unwrap_or_default(),
+    })
+}
+
+/// Parses the output of `git config --get-regexp '^remote\..*\.url$'`, e.g. `remote.origin.url https://...`.
+fn parse_remotes(output: &str) -> Vec<Remote> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(2, ' ');
+            let key = parts.next()?;
+            let url = parts.next()?;
+            let name = key.trim_start_matches("remote.").trim_end_matches(".url");
+            Some(Remote {
+                name: name.to_owned(),
+                url: url.to_owned(),
+            })
+        })
+        .collect()
+}
+
+#[test]
+fn test_parse() {
+    let head = parse_head("0123abcd\nAlice\nalice@example.com\nBob\nbob@example.com\nFix the frobnicator\n").unwrap();
+    assert_eq!(head.id, "0123abcd");
+    assert_eq!(head.author_name, "Alice");
+    assert_eq!(head.committer_email, "bob@example.com");
+    assert_eq!(head.message, "Fix the frobnicator");
+    assert_eq!(parse_head(""), None);
+
+    let remotes = parse_remotes("remote.origin.url https://github.com/kennytm/cov\nremote.my.fork.url git@example.com:cov.git\n");
+    assert_eq!(
+        remotes,
+        vec![
+            Remote {
+                name: "origin".to_owned(),
+                url: "https://github.com/kennytm/cov".to_owned(),
+            },
+            Remote {
+                name: "my.fork".to_owned(),
+                url: "git@example.com:cov.git".to_owned(),
+            },
+        ]
+    );
+
+    let diff = parse_diff(
+        "diff --git a/src/lib.rs b/src/lib.rs
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -3 +3,2 @@ fn main() {
+-    old();
++    new();
++    newer();
+@@ -10,2 +11,0 @@
+-    gone();
+-    gone();
+@@ -20,0 +20 @@
++    added();
+diff --git a/old.rs b/old.rs
+--- a/old.rs
++++ /dev/null
+@@ -1 +0,0 @@
+-    removed();
+",
+    );
+    assert_eq!(diff.len(), 1);
+    assert_eq!(diff["src/lib.rs"].iter().cloned().collect::<Vec<_>>(), vec![3, 4, 20]);
+}
+
+#[test]
+fn test_permalink() {
+    assert_eq!(web_url("https://github.com/kennytm/cov\n"), Some("https://github.com/kennytm/cov".to_owned()));
+    assert_eq!(web_url("git@github.com:kennytm/cov.git"), Some("https://github.com/kennytm/cov".to_owned()));
+    assert_eq!(web_url("ssh://git@gitlab.example.com:2222/group/cov.git"), Some("https://gitlab.example.com/group/cov".to_owned()));
+    assert_eq!(web_url("/srv/git/cov.git"), None);
+    assert_eq!(web_url("file:///srv/git/cov.git"), None);
+    assert_eq!(web_url(r"C:\git\cov"), None);
+
+    assert_eq!(blob_url("https://github.com/kennytm/cov", "0123abcd", ""), "https://github.com/kennytm/cov/blob/0123abcd/");
+    assert_eq!(blob_url("https://gitlab.com/group/cov", "0123abcd", "cov/"), "https://gitlab.com/group/cov/-/blob/0123abcd/cov/");
+}
+
+#[test]
+fn test_parse_hunks() {
+    let files = parse_hunks(
+        "diff --git a/src/lib.rs b/src/lib.rs
+index 0123456..789abcd 100644
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -2,3 +2,3 @@ fn main() {
+     keep();
+-    old();
++    new();
+ }
+\\ No newline at end of file
+diff --git a/old.rs b/old.rs
+--- a/old.rs
++++ /dev/null
+@@ -1 +0,0 @@
+-    removed();
+diff --git a/logo.png b/logo.png
+Binary files a/logo.png and b/logo.png differ
+",
+    );
+    let line = |kind, old, new, text: &str| DiffLine { kind, old, new, text: text.to_owned() };
+    assert_eq!(
+        files,
+        vec![
+            DiffFile {
+                path: "src/lib.rs".to_owned(),
+                hunks: vec![
+                    Hunk {
+                        header: "@@ -2,3 +2,3 @@ fn main() {".to_owned(),
+                        lines: vec![
+                            line(DiffLineKind::Context, Some(2), Some(2), "    keep();"),
+                            line(DiffLineKind::Removed, Some(3), None, "    old();"),
+                            line(DiffLineKind::Added, None, Some(3), "    new();"),
+                            line(DiffLineKind::Context, Some(4), Some(4), "}"),
+                        ],
+                    },
+                ],
+            },
+        ]
+    );
+}
@@ -0,0 +1,446 @@
+//! Syntax highlighting of the source code in the file pages.
+//!
+//! When the `[files]` section of a template has `highlight = true`, every line of the file page also includes
+//! `highlighted`, the source line as HTML where the tokens are wrapped in `<span>` elements of these classes:
+//!
+//! | Class | Token |
+//! |-------|-------|
+//! | `hl-comment` | Line and block comments |
+//! | `hl-string` | String, byte string and character literals |
+//! | `hl-number` | Numeric literals |
+//! | `hl-keyword` | Keywords of the [`Language`] |
+//! | `hl-lifetime` | Rust lifetimes and labels |
+//! | `hl-macro` | Rust macro invocations, e.g. `println!` |
+//! | `hl-attribute` | Rust attributes and C preprocessor directives |
+//!
+//! The lexer only needs to tell these tokens apart, so it is much simpler than a real one. It keeps its state from one
+//! line to the next, so block comments and strings spanning several lines are highlighted as a whole.
+//!
+//! When several functions share a line (e.g. one-liners, closures or code generated by macros), the parts of the line
+//! belonging to each function are further wrapped by [`wrap_regions()`] in `<span>` elements of the class `rg-covered`
+//! or `rg-zero`, depending on whether the function has been called.
+//!
+//! [`Language`]: ../sourcepath/enum.Language.html
+//! [`wrap_regions()`]: ./fn.wrap_regions.html
+
+use sourcepath::Language;
+
+use tera::escape_html;
+
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern", "false", "fn", "for", "if", "impl", "in", "let",
+    "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true", "type", "unsafe",
+    "use", "where", "while", "yield",
+];
+
+const C_KEYWORDS: &[&str] = &[
+    "auto", "bool", "break", "case", "catch", "char", "class", "const", "constexpr", "continue", "default", "delete", "do", "double", "else", "enum",
+    "explicit", "extern", "false", "float", "for", "friend", "goto", "if", "inline", "int", "long", "mutable", "namespace", "new", "noexcept",
+    "nullptr", "operator", "private", "protected", "public", "register", "restrict", "return", "short", "signed", "sizeof", "static", "struct",
+    "switch", "template", "this", "throw", "true", "try", "typedef", "typename", "union", "unsigned", "using", "virtual", "void", "volatile", "while",
+];
+
+/// A part of a source line, executed `count` times.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Region {
+    /// The 1-based column (in bytes) where the region starts.
+    pub start: usize,
+    /// The 1-based column (in bytes) where the region ends, inclusive.
+    pub end: usize,
+    /// Number of times the region is executed.
+    pub count: u64,
+}
+
+/// The token which continues to the next line.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum State {
+    Code,
+    /// Inside a block comment, with the nesting depth (block comments nest in Rust).
+    BlockComment(usize),
+    /// Inside a string literal.
+    Str,
+    /// Inside a Rust raw string literal, with the number of `#` around it.
+    RawStr(usize),
+}
+
+/// Highlights the lines of a source file, in order.
+#[derive(Debug)]
+pub struct Highlighter {
+    language: Language,
+    state: State,
+}
+
+/// Whether the byte can be part of an identifier. Non-ASCII bytes are treated as identifier characters, so a token is
+/// never split inside a UTF-8 sequence.
+fn is_ident_byte(b: u8) -> bool {
+    b == b'_' || b.is_ascii_alphanumeric() || b >= 0x80
+}
+
+/// Appends `text` wrapped in a `<span>` of the class `hl-<class>`.
+fn push_span(out: &mut String, class: &str, text: &str) {
+    if !text.is_empty() {
+        out.push_str("<span class=\"hl-");
+        out.push_str(class);
+        out.push_str("\">");
+        out.push_str(&escape_html(text));
+        out.push_str("</span>");
+    }
+}
+
+impl Highlighter {
+    /// Creates a highlighter for the first line of a file.
+    pub fn new(language: Language) -> Highlighter {
+        Highlighter { language, state: State::Code }
+    }
+
+    /// Highlights the next line of the file, which should not include the line terminator.
+    pub fn line(&mut self, source: &str) -> String {
+        let bytes = source.as_bytes();
+        let mut out = String::with_capacity(source.len() * 2);
+        let mut i = self.continue_token(source, &mut out);
+        let mut plain_start = i;
+
+        macro_rules! span {
+            ($class:expr, $end:expr) => {{
+                let end = $end;
+                out.push_str(&escape_html(&source[plain_start..i]));
+                push_span(&mut out, $class, &source[i..end]);
+                i = end;
+                plain_start = end;
+            }};
+        }
+
+        while i < bytes.len() {
+            let rest = &source[i..];
+            let b = bytes[i];
+            if rest.starts_with("//") {
+                span!("comment", bytes.len());
+            } else if rest.starts_with("/*") {
+                span!("comment", self.block_comment_end(source, i + 2, 1));
+            } else if b == b'"' {
+                span!("string", self.string_end(source, i + 1));
+            } else if b == b'\'' {
+                match self.quote_end(source, i) {
+                    (end, true) => span!("string", end),
+                    (end, false) => span!("lifetime", end),
+                }
+            } else if b == b'#' && self.language == Language::C && source[..i].trim().is_empty() {
+                span!("attribute", bytes.len());
+            } else if b == b'#' && self.language == Language::Rust && (rest.starts_with("#[") || rest.starts_with("#![")) {
+                span!("attribute", attribute_end(source, i));
+            } else if b.is_ascii_digit() {
+                span!("number", number_end(source, i));
+            } else if is_ident_byte(b) {
+                let end = i + bytes[i..].iter().take_while(|&&b| is_ident_byte(b)).count();
+                let ident = &source[i..end];
+                if let Some(hashes) = self.raw_string_prefix(ident, &source[end..]) {
+                    span!("string", self.raw_string_end(source, end + hashes + 1, hashes));
+                } else if self.language == Language::Rust && ident == "b" && source[end..].starts_with('\'') {
+                    span!("string", self.quote_end(source, end).0);
+                } else if self.language == Language::Rust && ident == "b" && source[end..].starts_with('"') {
+                    span!("string", self.string_end(source, end + 1));
+                } else if self.keywords().contains(&ident) {
+                    span!("keyword", end);
+                } else if self.language == Language::Rust && source[end..].starts_with('!') && !source[end..].starts_with("!=") {
+                    span!("macro", end + 1);
+                } else {
+                    i = end;
+                }
+            } else {
+                i += 1;
+            }
+        }
+        out.push_str(&escape_html(&source[plain_start..]));
+        out
+    }
+
+    /// Whether the last highlighted line ends inside a string or a block comment, i.e. a comment appended to the line
+    /// would not be a comment.
+    pub fn is_continued(&self) -> bool {
+        self.state != State::Code
+    }
+
+    fn keywords(&self) -> &'static [&'static str] {
+        match self.language {
+            Language::Rust => RUST_KEYWORDS,
+            Language::C => C_KEYWORDS,
+        }
+    }
+
+    /// Highlights the token continued from the previous line, and returns where it ends.
+    fn continue_token(&mut self, source: &str, out: &mut String) -> usize {
+        match self.state {
+            State::Code => 0,
+            State::BlockComment(depth) => {
+                let end = self.block_comment_end(source, 0, depth);
+                push_span(out, "comment", &source[..end]);
+                end
+            },
+            State::Str => {
+                let end = self.string_end(source, 0);
+                push_span(out, "string", &source[..end]);
+                end
+            },
+            State::RawStr(hashes) => {
+                let end = self.raw_string_end(source, 0, hashes);
+                push_span(out, "string", &source[..end]);
+                end
+            },
+        }
+    }
+
+    /// Finds the end of a block comment starting at `start` inside `depth` levels of comments.
+    fn block_comment_end(&mut self, source: &str, mut start: usize, mut depth: usize) -> usize {
+        while let Some(j) = source[start..].find(&['*', '/'][..]) {
+            let rest = &source[start + j..];
+            if rest.starts_with("*/") {
+                start += j + 2;
+                depth -= 1;
+                if depth == 0 {
+                    self.state = State::Code;
+                    return start;
+                }
+            } else if rest.starts_with("/*") && self.language == Language::Rust {
+                start += j + 2;
+                depth += 1;
+            } else {
+                start += j + 1;
+            }
+        }
+        self.state = State::BlockComment(depth);
+        source.len()
+    }
+
+    /// Finds the end of a string literal whose content starts at `start`.
+    fn string_end(&mut self, source: &str, start: usize) -> usize {
+        let bytes = source.as_bytes();
+        let mut i = start;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'\\' => i += 2,
+                b'"' => {
+                    self.state = State::Code;
+                    return i + 1;
+                },
+                _ => i += 1,
+            }
+        }
+        // Rust strings may contain line breaks, but C strings only continue after a backslash.
+        let continued = self.language == Language::Rust || source.ends_with('\\');
+        self.state = if continued { State::Str } else { State::Code };
+        source.len()
+    }
+
+    /// Returns the number of `#` if `ident` followed by `rest` starts a raw string literal, e.g. `r#"`.
+    fn raw_string_prefix(&self, ident: &str, rest: &str) -> Option<usize> {
+        if self.language != Language::Rust || (ident != "r" && ident != "br") {
+            return None;
+        }
+        let hashes = rest.bytes().take_while(|&b| b == b'#').count();
+        if rest[hashes..].starts_with('"') {
+            Some(hashes)
+        } else {
+            None
+        }
+    }
+
+    /// Finds the end of a raw string literal whose content starts at `start`.
+    fn raw_string_end(&mut self, source: &str, start: usize, hashes: usize) -> usize {
+        let mut terminator = String::from("\"");
+        terminator.extend((0..hashes).map(|_| '#'));
+        match source.get(start..).and_then(|rest| rest.find(&*terminator)) {
+            Some(j) => {
+                self.state = State::Code;
+                start + j + terminator.len()
+            },
+            None => {
+                self.state = State::RawStr(hashes);
+                source.len()
+            },
+        }
+    }
+
+    /// Finds the end of the token starting with the `'` at `start`, and whether it is a character literal instead of a
+    /// lifetime.
+    fn quote_end(&self, source: &str, start: usize) -> (usize, bool) {
+        let bytes = source.as_bytes();
+        let content = start + 1;
+        if self.language == Language::Rust {
+            // `'a'` and `'\n'` are characters, while `'a` is a lifetime.
+            let first_len = source[content..].chars().next().map_or(0, char::len_utf8);
+            let is_char = bytes.get(content) == Some(&b'\\') || bytes.get(content + first_len) == Some(&b'\'');
+            if !is_char {
+                let end = content + bytes[content..].iter().take_while(|&&b| is_ident_byte(b)).count();
+                return (end, false);
+            }
+        }
+        let mut i = content;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'\\' => i += 2,
+                b'\'' => return (i + 1, true),
+                _ => i += 1,
+            }
+        }
+        (bytes.len(), true)
+    }
+}
+
+/// Finds the end of a Rust attribute starting at `start`, or the end of the line if it spans several lines.
+fn attribute_end(source: &str, start: usize) -> usize {
+    let mut depth = 0;
+    for (j, b) in source[start..].bytes().enumerate() {
+        match b {
+            b'[' => depth += 1,
+            b']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return start + j + 1;
+                }
+            },
+            _ => {},
+        }
+    }
+    source.len()
+}
+
+/// Finds the end of a numeric literal starting at `start`, including its suffix, e.g. `1_000u32` or `0.5f64`.
+fn number_end(source: &str, start: usize) -> usize {
+    let bytes = source.as_bytes();
+    let mut i = start;
+    while i < bytes.len() {
+        let b = bytes[i];
+        // Excludes the ranges like `0..n` and the method calls like `1.max(2)`.
+        let is_fraction = b == b'.' && bytes.get(i + 1).map_or(false, u8::is_ascii_digit);
+        if is_ident_byte(b) || is_fraction {
+            i += 1;
+        } else {
+            break;
+        }
+    }
+    i
+}
+
+/// Wraps the parts of the `html` of a source line (produced by [`Highlighter::line()`] or `escape_html()`) covered by
+/// the `regions` in `<span>` elements of the class `rg-covered` or `rg-zero`. The `<span>` of a token crossing the
+/// boundary of a region is split in two, so the elements are still properly nested.
+///
+/// [`Highlighter::line()`]: ./struct.Highlighter.html#method.line
+pub fn wrap_regions(html: &str, regions: &[Region]) -> String {
+    let mut out = String::with_capacity(html.len() + regions.len() * 40);
+    // The start tag of the token being written is only written before its first character, after the region changes.
+    let mut token_tag = None;
+    let mut token_open = false;
+    let mut current = None;
+    let mut column = 1;
+    let mut i = 0;
+    while i < html.len() {
+        let rest = &html[i..];
+        if rest.starts_with('<') {
+            let end = rest.find('>').map_or(html.len(), |j| i + j + 1);
+            let tag = &html[i..end];
+            if tag.starts_with("</") {
+                if token_open {
+                    out.push_str(tag);
+                }
+                token_tag = None;
+            } else {
+                token_tag = Some(tag);
+            }
+            token_open = false;
+            i = end;
+            continue;
+        }
+
+        let region = regions.iter().find(|r| r.start <= column && column <= r.end);
+        if region != current {
+            if token_open {
+                out.push_str("</span>");
+                token_open = false;
+            }
+            if current.is_some() {
+                out.push_str("</span>");
+            }
+            if let Some(region) = region {
+                let class = if region.count > 0 { "rg-covered" } else { "rg-zero" };
+                out.push_str(&format!("<span class=\"{}\" title=\"{}\">", class, region.count));
+            }
+            current = region;
+        }
+        if let (Some(tag), false) = (token_tag, token_open) {
+            out.push_str(tag);
+            token_open = true;
+        }
+
+        // An escaped character is always a single ASCII byte in the source.
+        let (len, source_len) = if rest.starts_with('&') {
+            (rest.find(';').map_or(rest.len(), |j| j + 1), 1)
+        } else {
+            let c = rest.chars().next().expect("non-empty");
+            (c.len_utf8(), c.len_utf8())
+        };
+        out.push_str(&rest[..len]);
+        i += len;
+        column += source_len;
+    }
+    if current.is_some() {
+        out.push_str("</span>");
+    }
+    out
+}
+
+#[test]
+fn test_highlight() {
+    let mut highlighter = Highlighter::new(Language::Rust);
+    let mut highlight = |line| highlighter.line(line);
+    assert_eq!(
+        highlight("#[test] fn f<'a>(x: &'a str) -> u8 { /* a < b */"),
+        "<span class=\"hl-attribute\">#[test]</span> <span class=\"hl-keyword\">fn</span> f&lt;<span class=\"hl-lifetime\">&#x27;a</span>&gt;(x: \
+         &amp;<span class=\"hl-lifetime\">&#x27;a</span> str) -&gt; u8 { <span class=\"hl-comment\">&#x2F;* a &lt; b *&#x2F;</span>"
+    );
+    assert_eq!(
+        highlight("    println!(\"{}\", 'x'); let s = r#\"raw"),
+        "    <span class=\"hl-macro\">println!</span>(<span class=\"hl-string\">&quot;{}&quot;</span>, <span class=\"hl-string\">&#x27;x&#x27;</span>); \
+         <span class=\"hl-keyword\">let</span> s = <span class=\"hl-string\">r#&quot;raw</span>"
+    );
+    assert_eq!(
+        highlight("string\"# + 1.5e3 + 0..2; /* outer /* inner */"),
+        "<span class=\"hl-string\">string&quot;#</span> + <span class=\"hl-number\">1.5e3</span> + <span class=\"hl-number\">0</span>..\
+         <span class=\"hl-number\">2</span>; <span class=\"hl-comment\">&#x2F;* outer &#x2F;* inner *&#x2F;</span>"
+    );
+    assert_eq!(highlight("still comment */ x != y"), "<span class=\"hl-comment\">still comment *&#x2F;</span> x != y");
+
+    let mut highlighter = Highlighter::new(Language::C);
+    assert_eq!(highlighter.line("  #include <stdio.h>"), "  <span class=\"hl-attribute\">#include &lt;stdio.h&gt;</span>");
+    assert_eq!(
+        highlighter.line("int x = 'a'; /* a /* b */ // c"),
+        "<span class=\"hl-keyword\">int</span> x = <span class=\"hl-string\">&#x27;a&#x27;</span>; <span class=\"hl-comment\">&#x2F;* a &#x2F;* b *&#x2F;</span> \
+         <span class=\"hl-comment\">&#x2F;&#x2F; c</span>"
+    );
+}
+
+#[test]
+fn test_wrap_regions() {
+    let mut highlighter = Highlighter::new(Language::C);
+    let html = highlighter.line("int f() { return 1; } int g() { return 0; }");
+    let regions = [
+        Region { start: 5, end: 21, count: 3 },
+        Region { start: 27, end: 43, count: 0 },
+    ];
+    assert_eq!(
+        wrap_regions(&html, &regions),
+        "<span class=\"hl-keyword\">int</span> <span class=\"rg-covered\" title=\"3\">f() { <span class=\"hl-keyword\">return</span> \
+         <span class=\"hl-number\">1</span>; }</span> <span class=\"hl-keyword\">int</span> <span class=\"rg-zero\" title=\"0\">g() { \
+         <span class=\"hl-keyword\">return</span> <span class=\"hl-number\">0</span>; }</span>"
+    );
+
+    // A region boundary inside a token splits its `<span>`.
+    let regions = [Region { start: 1, end: 2, count: 1 }];
+    assert_eq!(
+        wrap_regions(&html, &regions),
+        "<span class=\"rg-covered\" title=\"1\"><span class=\"hl-keyword\">in</span></span><span class=\"hl-keyword\">t</span> f() { \
+         <span class=\"hl-keyword\">return</span> <span class=\"hl-number\">1</span>; } <span class=\"hl-keyword\">int</span> g() { \
+         <span class=\"hl-keyword\">return</span> <span class=\"hl-number\">0</span>; }"
+    );
+    assert_eq!(wrap_regions("a &lt; b", &[Region { start: 3, end: 3, count: 0 }]), "a <span class=\"rg-zero\" title=\"0\">&lt;</span> b");
+}
@@ -0,0 +1,97 @@
+//! Coverage history.
+//!
+//! When `cargo cov report --history` is used, the summary of every source file shown in the report is appended to
+//! `target/cov/history.json`, so the HTML summary can show how the coverage of each file changed over time. Only the
+//! latest snapshots are kept, 100 unless changed by `--history-limit`.
+
+use error::{Result, ResultExt};
+use utils::{percent, write_json_atomically};
+
+use cov::report::FileSummary;
+use serde_json::from_reader;
+
+use std::collections::BTreeMap;
+use std::fs::{File, create_dir_all};
+use std::io::ErrorKind as IoErrorKind;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Maximum number of entries used for a trend.
+const TREND_LENGTH: usize = 30;
+
+/// The default number of snapshots kept in the history.
+pub const DEFAULT_HISTORY_LIMIT: usize = 100;
+
+/// A snapshot of the coverage when a report was generated.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// Seconds since the Unix epoch when the report was generated.
+    pub timestamp: u64,
+    /// Summary of all files, keyed by their source paths.
+    pub files: BTreeMap<String, FileSummary>,
+}
+
+/// The recorded coverage history.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct History {
+    /// Snapshots in chronological order.
+    pub entries: Vec<HistoryEntry>,
+}
+
+impl History {
+    /// Reads the history from `path`. Returns an empty history if the file does not exist yet.
+    pub fn open(path: &Path) -> Result<History> {
+        match File::open(path) {
+            Ok(file) => Ok(from_reader(file).chain_err(|| format!("Cannot parse history at `{}`", path.display()))?),
+            Err(ref e) if e.kind() == IoErrorKind::NotFound => Ok(History::default()),
+            Err(e) => Err(e).chain_err(|| format!("Cannot read history at `{}`", path.display())),
+        }
+    }
+
+    /// Writes the history to `path`, replacing the file atomically. Of two concurrent reports, the snapshot of one may
+    /// be lost, but the file is never corrupted.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)?;
+        }
+        write_json_atomically(path, self).chain_err(|| format!("Cannot write history at `{}`", path.display()))
+    }
+
+    /// Appends a snapshot of the given files, timestamped with the current time. The oldest snapshots are dropped so
+    /// at most `limit` of them are kept.
+    pub fn record<'a, I: IntoIterator<Item = (&'a str, FileSummary)>>(&mut self, files: I, limit: usize) {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        let files = files.into_iter().map(|(path, summary)| (path.to_owned(), summary)).collect();
+        self.entries.push(HistoryEntry { timestamp, files });
+        let excess = self.entries.len().saturating_sub(limit);
+        self.entries.drain(..excess);
+    }
+
+    /// Obtains the line coverage percentages of a file in the most recent snapshots, oldest first.
+    ///
+    /// Snapshots without the file are skipped.
+    pub fn line_trend(&self, path: &str) -> Vec<f64> {
+        let mut trend = self.entries
+            .iter()
+            .rev()
+            .filter_map(|entry| entry.files.get(path))
+            .take(TREND_LENGTH)
+            .map(|s| percent(s.lines_covered, s.lines_count))
+            .collect::<Vec<_>>();
+        trend.reverse();
+        trend
+    }
+}
+
+#[test]
+fn test_record_limit() {
+    let mut history = History::default();
+    for lines_covered in 0..5 {
+        let mut summary = FileSummary::default();
+        summary.lines_count = 4;
+        summary.lines_covered = lines_covered;
+        history.record(vec![("src/lib.rs", summary)], 3);
+    }
+    assert_eq!(history.entries.len(), 3);
+    assert_eq!(history.line_trend("src/lib.rs"), vec![50.0, 75.0, 100.0]);
+}
@@ -0,0 +1,125 @@
+//! Commands run before and after the phases of `cargo cov`.
+//!
+//! Custom collection or publication steps (e.g. starting an emulator, uploading the report) can be injected by hooks
+//! configured in the workspace's `Cargo.toml`:
+//!
+//! ```toml
+//! [package.metadata.cov.hooks]
+//! pre-test = ["./scripts/start-emulator.sh"]
+//! post-report = ["python3", "scripts/publish.py", "--bucket", "coverage"]
+//! ```
+//!
+//! The hooks are `pre-build`, `post-build`, `pre-test`, `post-test`, `pre-run`, `post-run`, `pre-report` and
+//! `post-report`. Each is a program followed by its arguments, which runs in the directory of `Cargo.toml` with the
+//! environment variable `COV_HOOK` set to the hook name. The context is written to its `stdin` as a JSON object:
+//!
+//! ```json
+//! {
+//!     "hook": "post-report",
+//!     "manifest_path": "/path/to/workspace/Cargo.toml",
+//!     "cov_build_path": "/path/to/workspace/target/cov/build",
+//!     "outcome": {"totals": {...}, "thresholds": [...], "written": [...], ...}
+//! }
+//! ```
+//!
+//! The build hooks receive `subcommand`, `target` and `runner` instead of `outcome`. `pre-report` receives the resolved
+//! configuration as `config`, and `post-report` the [`ReportOutcome`], before the thresholds are enforced.
+//!
+//! A hook exiting with a non-zero status fails the whole command. The report hooks only run if `cargo cov report` can
+//! locate the workspace's `Cargo.toml`.
+//!
+//! [`ReportOutcome`]: ../outcome/struct.ReportOutcome.html
+
+use error::{ErrorKind, Result, ResultExt};
+
+use serde_json::{Value, to_writer};
+
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// The hooks configured in `Cargo.toml`.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Hooks {
+    /// Runs before `cargo cov build`.
+    pub pre_build: Option<Vec<String>>,
+    /// Runs after `cargo cov build`.
+    pub post_build: Option<Vec<String>>,
+    /// Runs before `cargo cov test`.
+    pub pre_test: Option<Vec<String>>,
+    /// Runs after `cargo cov test`.
+    pub post_test: Option<Vec<String>>,
+    /// Runs before `cargo cov run`.
+    pub pre_run: Option<Vec<String>>,
+    /// Runs after `cargo cov run`.
+    pub post_run: Option<Vec<String>>,
+    /// Runs before `cargo cov report`.
+    pub pre_report: Option<Vec<String>>,
+    /// Runs after `cargo cov report`.
+    pub post_report: Option<Vec<String>>,
+}
+
+impl Hooks {
+    /// Obtains the command of a hook, e.g. `"pre-test"`. Returns `None` if the hook is not configured.
+    pub fn command(&self, hook: &str) -> Option<&[String]> {
+        let command = match hook {
+            "pre-build" => &self.pre_build,
+            "post-build" => &self.post_build,
+            "pre-test" => &self.pre_test,
+            "post-test" => &self.post_test,
+            "pre-run" => &self.pre_run,
+            "post-run" => &self.post_run,
+            "pre-report" => &self.pre_report,
+            "post-report" => &self.post_report,
+            _ => &None,
+        };
+        command.as_ref().map(|c| &**c).filter(|c| !c.is_empty())
+    }
+
+    /// Runs a hook if it is configured, writing `context` to its `stdin`.
+    ///
+    /// The `hook` name is inserted into `context`, which should be a JSON object.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`HookFailed`] if the hook exits with a non-zero status.
+    ///
+    /// [`HookFailed`]: ../error/enum.ErrorKind.html#variant.HookFailed
+    pub fn run(&self, hook: &str, manifest_path: &Path, mut context: Value) -> Result<()> {
+        let command = match self.command(hook) {
+            Some(command) => command,
+            None => return Ok(()),
+        };
+        if let Value::Object(ref mut map) = context {
+            map.insert("hook".to_owned(), Value::from(hook));
+        }
+
+        let mut cmd = Command::new(&command[0]);
+        cmd.args(&command[1..]).env("COV_HOOK", hook).stdin(Stdio::piped());
+        if let Some(dir) = manifest_path.parent() {
+            cmd.current_dir(dir);
+        }
+        progress!("Hook", "{} {:?}", hook, cmd);
+
+        let mut child = cmd.spawn().chain_err(|| format!("Cannot run the {} hook", hook))?;
+        {
+            let mut stdin = child.stdin.take().expect("stdin");
+            // The hook may exit without reading its input, which is fine.
+            let _ = to_writer(&mut stdin, &context);
+            let _ = stdin.write_all(b"\n");
+        }
+        let status = child.wait()?;
+        ensure!(status.success(), ErrorKind::HookFailed(hook.to_owned(), status));
+        Ok(())
+    }
+}
+
+#[test]
+fn test_command() {
+    let hooks: Hooks = ::toml::from_str("pre-test = [\"echo\", \"hi\"]\npost-report = []\n").unwrap();
+    assert_eq!(hooks.command("pre-test"), Some(&["echo".to_owned(), "hi".to_owned()][..]));
+    assert_eq!(hooks.command("post-report"), None);
+    assert_eq!(hooks.command("post-test"), None);
+    assert_eq!(hooks.command("unknown"), None);
+}
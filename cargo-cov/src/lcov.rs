@@ -0,0 +1,280 @@
+//! Importing LCOV tracefiles produced by other tools.
+//!
+//! A project rarely consists of Rust code alone. The C dependencies built by a `build.rs` with `gcc --coverage`, or the
+//! programs run under `kcov`, are usually reported by `lcov` or `kcov` as a tracefile in the LCOV format:
+//!
+//! ```text
+//! TN:
+//! SF:/path/to/workspace/vendor/zlib/inflate.c
+//! FN:102,inflateResetKeep
+//! FNDA:3,inflateResetKeep
+//! DA:104,3
+//! DA:105,0
+//! BRDA:104,0,0,1
+//! BRDA:104,0,1,2
+//! end_of_record
+//! ```
+//!
+//! `cargo cov report --import-lcov` merges such files into the report after the GCNO and GCDA files are analyzed, so
+//! the mixed-language coverage is summarized and rendered together. Relative source paths (`SF`) are resolved against
+//! the workspace. Records of a file already in the report are added up with it, the same as `lcov --add-tracefile`
+//! would do.
+//!
+//! The format knows nothing about basic blocks, so the summary of an imported function counts its instrumented lines
+//! as blocks. A function covers the lines until its end line (`FN:<line>,<end line>,<name>`), or else until the next
+//! function of the file. A branch which is never evaluated (`BRDA:<line>,<block>,<branch>,-`) counts as not taken.
+//!
+//! The imported data is merged after the report is cached, like `--only-path` is applied, so the cache of the
+//! previous report stays valid when a tracefile changes.
+
+use error::{ErrorKind, Result, ResultExt};
+
+use cov::{Interner, Report, Symbol};
+use cov::raw::ArcAttr;
+use cov::report::{Branch, File as FileReport, Function, FunctionSummary};
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::Path;
+use std::str::from_utf8;
+
+/// A branch of an LCOV record: the line, the block ID and the number of times it is taken (`None` if never evaluated).
+type LcovBranch = (u32, u32, Option<u64>);
+
+/// The coverage of a source file, from `SF` to `end_of_record`.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Record {
+    /// Path to the source file.
+    source: String,
+    /// Execution count of every line.
+    lines: BTreeMap<u32, u64>,
+    /// Start and end line (zero if missing) of every function, by name.
+    functions: BTreeMap<String, (u32, u32)>,
+    /// Number of calls of every function, by name.
+    function_counts: HashMap<String, u64>,
+    /// All branches, in the order of the file.
+    branches: Vec<LcovBranch>,
+}
+
+/// Parses a number in an LCOV record.
+fn parse_number<T: ::std::str::FromStr>(value: Option<&str>, line_number: usize, line: &str) -> Result<T> {
+    value
+        .and_then(|v| v.trim().parse().ok())
+        .ok_or_else(|| ErrorKind::InvalidLcov(line_number, line.to_owned()).into())
+}
+
+/// Parses the content of a tracefile. The summary records (`LF`, `LH`, `FNF` etc.) are recomputed by the report and
+/// ignored, as well as the records unknown to this version.
+fn parse(content: &str) -> Result<Vec<Record>> {
+    let mut records = Vec::new();
+    let mut record = None;
+    for (index, line) in content.lines().enumerate() {
+        let text = line.trim();
+        let line_number = index + 1;
+        if text == "end_of_record" {
+            records.extend(record.take());
+            continue;
+        }
+        let (tag, value) = match text.find(':') {
+            Some(colon) => (&text[..colon], &text[colon + 1..]),
+            None => continue,
+        };
+        if tag == "SF" {
+            records.extend(record.take());
+            record = Some(Record {
+                source: value.to_owned(),
+                ..Record::default()
+            });
+            continue;
+        }
+        let record = match record {
+            Some(ref mut record) => record,
+            None => continue,
+        };
+        let mut fields = value.split(',');
+        match tag {
+            "DA" => {
+                let line = parse_number(fields.next(), line_number, text)?;
+                let count: u64 = parse_number(fields.next(), line_number, text)?;
+                *record.lines.entry(line).or_insert(0) += count;
+            },
+            "FN" => {
+                // Either `FN:<line>,<name>`, or `FN:<line>,<end line>,<name>` since lcov 2.0.
+                let fields = value.splitn(3, ',').collect::<Vec<_>>();
+                let start = parse_number(fields.first().cloned(), line_number, text)?;
+                let (end, name) = match fields.len() {
+                    3 => (parse_number(Some(fields[1]), line_number, text)?, fields[2]),
+                    2 => (0, fields[1]),
+                    _ => bail!(ErrorKind::InvalidLcov(line_number, text.to_owned())),
+                };
+                record.functions.insert(name.to_owned(), (start, end));
+            },
+            "FNDA" => {
+                let count: u64 = parse_number(fields.next(), line_number, text)?;
+                let name = value.splitn(2, ',').nth(1).ok_or_else(|| ErrorKind::InvalidLcov(line_number, text.to_owned()))?;
+                *record.function_counts.entry(name.to_owned()).or_insert(0) += count;
+            },
+            "BRDA" => {
+                let line = parse_number(fields.next(), line_number, text)?;
+                let block = parse_number(fields.next(), line_number, text)?;
+                let _branch: u32 = parse_number(fields.next(), line_number, text)?;
+                let count = match fields.next().map(str::trim) {
+                    Some("-") => None,
+                    count => Some(parse_number(count, line_number, text)?),
+                };
+                record.branches.push((line, block, count));
+            },
+            _ => {},
+        }
+    }
+    // A truncated file still contributes its last record.
+    records.extend(record);
+    Ok(records)
+}
+
+/// Computes the summary of a function spanning `lines` of the `record`.
+fn summarize(record: &Record, lines: (u32, u32), count: u64) -> FunctionSummary {
+    let (start, end) = lines;
+    let in_range = |line: u32| start <= line && line <= end;
+    let executed_lines = record.lines.iter().filter(|&(&line, _)| in_range(line));
+    let branches = record.branches.iter().filter(|&&(line, _, _)| in_range(line)).collect::<Vec<_>>();
+    let blocks = branches.iter().map(|&&(line, block, _)| (line, block)).collect::<BTreeSet<_>>();
+    let mut summary = FunctionSummary::default();
+    summary.blocks_count = executed_lines.clone().count();
+    summary.blocks_executed = executed_lines.filter(|&(_, &count)| count > 0).count();
+    summary.entry_count = count;
+    summary.exit_count = count;
+    summary.branches_count = branches.len();
+    summary.branches_executed = branches.iter().filter(|&&&(_, _, count)| count.is_some()).map(|&&(line, block, _)| (line, block)).collect::<BTreeSet<_>>().len();
+    summary.branches_taken = branches.iter().filter(|&&&(_, _, count)| count.map_or(false, |c| c > 0)).count();
+    summary.complexity = 1 + branches.len() - blocks.len();
+    summary
+}
+
+/// Merges a parsed record into the coverage of a file.
+fn merge_record(file: &mut FileReport, record: &Record, filename: Symbol, interner: &mut Interner) {
+    for (&line, &count) in &record.lines {
+        file.lines.entry(line).or_insert_with(Default::default).count += count;
+    }
+
+    let mut branches_of_lines = BTreeMap::new();
+    for &(line, _, count) in &record.branches {
+        branches_of_lines.entry(line).or_insert_with(Vec::new).push(count.unwrap_or(0));
+    }
+    for (line, counts) in branches_of_lines {
+        let branches = &mut file.lines.entry(line).or_insert_with(Default::default).branches;
+        if branches.len() == counts.len() {
+            for (branch, count) in branches.iter_mut().zip(counts) {
+                branch.count += count;
+            }
+        } else {
+            branches.extend(counts.into_iter().map(|count| Branch {
+                count,
+                attr: ArcAttr::empty(),
+                filename,
+                line: 0,
+                column: 0,
+            }));
+        }
+    }
+
+    let starts = record.functions.values().map(|&(start, _)| start).collect::<BTreeSet<_>>();
+    for (name, &(start, end)) in &record.functions {
+        let count = record.function_counts.get(name).cloned().unwrap_or(0);
+        let name = interner.intern(name.as_str());
+        if let Some(function) = file.functions.iter_mut().find(|f| f.name == name) {
+            function.summary.entry_count += count;
+            function.summary.exit_count += count;
+            continue;
+        }
+        let last = if end > 0 {
+            end
+        } else {
+            starts.range(start + 1..).next().map_or(u32::max_value(), |&next| next - 1)
+        };
+        file.functions.push(Function {
+            name,
+            line: start,
+            column: 0,
+            end_line: end,
+            end_column: 0,
+            summary: summarize(record, (start, last), count),
+        });
+    }
+    file.functions.sort_by_key(|f| (f.line, f.column));
+}
+
+/// Merges the `content` of the LCOV tracefile at `path` into the `report`, see the [module documentation]. Returns the
+/// number of source files in the tracefile.
+///
+/// The content is read by [`signing::read()`], so it can be verified before it is parsed.
+///
+/// [module documentation]: ./index.html
+/// [`signing::read()`]: ../signing/fn.read.html
+pub fn import(path: &Path, content: &[u8], workspace_path: &Path, report: &mut Report, interner: &mut Interner) -> Result<usize> {
+    let records = from_utf8(content)
+        .chain_err(|| "the tracefile is not UTF-8")
+        .and_then(parse)
+        .chain_err(|| format!("Cannot parse `{}`", path.display()))?;
+    for record in &records {
+        let source_path = workspace_path.join(&record.source);
+        let symbol = interner.intern(&*source_path.to_string_lossy());
+        let file = report.files.entry(symbol).or_insert_with(Default::default);
+        merge_record(file, record, symbol, interner);
+    }
+    Ok(records.len())
+}
+
+#[test]
+fn test_import() {
+    let content = "\
+TN:
+SF:src/inflate.c
+FN:10,reset
+FN:20,29,inflate
+FNDA:3,reset
+FNDA:0,inflate
+FNF:2
+FNH:1
+DA:11,3
+DA:12,1
+DA:21,0
+DA:22,0
+BRDA:11,0,0,2
+BRDA:11,0,1,1
+BRDA:21,1,0,-
+BRDA:21,1,1,-
+LF:4
+LH:2
+end_of_record
+SF:/usr/include/stdio.h
+DA:1,1
+";
+    let records = parse(content).unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].source, "src/inflate.c");
+    assert_eq!(records[0].functions["inflate"], (20, 29));
+    assert_eq!(records[0].branches[2], (21, 1, None));
+    assert_eq!(records[1].lines.len(), 1);
+    assert!(parse("SF:a.c\nDA:x,1\n").is_err());
+
+    let path = Path::new("coverage.info");
+    let mut interner = Interner::new();
+    let mut report = Report::default();
+    assert_eq!(import(path, content.as_bytes(), Path::new("/workspace"), &mut report, &mut interner).unwrap(), 2);
+    assert_eq!(import(path, content.as_bytes(), Path::new("/workspace"), &mut report, &mut interner).unwrap(), 2);
+    assert!(import(path, b"SF:\xff\n", Path::new("/workspace"), &mut report, &mut interner).is_err());
+
+    let file = &report.files[&interner.intern("/workspace/src/inflate.c")];
+    assert_eq!(file.lines[&11].count, 6);
+    assert_eq!(file.lines[&11].branches.iter().map(|b| b.count).collect::<Vec<_>>(), vec![4, 2]);
+    assert_eq!(file.functions.len(), 2);
+    let reset = &file.functions[0];
+    assert_eq!(&interner[reset.name], "reset");
+    assert_eq!(reset.summary.entry_count, 6);
+    assert_eq!((reset.summary.blocks_count, reset.summary.blocks_executed), (2, 2));
+    assert_eq!((reset.summary.branches_count, reset.summary.branches_executed, reset.summary.branches_taken), (2, 1, 2));
+    assert_eq!(reset.summary.complexity, 2);
+    let inflate = &file.functions[1].summary;
+    assert_eq!((inflate.branches_count, inflate.branches_executed, inflate.branches_taken), (2, 0, 0));
+    assert!(report.files.contains_key(&interner.intern("/usr/include/stdio.h")));
+}
@@ -0,0 +1,768 @@
+//! `cargo-cov` is a cargo subcommand which performs source coverage collection and reporting for Rust crates.
+//! `cargo-cov` utilizes LLVM's gcov-compatible profile generation pass, and supports a lot of platforms.
+//!
+//! Please see the [crate README](https://github.com/kennytm/cov#readme) for detail.
+//!
+//! The subcommands which only read the coverage data (`report`, `blame-report`, `ls`, `export`, `annotate` and `show`) do
+//! not need a Cargo workspace when `--workspace` is given, e.g. to generate a report from the `*.gcno` and `*.gcda`
+//! files copied from CI:
+//!
+//! ```sh
+//! cargo cov report --workspace path/to/checkout --gcno artifacts/gcno --gcda artifacts/gcda --output report/
+//! ```
+//!
+//! The command line program is also available as a library. Wrappers can reuse the report configuration resolution
+//! via the [`config`](./config/index.html) module, or drive the subcommands in-process via the
+//! [`driver`](./driver/index.html) module.
+
+#![recursion_limit = "256"] // needed for error_chain.
+
+#![cfg_attr(feature = "cargo-clippy", warn(warnings, clippy_pedantic))]
+#![cfg_attr(feature = "cargo-clippy", allow(missing_docs_in_private_items, non_ascii_literal, shadow_reuse, unused_results))]
+// `unused_results` caused too many false positive here.
+
+#[macro_use]
+extern crate bitflags;
+#[macro_use]
+extern crate clap;
+#[macro_use]
+extern crate error_chain;
+#[macro_use]
+extern crate log;
+#[macro_use]
+extern crate serde_derive;
+#[macro_use]
+extern crate lazy_static;
+#[macro_use]
+extern crate serde_json;
+extern crate cov;
+extern crate cov_types;
+extern crate atty;
+extern crate env_logger;
+extern crate flate2;
+extern crate fs_extra;
+extern crate fs2;
+extern crate glob;
+extern crate home;
+extern crate md5;
+extern crate natord;
+extern crate open;
+extern crate rand;
+extern crate regex;
+extern crate rustc_demangle;
+extern crate serde;
+extern crate shell_escape;
+extern crate tar;
+extern crate tempfile;
+extern crate tera;
+extern crate termcolor;
+extern crate term_size;
+extern crate toml;
+extern crate walkdir;
+extern crate zip;
+extern crate coveralls_api;
+extern crate curl;
+#[cfg(unix)]
+extern crate termion;
+
+#[macro_use]
+mod ui;
+mod annotate;
+mod archive;
+mod argparse;
+pub mod artifacts;
+mod assets;
+mod blame;
+mod budget;
+mod bundle;
+mod cache;
+mod cargo;
+pub mod config;
+pub mod driver;
+pub mod error;
+mod export;
+mod git;
+mod highlight;
+mod history;
+mod hooks;
+mod lcov;
+mod lock;
+mod lookup;
+mod ordering;
+pub mod outcome;
+mod owners;
+mod prune;
+mod reachability;
+mod repair;
+mod report;
+mod retention;
+pub mod rounding;
+mod serve;
+mod shim;
+mod show;
+mod signing;
+pub mod sourcepath;
+mod stability;
+mod template;
+mod timings;
+#[cfg(unix)]
+mod tui;
+mod upload;
+mod utils;
+mod waivers;
+
+use argparse::*;
+use cargo::{Cargo, manifest_exists};
+use clap::{App, AppSettings, ArgMatches};
+use termcolor::ColorChoice;
+use error::{ErrorKind, Result, ResultExt};
+use lock::CovLock;
+use outcome::ReportOutcome;
+use utils::join_2;
+
+use std::ffi::OsStr;
+use std::fs::File;
+use std::path::Path;
+use std::process::exit;
+use std::time::{Duration, Instant};
+
+/// Program entry. Calls [`run()`] and prints any error returned to `stderr`.
+///
+/// [`run()`]: ./fn.run.html
+pub fn main() {
+    if let Err(error) = run() {
+        ui::print_error(&error).expect("error while printing error 🤷");
+        exit(1);
+    }
+}
+
+/// Runs the `cargo-cov` program.
+fn run() -> Result<()> {
+    // Only the colors are configured, `clap` wraps the help to the terminal width by itself.
+    let color = match ui::color_choice(atty::Stream::Stderr) {
+        ColorChoice::Never => AppSettings::ColorNever,
+        ColorChoice::Always | ColorChoice::AlwaysAnsi => AppSettings::ColorAlways,
+        ColorChoice::Auto => AppSettings::ColorAuto,
+    };
+    let matches = app().global_setting(color).get_matches();
+    env_logger::init();
+
+    let (subcommand, matches) = matches.subcommand();
+    let matches = matches.expect("matches");
+
+    // Forward the shims. Otherwise, ensure it is run as `cargo cov`.
+    if subcommand.ends_with(".bat") {
+        let forward_args = matches.values_of_os("").unwrap_or_default();
+        return match subcommand {
+            "rustc-shim.bat" => shim::rustc(forward_args),
+            "rustdoc-shim.bat" => shim::rustdoc(forward_args),
+            "test-runner.bat" => shim::run(forward_args),
+            _ => panic!("Don't know how to run {}", subcommand),
+        };
+    } else if subcommand != "cov" {
+        panic!("This command should be executed as `cargo cov`.");
+    }
+    debug!("matches = {:?}", matches);
+
+    // Read the --profiler/--target/--manifest-path options specified before the subcommand:
+    //
+    //     cargo cov --manifest-path Cargo.toml clean ...
+    //               ^~~~~~~~~~~~~~~~~~~~~~~~~~
+    let mut special_args = SpecialMap::with_capacity(3);
+    update_from_clap(matches, &mut special_args);
+
+    // Read the --profiler/--target/--manifest-path options specified after the subcommand:
+    //
+    //     cargo cov clean --manifest-path Cargo.toml ...
+    //                     ^~~~~~~~~~~~~~~~~~~~~~~~~~
+    let (subcommand, matches) = matches.subcommand();
+    let matches = matches.expect("matches");
+    update_from_clap(matches, &mut special_args);
+
+    // Extracting --profiler/--target/--manifest-path if they are written in an external subcommand (build, test, run).
+    let forward_args = match matches.values_of_os("").or_else(|| matches.values_of_os("args")) {
+        Some(args) => normalize(args, &mut special_args),
+        None => Vec::new(),
+    };
+    let wait = special_args.contains_key("wait");
+    let no_lock = special_args.contains_key("no-lock");
+
+    // The subcommands which only read the coverage data can work purely from the --workspace/--gcno/--gcda paths, e.g.
+    // on artifacts copied from CI. Skip probing the Cargo workspace and the profiler if there is no `Cargo.toml`, so
+    // neither the manifest nor a Rust toolchain is needed. Inside a Cargo workspace, the hooks and the run log are kept,
+    // while the coverage data is still read from --workspace.
+    let read_only = match subcommand {
+        "report" | "blame-report" | "ls" | "export" | "upload" | "tui" | "annotate" | "show" | "serve" => true,
+        _ => false,
+    };
+    let cargo = if read_only && matches.is_present("workspace") {
+        if special_args.contains_key("manifest-path") || manifest_exists() {
+            Ok(Cargo::new(special_args, forward_args)?)
+        } else {
+            Err("running without a Cargo workspace".into())
+        }
+    } else {
+        Cargo::new(special_args, forward_args)
+    };
+
+    // Prevent concurrent invocations from interleaving their changes to `target/cov`.
+    let cov_path = match matches.value_of_os("workspace") {
+        Some(workspace) => Some(join_2(Path::new(workspace), "target", "cov")),
+        None => cargo.as_ref().ok().and_then(|c| c.cov_build_path().parent()).map(Path::to_owned),
+    };
+    let _lock = match cov_path {
+        // `cargo cov serve` runs until interrupted, so it only holds the lock while refreshing the report.
+        _ if subcommand == "serve" => None,
+        // Nothing can be interfered with if `target/cov` does not exist yet.
+        Some(ref cov_path) if read_only && !cov_path.is_dir() => None,
+        Some(ref cov_path) if !no_lock => Some(CovLock::acquire(cov_path, !read_only, wait)?),
+        _ => None,
+    };
+
+    // Actually run the subcommands. Please do not pass ArgMatches as a whole to the receiver functions.
+    match subcommand {
+        "build" | "test" | "run" => cargo?.forward(subcommand)?,
+        "stability" => check_stability(&cargo?, matches)?,
+        "clean" => clean(&cargo?, matches)?,
+        "gc" => collect_garbage(&cargo?, matches)?,
+        "repair" => repair_build(&cargo?, matches)?,
+        "report" => generate_reports(cargo, matches)?,
+        "blame-report" => blame_report(cargo, matches)?,
+        "ls" => list_artifacts(cargo, matches)?,
+        "export" => export_graph(cargo, matches)?,
+        "upload" => upload_report(cargo, matches)?,
+        "tui" => browse_report(cargo, matches)?,
+        "annotate" => annotate_sources(cargo, matches)?,
+        "show" => show_source(cargo, matches)?,
+        "serve" => serve_report(cargo, matches, if no_lock { None } else { cov_path.as_ref().map(|p| &**p) })?,
+        _ => ui::print_unknown_subcommand(subcommand)?,
+    }
+
+    Ok(())
+}
+
+/// Creates the `clap` parser of the command line arguments.
+fn app() -> App<'static, 'static> {
+    const HELP_TEMPLATE: &str = "\
+{about}
+
+Usage:
+    cargo cov <subcommand> [options]
+
+Options:
+{options}
+
+Subcommands:
+    build     Compile the crate and produce coverage data (*.gcno)
+    test      Test the crate and produce profile data (*.gcda)
+    run       Run a program and produces profile data (*.gcda)
+{subcommands}
+";
+
+    clap_app!(cargo =>
+        (bin_name: "cargo")
+        (@setting AllowExternalSubcommands)
+        (@subcommand cov =>
+            (author: crate_authors!(", "))
+            (about: crate_description!())
+            (version: crate_version!())
+            (template: HELP_TEMPLATE)
+            (@setting DeriveDisplayOrder)
+            (@setting ArgRequiredElseHelp)
+            (@setting GlobalVersion)
+            (@setting AllowExternalSubcommands)
+            (@arg profiler: --profiler [LIB] +global "Path to `libclang_rt.profile_*.a`")
+            (@arg target: --target [TRIPLE] +global "Target triple which the covered program will run in")
+            (@arg ("manifest-path"): --("manifest-path") [PATH] +global "Path to the manifest of the package")
+            (@arg runner: --runner [RUNNER] +global possible_values(shim::RUNNERS) "How to run the tests, e.g. 'adb' to run them on an Android device, default to 'native'")
+            (@arg wait: --wait +global "Wait for other `cargo cov` processes in the same workspace to finish")
+            (@arg ("no-lock"): --("no-lock") +global "Do not lock `target/cov`, allowing concurrent `cargo cov` processes")
+            (@arg ("link-dead-code"): --("link-dead-code") +global conflicts_with("no-link-dead-code") "Link functions never called, so they are reported as uncovered instead of missing")
+            (@arg ("no-link-dead-code"): --("no-link-dead-code") +global "Do not link functions never called, overriding the target presets and Cargo.toml")
+            (@arg ("changed-first"): --("changed-first") +global "Run the test programs covering the uncommitted changes first, see `cargo cov test`")
+            (@arg ("changed-since"): --("changed-since") [REV] +global "Run the test programs covering the lines changed since REV first, implies --changed-first")
+            (@arg ("only-path"): --("only-path") [PATH] +global "Only instrument the packages and report the source files inside PATH, relative to the workspace, e.g. `src/engine`")
+            (@subcommand clean =>
+                (about: "Clean coverage artifacts")
+                (@setting UnifiedHelpMessage)
+                (@group build =>
+                    (@arg gcda: --gcda "Remove the profile data only (*.gcda)")
+                    (@arg local: --local "Remove the build artifacts in current workspace")
+                    (@arg all_crates: --("all-crates") "Remove build artifacts of all crates")
+                )
+                (@arg report: --report "Remove the coverage report")
+            )
+            (@subcommand gc =>
+                (about: "Removes stale *.gcda files according to the retention policy")
+                (@arg keep_sessions: --("keep-sessions") [N] "Keep the *.gcda files from the last N runs of `cargo cov test` or `cargo cov run`")
+                (@arg keep_days: --("keep-days") [N] "Keep the *.gcda files modified in the last N days")
+            )
+            (@subcommand repair =>
+                (about: "Removes the leftovers of interrupted commands and unreadable *.gcno and *.gcda files")
+                (@arg dry_run: --("dry-run") "Only list the problems found without fixing them")
+            )
+            (@subcommand report =>
+                (about: "Generates a coverage report")
+                (@arg config: --config [PATH] "Read the options from a TOML file, overridden by the options in the command line")
+                (@arg template: --template [TEMPLATE] "Report template, one of 'html' (default), 'lcov', 'gcov', 'gcov-json', 'json', 'csv', 'markdown', 'badge', 'snapshot', 'cobertura', 'clover', 'codecov', 'coveralls' or 'gerrit'")
+                (@arg emit: --emit [TEMPLATES]... +use_delimiter conflicts_with("template") "Render several templates in one pass, e.g. `html,lcov,json`, or `all` for every built-in template")
+                (@arg template_override: --("template-override") [PATH] "Directory with templates and static files replacing parts of the built-in template")
+                (@arg theme: --theme [THEME] "Color theme of the HTML report: light (default), dark, auto (follows the browser), or one added by the template override")
+                (@arg allow_template_fs: --("allow-template-fs") "Allow the template override to read files outside its directory through symbolic links")
+                (@arg template_option: --("template-option") [OPTION]... number_of_values(1) "Override an option of the template, e.g. `branches=false`")
+                (@arg open: --open "Open the report in browser after it is generated")
+                (@arg porcelain: --porcelain "Print the outcome of the report as JSON in the last line of stdout")
+                (@arg no_summary: --("no-summary") "Do not print the coverage of every crate after the report is generated")
+                (@arg coverage_line: --("coverage-line") [FORMAT] min_values(0) require_equals(true) "Print the total line coverage to stdout for CI services parsing the log, formatted as FORMAT with `{}` replaced by the percentage, default to `Coverage: {}%`")
+                (@arg teamcity: --teamcity "Print the total line, function and branch coverage to stdout as TeamCity service messages")
+                (@arg github_annotations: --("github-annotations") "Print the uncovered lines of the files changed since --diff-base (default to the base branch of the pull request) as GitHub Actions annotations")
+                (@arg azure: --azure "Also render the cobertura template, and print the logging command publishing it with the HTML report to the Coverage tab of Azure Pipelines")
+                (@arg coveralls: --coveralls "send report in browser to coveralls")
+                (@arg include: --include [TYPES]... +use_delimiter possible_values(&[
+                    "local",
+                    "macros",
+                    "rustsrc",
+                    "crates",
+                    "unknown",
+                    "all",
+                ]) "Generate reports for some specific sources")
+                (@arg language: --language [LANG] possible_values(&["rust", "c"]) "Language of the profiled project, default to 'rust'. With 'c', only the sources inside the workspace are reported by default")
+                (@arg group_by: --("group-by") [GROUP] possible_values(&["crate", "dir", "module", "source-type"]) "How the files are grouped in the summary, default to 'source-type' (or 'dir' for the cobertura template)")
+                (@arg rounding: --rounding [MODE] possible_values(&["floor", "round", "bankers"]) "How the percentages are rounded in the report and compared with the thresholds, default to 'round'")
+                (@arg precision: --precision [DIGITS] "Number of decimal places of the percentages, default to 2")
+                (@arg max_files: --("max-files") [N] "Only show the N files with the lowest line coverage in the summary, e.g. for commit statuses")
+                (@arg top_uncovered: --("top-uncovered") "Rank the files in the summary by the number of uncovered lines instead, worst first")
+                (@arg functions_regex: --("functions-regex") [REGEX] "Only report the functions whose demangled name matches REGEX, e.g. to check the coverage of a module")
+                (@arg exclude_functions_regex: --("exclude-functions-regex") [REGEX] "Leave out the functions whose demangled name matches REGEX, e.g. generated `fmt::Debug` impls or FFI shims")
+                (@arg diff_base: --("diff-base") [REV] "Find the lines changed since the merge base with REV, e.g. to comment on the uncovered lines of a code review")
+                (@arg reachability: --reachability "Disassemble the test programs to tell the uncovered functions reachable from the code run by the tests from the unreachable ones")
+                (@arg permalink_base: --("permalink-base") [URL] "Link every line of the HTML report to the commit on the repository host at URL, e.g. https://github.com/owner/repo, or `auto` to use the origin remote")
+                (@arg workspace: --workspace [PATH] "The directory to find the source code, default to the current Cargo workspace")
+                (@arg output: --output -o [PATH] "The directory to store the generated report, default to `<src>/target/cov/report/`")
+                (@arg gcno: --gcno [PATH] "The directory, or a `.zip`/`.tar`/`.tar.gz` archive, that contains all *.gcno files, default to `<src>/target/cov/build/gcno/`")
+                (@arg gcda: --gcda [PATH] "The directory, or a `.zip`/`.tar`/`.tar.gz` archive, that contains all *.gcda files, default to `<src>/target/cov/build/gcda/`")
+                (@arg import: --import [FILE]... number_of_values(1) "Merge the coverage exported by `cargo cov export` into the report")
+                (@arg import_lcov: --("import-lcov") [FILE]... number_of_values(1) "Merge an LCOV tracefile produced by another tool (e.g. lcov or kcov) into the report, e.g. for C dependencies")
+                (@arg verify_key: --("verify-key") [PEM] "Fail unless the signature `<FILE>.sig` of every file given by --import or --import-lcov is verified by this public key")
+                (@arg prune_sysroot: --("prune-sysroot") "Skip functions from the Rust standard library entirely, which speeds up the report")
+                (@arg max_memory: --("max-memory") [MB] "Prune the sysroot if the graph is estimated to exceed MB megabytes, or fail if that would drop requested sources")
+                (@arg timings: --timings [FILE] min_values(0) require_equals(true) "Print the time spent in every phase, and optionally write a Chrome trace to FILE, e.g. `--timings=trace.json`")
+                (@arg fail_on_unwaived_uncovered: --("fail-on-unwaived-uncovered") "Fail if any uncovered line is not waived in the configuration file")
+                (@arg strict: --strict "Fail if any warning is printed, e.g. stale *.gcda files or unreadable sources in the workspace")
+                (@arg verbose: -v --verbose "Print the anomalies found in the *.gcno and *.gcda files, e.g. functions without basic blocks (implied by --strict)")
+                (@arg attribute_inlined: --("attribute-inlined") "Record the execution of code inlined from other crates (e.g. the standard library) per local calling function in `<output>/inlined.json`")
+                (@arg gcov_files: --("gcov-files") "Also write the annotated sources in the format of `gcov` (`*.gcov`) into `<output>/gcov/`")
+                (@arg single_file: --("single-file") "Also bundle the HTML pages with their stylesheets and scripts into the standalone `<output>/report.html`")
+                (@arg changed_only: --("changed-only") "Only render the pages of files whose coverage changed since the previous report, reusing the rest, and show the change of coverage of every file")
+                (@arg history: --history "Record the coverage to `<src>/target/cov/history.json` and show the trend of every file")
+                (@arg history_limit: --("history-limit") [N] "Keep only the latest N snapshots in the history, default to 100")
+                (@arg owners: --owners "Summarize coverage per owner listed in the CODEOWNERS file")
+                (@arg codeowners: --codeowners [PATH] "Path to the CODEOWNERS file, default to searching `<src>/CODEOWNERS` and `<src>/.github/CODEOWNERS`")
+                (@arg owner_threshold: --("owner-threshold") [OWNER_PERCENT]... number_of_values(1) "Fail if the line coverage of an owner is below the percentage, e.g. `@org/team=80`, or `*=80` for every owner")
+            )
+            (@subcommand blame_report =>
+                (name: "blame-report")
+                (about: "Explains where the coverage of a source line comes from")
+                (@arg location: +required "The source line, in the form `FILE:LINE`")
+                (@arg workspace: --workspace [PATH] "The directory to find the source code, default to the current Cargo workspace")
+                (@arg gcno: --gcno [PATH] "The directory, or a `.zip`/`.tar`/`.tar.gz` archive, that contains all *.gcno files, default to `<src>/target/cov/build/gcno/`")
+                (@arg gcda: --gcda [PATH] "The directory, or a `.zip`/`.tar`/`.tar.gz` archive, that contains all *.gcda files, default to `<src>/target/cov/build/gcda/`")
+                (@arg max_memory: --("max-memory") [MB] "Skip the contributing *.gcda files if the graph is estimated to exceed MB megabytes")
+            )
+            (@subcommand tui =>
+                (about: "Browses the coverage interactively in the terminal")
+                (@arg config: --config [PATH] "Read the report settings from the TOML file")
+                (@arg include: --include [TYPES]... +use_delimiter possible_values(&[
+                    "local",
+                    "macros",
+                    "rustsrc",
+                    "crates",
+                    "unknown",
+                    "all",
+                ]) "Browse the coverage of some specific sources")
+                (@arg language: --language [LANG] possible_values(&["rust", "c"]) "Language of the profiled project, default to 'rust'")
+                (@arg import: --import [FILE]... number_of_values(1) "Also browse the coverage exported by `cargo cov export` elsewhere")
+                (@arg import_lcov: --("import-lcov") [FILE]... number_of_values(1) "Also browse the coverage of an LCOV tracefile produced by another tool")
+                (@arg verify_key: --("verify-key") [PEM] "Fail unless the signature `<FILE>.sig` of every imported file is verified by this public key")
+                (@arg workspace: --workspace [PATH] "The directory to find the source code, default to the current Cargo workspace")
+                (@arg gcno: --gcno [PATH] "The directory, or a `.zip`/`.tar`/`.tar.gz` archive, that contains all *.gcno files, default to `<src>/target/cov/build/gcno/`")
+                (@arg gcda: --gcda [PATH] "The directory, or a `.zip`/`.tar`/`.tar.gz` archive, that contains all *.gcda files, default to `<src>/target/cov/build/gcda/`")
+            )
+            (@subcommand annotate =>
+                (about: "Marks the uncovered lines in sidecar `<source>.cov` files, or in the sources with --in-place")
+                (@arg in_place: --("in-place") "Append `// COV: 0 hits` to the uncovered lines of the source files instead")
+                (@arg strip: --strip "Remove the annotations (or the sidecar files) written before")
+                (@arg config: --config [PATH] "Read the report settings from the TOML file")
+                (@arg include: --include [TYPES]... +use_delimiter possible_values(&[
+                    "local",
+                    "macros",
+                    "rustsrc",
+                    "crates",
+                    "unknown",
+                    "all",
+                ]) "Annotate some specific sources")
+                (@arg language: --language [LANG] possible_values(&["rust", "c"]) "Language of the profiled project, default to 'rust'")
+                (@arg import: --import [FILE]... number_of_values(1) "Also annotate the coverage exported by `cargo cov export` elsewhere")
+                (@arg import_lcov: --("import-lcov") [FILE]... number_of_values(1) "Also annotate the coverage of an LCOV tracefile produced by another tool")
+                (@arg verify_key: --("verify-key") [PEM] "Fail unless the signature `<FILE>.sig` of every imported file is verified by this public key")
+                (@arg workspace: --workspace [PATH] "The directory to find the source code, default to the current Cargo workspace")
+                (@arg gcno: --gcno [PATH] "The directory, or a `.zip`/`.tar`/`.tar.gz` archive, that contains all *.gcno files, default to `<src>/target/cov/build/gcno/`")
+                (@arg gcda: --gcda [PATH] "The directory, or a `.zip`/`.tar`/`.tar.gz` archive, that contains all *.gcda files, default to `<src>/target/cov/build/gcda/`")
+            )
+            (@subcommand show =>
+                (about: "Prints a source file with the execution count of every line")
+                (@arg file: +required "The source file, the full path or any suffix of it, e.g. `src/lib.rs`")
+                (@arg uncovered_only: --("uncovered-only") "Only print the lines never executed")
+                (@arg config: --config [PATH] "Read the report settings from the TOML file")
+                (@arg language: --language [LANG] possible_values(&["rust", "c"]) "Language of the profiled project, default to 'rust'")
+                (@arg import: --import [FILE]... number_of_values(1) "Also show the coverage exported by `cargo cov export` elsewhere")
+                (@arg import_lcov: --("import-lcov") [FILE]... number_of_values(1) "Also show the coverage of an LCOV tracefile produced by another tool")
+                (@arg verify_key: --("verify-key") [PEM] "Fail unless the signature `<FILE>.sig` of every imported file is verified by this public key")
+                (@arg workspace: --workspace [PATH] "The directory to find the source code, default to the current Cargo workspace")
+                (@arg gcno: --gcno [PATH] "The directory, or a `.zip`/`.tar`/`.tar.gz` archive, that contains all *.gcno files, default to `<src>/target/cov/build/gcno/`")
+                (@arg gcda: --gcda [PATH] "The directory, or a `.zip`/`.tar`/`.tar.gz` archive, that contains all *.gcda files, default to `<src>/target/cov/build/gcda/`")
+            )
+            (@subcommand serve =>
+                (about: "Serves the report, generating it again whenever the *.gcda files change, with the change of coverage since the previous refresh")
+                (@arg port: --port [PORT] "Port to listen on 127.0.0.1, default to 8000, or 0 to pick an unused one")
+                (@arg interval: --interval [SECONDS] "Time between the checks of the *.gcno and *.gcda files, default to 1 second")
+                (@arg config: --config [PATH] "Read the report settings from the TOML file")
+                (@arg template: --template [TEMPLATE] "Report template, default to 'html'")
+                (@arg template_override: --("template-override") [PATH] "Directory with templates and static files replacing parts of the built-in template")
+                (@arg theme: --theme [THEME] "Color theme of the HTML report: light (default), dark, auto (follows the browser), or one added by the template override")
+                (@arg include: --include [TYPES]... +use_delimiter possible_values(&[
+                    "local",
+                    "macros",
+                    "rustsrc",
+                    "crates",
+                    "unknown",
+                    "all",
+                ]) "Serve the report of some specific sources")
+                (@arg language: --language [LANG] possible_values(&["rust", "c"]) "Language of the profiled project, default to 'rust'")
+                (@arg group_by: --("group-by") [GROUP] possible_values(&["crate", "dir", "module", "source-type"]) "How the files are grouped in the summary, default to 'source-type'")
+                (@arg import: --import [FILE]... number_of_values(1) "Also report the coverage exported by `cargo cov export` elsewhere")
+                (@arg import_lcov: --("import-lcov") [FILE]... number_of_values(1) "Also report the coverage of an LCOV tracefile produced by another tool")
+                (@arg workspace: --workspace [PATH] "The directory to find the source code, default to the current Cargo workspace")
+                (@arg output: --output -o [PATH] "The directory to store the generated report, default to `<src>/target/cov/report/`")
+                (@arg gcno: --gcno [PATH] "The directory that contains all *.gcno files, default to `<src>/target/cov/build/gcno/`")
+                (@arg gcda: --gcda [PATH] "The directory that contains all *.gcda files, default to `<src>/target/cov/build/gcda/`")
+            )
+            (@subcommand ls =>
+                (about: "Lists the collected *.gcno and *.gcda files")
+                (@arg json: --json "Print the list as JSON")
+                (@arg workspace: --workspace [PATH] "The directory to find the source code, default to the current Cargo workspace")
+                (@arg gcno: --gcno [PATH] "The directory, or a `.zip`/`.tar`/`.tar.gz` archive, that contains all *.gcno files, default to `<src>/target/cov/build/gcno/`")
+                (@arg gcda: --gcda [PATH] "The directory, or a `.zip`/`.tar`/`.tar.gz` archive, that contains all *.gcda files, default to `<src>/target/cov/build/gcda/`")
+            )
+            (@subcommand export =>
+                (about: "Exports the collected coverage, to be imported by `cargo cov report --import` elsewhere")
+                (@arg output: --output -o [PATH] "The file to store the exported coverage, default to `<src>/target/cov/export.json`")
+                (@arg sign: --sign [PEM] "Sign the exported file with this private key into `<FILE>.sig`, to be verified by `--verify-key` on import")
+                (@arg workspace: --workspace [PATH] "The directory to find the source code, default to the current Cargo workspace")
+                (@arg gcno: --gcno [PATH] "The directory, or a `.zip`/`.tar`/`.tar.gz` archive, that contains all *.gcno files, default to `<src>/target/cov/build/gcno/`")
+                (@arg gcda: --gcda [PATH] "The directory, or a `.zip`/`.tar`/`.tar.gz` archive, that contains all *.gcda files, default to `<src>/target/cov/build/gcda/`")
+            )
+            (@subcommand upload =>
+                (about: "Uploads the coverage to a hosted service")
+                (@arg service: --service [SERVICE] +required possible_values(&["codecov", "bitbucket"]) "The service to upload to")
+                (@arg token: --token [TOKEN] "The repository token, default to `upload-token` in the configuration file or $CODECOV_TOKEN (or $BITBUCKET_ACCESS_TOKEN)")
+                (@arg url: --url [URL] "The URL of a self-hosted service, default to https://codecov.io (or https://api.bitbucket.org)")
+                (@arg config: --config [PATH] "Read the report settings from the TOML file")
+                (@arg include: --include [TYPES]... +use_delimiter possible_values(&[
+                    "local",
+                    "macros",
+                    "rustsrc",
+                    "crates",
+                    "unknown",
+                    "all",
+                ]) "Upload the coverage of some specific sources")
+                (@arg language: --language [LANG] possible_values(&["rust", "c"]) "Language of the profiled project, default to 'rust'")
+                (@arg workspace: --workspace [PATH] "The directory to find the source code, default to the current Cargo workspace")
+                (@arg gcno: --gcno [PATH] "The directory, or a `.zip`/`.tar`/`.tar.gz` archive, that contains all *.gcno files, default to `<src>/target/cov/build/gcno/`")
+                (@arg gcda: --gcda [PATH] "The directory, or a `.zip`/`.tar`/`.tar.gz` archive, that contains all *.gcda files, default to `<src>/target/cov/build/gcda/`")
+            )
+            (@subcommand stability =>
+                (about: "Runs the tests multiple times and reports coverage which varies between runs")
+                (@setting TrailingVarArg)
+                (@arg runs: --runs [N] "Number of times to run the tests, default to 5")
+                (@arg args: [ARGS]... +allow_hyphen_values "Arguments forwarded to `cargo test`")
+            )
+        )
+    )
+}
+
+/// Parses the command line arguments and forwards to [`report::generate()`].
+///
+/// [`report::generate()`]: report/fn.generate.html
+fn generate_reports(cargo: Result<Cargo>, matches: &ArgMatches) -> Result<()> {
+    let config = parse_config(matches, None)?;
+    let (cargo, cov_build_path) = match cargo {
+        Ok(cargo) => {
+            let cov_build_path = cargo.cov_build_path().to_owned();
+            (Some(cargo), Ok(cov_build_path))
+        },
+        Err(e) => (None, Err(e)),
+    };
+    let report_config = ReportConfig::resolve(&config, cov_build_path)?;
+    if let Some(ref cargo) = cargo {
+        cargo.run_hook("pre-report", json!({ "config": config }))?;
+    }
+    let started = Instant::now();
+    let outcome = report::generate(&report_config, matches.is_present("coveralls"))?;
+    if let Some(ref cargo) = cargo {
+        let mut events = outcome.phases.iter().map(|p| ("report", p.phase, p.total)).collect::<Vec<_>>();
+        events.push(("command", "report", started.elapsed()));
+        cargo.record_run(&events)?;
+        cargo.run_hook("post-report", json!({ "outcome": outcome }))?;
+    }
+    if !matches.is_present("no_summary") {
+        ui::print_crate_table(&outcome, report_config.rounding)?;
+    }
+    if let Some(ref format) = config.coverage_line {
+        println!("{}", outcome.coverage_line(format, report_config.rounding));
+    }
+    if config.teamcity {
+        for message in outcome.teamcity_messages(report_config.rounding) {
+            println!("{}", message);
+        }
+    }
+    if config.github_annotations {
+        for annotation in outcome.github_annotations() {
+            println!("{}", annotation);
+        }
+    }
+    if config.azure {
+        let summary_path = report_config.output_path.join("cobertura.xml");
+        let report_path = report_config.output_path.join(bundle::AZURE_DIR_NAME);
+        println!("{}", ReportOutcome::azure_publish_command(&summary_path, &report_path));
+    }
+    if matches.is_present("porcelain") {
+        println!("{}", serde_json::to_string(&outcome)?);
+    }
+    outcome.check()?;
+    if matches.is_present("open") {
+        if let Some(path) = outcome.summary_path {
+            progress!("Opening", "{}", path.display());
+            let status = open::that(path)?;
+            if !status.success() {
+                warning!("failed to open report, result: {}", status);
+            }
+        } else {
+            warning!("nothing to open");
+        }
+    }
+
+    Ok(())
+}
+
+/// Parses the command line arguments and forwards to [`blame::blame()`].
+///
+/// [`blame::blame()`]: blame/fn.blame.html
+fn blame_report(cargo: Result<Cargo>, matches: &ArgMatches) -> Result<()> {
+    let config = parse_config(matches, None)?;
+    let report_config = ReportConfig::resolve(&config, cargo.map(Cargo::into_cov_build_path))?;
+    let (file, line) = blame::parse_location(matches.value_of("location").expect("location"))?;
+    let blame = blame::blame(&report_config, file, line)?;
+    ui::print_blame(&blame)?;
+    Ok(())
+}
+
+/// Parses the command line arguments and forwards to [`artifacts::list()`].
+///
+/// [`artifacts::list()`]: artifacts/fn.list.html
+fn list_artifacts(cargo: Result<Cargo>, matches: &ArgMatches) -> Result<()> {
+    let config = parse_config(matches, None)?;
+    let report_config = ReportConfig::resolve(&config, cargo.map(Cargo::into_cov_build_path))?;
+    let artifacts = artifacts::list(&report_config.gcno_path, &report_config.gcda_path)?;
+    if matches.is_present("json") {
+        serde_json::to_writer_pretty(std::io::stdout(), &artifacts)?;
+        println!();
+    } else {
+        ui::print_artifacts(&artifacts)?;
+    }
+    Ok(())
+}
+
+/// Parses the command line arguments and forwards to [`export::export()`].
+///
+/// [`export::export()`]: export/fn.export.html
+fn export_graph(cargo: Result<Cargo>, matches: &ArgMatches) -> Result<()> {
+    let config = parse_config(matches, None)?;
+    let report_config = ReportConfig::resolve(&config, cargo.map(Cargo::into_cov_build_path))?;
+    let output_path = match config.output {
+        Some(ref path) => path.clone(),
+        None => report_config.output_path.with_file_name("export.json"),
+    };
+    let outcome = export::export(&report_config, &output_path)?;
+    progress!("Exported", "{} *.gcno and {} *.gcda files to {}", outcome.gcno_count, outcome.gcda_count, output_path.display());
+    if let Some(key_path) = matches.value_of_os("sign") {
+        let signature_path = signing::sign(&output_path, Path::new(key_path))?;
+        progress!("Signed", "{}", signature_path.display());
+    }
+    Ok(())
+}
+
+/// Parses the command line arguments and forwards to [`upload::upload()`].
+///
+/// [`upload::upload()`]: upload/fn.upload.html
+fn upload_report(cargo: Result<Cargo>, matches: &ArgMatches) -> Result<()> {
+    let config = parse_config(matches, None)?;
+    let report_config = ReportConfig::resolve(&config, cargo.map(Cargo::into_cov_build_path))?;
+    let upload_config = upload::UploadConfig {
+        service: matches.value_of("service").expect("service").parse().expect("validated by clap"),
+        token: config.upload_token.as_ref().map(|token| &**token),
+        url: matches.value_of("url"),
+    };
+    match upload::upload(report_config, &upload_config)? {
+        Some(url) => progress!("Uploaded", "{}", url),
+        None => progress!("Uploaded", "to {}", upload_config.service.name()),
+    }
+    Ok(())
+}
+
+/// Parses the command line arguments and forwards to [`tui::browse()`].
+///
+/// [`tui::browse()`]: tui/fn.browse.html
+#[cfg(unix)]
+fn browse_report(cargo: Result<Cargo>, matches: &ArgMatches) -> Result<()> {
+    let config = parse_config(matches, None)?;
+    let report_config = ReportConfig::resolve(&config, cargo.map(Cargo::into_cov_build_path))?;
+    tui::browse(&report_config)
+}
+
+/// `cargo cov tui` needs a Unix terminal.
+#[cfg(not(unix))]
+fn browse_report(_: Result<Cargo>, _: &ArgMatches) -> Result<()> {
+    bail!("`cargo cov tui` is only supported on Unix")
+}
+
+/// Parses the command line arguments and forwards to [`annotate::annotate()`].
+///
+/// [`annotate::annotate()`]: annotate/fn.annotate.html
+fn annotate_sources(cargo: Result<Cargo>, matches: &ArgMatches) -> Result<()> {
+    let config = parse_config(matches, None)?;
+    let report_config = ReportConfig::resolve(&config, cargo.map(Cargo::into_cov_build_path))?;
+    let mode = if matches.is_present("in_place") { annotate::Mode::InPlace } else { annotate::Mode::Sidecar };
+    annotate::annotate(&report_config, mode, matches.is_present("strip"))
+}
+
+/// Parses the command line arguments and forwards to [`show::show()`].
+///
+/// [`show::show()`]: show/fn.show.html
+fn show_source(cargo: Result<Cargo>, matches: &ArgMatches) -> Result<()> {
+    let config = parse_config(matches, None)?;
+    let report_config = ReportConfig::resolve(&config, cargo.map(Cargo::into_cov_build_path))?;
+    let file = matches.value_of("file").expect("file");
+    let shown = show::show(&report_config, file, matches.is_present("uncovered_only"))?;
+    ui::print_source(&shown)?;
+    Ok(())
+}
+
+/// Parses the command line arguments and forwards to [`serve::serve()`].
+///
+/// [`serve::serve()`]: serve/fn.serve.html
+fn serve_report(cargo: Result<Cargo>, matches: &ArgMatches, cov_path: Option<&Path>) -> Result<()> {
+    let config = parse_config(matches, None)?;
+    let report_config = ReportConfig::resolve(&config, cargo.map(Cargo::into_cov_build_path))?;
+    let port = match matches.value_of("port") {
+        Some(port) => port.parse().chain_err(|| format!("Invalid port `{}`", port))?,
+        None => 8000,
+    };
+    let interval = match matches.value_of("interval") {
+        Some(interval) => interval.parse().chain_err(|| format!("Invalid interval `{}`", interval))?,
+        None => 1,
+    };
+    ensure!(interval > 0, "the interval must be at least 1 second");
+    serve::serve(report_config, &serve::ServeConfig { port, interval: Duration::from_secs(interval), cov_path })
+}
+
+/// Parses the command line arguments and forwards to [`stability::check()`]. The result is printed and written to
+/// `target/cov/stability.json`.
+///
+/// [`stability::check()`]: stability/fn.check.html
+fn check_stability(cargo: &Cargo, matches: &ArgMatches) -> Result<()> {
+    let runs = match matches.value_of("runs") {
+        Some(runs) => runs.parse().chain_err(|| format!("Invalid number of runs `{}`", runs))?,
+        None => 5,
+    };
+    ensure!(runs >= 2, ErrorKind::InvalidRuns(runs));
+
+    let report = stability::check(cargo, runs)?;
+    ui::print_unstable_coverage(&report)?;
+
+    let path = cargo.cov_build_path().with_file_name("stability.json");
+    let file = File::create(&path)?;
+    serde_json::to_writer_pretty(file, &report)?;
+    progress!("Created", "{}", path.display());
+    Ok(())
+}
+
+/// Parses the command line arguments and forwards to [`Cargo::gc()`]. The limits given in the command line override
+/// the retention policy in `Cargo.toml`.
+///
+/// [`Cargo::gc()`]: cargo/struct.Cargo.html#method.gc
+fn collect_garbage(cargo: &Cargo, matches: &ArgMatches) -> Result<()> {
+    let keep_sessions = match matches.value_of("keep_sessions") {
+        Some(n) => Some(n.parse().chain_err(|| format!("Invalid number of sessions `{}`", n))?),
+        None => None,
+    };
+    let keep_days = match matches.value_of("keep_days") {
+        Some(n) => Some(n.parse().chain_err(|| format!("Invalid number of days `{}`", n))?),
+        None => None,
+    };
+    let policy = cargo.retention().overridden_by(retention::RetentionPolicy { keep_sessions, keep_days });
+    if policy.is_empty() {
+        warning!("no retention policy in Cargo.toml nor the command line, nothing to remove");
+    }
+    cargo.gc(policy)
+}
+
+/// Parses the command line arguments and forwards to [`repair::repair()`].
+///
+/// [`repair::repair()`]: repair/fn.repair.html
+fn repair_build(cargo: &Cargo, matches: &ArgMatches) -> Result<()> {
+    let dry_run = matches.is_present("dry_run");
+    let outcome = repair::repair(cargo.cov_build_path(), true, dry_run)?;
+    if outcome.running {
+        warning!("another `cargo cov` process is running, its files are left untouched");
+    }
+    for subcommand in &outcome.interrupted {
+        progress!("Interrupted", "cargo cov {}", subcommand);
+    }
+    let verb = if dry_run { "Found" } else { "Remove" };
+    for path in outcome.temporaries.iter().chain(&outcome.stray).chain(&outcome.corrupt).chain(&outcome.corrupt_sessions) {
+        progress!(verb, "{}", path.display());
+    }
+    if outcome.corrupt.iter().any(|path| path.extension() == Some(OsStr::new("gcno"))) {
+        warning!("some *.gcno files were unreadable, run `cargo cov clean --local` to rebuild them");
+    }
+    if outcome.is_empty() {
+        progress!("Repaired", "nothing to repair");
+    }
+    Ok(())
+}
+
+/// Parses the command line arguments and forwards to [`Cargo::clean()`].
+///
+/// [`Cargo::clean()`]: cargo/struct.Cargo.html#method.clean
+fn clean(cargo: &Cargo, matches: &ArgMatches) -> Result<()> {
+    use cargo::*;
+
+    let mut clean_target = CleanTargets::empty();
+
+    if matches.is_present("gcda") {
+        clean_target |= CleanTargets::BUILD_GCDA;
+    } else if matches.is_present("local") {
+        clean_target |= CleanTargets::BUILD_GCDA | CleanTargets::BUILD_GCNO;
+    } else if matches.is_present("all_crates") {
+        clean_target |= CleanTargets::BUILD_EXTERNAL | CleanTargets::BUILD_GCDA | CleanTargets::BUILD_GCNO;
+    }
+    if matches.is_present("report") {
+        clean_target |= CleanTargets::REPORT;
+    }
+
+    if clean_target.is_empty() {
+        clean_target = CleanTargets::BUILD_GCDA | CleanTargets::BUILD_GCNO;
+    }
+    cargo.clean(clean_target)
+}
@@ -0,0 +1,66 @@
+//! Advisory lock of the `target/cov` directory.
+//!
+//! Two `cargo cov` processes working on the same workspace at the same time (e.g. two `cargo cov test`, or a
+//! `cargo cov test` and a `cargo cov report`) could interleave moving, collecting and reading the GCDA files. To prevent
+//! this, every `cargo cov` invocation locks `target/cov/.lock`:
+//!
+//! * Subcommands which only read the coverage data (`report`, `blame-report`, `ls` and `export`) take a shared lock, so
+//!   they can run concurrently with each other. They do not lock at all if `target/cov` does not exist.
+//! * All other subcommands take an exclusive lock.
+//!
+//! By default, `cargo cov` fails immediately if the lock is held by another process. Pass `--wait` to block until the
+//! lock is released instead, or `--no-lock` to skip locking entirely.
+
+use error::{ErrorKind, Result, ResultExt};
+
+use fs2::{FileExt, lock_contended_error};
+
+use std::fs::{File, OpenOptions, create_dir_all};
+use std::path::Path;
+
+/// A held lock of the `target/cov` directory. The lock is released when this is dropped.
+#[derive(Debug)]
+pub struct CovLock(File);
+
+impl CovLock {
+    /// Locks `cov_path/.lock`, creating the file if needed.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Locked`] if the lock is held by another process and `wait` is false.
+    ///
+    /// [`Locked`]: ../error/enum.ErrorKind.html#variant.Locked
+    pub fn acquire(cov_path: &Path, exclusive: bool, wait: bool) -> Result<CovLock> {
+        create_dir_all(cov_path)?;
+        let path = cov_path.join(".lock");
+        let file = OpenOptions::new().read(true).write(true).create(true).open(&path).chain_err(|| format!("Cannot open lock file `{}`", path.display()))?;
+
+        let result = if exclusive {
+            FileExt::try_lock_exclusive(&file)
+        } else {
+            FileExt::try_lock_shared(&file)
+        };
+        match result {
+            Ok(()) => {},
+            Err(ref e) if e.kind() == lock_contended_error().kind() => {
+                ensure!(wait, ErrorKind::Locked(path));
+                progress!("Blocking", "waiting for lock on {}", path.display());
+                if exclusive {
+                    FileExt::lock_exclusive(&file)?;
+                } else {
+                    FileExt::lock_shared(&file)?;
+                }
+            },
+            Err(e) => return Err(e).chain_err(|| format!("Cannot lock `{}`", path.display())),
+        }
+        Ok(CovLock(file))
+    }
+}
+
+impl Drop for CovLock {
+    fn drop(&mut self) {
+        if let Err(e) = FileExt::unlock(&self.0) {
+            warning!("cannot release lock: {}", e);
+        }
+    }
+}
@@ -6,6 +6,7 @@ use utils::compare_naturally;
 use glob::{MatchOptions, glob_with};
 
 use std::env;
+use std::env::consts::EXE_SUFFIX;
 use std::ffi::{OsStr, OsString};
 use std::fs::File;
 use std::io::Read;
@@ -26,13 +27,22 @@ const PROFILER_GLOB_PATTERNS: &[&str] = &[
     // FreeBSD's base compiler, on most architectures
     "/usr/lib*/clang/*/lib/freebsd/",
 
-    // macOS via Homebrew
+    // macOS via Homebrew, on Intel and Apple Silicon
     "/usr/local/opt/llvm/lib/clang/*/lib/darwin/",
+    "/opt/homebrew/opt/llvm/lib/clang/*/lib/darwin/",
 
     // LLVM installer on Windows
     r"C:\Program Files\LLVM\lib\clang\*\lib\windows\",
     r"C:\Program Files (x86)\LLVM\lib\clang\*\lib\windows\",
 
+    // The "C++ Clang tools for Windows" component of Visual Studio 2019+, 64-bit and 32-bit
+    r"C:\Program Files*\Microsoft Visual Studio\*\*\VC\Tools\Llvm\x64\lib\clang\*\lib\windows\",
+    r"C:\Program Files*\Microsoft Visual Studio\*\*\VC\Tools\Llvm\lib\clang\*\lib\windows\",
+
+    // MSYS2 clang packages, for the windows-gnu targets
+    r"C:\msys64\clang64\lib\clang\*\lib\windows\",
+    r"C:\msys64\mingw64\lib\clang\*\lib\windows\",
+
     // Android NDK on macOS, installed via Homebrew
     "/usr/local/share/android-sdk/ndk-bundle/toolchains/llvm/prebuilt/*/lib*/clang/*/lib/linux/",
 
@@ -56,8 +66,8 @@ fn profiler_name_part(target: &str) -> Result<&str> {
     Ok(match target {
         // iOS and macOS
         "aarch64-apple-ios" | "armv7-apple-ios" | "armv7s-apple-ios" => "_ios",
-        "i386-apple-ios" | "x86_64-apple-ios" => "_iossim",
-        "i686-apple-darwin" | "x86_64-apple-darwin" => "_osx",
+        "i386-apple-ios" | "x86_64-apple-ios" | "aarch64-apple-ios-sim" => "_iossim",
+        "i686-apple-darwin" | "x86_64-apple-darwin" | "aarch64-apple-darwin" => "_osx",
 
         // Android
         "aarch64-linux-android" => "-aarch64-android",
@@ -68,7 +78,7 @@ fn profiler_name_part(target: &str) -> Result<&str> {
         "mips64el-linux-android" => "-mips64el-android",
 
         // Windows -- LLVM's installer provides -i386 packages.
-        "i586-pc-windows-msvc" | "i686-pc-windows-msvc" => "-i386",
+        "i586-pc-windows-msvc" | "i686-pc-windows-msvc" | "i686-pc-windows-gnu" => "-i386",
 
         // ARM with hard-float support
         "arm-unknown-linux-gnueabihf" |
@@ -105,6 +115,10 @@ fn profiler_name_part(target: &str) -> Result<&str> {
 ///
 /// This function returns the folder and name of the library, so they can be passed as `-L` and `-l` flags to `rustc`.
 ///
+/// The Apple libraries are universal binaries shared by all architectures, e.g. `libclang_rt.profile_osx.a` is used for
+/// both `x86_64-apple-darwin` and `aarch64-apple-darwin`. Libraries from older toolchains lacking the architecture of
+/// the target are skipped, so a newer toolchain elsewhere can be found.
+///
 /// # Errors
 ///
 /// Returns [`NoDefaultProfilerLibrary`] if the profiler library is not found.
@@ -138,6 +152,7 @@ pub fn find_native_profiler_lib(target: &str) -> Result<(PathBuf, String)> {
                     None
                 },
             })
+            .filter(|path| supports_architecture(path, target))
             .max_by(|a, b| compare_naturally(a, b));
         if let Some(mut path) = path {
             path.pop();
@@ -148,6 +163,61 @@ pub fn find_native_profiler_lib(target: &str) -> Result<(PathBuf, String)> {
     Err(ErrorKind::NoDefaultProfilerLibrary.into())
 }
 
+/// CPU types of Mach-O binaries, see `<mach/machine.h>`.
+const CPU_TYPES: &[(&str, u32)] = &[
+    ("x86_64", 0x0100_0007),
+    ("i386", 7),
+    ("i686", 7),
+    ("aarch64", 0x0100_000c),
+    ("armv7", 12),
+    ("armv7s", 12),
+];
+
+/// Checks whether the library at `path` contains code for the architecture of the `target`.
+///
+/// Only universal ("fat") binaries can be checked. Any other library is assumed to support the target.
+fn supports_architecture(path: &Path, target: &str) -> bool {
+    let arch = target.split('-').next().unwrap_or("");
+    let cpu_type = match CPU_TYPES.iter().find(|&&(a, _)| a == arch) {
+        Some(&(_, cpu_type)) => cpu_type,
+        None => return true,
+    };
+    let mut header = Vec::new();
+    if File::open(path).and_then(|f| f.take(4096).read_to_end(&mut header)).is_err() {
+        return true;
+    }
+    let result = fat_binary_cpu_types(&header).map_or(true, |cpu_types| cpu_types.contains(&cpu_type));
+    debug!("supports_architecture({:?}, {:?}) = {}", path, target, result);
+    result
+}
+
+/// Reads the CPU types of every architecture in the header of a universal binary. Returns `None` if the `header` does
+/// not belong to a universal binary.
+fn fat_binary_cpu_types(header: &[u8]) -> Option<Vec<u32>> {
+    fn read_u32_be(bytes: &[u8]) -> u32 {
+        bytes.iter().fold(0, |acc, &b| acc << 8 | u32::from(b))
+    }
+
+    // The header is `struct fat_header { magic, nfat_arch }` followed by `struct fat_arch { cputype, cpusubtype,
+    // offset, size, align }` for every architecture, all in big-endian.
+    const FAT_MAGIC: u32 = 0xcafe_babe;
+    if header.len() < 8 || read_u32_be(&header[..4]) != FAT_MAGIC {
+        return None;
+    }
+    let count = read_u32_be(&header[4..8]) as usize;
+    Some(header[8..].chunks(20).take(count).filter(|c| c.len() == 20).map(|c| read_u32_be(&c[..4])).collect())
+}
+
+#[test]
+fn test_fat_binary_cpu_types() {
+    let mut header = vec![0xca, 0xfe, 0xba, 0xbe, 0, 0, 0, 2];
+    header.extend_from_slice(&[1, 0, 0, 7, 0, 0, 0, 3, 0, 0, 0x40, 0, 0, 0, 0, 0x10, 0, 0, 0, 14]);
+    header.extend_from_slice(&[1, 0, 0, 12, 0, 0, 0, 0, 0, 0, 0x80, 0, 0, 0, 0, 0x10, 0, 0, 0, 14]);
+    assert_eq!(fat_binary_cpu_types(&header), Some(vec![0x0100_0007, 0x0100_000c]));
+    assert_eq!(fat_binary_cpu_types(b"!<arch>\n"), None);
+    assert_eq!(profiler_name_part("aarch64-apple-darwin").unwrap(), "_osx");
+}
+
 /// Splits the full path of a library to the folder and library name.
 ///
 /// For instance `/usr/lib/libfoo.a` will be transformed to `("/usr/lib/", "foo")`. The result can be passed as `-L` and
@@ -195,14 +265,29 @@ pub fn find_cargo() -> OsString {
 /// This function will read the environment variable defined by `tool_name`, which should be the string `"RUSTC"` or
 /// `"RUSTDOC"`. If the environment variable is not defined, it will try to read from the Cargo configuration at
 /// `.cargo/config`.
+///
+/// On Windows, a path written without the `.exe` extension is completed, since the path is passed through batch files
+/// and environment variables before it is executed.
 pub fn find_rustc(tool_name: &str) -> String {
-    if let Ok(rustc) = env::var(tool_name) {
-        return rustc;
+    let rustc = match env::var(tool_name) {
+        Ok(rustc) => rustc,
+        Err(_) => find_rustc_via_cargo_config(tool_name).unwrap_or_else(|_| tool_name.to_lowercase()),
+    };
+    with_exe_suffix(rustc)
+}
+
+/// Appends the platform-specific executable extension (`.exe` on Windows) to the path, if the path has no extension
+/// and only the path with the extension exists.
+fn with_exe_suffix(path: String) -> String {
+    if EXE_SUFFIX.is_empty() || Path::new(&path).extension().is_some() || Path::new(&path).is_file() {
+        return path;
     }
-    if let Ok(rustc) = find_rustc_via_cargo_config(tool_name) {
-        return rustc;
+    let path_with_suffix = [&*path, EXE_SUFFIX].concat();
+    if Path::new(&path_with_suffix).is_file() {
+        path_with_suffix
+    } else {
+        path
     }
-    tool_name.to_lowercase()
 }
 
 /// Finds the path to `rustc` or `rustdoc`.
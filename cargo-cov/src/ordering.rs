@@ -0,0 +1,311 @@
+//! Ordering of the test programs by the changed lines they covered before.
+//!
+//! Every time the test runner shim runs a test program, the names of the GCDA files it produced are appended to
+//! `target/cov/build/gcda/programs.jsonl`, one JSON object per line:
+//!
+//! ```json
+//! {"program":"foo","path":"/path/to/target/debug/deps/foo-fedcba9876543210","gcda":["8b7a6f5e4d3c2b1a.@0123456789abcdef.foo-0123456789abcdef.gcda"]}
+//! ```
+//!
+//! The program is identified by its file name without the hash cargo appends, so the attribution survives rebuilds.
+//! The full `path` is recorded as well, so a GCDA file matching no GCNO (e.g. written by an old test program still on
+//! disk) can be traced back to the program which produced it, see [`producing_programs()`].
+//! Cleaning the GCDA files also forgets the attribution, and the GCDA files removed by the retention policy are removed
+//! from the log as well, see [`compact()`]. Entries without the `path`, recorded by older versions, are ignored.
+//!
+//! `cargo cov test --changed-first` finds the lines changed in the working tree (since `--changed-since`, default to
+//! the uncommitted changes), and counts how many of them were covered by the recorded GCDA files of every test program.
+//! The programs are then run one by one, the highest count first, so a test exercising the changed code fails as early
+//! as possible. Programs never run before come right after those covering changed lines, since nothing is known about
+//! them. Every program still runs (unless one fails without `--no-fail-fast`), so the coverage is as complete as with a
+//! plain `cargo cov test`.
+//!
+//! [`producing_programs()`]: ./fn.producing_programs.html
+//! [`compact()`]: ./fn.compact.html
+
+use archive;
+use error::{Result, ResultExt};
+use git;
+use shim::{GcnoHashes, artifact_crate_name};
+
+use cov::{Gcov, Graph, Interner};
+use fs2::FileExt;
+use serde_json::{from_str, to_string};
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, ErrorKind as IoErrorKind, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// An entry of `programs.jsonl`.
+#[derive(Debug, Serialize, Deserialize)]
+struct ProgramEntry {
+    /// Name of the test program, without the hash.
+    program: String,
+    /// Path to the test program.
+    path: String,
+    /// File names of the GCDA files produced by the program, relative to the GCDA folder.
+    gcda: Vec<String>,
+}
+
+/// Path to the attribution log in the GCDA folder.
+fn programs_path(gcda_path: &Path) -> PathBuf {
+    gcda_path.join("programs.jsonl")
+}
+
+/// Identifies a test program by its file name without the hash, e.g. `foo` for `target/debug/deps/foo-0123456789abcdef`.
+pub fn program_name(program: &Path) -> String {
+    artifact_crate_name(program).map_or_else(|| program.to_string_lossy().into_owned(), str::to_owned)
+}
+
+/// Records the GCDA files produced by running `program`.
+pub fn record_program(gcda_path: &Path, program: &Path, gcda_paths: &[PathBuf]) -> Result<()> {
+    if gcda_paths.is_empty() {
+        return Ok(());
+    }
+    let entry = ProgramEntry {
+        program: program_name(program),
+        path: program.to_string_lossy().into_owned(),
+        gcda: gcda_paths.iter().filter_map(|p| p.file_name()).map(|p| p.to_string_lossy().into_owned()).collect(),
+    };
+    let path = programs_path(gcda_path);
+    let mut file = OpenOptions::new().create(true).append(true).open(&path).chain_err(|| format!("Cannot open `{}`", path.display()))?;
+    // Programs run concurrently may append at the same time, so the line is written while holding an exclusive lock.
+    let line = format!("{}\n", to_string(&entry)?);
+    FileExt::lock_exclusive(&file).chain_err(|| format!("Cannot lock `{}`", path.display()))?;
+    let result = file.write_all(line.as_bytes()).and_then(|_| file.flush());
+    FileExt::unlock(&file)?;
+    result?;
+    Ok(())
+}
+
+/// Reads all entries of the attribution log.
+fn read_entries(gcda_path: &Path) -> Result<Vec<ProgramEntry>> {
+    let path = programs_path(gcda_path);
+    let file = match File::open(&path) {
+        Ok(file) => file,
+        Err(ref e) if e.kind() == IoErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).chain_err(|| format!("Cannot read `{}`", path.display())),
+    };
+    let mut entries = Vec::new();
+    for line in BufReader::new(file).lines() {
+        // A line may be truncated if a program was interrupted while recording.
+        if let Ok(entry) = from_str::<ProgramEntry>(&line?) {
+            entries.push(entry);
+        }
+    }
+    Ok(entries)
+}
+
+/// Reads the attribution log, and returns the existing GCDA files produced by every test program.
+fn read_programs(gcda_path: &Path) -> Result<BTreeMap<String, BTreeSet<PathBuf>>> {
+    let mut programs = BTreeMap::new();
+    for entry in read_entries(gcda_path)? {
+        let gcda_paths = entry.gcda.iter().map(|name| gcda_path.join(name)).filter(|p| p.is_file());
+        programs.entry(entry.program).or_insert_with(BTreeSet::new).extend(gcda_paths);
+    }
+    Ok(programs)
+}
+
+/// Reads the attribution log, and returns the paths of the test programs which produced every GCDA file, keyed by the
+/// file name.
+pub fn producing_programs(gcda_path: &Path) -> Result<HashMap<String, BTreeSet<String>>> {
+    let mut programs = HashMap::new();
+    for entry in read_entries(gcda_path)? {
+        for name in entry.gcda {
+            programs.entry(name).or_insert_with(BTreeSet::new).insert(entry.path.clone());
+        }
+    }
+    Ok(programs)
+}
+
+/// Reads the attribution log, and returns the paths of the recorded test programs still on disk, without duplicates.
+pub fn program_paths(gcda_path: &Path) -> Result<Vec<PathBuf>> {
+    let paths = read_entries(gcda_path)?
+        .into_iter()
+        .map(|entry| PathBuf::from(entry.path))
+        .filter(|path| path.is_file())
+        .collect::<BTreeSet<_>>();
+    Ok(paths.into_iter().collect())
+}
+
+/// Removes the GCDA files which no longer exist (e.g. removed by the [retention policy]) from the attribution log, and
+/// the entries left without any, so the log does not grow forever. The log is rewritten in place while holding the same
+/// lock as [`record_program()`], since a test program may be recording concurrently.
+///
+/// [retention policy]: ../retention/index.html
+/// [`record_program()`]: ./fn.record_program.html
+pub fn compact(gcda_path: &Path) -> Result<()> {
+    let path = programs_path(gcda_path);
+    let mut file = match OpenOptions::new().read(true).write(true).open(&path) {
+        Ok(file) => file,
+        Err(ref e) if e.kind() == IoErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).chain_err(|| format!("Cannot open `{}`", path.display())),
+    };
+    FileExt::lock_exclusive(&file).chain_err(|| format!("Cannot lock `{}`", path.display()))?;
+    let result = compact_entries(&mut file, gcda_path);
+    FileExt::unlock(&file)?;
+    result.chain_err(|| format!("Cannot compact `{}`", path.display()))
+}
+
+/// Rewrites the locked attribution log with only the existing GCDA files.
+fn compact_entries(file: &mut File, gcda_path: &Path) -> Result<()> {
+    let mut content = String::new();
+    for line in BufReader::new(&*file).lines() {
+        let mut entry = match from_str::<ProgramEntry>(&line?) {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+        entry.gcda.retain(|name| gcda_path.join(name).is_file());
+        if !entry.gcda.is_empty() {
+            content.push_str(&to_string(&entry)?);
+            content.push('\n');
+        }
+    }
+    file.set_len(0)?;
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(content.as_bytes())?;
+    file.flush()?;
+    Ok(())
+}
+
+/// Counts the changed lines covered by the recorded GCDA files of every test program.
+///
+/// `changed_lines` is keyed by the path relative to `workspace_path`, as returned by
+/// [`git::working_tree_changed_lines()`].
+///
+/// [`git::working_tree_changed_lines()`]: ../git/fn.working_tree_changed_lines.html
+fn scores(gcno_path: &Path, gcda_path: &Path, workspace_path: &Path, changed_lines: &BTreeMap<String, BTreeSet<u32>>) -> Result<HashMap<String, usize>> {
+    let programs = read_programs(gcda_path)?;
+    let mut scores = programs.keys().map(|name| (name.clone(), 0)).collect::<HashMap<_, _>>();
+    if programs.is_empty() || changed_lines.is_empty() {
+        return Ok(scores);
+    }
+
+    let changed = changed_lines
+        .iter()
+        .flat_map(|(path, lines)| lines.iter().map(move |&line| (workspace_path.join(path), line)))
+        .collect::<HashSet<_>>();
+
+    // All GCNOs are shared by the programs, so they are only parsed once.
+    let mut interner = Interner::new();
+    let mut base_graph = Graph::default();
+    let mut gcno_hashes = GcnoHashes::default();
+    progress!("Parsing", "{}/*.gcno", gcno_path.display());
+    archive::for_each(gcno_path, "gcno", |file, reader| -> Result<()> {
+        gcno_hashes.insert(&file.path);
+        base_graph.merge(Gcov::read(reader, file.path.clone(), &mut interner)?)?;
+        Ok(())
+    })?;
+
+    for (name, gcda_paths) in &programs {
+        let mut graph = base_graph.clone();
+        for path in gcda_paths {
+            if gcno_hashes.stale_hash(path).is_some() {
+                continue;
+            }
+            let merged = Gcov::open(path, &mut interner).and_then(|gcov| graph.merge(gcov));
+            if let Err(e) = merged {
+                debug!("cannot merge `{}`: {}", path.display(), e);
+            }
+        }
+        graph.analyze();
+        let report = graph.report();
+        let mut score = 0;
+        for (&symbol, file) in &report.files {
+            let source_path = workspace_path.join(&interner[symbol]);
+            score += file.lines.iter().filter(|&(&line, record)| record.count > 0 && changed.contains(&(source_path.clone(), line))).count();
+        }
+        scores.insert(name.clone(), score);
+    }
+    Ok(scores)
+}
+
+/// Sorts the test programs by their scores, descending. Programs without a score (never run before) are placed after
+/// those with a positive score, and the order is otherwise kept.
+fn order(programs: &mut [PathBuf], scores: &HashMap<String, usize>) {
+    programs.sort_by_key(|program| match scores.get(&program_name(program)) {
+        Some(&score) if score > 0 => (0, Reverse(score)),
+        Some(_) => (2, Reverse(0)),
+        None => (1, Reverse(0)),
+    });
+}
+
+/// Orders the test programs to run those covering the lines changed since `base` first, see the [module documentation].
+///
+/// [module documentation]: ./index.html
+pub fn order_by_changes(programs: &mut [PathBuf], cov_build_path: &Path, workspace_path: &Path, base: &str) -> Result<()> {
+    let changed_lines = match git::working_tree_changed_lines(workspace_path, base) {
+        Some(changed_lines) => changed_lines,
+        None => {
+            warning!("cannot find the lines changed since `{}`, the tests are run in the original order", base);
+            return Ok(());
+        },
+    };
+    let scores = scores(&cov_build_path.join("gcno"), &cov_build_path.join("gcda"), workspace_path, &changed_lines)?;
+    order(programs, &scores);
+    for program in programs.iter() {
+        if let Some(&score) = scores.get(&program_name(program)).filter(|&&score| score > 0) {
+            progress!("Prioritize", "{} (covered {} changed lines)", program_name(program), score);
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_order() {
+    let mut programs = ["deps/a-0123456789abcdef", "deps/b-0123456789abcdef", "deps/c-0123456789abcdef", "deps/d-0123456789abcdef", "deps/e-0123456789abcdef"]
+        .iter()
+        .map(PathBuf::from)
+        .collect::<Vec<_>>();
+    let scores = [("a", 0), ("b", 3), ("d", 7), ("e", 0)].iter().map(|&(name, score)| (name.to_owned(), score)).collect();
+    order(&mut programs, &scores);
+    let names = programs.iter().map(|p| program_name(p)).collect::<Vec<_>>();
+    assert_eq!(names, vec!["d", "b", "c", "a", "e"]);
+}
+
+#[test]
+fn test_record_program() {
+    let dir = ::tempfile::TempDir::new().unwrap();
+    let gcda_path = dir.path();
+    let gcda_file = gcda_path.join("8b7a6f5e4d3c2b1a.@0123456789abcdef.foo-0123456789abcdef.gcda");
+    File::create(&gcda_file).unwrap();
+    record_program(gcda_path, Path::new("deps/foo-fedcba9876543210"), &[gcda_file.clone()]).unwrap();
+    record_program(gcda_path, Path::new("deps/bar-fedcba9876543210"), &[gcda_path.join("removed.gcda")]).unwrap();
+    record_program(gcda_path, Path::new("deps/baz-fedcba9876543210"), &[]).unwrap();
+
+    let programs = read_programs(gcda_path).unwrap();
+    assert_eq!(programs.len(), 2);
+    assert_eq!(programs["foo"], Some(gcda_file).into_iter().collect());
+    assert!(programs["bar"].is_empty());
+
+    let producing_programs = producing_programs(gcda_path).unwrap();
+    assert_eq!(producing_programs.len(), 2);
+    assert_eq!(producing_programs["8b7a6f5e4d3c2b1a.@0123456789abcdef.foo-0123456789abcdef.gcda"], Some("deps/foo-fedcba9876543210".to_owned()).into_iter().collect());
+}
+
+#[test]
+fn test_compact() {
+    let dir = ::tempfile::TempDir::new().unwrap();
+    let gcda_path = dir.path();
+    let kept = gcda_path.join("1111111111111111.foo.gcda");
+    let removed = gcda_path.join("2222222222222222.foo.gcda");
+    File::create(&kept).unwrap();
+    File::create(&removed).unwrap();
+    record_program(gcda_path, Path::new("deps/foo-fedcba9876543210"), &[kept.clone(), removed.clone()]).unwrap();
+    record_program(gcda_path, Path::new("deps/bar-fedcba9876543210"), &[removed.clone()]).unwrap();
+    // An entry recorded by an older version, without the path.
+    OpenOptions::new().append(true).open(programs_path(gcda_path)).unwrap().write_all(b"{\"program\":\"baz\",\"gcda\":[\"1111111111111111.foo.gcda\"]}\n").unwrap();
+    assert_eq!(read_entries(gcda_path).unwrap().len(), 2);
+
+    ::std::fs::remove_file(&removed).unwrap();
+    compact(gcda_path).unwrap();
+    let entries = read_entries(gcda_path).unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].path, "deps/foo-fedcba9876543210");
+    assert_eq!(entries[0].gcda, vec!["1111111111111111.foo.gcda".to_owned()]);
+    assert_eq!(::std::fs::read_to_string(programs_path(gcda_path)).unwrap().lines().count(), 1);
+
+    compact(&gcda_path.join("missing")).unwrap();
+}
@@ -0,0 +1,281 @@
+//! Structured result of `cargo cov report`.
+//!
+//! [`driver::run_report()`] returns a [`ReportOutcome`], and `cargo cov report --porcelain` prints the same outcome as
+//! the last line of `stdout` in JSON, so scripts need not parse the human-readable messages:
+//!
+//! ```json
+//! {"totals":{"lines_count":10,"lines_covered":8,...},"crates":[{"name":".","files_count":3,"summary":{...}}],"thresholds":[{"check":"owner","owner":"@org/core","value":80.0,"threshold_value":75.0,"passed":true}],"written":["target/cov/report/index.html",...],"warnings":[],"summary_path":"target/cov/report/index.html","sessions":[{"started":1526300000,"subcommand":"test","args":["--","--include-ignored"]}]}
+//! ```
+//!
+//! A failed threshold does not stop the report from being generated. The command line program fails afterwards via
+//! [`ReportOutcome::check()`]; library users should call it as well if a failed threshold is an error.
+//!
+//! [`driver::run_report()`]: ../driver/fn.run_report.html
+//! [`ReportOutcome`]: ./struct.ReportOutcome.html
+//! [`ReportOutcome::check()`]: ./struct.ReportOutcome.html#method.check
+
+use error::{ErrorKind, Result};
+use rounding::Rounding;
+use timings::PhaseTiming;
+
+pub use retention::Session;
+
+use cov::report::FileSummary;
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// The default format of [`ReportOutcome::coverage_line()`], matched by the regular expression `Coverage: \d+\.\d+%`
+/// (e.g. in the `coverage` keyword of GitLab CI).
+///
+/// [`ReportOutcome::coverage_line()`]: ./struct.ReportOutcome.html#method.coverage_line
+pub const DEFAULT_COVERAGE_LINE: &str = "Coverage: {}%";
+
+/// The result of generating a report.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ReportOutcome {
+    /// Sum of the summaries of all reported files.
+    pub totals: FileSummary,
+    /// Sum of the summaries of the reported files of every crate, sorted by name.
+    pub crates: Vec<CrateCoverage>,
+    /// Every threshold checked, in the order they are checked.
+    pub thresholds: Vec<ThresholdCheck>,
+    /// Paths of all files written, except the static resources of the template.
+    pub written: Vec<PathBuf>,
+    /// The warnings printed while generating the report.
+    pub warnings: Vec<String>,
+    /// Path of the main page of the report, if the template has one.
+    pub summary_path: Option<PathBuf>,
+    /// The sessions of `cargo cov test` and `cargo cov run` which produced the GCDA files, with their arguments.
+    pub sessions: Vec<Session>,
+    /// The uncovered lines of the files changed since the diff base, found with `--github-annotations`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub uncovered_changes: Vec<UncoveredLine>,
+    /// Total time spent in every phase of the report, recorded in the [run log](../timings/index.html#run-log).
+    #[serde(skip)]
+    pub phases: Vec<PhaseTiming>,
+    /// Summary of every reported file, keyed by their source paths.
+    #[serde(skip)]
+    pub summaries: BTreeMap<String, FileSummary>,
+}
+
+/// An uncovered line of a changed file.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct UncoveredLine {
+    /// Path of the file relative to the root of the repository, with `/` as separator.
+    pub path: String,
+    /// The line number.
+    pub line: u32,
+}
+
+/// Coverage summary of the reported files of a crate, as grouped by `--group-by crate`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct CrateCoverage {
+    /// Name of the crate, e.g. `./cov` for a workspace member.
+    pub name: String,
+    /// Number of reported files.
+    pub files_count: usize,
+    /// Sum of the summaries of the files.
+    pub summary: FileSummary,
+}
+
+/// The kind of a threshold.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "check", rename_all = "kebab-case")]
+pub enum Threshold {
+    /// The minimum line coverage percentage of an owner, given by `--owner-threshold`.
+    Owner {
+        /// Name of the owner.
+        owner: String,
+    },
+    /// No uncovered line should be left unwaived, given by `--fail-on-unwaived-uncovered`.
+    UnwaivedUncovered {
+        /// The first unwaived line in the form `FILE:LINE`, if any.
+        first: Option<String>,
+    },
+    /// No warning should be printed, given by `--strict`.
+    Warnings {
+        /// The first warning, if any.
+        first: Option<String>,
+    },
+}
+
+/// The result of checking a threshold.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ThresholdCheck {
+    /// What is checked.
+    #[serde(flatten)]
+    pub threshold: Threshold,
+    /// The measured value, e.g. the coverage percentage or the number of unwaived lines.
+    pub value: f64,
+    /// The required value.
+    pub threshold_value: f64,
+    /// Whether the check passed.
+    pub passed: bool,
+}
+
+impl ReportOutcome {
+    /// Formats the total line coverage for CI services which parse it from the log. Every `{}` in `format` is replaced by
+    /// the percentage, rounded by `rounding`.
+    ///
+    /// ```
+    /// use cargo_cov::outcome::{DEFAULT_COVERAGE_LINE, ReportOutcome};
+    /// use cargo_cov::rounding::Rounding;
+    ///
+    /// let mut outcome = ReportOutcome::default();
+    /// outcome.totals.lines_count = 3;
+    /// outcome.totals.lines_covered = 2;
+    /// assert_eq!(outcome.coverage_line(DEFAULT_COVERAGE_LINE, Rounding::default()), "Coverage: 66.67%");
+    /// ```
+    pub fn coverage_line(&self, format: &str, rounding: Rounding) -> String {
+        format.replace("{}", &rounding.format(rounding.percent(self.totals.lines_covered, self.totals.lines_count)))
+    }
+
+    /// Formats the totals as TeamCity service messages, which set the coverage statistics of the build. TeamCity has no
+    /// statistics for branches, so the branches taken are reported as the covered blocks (`B`), and the functions
+    /// called as the covered methods (`M`).
+    ///
+    /// ```
+    /// use cargo_cov::outcome::ReportOutcome;
+    /// use cargo_cov::rounding::Rounding;
+    ///
+    /// let mut outcome = ReportOutcome::default();
+    /// outcome.totals.lines_count = 3;
+    /// outcome.totals.lines_covered = 2;
+    /// let messages = outcome.teamcity_messages(Rounding::default());
+    /// assert_eq!(messages[0], "##teamcity[buildStatisticValue key='CodeCoverageAbsLCovered' value='2']");
+    /// assert_eq!(messages[2], "##teamcity[buildStatisticValue key='CodeCoverageL' value='66.67']");
+    /// ```
+    pub fn teamcity_messages(&self, rounding: Rounding) -> Vec<String> {
+        let totals = &self.totals;
+        let mut messages = Vec::new();
+        for &(kind, covered, count) in &[
+            ("L", totals.lines_covered, totals.lines_count),
+            ("M", totals.functions_called, totals.functions_count),
+            ("B", totals.branches_taken, totals.branches_count),
+        ] {
+            let statistic = |key: String, value: String| format!("##teamcity[buildStatisticValue key='CodeCoverage{}' value='{}']", key, value);
+            messages.push(statistic(format!("Abs{}Covered", kind), covered.to_string()));
+            messages.push(statistic(format!("Abs{}Total", kind), count.to_string()));
+            messages.push(statistic(kind.to_owned(), rounding.format(rounding.percent(covered, count))));
+        }
+        messages
+    }
+
+    /// Formats the [`uncovered_changes`] as GitHub Actions workflow commands, which annotate the lines in the files
+    /// view of the pull request.
+    ///
+    /// ```
+    /// use cargo_cov::outcome::{ReportOutcome, UncoveredLine};
+    ///
+    /// let mut outcome = ReportOutcome::default();
+    /// outcome.uncovered_changes.push(UncoveredLine { path: "src/a,b.rs".to_owned(), line: 12 });
+    /// assert_eq!(outcome.github_annotations(), vec!["::warning file=src/a%2Cb.rs,line=12::line not covered"]);
+    /// ```
+    ///
+    /// [`uncovered_changes`]: #structfield.uncovered_changes
+    pub fn github_annotations(&self) -> Vec<String> {
+        self.uncovered_changes
+            .iter()
+            .map(|uncovered| format!("::warning file={},line={}::line not covered", escape_github_property(&uncovered.path), uncovered.line))
+            .collect()
+    }
+
+    /// Formats the logging command of Azure Pipelines which publishes the Cobertura summary at `summary_path`, and the
+    /// HTML report in `report_path` for the Coverage tab, like the `PublishCodeCoverageResults` task.
+    ///
+    /// ```
+    /// use cargo_cov::outcome::ReportOutcome;
+    /// use std::path::Path;
+    ///
+    /// let command = ReportOutcome::azure_publish_command(Path::new("/r/cobertura.xml"), Path::new("/r/a;b"));
+    /// assert_eq!(command, "##vso[codecoverage.publish codecoveragetool=Cobertura;summaryfile=/r/cobertura.xml;reportdirectory=/r/a%3Bb;]");
+    /// ```
+    pub fn azure_publish_command(summary_path: &Path, report_path: &Path) -> String {
+        format!(
+            "##vso[codecoverage.publish codecoveragetool=Cobertura;summaryfile={};reportdirectory={};]",
+            escape_azure_property(&summary_path.to_string_lossy()),
+            escape_azure_property(&report_path.to_string_lossy())
+        )
+    }
+
+    /// Ensures every threshold passed.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`OwnerCoverageTooLow`], [`UnwaivedUncoveredLines`] or [`StrictWarnings`] for the first failed threshold.
+    ///
+    /// [`OwnerCoverageTooLow`]: ../error/enum.ErrorKind.html#variant.OwnerCoverageTooLow
+    /// [`UnwaivedUncoveredLines`]: ../error/enum.ErrorKind.html#variant.UnwaivedUncoveredLines
+    /// [`StrictWarnings`]: ../error/enum.ErrorKind.html#variant.StrictWarnings
+    #[cfg_attr(feature = "cargo-clippy", allow(cast_possible_truncation, cast_sign_loss))]
+    pub fn check(&self) -> Result<()> {
+        match self.thresholds.iter().find(|t| !t.passed) {
+            None => Ok(()),
+            Some(&ThresholdCheck {
+                threshold: Threshold::Owner { ref owner },
+                value,
+                threshold_value,
+                ..
+            }) => bail!(ErrorKind::OwnerCoverageTooLow(owner.clone(), value, threshold_value)),
+            Some(&ThresholdCheck {
+                threshold: Threshold::UnwaivedUncovered { ref first },
+                value,
+                ..
+            }) => bail!(ErrorKind::UnwaivedUncoveredLines(value as usize, first.clone().unwrap_or_default())),
+            Some(&ThresholdCheck {
+                threshold: Threshold::Warnings { ref first },
+                value,
+                ..
+            }) => bail!(ErrorKind::StrictWarnings(value as usize, first.clone().unwrap_or_default())),
+        }
+    }
+}
+
+/// Escapes a property of a GitHub Actions workflow command.
+fn escape_github_property(value: &str) -> String {
+    value.replace('%', "%25").replace('\r', "%0D").replace('\n', "%0A").replace(':', "%3A").replace(',', "%2C")
+}
+
+/// Escapes a property of an Azure Pipelines logging command.
+fn escape_azure_property(value: &str) -> String {
+    value.replace('%', "%AZP25").replace('\r', "%0D").replace('\n', "%0A").replace(';', "%3B").replace(']', "%5D")
+}
+
+#[test]
+fn test_check() {
+    let mut outcome = ReportOutcome::default();
+    assert!(outcome.check().is_ok());
+
+    outcome.thresholds.push(ThresholdCheck {
+        threshold: Threshold::Owner { owner: "@org/core".to_owned() },
+        value: 80.0,
+        threshold_value: 75.0,
+        passed: true,
+    });
+    outcome.thresholds.push(ThresholdCheck {
+        threshold: Threshold::UnwaivedUncovered { first: Some("src/lib.rs:7".to_owned()) },
+        value: 2.0,
+        threshold_value: 0.0,
+        passed: false,
+    });
+    match *outcome.check().unwrap_err().kind() {
+        ErrorKind::UnwaivedUncoveredLines(2, ref first) => assert_eq!(first, "src/lib.rs:7"),
+        ref kind => panic!("unexpected error {:?}", kind),
+    }
+
+    let json = ::serde_json::to_value(&outcome.thresholds[0]).unwrap();
+    assert_eq!(json, json!({"check": "owner", "owner": "@org/core", "value": 80.0, "threshold_value": 75.0, "passed": true}));
+
+    outcome.thresholds.remove(1);
+    outcome.thresholds.push(ThresholdCheck {
+        threshold: Threshold::Warnings { first: Some("stale".to_owned()) },
+        value: 1.0,
+        threshold_value: 0.0,
+        passed: false,
+    });
+    match *outcome.check().unwrap_err().kind() {
+        ErrorKind::StrictWarnings(1, ref first) => assert_eq!(first, "stale"),
+        ref kind => panic!("unexpected error {:?}", kind),
+    }
+}
@@ -0,0 +1,218 @@
+//! Code-owners aware aggregation.
+//!
+//! A `CODEOWNERS` file (as understood by GitHub, GitLab and Bitbucket) assigns owners to paths in the repository. Each
+//! non-empty, non-comment line contains a gitignore-style path pattern followed by a whitespace-separated list of
+//! owners:
+//!
+//! ```text
+//! # The default owners of everything.
+//! *                   @org/core
+//! /src/engine/        @org/engine @alice
+//! *.md                docs@example.com
+//! ```
+//!
+//! When several rules match the same path, the *last* one wins. This module uses the rules to sum up the coverage of
+//! every source file per owner, so teams can track (and enforce) the coverage of the code they are responsible for.
+
+use error::{Result, ResultExt};
+use outcome::{Threshold, ThresholdCheck};
+use rounding::Rounding;
+
+use cov::report::FileSummary;
+use glob::{MatchOptions, Pattern};
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{MAIN_SEPARATOR, Path};
+
+/// The pseudo-owner which collects all files not matched by any rule.
+pub const UNOWNED: &str = "(unowned)";
+
+/// The default locations of the `CODEOWNERS` file, relative to the workspace, in the order of search.
+pub const DEFAULT_PATHS: &[&str] = &["CODEOWNERS", ".github/CODEOWNERS", ".gitlab/CODEOWNERS", "docs/CODEOWNERS"];
+
+/// A single rule in a `CODEOWNERS` file.
+#[derive(Debug)]
+struct Rule {
+    /// Patterns matching the paths. The rule applies when any of them matches.
+    patterns: Vec<Pattern>,
+    /// Owners of the matched paths. An empty list means the paths are explicitly unowned.
+    owners: Vec<String>,
+}
+
+/// Parsed content of a `CODEOWNERS` file.
+#[derive(Debug, Default)]
+pub struct CodeOwners {
+    rules: Vec<Rule>,
+}
+
+impl CodeOwners {
+    /// Reads and parses the `CODEOWNERS` file at `path`.
+    pub fn open(path: &Path) -> Result<CodeOwners> {
+        let mut content = String::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut content))
+            .chain_err(|| format!("Cannot read code owners from `{}`", path.display()))?;
+        Ok(CodeOwners::parse(&content))
+    }
+
+    /// Parses the content of a `CODEOWNERS` file. Lines with invalid patterns are skipped with a warning.
+    pub fn parse(content: &str) -> CodeOwners {
+        let mut rules = Vec::new();
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            let pattern = words.next().expect("non-empty line");
+            let owners = words.take_while(|w| !w.starts_with('#')).map(str::to_owned).collect();
+            match convert_pattern(pattern) {
+                Ok(patterns) => rules.push(Rule { patterns, owners }),
+                Err(e) => warning!("ignoring invalid CODEOWNERS pattern `{}`: {}", pattern, e),
+            }
+        }
+        CodeOwners { rules }
+    }
+
+    /// Finds the owners of a path relative to the workspace. Returns `None` if no rules matched.
+    pub fn owners_of(&self, path: &str) -> Option<&[String]> {
+        let options = MatchOptions {
+            case_sensitive: true,
+            require_literal_separator: true,
+            require_literal_leading_dot: false,
+        };
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| rule.patterns.iter().any(|p| p.matches_with(path, &options)))
+            .map(|rule| &*rule.owners)
+    }
+}
+
+/// Converts a gitignore-style pattern to glob patterns.
+///
+/// A pattern containing a non-trailing `/` is anchored at the repository root, otherwise it can match at any depth. A
+/// pattern matching a directory also matches everything inside it.
+fn convert_pattern(pattern: &str) -> ::std::result::Result<Vec<Pattern>, ::glob::PatternError> {
+    let is_dir = pattern.ends_with('/');
+    let pattern = pattern.trim_end_matches('/');
+    let is_anchored = pattern.contains('/');
+    let pattern = pattern.trim_start_matches('/');
+
+    let mut glob = if is_anchored { String::new() } else { "**/".to_owned() };
+    glob.push_str(pattern);
+
+    let mut patterns = Vec::with_capacity(2);
+    if !is_dir {
+        patterns.push(Pattern::new(&glob)?);
+    }
+    glob.push_str("/**");
+    patterns.push(Pattern::new(&glob)?);
+    Ok(patterns)
+}
+
+/// Coverage summary of everything owned by a single owner.
+#[derive(Debug, Serialize)]
+pub struct OwnerCoverage {
+    /// Name of the owner, or [`UNOWNED`](./constant.UNOWNED.html).
+    pub owner: String,
+    /// Number of files owned.
+    pub files_count: usize,
+    /// Sum of the summaries of all owned files.
+    pub summary: FileSummary,
+}
+
+/// Sums up the coverage per owner.
+///
+/// `files` is an iterator of source paths and their summaries. Paths outside of the `workspace_path` are ignored. A
+/// file with multiple owners counts towards each of them.
+pub fn aggregate<'a, I>(code_owners: &CodeOwners, workspace_path: &str, files: I) -> Vec<OwnerCoverage>
+where
+    I: IntoIterator<Item = (&'a str, FileSummary)>,
+{
+    let mut result = BTreeMap::new();
+    for (path, summary) in files {
+        let relative_path = match relativize(path, workspace_path) {
+            Some(p) => p,
+            None => continue,
+        };
+        let unowned = [UNOWNED.to_owned()];
+        let owners = match code_owners.owners_of(&relative_path) {
+            Some(owners) if !owners.is_empty() => owners,
+            _ => &unowned,
+        };
+        for owner in owners {
+            let entry = result.entry(owner.clone()).or_insert_with(|| (0, FileSummary::default()));
+            entry.0 += 1;
+            entry.1 += summary;
+        }
+    }
+
+    result
+        .into_iter()
+        .map(|(owner, (files_count, summary))| OwnerCoverage {
+            owner,
+            files_count,
+            summary,
+        })
+        .collect()
+}
+
+/// Converts a source path into a `/`-separated path relative to the workspace, or returns `None` if the path is outside
+/// the workspace.
+pub fn relativize(path: &str, workspace_path: &str) -> Option<String> {
+    let relative_path = if Path::new(path).is_absolute() {
+        if !path.starts_with(workspace_path) || !path[workspace_path.len()..].starts_with(MAIN_SEPARATOR) {
+            return None;
+        }
+        &path[(workspace_path.len() + MAIN_SEPARATOR.len_utf8())..]
+    } else if path.starts_with('<') {
+        // Macro pseudo-paths like `<std macros>`.
+        return None;
+    } else {
+        path
+    };
+    Some(relative_path.replace('\\', "/"))
+}
+
+/// Checks every owner against the required minimum line coverage.
+///
+/// `thresholds` is a list of owners and the minimum percentage of covered lines. The owner `*` applies to every owner
+/// without a specific threshold. Owners without any threshold are not checked. The coverage is rounded by `rounding`
+/// before comparing, so the check agrees with the percentage shown.
+pub fn check_thresholds(owners: &[OwnerCoverage], thresholds: &[(&str, f64)], rounding: Rounding) -> Vec<ThresholdCheck> {
+    let default_threshold = thresholds.iter().rev().find(|&&(o, _)| o == "*").map(|&(_, t)| t);
+    owners
+        .iter()
+        .filter_map(|owner| {
+            let threshold = thresholds.iter().rev().find(|&&(o, _)| o == owner.owner).map(|&(_, t)| t).or(default_threshold)?;
+            let coverage = rounding.percent(owner.summary.lines_covered, owner.summary.lines_count);
+            Some(ThresholdCheck {
+                threshold: Threshold::Owner { owner: owner.owner.clone() },
+                value: coverage,
+                threshold_value: threshold,
+                passed: coverage >= threshold,
+            })
+        })
+        .collect()
+}
+
+#[test]
+fn test_owners_of() {
+    let code_owners = CodeOwners::parse(
+        "
+        # comment
+        *           @org/core
+        *.md        docs@example.com
+        /src/engine/  @org/engine @alice # trailing comment
+        src/gen/
+        ",
+    );
+    assert_eq!(code_owners.owners_of("src/lib.rs"), Some(&["@org/core".to_owned()][..]));
+    assert_eq!(code_owners.owners_of("doc/README.md"), Some(&["docs@example.com".to_owned()][..]));
+    assert_eq!(code_owners.owners_of("src/engine/mod.rs"), Some(&["@org/engine".to_owned(), "@alice".to_owned()][..]));
+    assert_eq!(code_owners.owners_of("lib/src/engine/mod.rs"), Some(&["@org/core".to_owned()][..]));
+    assert_eq!(code_owners.owners_of("src/gen/out.rs"), Some(&[][..]));
+}
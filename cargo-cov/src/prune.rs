@@ -0,0 +1,152 @@
+//! Pruning of sysroot functions before graph construction.
+//!
+//! Most users exclude the Rust standard library sources (`--include` without `rustsrc`) from the report anyway, but the
+//! functions instantiated from them (e.g. generic collections and iterators) often make up the majority of every GCNO.
+//! With `cargo cov report --prune-sysroot`, these functions are removed right after parsing, so they are never merged
+//! into the graph nor analyzed.
+
+use sourcepath::{Language, SourceClassifier, SourceType};
+
+use cov::{Gcov, Interner};
+use cov::raw::{Line, Record, Type};
+
+use std::collections::HashSet;
+
+/// Removes the functions attributable only to sysroot sources from parsed GCNO and GCDA files.
+///
+/// All GCNOs must be pruned before the GCDAs, so that the counts of the removed functions can be dropped as well.
+#[derive(Debug)]
+pub struct Pruner {
+    /// Classifies the source paths relative to the workspace.
+    classifier: SourceClassifier,
+    /// The stamp and identifier of every pruned function.
+    pruned: HashSet<(u32, u32)>,
+    /// Total number of functions seen in the GCNOs.
+    pub functions_count: usize,
+    /// Total number of records removed.
+    pub records_pruned: usize,
+}
+
+impl Pruner {
+    /// Creates a new pruner for the workspace.
+    pub fn new(workspace_path: String) -> Pruner {
+        Pruner {
+            classifier: SourceClassifier::new(Language::Rust, workspace_path),
+            pruned: HashSet::new(),
+            functions_count: 0,
+            records_pruned: 0,
+        }
+    }
+
+    /// Number of functions pruned so far.
+    pub fn functions_pruned(&self) -> usize {
+        self.pruned.len()
+    }
+
+    /// Removes the sysroot functions from a parsed GCNO or GCDA.
+    pub fn prune(&mut self, gcov: &mut Gcov, interner: &Interner) {
+        let records_count = gcov.records.len();
+        let records = ::std::mem::replace(&mut gcov.records, Vec::new());
+        gcov.records = match gcov.ty {
+            Type::Gcno => self.prune_gcno(gcov.stamp, records, interner),
+            Type::Gcda => self.prune_gcda(gcov.stamp, records),
+        };
+        self.records_pruned += records_count - gcov.records.len();
+    }
+
+    /// Removes the records of functions whose lines are all in the sysroot, and remembers them.
+    fn prune_gcno(&mut self, stamp: u32, records: Vec<Record>, interner: &Interner) -> Vec<Record> {
+        let mut result = Vec::with_capacity(records.len());
+        let mut function_start = 0;
+        let mut current_ident = None;
+
+        for record in records {
+            if let Record::Function(ident, _) = record {
+                self.finish_gcno_function(stamp, current_ident, &mut result, function_start, interner);
+                function_start = result.len();
+                current_ident = Some(ident.0);
+                self.functions_count += 1;
+            }
+            result.push(record);
+        }
+        self.finish_gcno_function(stamp, current_ident, &mut result, function_start, interner);
+        result
+    }
+
+    /// Checks the records of a complete function in `records[function_start..]`, and removes them if they only refer
+    /// to the sysroot.
+    fn finish_gcno_function(&mut self, stamp: u32, ident: Option<u32>, records: &mut Vec<Record>, function_start: usize, interner: &Interner) {
+        let ident = match ident {
+            Some(ident) => ident,
+            None => return,
+        };
+        let mut has_lines = false;
+        let all_sysroot = records[function_start..]
+            .iter()
+            .filter_map(|record| match *record {
+                Record::Lines(ref lines) => Some(&lines.lines),
+                _ => None,
+            })
+            .flat_map(|lines| lines)
+            .filter_map(|line| match *line {
+                Line::FileName(filename) => Some(filename),
+                Line::LineNumber(_) => None,
+            })
+            .all(|filename| {
+                has_lines = true;
+                self.classifier.identify(filename, interner).0 == SourceType::RUSTSRC
+            });
+        if has_lines && all_sysroot {
+            records.truncate(function_start);
+            self.pruned.insert((stamp, ident));
+        }
+    }
+
+    /// Removes the counts of functions pruned from the GCNOs.
+    fn prune_gcda(&self, stamp: u32, records: Vec<Record>) -> Vec<Record> {
+        let mut is_pruned = false;
+        records
+            .into_iter()
+            .filter(|record| {
+                if let Record::Function(ident, _) = *record {
+                    is_pruned = self.pruned.contains(&(stamp, ident.0));
+                }
+                !is_pruned
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn test_prune() {
+    use cov::raw::{ArcCounts, BlockIndex, Function, Ident, Lines, VERSION_4_7};
+
+    let mut interner = Interner::new();
+    let local = interner.intern("/workspace/src/lib.rs");
+    let sysroot = interner.intern("/checkout/src/libcore/option.rs");
+    let function = |ident| Record::Function(Ident(ident), Function::default());
+    let lines = |filename| {
+        Record::Lines(Lines {
+            block_number: BlockIndex(1),
+            lines: vec![Line::FileName(filename), Line::LineNumber(1)],
+        })
+    };
+    let counts = || Record::ArcCounts(ArcCounts { counts: vec![1] });
+    let gcov = |ty, records| Gcov {
+        ty,
+        version: VERSION_4_7,
+        stamp: 1,
+        records,
+        src: None,
+    };
+
+    let mut gcno = gcov(Type::Gcno, vec![function(1), lines(sysroot), function(2), lines(sysroot), lines(local), function(3), lines(local)]);
+    let mut gcda = gcov(Type::Gcda, vec![function(1), counts(), function(2), counts(), function(3), counts()]);
+    let mut pruner = Pruner::new("/workspace/".to_owned());
+    pruner.prune(&mut gcno, &interner);
+    pruner.prune(&mut gcda, &interner);
+
+    assert_eq!(gcno.records, vec![function(2), lines(sysroot), lines(local), function(3), lines(local)]);
+    assert_eq!(gcda.records, vec![function(2), counts(), function(3), counts()]);
+    assert_eq!((pruner.functions_pruned(), pruner.functions_count, pruner.records_pruned), (1, 3, 4));
+}
@@ -0,0 +1,180 @@
+//! Reachability of the uncovered functions from the code run by the tests.
+//!
+//! Not every uncovered function is a realistic target for a new test. A function called by code the tests already run
+//! is only missed because no test takes that path, e.g. an error handler, while a function which nothing run by the
+//! tests can ever call needs a new test entry point altogether, or is simply dead code.
+//!
+//! The GCNO files do not record the calls between functions, so the call edges are read from the test programs
+//! recorded in the [attribution log], by disassembling them with `objdump -d`. A function references another if its
+//! code calls, jumps to or takes the address of it. Every function executed at least once is an entry point, since a
+//! test entry point has reached it, and the uncovered functions referenced (directly or through any other function
+//! in the programs) by an entry point are *reachable*. The others are *unreachable* from the tests.
+//!
+//! Calls through a pointer computed elsewhere (e.g. a trait object stored in a static) cannot be seen in the code, so a
+//! function only called that way is reported as unreachable. A function removed from the programs by the linker, or
+//! inlined into all its callers, is unreachable as well.
+//!
+//! [attribution log]: ../ordering/index.html
+
+use ordering;
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// The functions referenced by every function of the disassembled programs, by their symbol names.
+#[derive(Clone, Debug, Default)]
+pub struct CallGraph {
+    edges: HashMap<String, HashSet<String>>,
+}
+
+impl CallGraph {
+    /// Adds the references found in the output of `objdump -d`.
+    ///
+    /// A function starts at a line `0000000000001139 <name>:`, and its instructions refer to other functions as
+    /// `<name>`, or `<name@plt>` through the procedure linkage table. A target with an offset, e.g. `<name+0x10>`, is a
+    /// jump inside a function and is ignored.
+    pub fn add_disassembly(&mut self, output: &str) {
+        let mut current: Option<&str> = None;
+        for line in output.lines() {
+            let line = line.trim_end();
+            if line.ends_with(">:") && !line.starts_with(char::is_whitespace) {
+                current = line.find(" <").map(|start| &line[start + 2..line.len() - 2]);
+                continue;
+            }
+            let caller = match current {
+                Some(caller) if line.starts_with(char::is_whitespace) => caller,
+                _ => continue,
+            };
+            let target = match (line.rfind('<'), line.rfind('>')) {
+                (Some(start), Some(end)) if start < end => &line[start + 1..end],
+                _ => continue,
+            };
+            let target = target.trim_end_matches("@plt");
+            if target.is_empty() || target.contains('+') || target == caller {
+                continue;
+            }
+            self.edges.entry(caller.to_owned()).or_insert_with(HashSet::new).insert(target.to_owned());
+        }
+    }
+
+    /// Finds the functions reachable from the `entry_points`. Every function is mapped to the entry point it is first
+    /// reached from, in breadth-first order, so the entry points are mapped to themselves.
+    pub fn reach<'a, I>(&self, entry_points: I) -> HashMap<String, String>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let mut reached = HashMap::new();
+        let mut queue = VecDeque::new();
+        for entry_point in entry_points {
+            if !reached.contains_key(entry_point) {
+                reached.insert(entry_point.to_owned(), entry_point.to_owned());
+                queue.push_back(entry_point.to_owned());
+            }
+        }
+        while let Some(function) = queue.pop_front() {
+            let entry_point = reached[&function].clone();
+            for target in self.edges.get(&function).into_iter().flat_map(|targets| targets) {
+                if !reached.contains_key(target) {
+                    reached.insert(target.clone(), entry_point.clone());
+                    queue.push_back(target.clone());
+                }
+            }
+        }
+        reached
+    }
+}
+
+/// Disassembles `program` with `objdump`, and returns the output if successful.
+fn objdump(program: &Path) -> Option<String> {
+    let output = Command::new("objdump")
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .args(&["-d", "--no-show-raw-insn"])
+        .arg(program)
+        .output()
+        .ok()?;
+    if output.status.success() {
+        Some(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        debug!("objdump failed on {:?} with {}", program, output.status);
+        None
+    }
+}
+
+/// Builds the call graph of the test programs which produced the GCDA files in `gcda_path`. Returns `None` with a
+/// warning if no test program is recorded, or none of them can be disassembled.
+pub fn call_graph(gcda_path: &Path) -> Option<CallGraph> {
+    let programs = if gcda_path.is_dir() {
+        ordering::program_paths(gcda_path).unwrap_or_else(|e| {
+            warning!("cannot read the test programs: {}", e);
+            Vec::new()
+        })
+    } else {
+        Vec::new()
+    };
+    if programs.is_empty() {
+        warning!("no test program run by `cargo cov test` is found, the reachability cannot be computed");
+        return None;
+    }
+
+    let mut graph = CallGraph::default();
+    let mut disassembled = false;
+    for program in &programs {
+        if let Some(output) = objdump(program) {
+            graph.add_disassembly(&output);
+            disassembled = true;
+        }
+    }
+    if !disassembled {
+        warning!("cannot disassemble the test programs with `objdump`, the reachability cannot be computed");
+        return None;
+    }
+    progress!("Analyzed", "the calls of {} test programs", programs.len());
+    Some(graph)
+}
+
+#[test]
+fn test_call_graph() {
+    let output = "
+/path/to/tests-0123456789abcdef:     file format elf64-x86-64
+
+Disassembly of section .text:
+
+0000000000001000 <tests::it_works>:
+    1000:	push   %rax
+    1001:	call   1100 <foo::parse>
+    1006:	jne    1000 <tests::it_works+0x20>
+    1008:	lea    0x1ff(%rip),%rdi        # 1200 <foo::on_error>
+    100f:	jmp    1030 <free@plt>
+
+0000000000001100 <foo::parse>:
+    1100:	call   1150 <foo::helper>
+    1105:	call   1100 <foo::parse>
+
+0000000000001150 <foo::helper>:
+    1150:	ret
+
+0000000000001200 <foo::on_error>:
+    1200:	call   1250 <foo::report>
+
+0000000000001250 <foo::report>:
+    1250:	ret
+
+0000000000001300 <foo::unused>:
+    1300:	call   1250 <foo::report>
+";
+    let mut graph = CallGraph::default();
+    graph.add_disassembly(output);
+    assert_eq!(graph.edges.len(), 4);
+    assert!(!graph.edges["foo::parse"].contains("foo::parse"));
+
+    let reached = graph.reach(vec!["tests::it_works", "foo::parse"]);
+    assert_eq!(reached["tests::it_works"], "tests::it_works");
+    assert_eq!(reached["foo::parse"], "foo::parse");
+    assert_eq!(reached["foo::helper"], "foo::parse");
+    assert_eq!(reached["foo::on_error"], "tests::it_works");
+    assert_eq!(reached["foo::report"], "tests::it_works");
+    assert_eq!(reached["free"], "tests::it_works");
+    assert!(!reached.contains_key("foo::unused"));
+}
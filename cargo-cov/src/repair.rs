@@ -0,0 +1,263 @@
+//! Recovery of `target/cov/build` after an interrupted `cargo cov` command.
+//!
+//! If `cargo cov build`, `test` or `run` is killed (e.g. by Ctrl+C), some GCNO or GCDA files may be left in the build
+//! directory without being moved into `gcno/` or `gcda/`, possibly half-written, together with temporary files and
+//! directories. The next run would then collect these files as if they were complete.
+//!
+//! To detect this, every such command writes a journal `target/cov/build/.cov-journal-<pid>` and locks it while
+//! running. The journal is removed when the command finishes, even if it fails. When the next command starts, a journal
+//! which is no longer locked means its owner was interrupted, and the leftovers are removed before anything else is
+//! done.
+//!
+//! The files moved into `gcno/` and `gcda/` are always complete, since moving is an atomic rename on the same file
+//! system. `cargo cov repair` additionally parses every collected file, and removes those which are still unreadable
+//! (e.g. after a disk error), as well as an unreadable session log.
+
+use error::{Result, ResultExt};
+use retention;
+
+use cov::{Gcov, Interner};
+use fs2::FileExt;
+use serde_json::{from_reader, to_writer};
+use walkdir::WalkDir;
+
+use std::ffi::OsStr;
+use std::fs::{File, OpenOptions, read_dir, remove_dir_all, remove_file};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Prefix of the journal file names.
+const JOURNAL_PREFIX: &str = ".cov-journal-";
+
+/// Prefix of the temporary files and directories created by the `tempfile` crate.
+const TEMPORARY_PREFIX: &str = ".tmp";
+
+/// The content of a journal.
+#[derive(Debug, Serialize, Deserialize)]
+struct JournalEntry {
+    /// The subcommand being run, e.g. `test`.
+    subcommand: String,
+    /// Seconds since the Unix epoch when the subcommand started.
+    started: u64,
+}
+
+/// A journal of a running subcommand, see the [module documentation](./index.html). The journal is removed when this
+/// is dropped.
+#[derive(Debug)]
+pub struct Journal {
+    path: PathBuf,
+    file: Option<File>,
+}
+
+impl Journal {
+    /// Writes and locks the journal of `subcommand` in `cov_build_path`.
+    pub fn begin(cov_build_path: &Path, subcommand: &str) -> Result<Journal> {
+        let path = cov_build_path.join(format!("{}{}", JOURNAL_PREFIX, process::id()));
+        let file = File::create(&path).chain_err(|| format!("Cannot write journal at `{}`", path.display()))?;
+        file.lock_exclusive()?;
+        let started = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        to_writer(&file, &JournalEntry { subcommand: subcommand.to_owned(), started })?;
+        Ok(Journal { path, file: Some(file) })
+    }
+}
+
+impl Drop for Journal {
+    /// Unlocks and removes the journal, but ignore errors.
+    fn drop(&mut self) {
+        if let Some(file) = self.file.take() {
+            let _ = file.unlock();
+        }
+        let _ = remove_file(&self.path);
+    }
+}
+
+/// What [`repair()`] found in the build directory.
+///
+/// [`repair()`]: ./fn.repair.html
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct RepairOutcome {
+    /// The subcommands which were interrupted.
+    pub interrupted: Vec<String>,
+    /// Whether another `cargo cov` process is still running, in which case the leftovers are not touched.
+    pub running: bool,
+    /// Temporary files and directories left behind.
+    pub temporaries: Vec<PathBuf>,
+    /// GCNO and GCDA files which were never moved into `gcno/` or `gcda/`.
+    pub stray: Vec<PathBuf>,
+    /// GCNO and GCDA files in `gcno/` or `gcda/` which cannot be parsed. Only checked if `thorough` is set.
+    pub corrupt: Vec<PathBuf>,
+    /// The session log, if it cannot be parsed. Only checked if `thorough` is set.
+    pub corrupt_sessions: Option<PathBuf>,
+}
+
+impl RepairOutcome {
+    /// Whether nothing needs to be repaired.
+    pub fn is_empty(&self) -> bool {
+        self.interrupted.is_empty() && self.temporaries.is_empty() && self.stray.is_empty() && self.corrupt.is_empty() && self.corrupt_sessions.is_none()
+    }
+}
+
+/// Detects and (unless `dry_run`) fixes the inconsistencies left by interrupted commands in `cov_build_path`.
+///
+/// The leftovers are only looked for if some command was interrupted, or if `thorough` is set. `thorough` also parses
+/// every collected GCNO and GCDA file and the session log, which is slow on a large workspace.
+pub fn repair(cov_build_path: &Path, thorough: bool, dry_run: bool) -> Result<RepairOutcome> {
+    let mut outcome = RepairOutcome::default();
+    if !cov_build_path.is_dir() {
+        return Ok(outcome);
+    }
+
+    // Find the journals no longer locked by their owners.
+    let mut journals = Vec::new();
+    for entry in read_dir(cov_build_path)? {
+        let path = entry?.path();
+        if !file_name_starts_with(&path, JOURNAL_PREFIX) {
+            continue;
+        }
+        let file = OpenOptions::new().read(true).write(true).open(&path)?;
+        if file.try_lock_exclusive().is_err() {
+            outcome.running = true;
+            continue;
+        }
+        let subcommand = from_reader(&file).map_or_else(|_| "?".to_owned(), |entry: JournalEntry| entry.subcommand);
+        file.unlock()?;
+        outcome.interrupted.push(subcommand);
+        journals.push(path);
+    }
+
+    if !outcome.running && (thorough || !outcome.interrupted.is_empty()) {
+        find_leftovers(cov_build_path, &mut outcome)?;
+    }
+    if thorough {
+        find_corrupt_files(cov_build_path, &mut outcome)?;
+    }
+
+    if !dry_run {
+        for path in outcome.temporaries.iter().chain(&outcome.stray).chain(&outcome.corrupt).chain(&outcome.corrupt_sessions).chain(&journals) {
+            trace!("rm {:?}", path);
+            if path.is_dir() {
+                remove_dir_all(path)?;
+            } else {
+                remove_file(path)?;
+            }
+        }
+    }
+    Ok(outcome)
+}
+
+/// Recovers from interrupted commands before a new command modifies `cov_build_path`, printing what was removed.
+pub fn recover(cov_build_path: &Path) -> Result<()> {
+    let outcome = repair(cov_build_path, false, false)?;
+    for subcommand in &outcome.interrupted {
+        warning!("a previous `cargo cov {}` was interrupted, removing its leftovers", subcommand);
+    }
+    for path in outcome.temporaries.iter().chain(&outcome.stray) {
+        progress!("Remove", "{}", path.display());
+    }
+    Ok(())
+}
+
+/// Checks whether the file name of `path` starts with `prefix`.
+fn file_name_starts_with(path: &Path, prefix: &str) -> bool {
+    path.file_name().and_then(OsStr::to_str).map_or(false, |name| name.starts_with(prefix))
+}
+
+/// Finds the temporary files and the GCNO and GCDA files outside `gcno/` and `gcda/`.
+fn find_leftovers(cov_build_path: &Path, outcome: &mut RepairOutcome) -> Result<()> {
+    for dir in &[cov_build_path.to_owned(), cov_build_path.join("gcno"), cov_build_path.join("gcda")] {
+        if !dir.is_dir() {
+            continue;
+        }
+        for entry in read_dir(dir)? {
+            let path = entry?.path();
+            if file_name_starts_with(&path, TEMPORARY_PREFIX) {
+                outcome.temporaries.push(path);
+            }
+        }
+    }
+
+    let it = WalkDir::new(cov_build_path).into_iter().filter_entry(|entry| {
+        let file_name = entry.file_name();
+        match entry.depth() {
+            0 => true,
+            1 if entry.file_type().is_dir() && (file_name == "gcno" || file_name == "gcda") => false,
+            _ => !file_name_starts_with(entry.path(), TEMPORARY_PREFIX),
+        }
+    });
+    for entry in it {
+        let entry = entry?;
+        let path = entry.path();
+        let extension = path.extension();
+        if entry.file_type().is_file() && (extension == Some(OsStr::new("gcno")) || extension == Some(OsStr::new("gcda"))) {
+            outcome.stray.push(path.to_owned());
+        }
+    }
+    Ok(())
+}
+
+/// Finds the collected GCNO and GCDA files and the session log which cannot be parsed.
+fn find_corrupt_files(cov_build_path: &Path, outcome: &mut RepairOutcome) -> Result<()> {
+    for extension in &["gcno", "gcda"] {
+        let dir = cov_build_path.join(extension);
+        if !dir.is_dir() {
+            continue;
+        }
+        for entry in read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension() == Some(OsStr::new(extension)) && !file_name_starts_with(&path, TEMPORARY_PREFIX) {
+                if let Err(e) = Gcov::open(&path, &mut Interner::new()) {
+                    debug!("cannot parse {:?}: {}", path, e);
+                    outcome.corrupt.push(path);
+                }
+            }
+        }
+    }
+
+    let gcda_path = cov_build_path.join("gcda");
+    if retention::read_sessions(&gcda_path).is_err() {
+        outcome.corrupt_sessions = Some(retention::sessions_path(&gcda_path));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_repair() {
+    use std::fs::{copy, create_dir_all, write};
+
+    let dir = ::tempfile::TempDir::new().unwrap();
+    let cov_build_path = dir.path();
+    create_dir_all(cov_build_path.join("gcno")).unwrap();
+    create_dir_all(cov_build_path.join("gcda")).unwrap();
+    copy("../cov/test-data/branches.clang/x.gcno", cov_build_path.join("gcno/x.gcno")).unwrap();
+    write(cov_build_path.join("gcda/y.gcda"), b"truncated").unwrap();
+    create_dir_all(cov_build_path.join("debug/deps")).unwrap();
+    write(cov_build_path.join("debug/deps/z.gcda"), b"").unwrap();
+    create_dir_all(cov_build_path.join(".tmp1234")).unwrap();
+
+    // Nothing was interrupted, so nothing is touched unless being thorough.
+    assert!(repair(cov_build_path, false, false).unwrap().is_empty());
+
+    // A running command blocks the cleanup.
+    let journal = Journal::begin(cov_build_path, "test").unwrap();
+    let outcome = repair(cov_build_path, false, false).unwrap();
+    assert!(outcome.running && outcome.interrupted.is_empty() && outcome.stray.is_empty());
+
+    // An interrupted command leaves the journal unlocked.
+    let journal_path = journal.path.clone();
+    let file = journal.file.as_ref().unwrap();
+    file.unlock().unwrap();
+    let outcome = repair(cov_build_path, false, true).unwrap();
+    assert_eq!(outcome.interrupted, vec!["test".to_owned()]);
+    assert_eq!(outcome.temporaries, vec![cov_build_path.join(".tmp1234")]);
+    assert_eq!(outcome.stray, vec![cov_build_path.join("debug/deps/z.gcda")]);
+    assert!(outcome.corrupt.is_empty());
+    assert!(journal_path.exists());
+
+    let outcome = repair(cov_build_path, true, false).unwrap();
+    assert_eq!(outcome.corrupt, vec![cov_build_path.join("gcda/y.gcda")]);
+    assert!(!journal_path.exists() && !cov_build_path.join(".tmp1234").exists() && cov_build_path.join("gcno/x.gcno").exists());
+    drop(journal);
+
+    assert!(repair(cov_build_path, true, false).unwrap().is_empty());
+}
@@ -23,6 +23,11 @@
 //!         ...
 //! ```
 //!
+//! The directory is embedded into the binary at compile time (see the [`assets` module]), so adding a template
+//! requires rebuilding `cargo-cov`.
+//!
+//! [`assets` module]: ../assets/index.html
+//!
 //! When rendered, the output will have this structure:
 //!
 //! ```text
@@ -37,6 +42,20 @@
 //!     ...
 //! ```
 //!
+//! # Context schema version
+//!
+//! The data given to the templates (described below) may change between versions of `cargo cov`. Every template must
+//! declare the version of the data it is written against in `config.toml`:
+//!
+//! ```toml
+//! schema = 1
+//! ```
+//!
+//! The report fails before rendering anything if the version is not supported (see [`TEMPLATE_SCHEMA`]), instead of
+//! failing in the middle of rendering with a cryptic Tera error.
+//!
+//! [`TEMPLATE_SCHEMA`]: ./constant.TEMPLATE_SCHEMA.html
+//!
 //! # Summary page
 //!
 //! If a summary page is needed, add the following section to `config.toml`:
@@ -52,10 +71,13 @@
 //! ```json
 //! {
 //!     "crate_path": "/path/to/workspace",
+//!     "language": "rust",
 //!     "files": [
 //!         {
 //!             "symbol": 123,
 //!             "path": "/path/to/workspace/src/lib.rs",
+//!             "source_type": "local",
+//!             "group": "local",
 //!             "summary": {
 //!                 "lines_count": 500,
 //!                 "lines_covered": 499,
@@ -67,10 +89,205 @@
 //!             }
 //!         },
 //!         ...
+//!     ],
+//!     "group_by": "source-type",
+//!     "groups": [
+//!         {
+//!             "name": "local",
+//!             "summary": {"lines_count": 500, ...},
+//!             "files": [0, 1, 2]
+//!         },
+//!         ...
+//!     ],
+//!     "totals": {"lines_count": 1500, ...}
+//! }
+//! ```
+//!
+//! The `files` are sorted by their `group`, which is determined by `--group-by` (see [`GroupBy`]). Every entry of
+//! `groups` is the total of the files at the listed indices of `files`, so templates can render an index of any grouping
+//! without hardcoding one. `totals` is the total of all files.
+//!
+//! With `--max-files N` or `--top-uncovered`, the `files` are instead ranked worst first (by the lowest line coverage,
+//! or by the most uncovered lines with `--top-uncovered`), and only the first `N` are kept. `omitted_files` is the
+//! number of files left out, while `totals` still includes them.
+//!
+//! A template may choose the grouping used when `--group-by` is not given, in `config.toml`:
+//!
+//! ```toml
+//! group_by = "dir"
+//! ```
+//!
+//! [`GroupBy`]: ../sourcepath/enum.GroupBy.html
+//!
+//! When the history is recorded (`--history`), every entry of `files` will also include `history`, the line coverage
+//! percentages of the file in the recent reports (including the current one), oldest first.
+//!
+//! With `--diff-base REV`, every entry of `files` will also include `changed_lines`, the line numbers added or modified
+//! since the merge base of `REV` and `HEAD` in Git, so code review tools can comment on the uncovered changes only. It
+//! is empty if the file is unchanged, and absent if the diff cannot be computed.
+//! The summary also includes `diff_base`, the `REV` given to `--diff-base`, or `null` without it.
+//!
+//! With `--changed-only`, if the previous report is found in the output directory, every entry of `files` will also
+//! include `delta`, the change of the coverage percentages since the previous report, or `null` if the file is new:
+//!
+//! ```json
+//! {"lines": 2.5, "branches": 0.0, "functions": -10.0}
+//! ```
+//!
+//! `unattributed` lists the *.gcda files skipped because their crate hash matches no *.gcno, and the test programs
+//! which produced them according to the attribution log of `cargo cov test` (see the [`ordering` module]), or `null`
+//! if unknown:
+//!
+//! ```json
+//! [
+//!     {
+//!         "path": "/path/to/workspace/target/cov/build/gcda/8b7a6f5e4d3c2b1a.@0123456789abcdef.foo-0123456789abcdef.gcda",
+//!         "crate_hash": "0123456789abcdef",
+//!         "programs": ["/path/to/workspace/target/debug/deps/foo-fedcba9876543210"]
+//!     },
+//!     ...
+//! ]
+//! ```
+//!
+//! [`ordering` module]: ../ordering/index.html
+//!
+//! When waivers are configured (see the [`waivers` module]), the summary also includes `waivers`, the configured
+//! waivers and the uncovered lines each of them waived:
+//!
+//! ```json
+//! [
+//!     {
+//!         "path": "src/generated/**",
+//!         "function": null,
+//!         "marker": null,
+//!         "justification": "Generated code",
+//!         "lines": [
+//!             {"symbol": 123, "path": "/path/to/workspace/src/generated/parser.rs", "line": 45},
+//!             ...
+//!         ]
+//!     },
+//!     ...
+//! ]
+//! ```
+//!
+//! [`waivers` module]: ../waivers/index.html
+//!
+//! If the summary needs the line and function details of every file (e.g. for exporting to formats like LCOV which
+//! put everything into a single file), add `detailed = true` to the `[summary]` section. Every entry of `files` will
+//! then also include the `lines` (without `source`) and `functions` arrays described in the file pages below.
+//!
+//! Services which identify files by their content (e.g. Coveralls) need to know the source files as well. Add
+//! `sources = true` to the section, and every entry of `files` will include `source`, or `null` if the file cannot be
+//! read:
+//!
+//! ```json
+//! {"digest": "<MD5 of the file>", "lines_count": 120}
+//! ```
+//!
+//! Add `git = true` to the section to include `git`, the commit and branch of the workspace, or `null` if the workspace
+//! is not a Git repository:
+//!
+//! ```json
+//! {
+//!     "head": {
+//!         "id": "<commit hash>",
+//!         "author_name": "...",
+//!         "author_email": "...",
+//!         "committer_name": "...",
+//!         "committer_email": "...",
+//!         "message": "<first line of the commit message>"
+//!     },
+//!     "branch": "master",
+//!     "remotes": [{"name": "origin", "url": "https://github.com/kennytm/cov"}]
+//! }
+//! ```
+//!
+//! # Additional pages
+//!
+//! A template may render more pages using the same data as the summary page, each in its own `[[pages]]` section:
+//!
+//! ```toml
+//! [[pages]]
+//! template = "risk.html"
+//! output = "risk.html"
+//! detailed = true
+//! ```
+//!
+//! The built-in `html` template uses this to render `risk.html`, which ranks the functions by their complexity and
+//! coverage. The thresholds of the risky quadrant are the template options `risk_complexity` and `risk_coverage`. Its
+//! option `heatmap` shades the covered lines of the file pages by their execution count.
+//!
+//! A page with `diff = true` is only rendered with `--diff-base REV`, and also includes `diff`, the changes since the
+//! merge base of `REV` and `HEAD` in Git with 3 lines of context, or `null` if the diff cannot be computed. The added
+//! lines carry the `count` of the line in the report, or `null` if the line has no code. `symbol` is the file in the
+//! report, or `null` if it is not reported, and `summary` counts the added lines with code and those covered:
+//!
+//! ```json
+//! {
+//!     "files": [
+//!         {
+//!             "path": "src/lib.rs",
+//!             "symbol": 123,
+//!             "summary": {"lines_count": 4, "lines_covered": 3},
+//!             "hunks": [
+//!                 {
+//!                     "header": "@@ -12,3 +12,4 @@ fn main() {",
+//!                     "lines": [
+//!                         {"kind": "context", "old": 12, "new": 12, "text": "    let x = f();"},
+//!                         {"kind": "removed", "old": 13, "new": null, "text": "    g(x);"},
+//!                         {"kind": "added", "old": null, "new": 13, "text": "    h(x);", "count": 0},
+//!                         ...
+//!                     ]
+//!                 },
+//!                 ...
+//!             ]
+//!         },
+//!         ...
+//!     ],
+//!     "totals": {"lines_count": 10, "lines_covered": 7}
+//! }
+//! ```
+//!
+//! The built-in `html` template renders it as `diff.html`, the patch coverage with the added lines colored by whether
+//! they are covered, for reviewing a change.
+//!
+//! A page with `reachability = true` is only rendered with `--reachability`, and also includes `reachability`, the
+//! uncovered functions of the shown files, each either reachable from the functions run by the tests (`via` the
+//! executed function calling it, directly or indirectly) or not, see the [`reachability` module]. It is `null` if the
+//! calls between the functions cannot be found. The summary page knows whether the option is given from
+//! `reachability_enabled`.
+//!
+//! ```json
+//! {
+//!     "reachable_count": 1,
+//!     "unreachable_count": 1,
+//!     "functions": [
+//!         {"symbol": 123, "path": "src/lib.rs", "name": "_ZN3foo8on_error17h0123456789abcdefE", "line": 20,
+//!          "summary": {...}, "reachable": true, "via": "_ZN3foo5parse17h0123456789abcdefE"},
+//!         {"symbol": 123, "path": "src/lib.rs", "name": "_ZN3foo6unused17h0123456789abcdefE", "line": 40,
+//!          "summary": {...}, "reachable": false, "via": null},
 //!     ]
 //! }
 //! ```
 //!
+//! The built-in `html` template renders it as `reachability.html`, with the reachable functions apart from the
+//! unreachable ones, so the realistically testable gaps can be told from the code needing a new test entry point.
+//!
+//! [`reachability` module]: ../reachability/index.html
+//!
+//! # Template options
+//!
+//! A template may declare options with their default values in `config.toml`:
+//!
+//! ```toml
+//! [options]
+//! functions = true
+//! branches = true
+//! ```
+//!
+//! The options can be overridden from the command line with `--template-option functions=false`, and are available
+//! to both summary and file pages as the `options` object.
+//!
 //! # File pages
 //!
 //! If the file pages are needed, add the following section to `config.toml`:
@@ -86,12 +303,15 @@
 //! ```json
 //! {
 //!     "crate_path": "/path/to/workspace",
+//!     "language": "rust",
 //!     "symbol": 123,
 //!     "path": "/path/to/workspace/src/lib.rs",
 //!     "summary": {
 //!         "lines_count": 500,
 //!         ...
 //!     },
+//!     "max_count": 1024,
+//!     "permalink": "https://github.com/owner/repo/blob/<commit hash>/src/lib.rs",
 //!     "lines": [
 //!         {
 //!             "line": 1,
@@ -109,7 +329,10 @@
 //!                     "symbol": 456,
 //!                     "path": "/path/to/workspace/src/lib.rs",
 //!                     "line": 3,
-//!                     "column: 0
+//!                     "column: 0,
+//!                     "outcome": "taken",
+//!                     "fallthrough": true,
+//!                     "throw": false
 //!                 },
 //!                 ...
 //!             ]
@@ -120,8 +343,12 @@
 //!         {
 //!             "symbol": 789,
 //!             "name": "_ZN10crate_name26second_line_of_source_code17hce04ea776f1a67beE",
+//!             "demangled_name": "crate_name::second_line_of_source_code",
+//!             "short_name": "crate_name::second_line_of_source_code",
 //!             "line": 2,
 //!             "column": 0,
+//!             "end_line": 0,
+//!             "end_column": 0,
 //!             "summary": {
 //!                 "blocks_count": 100,
 //!                 "blocks_executed": 90,
@@ -129,42 +356,366 @@
 //!                 "exit_count": 10,
 //!                 "branches_count": 250,
 //!                 "branches_executed": 225,
-//!                 "branches_taken": 219
+//!                 "branches_taken": 219,
+//!                 "complexity": 12
 //!             }
 //!         },
 //!         ...
 //!     ]
 //! }
 //! ```
+//!
+//! `max_count` is the highest execution count of the lines, which the `heatmap` filter (see the [`template` module])
+//! scales the counts against.
+//!
+//! `permalink` is the URL of the file at the commit checked out on the repository host, given `--permalink-base`, and
+//! is `null` otherwise or if the file is outside the workspace. Append `#L<line>` to link to a line, which both GitHub
+//! and GitLab understand.
+//!
+//! The `branches` of a line are the arcs leaving its last basic block. The `outcome` of a branch is `taken`,
+//! `not_taken`, or `not_executed` if the line itself is never executed (the `+`, `-` and `#` of genhtml). `fallthrough`
+//! and `throw` tell whether the arc is the fall-through edge of a conditional jump or an exception.
+//!
+//! Add `highlight = true` to the `[files]` section to include `highlighted` in every line read from the source file,
+//! the HTML of the `source` with its comments, strings, keywords etc. wrapped in `<span>` elements, see the
+//! [`highlight` module]. The HTML is already escaped, so it should be written with the `safe` filter.
+//!
+//! The `name` of a function is its raw symbol, `demangled_name` is demangled without the hash, and `short_name` is the
+//! path of the function without the generic arguments and the trait of a qualified path, e.g.
+//! `alloc::vec::Vec::drop` for `<alloc::vec::Vec<T> as core::ops::drop::Drop>::drop`. They are the same for C and
+//! C++. The `function_name` filter (see the [`template` module]) switches between these forms, e.g. following a template
+//! option.
+//!
+//! The `column`, `end_line` and `end_column` of a function are only recorded by GCC 8 and later, and are zero
+//! otherwise. When several functions with these columns share a line, e.g. a one-liner or a lambda, the line also
+//! includes `regions`, the parts of the line belonging to each function (the innermost one if they are nested):
+//!
+//! ```json
+//! "regions": [{"start": 5, "end": 21, "count": 3}, {"start": 27, "end": 43, "count": 0}]
+//! ```
+//!
+//! `start` and `end` are the 1-based byte columns, inclusive, and `count` is the number of calls of the function. The
+//! `highlighted` HTML of such a line wraps the regions in `<span>` elements of the class `rg-covered` or `rg-zero`.
+//!
+//! [`highlight` module]: ../highlight/index.html
+//! [`template` module]: ../template/index.html
+//!
+//! With `--changed-only`, a file page is only rendered again if this data changed since the previous report, see the
+//! [`cache` module].
+//!
+//! [`cache` module]: ../cache/index.html
+//!
+//! # The `json` report
+//!
+//! The data above mirrors the internals of `cargo cov` and may change with the context schema version. Tools should
+//! read the `coverage.json` written by the built-in `json` template instead, whose format is kept stable:
+//!
+//! ```json
+//! {
+//!     "format_version": 1,
+//!     "crate_path": "/path/to/workspace",
+//!     "language": "rust",
+//!     "files": [
+//!         {
+//!             "path": "src/lib.rs",
+//!             "source_type": "local",
+//!             "group": "local",
+//!             "summary": {"lines_count": 500, ...},
+//!             "lines": [
+//!                 {"line": 2, "count": 12, "branches": [{"id": 0, "count": 6, "total": 2}, {"id": 1, "count": 6, "total": 2}]},
+//!                 ...
+//!             ],
+//!             "functions": [
+//!                 {
+//!                     "name": "_ZN10crate_name26second_line_of_source_code17hce04ea776f1a67beE",
+//!                     "demangled_name": "crate_name::second_line_of_source_code",
+//!                     "line": 2,
+//!                     "column": 0,
+//!                     "end_line": 0,
+//!                     "end_column": 0,
+//!                     "summary": {"blocks_count": 100, ...}
+//!                 },
+//!                 ...
+//!             ]
+//!         },
+//!         ...
+//!     ],
+//!     "omitted_files": 0,
+//!     "totals": {"lines_count": 1500, ...},
+//!     "group_by": "source-type",
+//!     "groups": [{"name": "local", "summary": {"lines_count": 500, ...}, "files": [0, 1, 2]}, ...]
+//! }
+//! ```
+//!
+//! * `path` is the path of the source file as recorded by the compiler.
+//! * `lines` only lists the lines with code, ascending. `count` is the number of times the line is executed.
+//! * `branches` are in the order of the branches of the line, `id` being the index and `total` their number. They are
+//!     omitted with `--template-option branches=false`.
+//! * `demangled_name` of a function is the name without the hash suffix, the same as `name` for C and C++. The
+//!     `functions` are omitted with `--template-option functions=false`.
+//! * `column`, `end_line` and `end_column` of a function are zero unless recorded by the compiler (GCC 8 and later).
+//! * `summary`, `totals`, `group_by`, `groups` and `omitted_files` are the same as in the context of the summary page.
+//!
+//! `format_version` is only increased when a field is removed or changes its meaning. New fields may be added to any
+//! object without changing it, so readers should ignore the fields they do not know.
+//!
+//! # Overriding parts of a template
+//!
+//! Instead of forking the whole template, `--template-override ./overrides/` replaces individual files of the
+//! template in use. The override directory has the same structure as the template directory, except that
+//! `config.toml` is optional:
+//!
+//! ```text
+//! overrides/
+//!     tera/
+//!         layout.html
+//!     static/
+//!         custom.css
+//! ```
+//!
+//! Every file in `tera/` replaces the template of the same name, and every file in `static/` is copied over the
+//! built-in resources. The override directory may contain a `config.toml` with the `schema` version as well, which is
+//! checked in the same way as the template's. The pages of the built-in `html` template extend `layout.html`, which by
+//! itself just extends `base.html`. Thus a custom header or footer can be added by overriding `layout.html`:
+//!
+//! ```text
+//! {% extends "base.html" %}
+//! {% block header %}<header>My Project</header>{% endblock header %}
+//! {% block footer %}<footer>Generated on CI</footer>{% endblock footer %}
+//! ```
+//!
+//! The blocks available are `title`, `head`, `header`, `content`, `footer` and `scripts`. Every page also links to
+//! `static/custom.css`, which is empty by default and can be overridden to restyle the report.
+//!
+//! ## Themes
+//!
+//! The colors of the `html` template are CSS variables, assigned by a theme in `static/themes/`. `--theme` picks one
+//! of `light` (the default), `dark`, or `auto` which follows the color scheme preferred by the browser. The chosen
+//! theme is written to `static/theme.css`, which every page links before `common.css`.
+//!
+//! An override can thus restyle the report without copying the template: either reassign a few variables in
+//! `static/custom.css`,
+//!
+//! ```css
+//! :root {
+//!     --accent: #a0a;
+//!     --ln-zero-odd: #b33;
+//! }
+//! ```
+//!
+//! or add a complete theme as `static/themes/solarized.css` and select it with `--theme solarized`. See
+//! `static/themes/light.css` for the list of variables.
+//!
+//! ## Sandbox
+//!
+//! The templates can only `include`, `import` or `extend` other templates, so they see no files beyond the override
+//! directory and the report data. The override directory itself may come from an untrusted configuration file, e.g.
+//! in a pull request built on CI, so every file read from it must resolve inside it: a symbolic link in `tera/` or
+//! `static/` pointing elsewhere (say `~/.ssh/id_rsa`) fails the report with [`TemplateOutsideSandbox`] instead of
+//! leaking the file into the output. Pass `--allow-template-fs` to lift this restriction. It can only be given in the
+//! command line, not in the configuration file.
+//!
+//! For the same reason, a `template-override` set in the configuration file must be inside the workspace, otherwise the
+//! report fails with [`TemplateOverrideOutsideWorkspace`]. Only `--template-override` in the command line may point
+//! anywhere.
+//!
+//! [`TemplateOutsideSandbox`]: ../error/enum.ErrorKind.html#variant.TemplateOutsideSandbox
+//! [`TemplateOverrideOutsideWorkspace`]: ../error/enum.ErrorKind.html#variant.TemplateOverrideOutsideWorkspace
 
+use archive;
 use argparse::ReportConfig;
-use error::{Result, ResultExt};
-use sourcepath::{SourceType, identify_source_path};
-use template::new as new_template;
-use utils::clean_dir;
+use assets;
+use budget;
+use bundle;
+use cache::{self, CachedPage, PageCache, ReportCache};
+use export;
+use git::{self, DiffLineKind};
+use error::{ErrorKind, Result, ResultExt};
+use highlight::{Highlighter, Region, wrap_regions};
+use history::History;
+use lcov;
+use ordering;
+use reachability;
+use outcome::{CrateCoverage, ReportOutcome, Threshold, ThresholdCheck, UncoveredLine};
+use owners::{self, CodeOwners};
+use prune::Pruner;
+use retention;
+use rounding::Rounding;
+use shim::GcnoHashes;
+use signing;
+use sourcepath::{GroupBy, Language, SourceClassifier, SourceType};
+use template::{new as new_template, short_name};
+use timings::Timings;
+use ui;
+use utils::{clean_dir, format_duration};
+use waivers::{self, WaivedLines};
 
-use fs_extra::dir;
+use fs_extra::{copy_items, dir};
+use md5;
 use cov::{self, Gcov, Graph, Interner, Report, Symbol};
-use serde_json::Value;
+use cov::raw::{ArcAttr, Type};
+use cov::report::FileSummary;
+use rustc_demangle::demangle;
+use serde_json::{Map, Value};
+use tempfile::TempDir;
 use tera::{Context, Tera};
+use walkdir::WalkDir;
 use coveralls_api::{Identity, Service, CoverallsReport, CiService, Source};
 
+use std::borrow::Cow;
+use std::cmp::{self, Ordering, Reverse};
 use std::env;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::ffi::OsStr;
-use std::fs::{File, create_dir_all, read_dir};
+use std::fs::{File, create_dir_all, read_dir, remove_file};
 use std::io::{BufRead, BufReader, Read, Write};
+use std::mem::replace;
 use std::path::{Path, PathBuf};
 
 /// Entry point of `cargo cov report` subcommand. Renders the coverage report using a template.
-pub fn generate(config: &ReportConfig, coveralls: bool) -> Result<Option<PathBuf>> {
+///
+/// The thresholds are checked but not enforced, call [`ReportOutcome::check()`] to turn a failed threshold into an error.
+/// With `--strict`, every warning printed counts as a failed threshold as well.
+///
+/// [`ReportOutcome::check()`]: ../outcome/struct.ReportOutcome.html#method.check
+pub fn generate(config: &ReportConfig, coveralls: bool) -> Result<ReportOutcome> {
+    let (outcome, warnings) = ui::capture_warnings(|| generate_outcome(config, coveralls));
+    let mut outcome = outcome?;
+    if config.strict {
+        #[cfg_attr(feature = "cargo-clippy", allow(cast_precision_loss))]
+        outcome.thresholds.push(ThresholdCheck {
+            threshold: Threshold::Warnings { first: warnings.first().cloned() },
+            value: warnings.len() as f64,
+            threshold_value: 0.0,
+            passed: warnings.is_empty(),
+        });
+    }
+    outcome.warnings = warnings;
+    Ok(outcome)
+}
+
+/// Renders the coverage report, without collecting the warnings.
+fn generate_outcome(config: &ReportConfig, coveralls: bool) -> Result<ReportOutcome> {
+    let mut outcome = ReportOutcome::default();
+    outcome.sessions = retention::read_sessions(&config.gcda_path)?;
     let report_path = &config.output_path;
-    clean_dir(report_path).chain_err(|| "Cannot clean report directory")?;
+    let report_cache_path = report_path.join(cache::REPORT_CACHE_FILE_NAME);
+    let previous_report = ReportCache::open(&report_cache_path);
+    if !config.changed_only {
+        clean_dir(report_path).chain_err(|| "Cannot clean report directory")?;
+    }
     create_dir_all(report_path)?;
 
+    let obstacle = if config.language != Language::Rust {
+        Some(format!("nothing can be pruned from a project in {}", config.language.name()))
+    } else if config.allowed_source_types.contains(SourceType::RUSTSRC) {
+        Some("pruning the sysroot functions would drop the `rustsrc` sources requested by --include".to_owned())
+    } else {
+        None
+    };
+    let degraded = budget::exceeds(
+        &config.gcno_path,
+        &config.gcda_path,
+        config.import_paths,
+        config.max_memory,
+        "pruning the sysroot functions",
+        obstacle.as_ref().map(|o| &**o),
+    )?;
+
     let mut interner = Interner::new();
-    let graph = create_graph(config, &mut interner).chain_err(|| "Cannot create graph")?;
-    let report = graph.report();
+    if config.prune_sysroot && config.language != Language::Rust {
+        warning!("--prune-sysroot has no effect on a project in {}", config.language.name());
+    }
+    let mut pruner = if (config.prune_sysroot || degraded) && config.language == Language::Rust {
+        Some(Pruner::new(config.workspace_path.to_string_lossy().into_owned()))
+    } else {
+        None
+    };
+    let mut timings = Timings::new();
+
+    // The graph itself is not cached, so the cache is skipped if anything needs it.
+    let needs_graph = config.attribute_inlined || config.waivers.iter().any(|w| w.function.is_some());
+    let digest = if needs_graph {
+        None
+    } else {
+        cache::digest_inputs(
+            &config.gcno_path,
+            &config.gcda_path,
+            config.import_paths,
+            &config.workspace_path,
+            config.language,
+            pruner.is_some(),
+            config.verbose,
+        )
+    };
+    // Read and verified even if the previous report is reused, since the key may have changed.
+    let exported = read_imports(config.import_paths, config.verify_key_path)?;
+    let tracefiles = read_imports(config.import_lcov_paths, config.verify_key_path)?;
+    let cached_report = match previous_report {
+        Some(previous_report) if Some(previous_report.digest) == digest => {
+            let warnings = previous_report.warnings.clone();
+            previous_report.restore(&mut interner).map(|report| (report, warnings))
+        },
+        _ => None,
+    };
+
+    let (graph, mut report, warnings) = if let Some((report, warnings)) = cached_report {
+        progress!("Reused", "the coverage of the previous report, the *.gcno and *.gcda files are unchanged");
+        for warning in &warnings {
+            warning!("{}", warning);
+        }
+        (None, report, warnings)
+    } else {
+        let mut imported = Vec::new();
+        for (import_path, content) in exported {
+            let gcovs = timings.measure("import", Some(import_path), || export::import(import_path, &content, &config.workspace_path, &mut interner))?;
+            imported.extend(gcovs);
+        }
+        let (graph, warnings) = ui::capture_warnings(|| -> cov::Result<Graph> {
+            let graph = create_graph(&config.gcno_path, &config.gcda_path, imported, &mut interner, pruner.as_mut(), &mut timings)?;
+            print_parse_warnings(&graph, &interner, config.verbose);
+            Ok(graph)
+        });
+        let graph = graph.chain_err(|| "Cannot create graph")?;
+        if let Some(pruner) = pruner {
+            // The time saved cannot be measured without building the full graph, so report the time actually spent.
+            let prune_time = timings.phases().into_iter().find(|p| p.phase == "prune").map_or_else(Default::default, |p| p.total);
+            progress!(
+                "Pruned",
+                "{} of {} functions from the sysroot ({} records skipped) in {}, the graph was ready {} after starting",
+                pruner.functions_pruned(),
+                pruner.functions_count,
+                pruner.records_pruned,
+                format_duration(prune_time),
+                format_duration(timings.elapsed())
+            );
+        }
+        let report = timings.measure("report", None, || graph.report());
+        // Still count the warnings towards `--strict` of the enclosing capture.
+        for warning in &warnings {
+            ui::record_warning(warning.clone());
+        }
+        (Some(graph), report, warnings)
+    };
+    // Written again even if reused, since the output directory has been cleaned.
+    if let Some(digest) = digest {
+        ReportCache::new(digest, &interner, report.clone(), warnings).save(&report_cache_path)?;
+    }
+    // Merged after caching as well, see the `lcov` module.
+    for (lcov_path, content) in tracefiles {
+        let count = timings.measure("import", Some(lcov_path), || lcov::import(lcov_path, &content, &config.workspace_path, &mut report, &mut interner))?;
+        progress!("Imported", "{} source files from {}", count, lcov_path.display());
+    }
+    // Filtered after caching, so the cache can be reused with another `--only-path`.
+    if config.only_path.is_some() {
+        report.files.retain(|&symbol, _| config.includes_path(&interner[symbol]));
+    }
+    // The lines are kept, since a line may belong to several functions.
+    if config.functions_regex.is_some() || config.exclude_functions_regex.is_some() {
+        for file in report.files.values_mut() {
+            file.functions.retain(|f| config.includes_function(&interner[f.name]));
+        }
+    }
 
     if coveralls {
         let mut coveralls_report =
@@ -199,38 +750,565 @@ pub fn generate(config: &ReportConfig, coveralls: bool) -> Result<Option<PathBuf
 
     }
 
-    render(config, &report, &interner).chain_err(|| "Cannot render report")
+    let mut classifier = SourceClassifier::new(config.language, config.workspace_path.to_string_lossy().into_owned());
+
+    let history = match config.history_path {
+        Some(ref history_path) => {
+            let mut history = History::open(history_path)?;
+            // Only the files shown in the report are recorded, e.g. not the sources of the dependencies.
+            let shown_files = report.files.iter().filter(|&(&symbol, _)| config.allowed_source_types.contains(classifier.identify(symbol, &interner).0));
+            history.record(shown_files.map(|(&symbol, file)| (&interner[symbol], file.summary())), config.history_limit);
+            history.save(history_path)?;
+            outcome.written.push(history_path.clone());
+            Some(history)
+        },
+        _ => None,
+    };
+
+    let waived_lines = if config.waivers.is_empty() && !config.fail_on_unwaived_uncovered {
+        None
+    } else {
+        let function_lines = match graph {
+            Some(ref graph) if config.waivers.iter().any(|w| w.function.is_some()) => graph.function_lines(),
+            _ => Vec::new(),
+        };
+        Some(waivers::apply(config, &report, &function_lines, &interner)?)
+    };
+
+    if config.attribute_inlined {
+        let graph = graph.as_ref().expect("graph created for --attribute-inlined");
+        let path = write_inlined(config, graph, &interner, &mut classifier)?;
+        outcome.written.push(path);
+    }
+
+    outcome.summary_path = render(config, &report, &interner, &mut classifier, history.as_ref(), waived_lines.as_ref(), &mut outcome.written, &mut timings)
+        .chain_err(|| "Cannot render report")?;
+    for &name in &config.emit {
+        // The template override is specific to the main template.
+        let emit_config = ReportConfig {
+            template_name: OsStr::new(name),
+            template_override_path: None,
+            changed_only: false,
+            ..config.clone()
+        };
+        render(&emit_config, &report, &interner, &mut classifier, history.as_ref(), waived_lines.as_ref(), &mut outcome.written, &mut timings)
+            .chain_err(|| format!("Cannot render the `{}` report", name))?;
+    }
+    if config.gcov_files && config.template_name != "gcov" {
+        write_gcov_files(config, &report, &interner, &mut classifier, &mut outcome.written, &mut timings).chain_err(|| "Cannot write the gcov files")?;
+    }
+    if config.single_file {
+        let index_path = match outcome.summary_path {
+            Some(ref path) if path.extension().map_or(false, |e| e == "html") => path.clone(),
+            _ => config.output_path.join("index.html"),
+        };
+        let bundle_path = bundle::bundle(&config.output_path, &index_path).chain_err(|| "Cannot bundle the report into a single file")?;
+        progress!("Created", "{}", bundle_path.display());
+        outcome.written.push(bundle_path);
+    }
+    if config.azure {
+        let azure_path = config.output_path.join(bundle::AZURE_DIR_NAME);
+        let pages = bundle::inline_pages(&config.output_path, &azure_path).chain_err(|| "Cannot copy the report for Azure Pipelines")?;
+        progress!("Created", "{} ({} pages)", azure_path.display(), pages.len());
+        outcome.written.extend(pages);
+    }
+
+    let mut crates = BTreeMap::new();
+    for (&symbol, file) in &report.files {
+        let (source_type, prefix_len) = classifier.identify(symbol, &interner);
+        if config.allowed_source_types.contains(source_type) {
+            let summary = file.summary();
+            outcome.totals += summary;
+            outcome.summaries.insert(interner[symbol].to_owned(), summary);
+            let name = GroupBy::Crate.group_name(&interner[symbol], source_type, prefix_len, config.language);
+            let entry = crates.entry(name).or_insert((0, FileSummary::default()));
+            entry.0 += 1;
+            entry.1 += summary;
+        }
+    }
+    outcome.crates = crates
+        .into_iter()
+        .map(|(name, (files_count, summary))| CrateCoverage { name, files_count, summary })
+        .collect();
+
+    if config.github_annotations {
+        outcome.uncovered_changes = find_uncovered_changes(config, &report, &interner, &mut classifier);
+    }
+
+    if let Some(ref codeowners_path) = config.codeowners_path {
+        summarize_owners(config, codeowners_path, &report, &interner, &mut outcome)?;
+    }
+
+    outcome.phases = timings.phases();
+    if config.timings {
+        ui::print_timings(&outcome.phases, timings.elapsed())?;
+    }
+    if let Some(ref trace_path) = config.timings_trace_path {
+        timings.write_trace(trace_path).chain_err(|| format!("Cannot write timings at `{}`", trace_path.display()))?;
+        progress!("Created", "{}", trace_path.display());
+        outcome.written.push(trace_path.to_path_buf());
+    }
+
+    if let Some(waived_lines) = waived_lines {
+        let waived_count = waived_lines.waived.iter().map(Vec::len).sum::<usize>();
+        progress!("Waived", "{} uncovered lines, {} not waived", waived_count, waived_lines.unwaived.len());
+        if config.fail_on_unwaived_uncovered {
+            #[cfg_attr(feature = "cargo-clippy", allow(cast_precision_loss))]
+            outcome.thresholds.push(ThresholdCheck {
+                threshold: Threshold::UnwaivedUncovered {
+                    first: waived_lines.unwaived.first().map(|&(symbol, line)| format!("{}:{}", &interner[symbol], line)),
+                },
+                value: waived_lines.unwaived.len() as f64,
+                threshold_value: 0.0,
+                passed: waived_lines.unwaived.is_empty(),
+            });
+        }
+    }
+
+    Ok(outcome)
+}
+
+/// Finds the uncovered lines of the files changed since `--diff-base`, or since the base branch of the pull request when
+/// running on GitHub Actions.
+fn find_uncovered_changes(config: &ReportConfig, report: &Report, interner: &Interner, classifier: &mut SourceClassifier) -> Vec<UncoveredLine> {
+    let base = match (config.diff_base, env::var("GITHUB_BASE_REF")) {
+        (Some(base), _) => Cow::Borrowed(base),
+        (None, Ok(ref base_ref)) if !base_ref.is_empty() => Cow::Owned(format!("origin/{}", base_ref)),
+        (None, _) => {
+            warning!("--github-annotations needs --diff-base outside of a pull request");
+            return Vec::new();
+        },
+    };
+    let changed_lines = match git::changed_lines(&config.workspace_path, &base) {
+        Some(changed_lines) => changed_lines,
+        None => {
+            warning!("cannot find the lines changed since {}", base);
+            return Vec::new();
+        },
+    };
+    // The diff is relative to the workspace, but the annotations are relative to the root of the repository.
+    let prefix = git::prefix(&config.workspace_path).unwrap_or_default();
+
+    let mut uncovered_changes = Vec::new();
+    for (&symbol, file) in &report.files {
+        let (source_type, _) = classifier.identify(symbol, interner);
+        if !config.allowed_source_types.contains(source_type) {
+            continue;
+        }
+        let path = Path::new(&interner[symbol]);
+        let path = path.strip_prefix(&config.workspace_path).unwrap_or(path).to_string_lossy().replace('\\', "/");
+        if !changed_lines.contains_key(&path) {
+            continue;
+        }
+        let path = format!("{}{}", prefix, path);
+        for (&line_number, line) in &file.lines {
+            if line.count == 0 {
+                uncovered_changes.push(UncoveredLine { path: path.clone(), line: line_number });
+            }
+        }
+    }
+    uncovered_changes.sort();
+    uncovered_changes
+}
+
+/// Renders the built-in `gcov` template into `<output>/gcov/`, next to the main report.
+fn write_gcov_files(
+    config: &ReportConfig,
+    report: &Report,
+    interner: &Interner,
+    classifier: &mut SourceClassifier,
+    written: &mut Vec<PathBuf>,
+    timings: &mut Timings,
+) -> Result<()> {
+    let gcov_path = config.output_path.join("gcov");
+    create_dir_all(&gcov_path)?;
+    // The options and overrides of the main template do not apply to the gcov template.
+    let gcov_config = ReportConfig {
+        output_path: Cow::Owned(gcov_path),
+        template_name: OsStr::new("gcov"),
+        template_override_path: None,
+        template_options: Vec::new(),
+        changed_only: false,
+        ..config.clone()
+    };
+    let count = written.len();
+    render(&gcov_config, report, interner, classifier, None, None, written, timings)?;
+    progress!("Created", "{}/*.gcov for {} source files", gcov_config.output_path.display(), written.len() - count);
+    Ok(())
+}
+
+/// Aggregates the coverage per owner listed in the `CODEOWNERS` file, writes the result to `owners.json` and checks
+/// the owner thresholds.
+fn summarize_owners(config: &ReportConfig, codeowners_path: &Path, report: &Report, interner: &Interner, outcome: &mut ReportOutcome) -> Result<()> {
+    let code_owners = CodeOwners::open(codeowners_path)?;
+    let workspace_str = config.workspace_path.to_string_lossy();
+    let files = report.files.iter().map(|(&symbol, file)| (&interner[symbol], file.summary()));
+    let owner_coverages = owners::aggregate(&code_owners, &workspace_str, files);
+
+    ui::print_owner_table(&owner_coverages, config.rounding)?;
+
+    let path = config.output_path.join("owners.json");
+    let file = File::create(&path)?;
+    ::serde_json::to_writer_pretty(file, &owner_coverages)?;
+    progress!("Created", "{}", path.display());
+    outcome.written.push(path);
+
+    outcome.thresholds.extend(owners::check_thresholds(&owner_coverages, &config.owner_thresholds, config.rounding));
+    Ok(())
+}
+
+/// Writes `inlined.json`, attributing the lines inlined from non-local source files to the local functions they are
+/// inlined into.
+///
+/// Only inlining is tracked this way. A generic function instantiated by the local crate but not inlined still counts
+/// under the crate defining it, since the GCNO does not record its caller.
+fn write_inlined(config: &ReportConfig, graph: &Graph, interner: &Interner, classifier: &mut SourceClassifier) -> Result<PathBuf> {
+    let mut entries = Vec::new();
+    let mut callers = HashSet::new();
+    let mut lines_count = 0;
+    for inlined in graph.inlined_lines() {
+        if classifier.identify(inlined.filename, interner).0 != SourceType::LOCAL {
+            continue;
+        }
+        let inlined_source_type = classifier.identify(inlined.inlined_filename, interner).0;
+        if inlined_source_type == SourceType::LOCAL {
+            continue;
+        }
+        let name = &interner[inlined.name];
+        let function = match config.language {
+            Language::Rust => format!("{:#}", demangle(name)),
+            Language::C => name.to_owned(),
+        };
+        callers.insert((inlined.filename, inlined.name));
+        lines_count += inlined.lines.len();
+        entries.push(json!({
+            "path": &interner[inlined.filename],
+            "function": function,
+            "line": inlined.line,
+            "inlined_path": &interner[inlined.inlined_filename],
+            "inlined_source_type": inlined_source_type.name(),
+            "lines": inlined.lines,
+        }));
+    }
+
+    let path = config.output_path.join("inlined.json");
+    ::serde_json::to_writer_pretty(File::create(&path)?, &entries)?;
+    progress!("Attributed", "{} inlined lines to {} local functions", lines_count, callers.len());
+    progress!("Created", "{}", path.display());
+    Ok(path)
+}
+
+/// Collects the coverage the same way as [`generate()`], without the cache, the pruning or any output. This is used by
+/// the subcommands which only look at the coverage, e.g. `cargo cov tui`.
+///
+/// The files given by `--import` are merged into the graph and those given by `--import-lcov` into the report, after
+/// verifying their signatures with `--verify-key`.
+///
+/// [`generate()`]: ./fn.generate.html
+pub fn collect(config: &ReportConfig, interner: &mut Interner) -> Result<Report> {
+    let mut timings = Timings::new();
+    let mut imported = Vec::new();
+    for (import_path, content) in read_imports(config.import_paths, config.verify_key_path)? {
+        imported.extend(export::import(import_path, &content, &config.workspace_path, interner)?);
+    }
+    let tracefiles = read_imports(config.import_lcov_paths, config.verify_key_path)?;
+    let graph = create_graph(&config.gcno_path, &config.gcda_path, imported, interner, None, &mut timings).chain_err(|| "Cannot create graph")?;
+    let mut report = graph.report();
+    for (lcov_path, content) in tracefiles {
+        lcov::import(lcov_path, &content, &config.workspace_path, &mut report, interner)?;
+    }
+    Ok(report)
+}
+
+/// Reads the files given by `--import` or `--import-lcov`, verifying their signatures if `verify_key_path` is given, see
+/// the [`signing` module](../signing/index.html).
+pub fn read_imports<'a>(paths: &'a [PathBuf], verify_key_path: Option<&Path>) -> Result<Vec<(&'a Path, Vec<u8>)>> {
+    paths.iter().map(|path| Ok((&**path, signing::read(path, verify_key_path)?))).collect()
 }
 
-/// Creates an analyzed [`Graph`] from all GCNO and GCDA inside the `target/cov/build` folder.
+/// Creates an analyzed [`Graph`] from all GCNO and GCDA inside the given folders, usually `target/cov/build/gcno` and
+/// `target/cov/build/gcda`.
+///
+/// A GCDA recorded with a [crate hash] not matching any GCNO is stale (e.g. produced by a crate with the same name but
+/// different metadata which has since been rebuilt or cleaned), and is skipped with a warning.
+///
+/// The `imported` GCNOs and GCDAs (see [`export::import()`]) are merged together with the local files.
+///
+/// If a `pruner` is provided, the functions from the sysroot will be removed before merging.
 ///
 /// [`Graph`]: ../../cov/graph/struct.Graph.html
-fn create_graph(config: &ReportConfig, interner: &mut Interner) -> cov::Result<Graph> {
+/// [crate hash]: ../shim/fn.artifact_hash.html
+/// [`export::import()`]: ../export/fn.import.html
+pub fn create_graph(
+    gcno_path: &Path,
+    gcda_path: &Path,
+    imported: Vec<Gcov>,
+    interner: &mut Interner,
+    mut pruner: Option<&mut Pruner>,
+    timings: &mut Timings,
+) -> cov::Result<Graph> {
     let mut graph = Graph::default();
+    let (imported_gcnos, imported_gcdas): (Vec<_>, Vec<_>) = imported.into_iter().partition(|gcov| gcov.ty == Type::Gcno);
+
+    {
+        // All GCNOs need to be merged before the GCDAs.
+        let mut merge = |mut gcov: Gcov, interner: &mut Interner, timings: &mut Timings| -> cov::Result<()> {
+            let path = gcov.src.clone().unwrap_or_default();
+            if let Some(ref mut pruner) = pruner {
+                timings.measure("prune", Some(&path), || pruner.prune(&mut gcov, interner));
+            }
+            timings.measure("merge", Some(&path), || graph.merge(gcov))
+        };
+        for gcov in imported_gcnos {
+            merge(gcov, interner, timings)?;
+        }
+        for_each_gcov(gcno_path, gcda_path, interner, timings, &mut merge)?;
+        for gcov in imported_gcdas {
+            merge(gcov, interner, timings)?;
+        }
+    }
+
+    timings.measure("analyze", None, || graph.analyze());
+    Ok(graph)
+}
 
-    for &(extension, dir_path) in &[("gcno", &config.gcno_path), ("gcda", &config.gcda_path)] {
+/// Prints the recoverable anomalies found while merging the GCNO and GCDA files into the `graph`, see the [`warning`
+/// module]. They are only logged for debugging unless `verbose`, since they seldom affect the report.
+///
+/// [`warning` module]: ../../cov/warning/index.html
+fn print_parse_warnings(graph: &Graph, interner: &Interner, verbose: bool) {
+    for warning in graph.warnings() {
+        if verbose {
+            warning!("{}", warning.describe(interner));
+        } else {
+            debug!("{}", warning.describe(interner));
+        }
+    }
+}
+
+/// Lists the GCDA files in `gcda_path` recorded with a [crate hash] not matching any GCNO in `gcno_path`, i.e. those
+/// skipped by [`for_each_gcov()`]. Such a file is usually written by an old test program still on disk, which is found
+/// from the [attribution log] if it was run by `cargo cov test`.
+///
+/// [crate hash]: ../shim/fn.artifact_hash.html
+/// [`for_each_gcov()`]: ./fn.for_each_gcov.html
+/// [attribution log]: ../ordering/index.html
+pub fn find_unattributed(gcno_path: &Path, gcda_path: &Path) -> Result<Vec<Value>> {
+    let mut gcno_hashes = GcnoHashes::default();
+    for file in archive::list(gcno_path, "gcno")? {
+        gcno_hashes.insert(&file.path);
+    }
+
+    let mut producing_programs = None;
+    let mut unattributed = Vec::new();
+    for file in archive::list(gcda_path, "gcda")? {
+        let hash = match gcno_hashes.stale_hash(&file.path) {
+            Some(hash) => hash,
+            None => continue,
+        };
+        // The attribution log is only read if needed, which is rare.
+        if producing_programs.is_none() {
+            producing_programs = Some(ordering::producing_programs(gcda_path)?);
+        }
+        let programs = file
+            .path
+            .file_name()
+            .and_then(|name| producing_programs.as_ref().and_then(|p| p.get(&*name.to_string_lossy())));
+        unattributed.push(json!({
+            "path": file.path,
+            "crate_hash": hash,
+            "programs": programs,
+        }));
+    }
+    Ok(unattributed)
+}
+
+/// Parses all GCNO and GCDA inside the given folders or archives, and calls `f` with each of them, all GCNOs first.
+///
+/// A GCDA recorded with a [crate hash] not matching any GCNO is stale, and is skipped with a warning.
+///
+/// [crate hash]: ../shim/fn.artifact_hash.html
+pub fn for_each_gcov<F>(gcno_path: &Path, gcda_path: &Path, interner: &mut Interner, timings: &mut Timings, mut f: F) -> cov::Result<()>
+where
+    F: FnMut(Gcov, &mut Interner, &mut Timings) -> cov::Result<()>,
+{
+    let mut gcno_hashes = GcnoHashes::default();
+
+    for &(extension, dir_path) in &[("gcno", gcno_path), ("gcda", gcda_path)] {
         progress!("Parsing", "{}/*.{}", dir_path.display(), extension);
-        for entry in read_dir(dir_path)? {
+        // Every archive is read in a single pass.
+        archive::for_each(dir_path, extension, |file, reader| -> cov::Result<()> {
+            let path = &file.path;
+            if extension == "gcno" {
+                gcno_hashes.insert(path);
+            } else if gcno_hashes.skips(path) {
+                return Ok(());
+            }
+            trace!("merging {} {:?}", extension, path);
+            let gcov = timings.measure("parse", Some(path), || Gcov::read(reader, path.clone(), interner))?;
+            f(gcov, interner, timings)
+        })?;
+    }
+
+    Ok(())
+}
+
+/// The context schema version provided by this version of `cargo cov`, see the [module documentation]. The version is
+/// increased whenever the data given to the templates changes incompatibly.
+///
+/// [module documentation]: ./index.html#context-schema-version
+pub const TEMPLATE_SCHEMA: u32 = 1;
+
+/// The oldest context schema version still supported.
+pub const MIN_TEMPLATE_SCHEMA: u32 = 1;
+
+/// Ensures the template at `path` is written against a supported context schema version.
+///
+/// # Errors
+///
+/// Returns [`IncompatibleTemplate`] if the `schema` is not supported.
+///
+/// [`IncompatibleTemplate`]: ../error/enum.ErrorKind.html#variant.IncompatibleTemplate
+fn check_schema(path: &str, schema: u32) -> Result<()> {
+    ensure!(
+        MIN_TEMPLATE_SCHEMA <= schema && schema <= TEMPLATE_SCHEMA,
+        ErrorKind::IncompatibleTemplate(path.to_owned(), schema, MIN_TEMPLATE_SCHEMA, TEMPLATE_SCHEMA)
+    );
+    Ok(())
+}
+
+/// Replaces parts of the built-in template with the files in `override_path`.
+///
+/// Every file in `override_path/tera/` replaces (or adds) the template of the same name, and every file in
+/// `override_path/static/` is copied into `static_path`, overwriting the built-in resources.
+///
+/// If `sandboxed`, every file read must resolve to a path inside `override_path`.
+fn apply_template_override(tera: &mut Tera, override_path: &Path, static_path: &Path, sandboxed: bool) -> Result<()> {
+    let sandbox_path = if sandboxed { Some(override_path.canonicalize()?) } else { None };
+    let check_sandbox = |path: &Path| -> Result<()> {
+        if let Some(ref sandbox_path) = sandbox_path {
+            ensure!(path.canonicalize()?.starts_with(sandbox_path), ErrorKind::TemplateOutsideSandbox(path.to_owned()));
+        }
+        Ok(())
+    };
+
+    let config_path = override_path.join("config.toml");
+    if config_path.is_file() {
+        check_sandbox(&config_path)?;
+        let mut config_bytes = Vec::new();
+        File::open(&config_path)?.read_to_end(&mut config_bytes)?;
+        let override_config: OverrideConfig = ::toml::de::from_slice(&config_bytes).chain_err(|| "Cannot read override configuration")?;
+        if let Some(schema) = override_config.schema {
+            check_schema(&override_path.to_string_lossy(), schema)?;
+        }
+    }
+
+    let tera_path = override_path.join("tera");
+    if tera_path.is_dir() {
+        let mut files = Vec::new();
+        for entry in read_dir(&tera_path)? {
             let path = entry?.path();
-            if path.extension() == Some(OsStr::new(extension)) {
-                trace!("merging {} {:?}", extension, path);
-                graph.merge(Gcov::open(path, interner)?)?;
+            if path.is_file() {
+                check_sandbox(&path)?;
+                let name = path.file_name().and_then(OsStr::to_str).expect("UTF-8 template name").to_owned();
+                files.push((path, name));
             }
         }
+        tera.add_template_files(files.iter().map(|&(ref path, ref name)| (path, Some(&**name))).collect())?;
     }
 
-    graph.analyze();
-    Ok(graph)
+    let override_static_path = override_path.join("static");
+    if override_static_path.is_dir() {
+        if sandbox_path.is_some() {
+            for entry in WalkDir::new(&override_static_path).follow_links(true) {
+                check_sandbox(entry?.path())?;
+            }
+        }
+        let mut items = Vec::new();
+        for entry in read_dir(&override_static_path)? {
+            items.push(entry?.path());
+        }
+        let mut options = dir::CopyOptions::new();
+        options.overwrite = true;
+        create_dir_all(static_path)?;
+        copy_items(&items, static_path, &options)?;
+    }
+
+    Ok(())
+}
+
+/// Writes the color theme `name` of the template to `static_path/theme.css`.
+///
+/// The themes are the files `static_path/themes/<name>.css`, including those added by the template override. The
+/// `auto` theme is composed of `light` and `dark`, picking the latter when the browser prefers a dark color scheme.
+/// Does nothing if the template has no themes.
+fn apply_theme(static_path: &Path, name: &str) -> Result<()> {
+    let themes_path = static_path.join("themes");
+    if !themes_path.is_dir() {
+        return Ok(());
+    }
+
+    let read_theme = |name: &str| -> Result<Option<String>> {
+        let path = themes_path.join(name).with_extension("css");
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let mut css = String::new();
+        File::open(&path)?.read_to_string(&mut css)?;
+        Ok(Some(css))
+    };
+
+    let css = match (name, read_theme(name)?) {
+        (_, Some(css)) => css,
+        ("auto", None) => match (read_theme("light")?, read_theme("dark")?) {
+            (Some(light), Some(dark)) => {
+                let dark = dark.lines().map(|line| if line.is_empty() { String::new() } else { format!("    {}", line) }).collect::<Vec<_>>().join("\n");
+                format!("{}\n@media (prefers-color-scheme: dark) {{\n{}\n}}\n", light, dark)
+            },
+            _ => bail!(ErrorKind::UnknownTheme(name.to_owned(), theme_names(&themes_path)?.join(", "))),
+        },
+        (_, None) => bail!(ErrorKind::UnknownTheme(name.to_owned(), theme_names(&themes_path)?.join(", "))),
+    };
+    File::create(static_path.join("theme.css"))?.write_all(css.as_bytes())?;
+    Ok(())
+}
+
+/// Lists the names of the themes in `themes_path`, sorted.
+fn theme_names(themes_path: &Path) -> Result<Vec<String>> {
+    let mut names = Vec::new();
+    for entry in read_dir(themes_path)? {
+        let path = entry?.path();
+        if path.extension() == Some(OsStr::new("css")) {
+            names.extend(path.file_stem().map(|stem| stem.to_string_lossy().into_owned()));
+        }
+    }
+    if names.iter().any(|name| name == "light") && names.iter().any(|name| name == "dark") {
+        names.push("auto".to_owned());
+    }
+    names.sort();
+    names.dedup();
+    Ok(names)
 }
 
 /// Renders the `report` into `report_path` using a template.
 ///
 /// If the template has a summary page, returns the path of the rendered summary.
-fn render(config: &ReportConfig, report: &Report, interner: &Interner) -> Result<Option<PathBuf>> {
+fn render(
+    config: &ReportConfig,
+    report: &Report,
+    interner: &Interner,
+    classifier: &mut SourceClassifier,
+    history: Option<&History>,
+    waived_lines: Option<&WaivedLines>,
+    written: &mut Vec<PathBuf>,
+    timings: &mut Timings,
+) -> Result<Option<PathBuf>> {
     use toml::de::from_slice;
 
-    let mut template_path = [env!("CARGO_MANIFEST_DIR"), "res", "templates"].iter().collect::<PathBuf>();
-    template_path.push(config.template_name);
+    let template_dir = TempDir::new().chain_err(|| "Cannot create temporary directory for the template")?;
+    let template_name = config.template_name.to_str().ok_or_else(|| ErrorKind::UnknownTemplate(config.template_name.to_string_lossy().into_owned(), assets::template_names().join(", ")))?;
+    assets::materialize(template_name, template_dir.path())?;
+    let mut template_path = template_dir.path().to_owned();
     trace!("using templates at {:?}", template_path);
 
     // Read the template configuration.
@@ -239,33 +1317,45 @@ fn render(config: &ReportConfig, report: &Report, interner: &Interner) -> Result
     let mut config_bytes = Vec::new();
     config_file.read_to_end(&mut config_bytes)?;
     let template_config: Config = from_slice(&config_bytes).chain_err(|| "Cannot read template configuration")?;
+    check_schema(template_name, template_config.schema)?;
 
     // Copy the static resources if exist.
     template_path.set_file_name("static");
     if template_path.is_dir() {
         let mut options = dir::CopyOptions::new();
         options.copy_inside = true;
+        options.overwrite = true;
         dir::copy(&template_path, config.output_path.join("static"), &options)?;
     }
 
     template_path.set_file_name("tera");
     template_path.push("*");
 
-    let workspace_str = config.workspace_path.to_string_lossy();
+    let mut tera = new_template(template_path.to_str().expect("UTF-8 template path"), config.rounding)?;
+    if let Some(ref override_path) = config.template_override_path {
+        apply_template_override(&mut tera, override_path, &config.output_path.join("static"), !config.allow_template_fs)
+            .chain_err(|| format!("Cannot apply template override at `{}`", override_path.display()))?;
+    }
+    apply_theme(&config.output_path.join("static"), config.theme.unwrap_or("light"))?;
 
-    let mut tera = new_template(template_path.to_str().expect("UTF-8 template path"))?;
+    let mut options = template_config.options;
+    for &(name, ref value) in &config.template_options {
+        options.insert(name.to_owned(), value.clone());
+    }
 
+    let group_by = config.group_by.or(template_config.group_by).unwrap_or_default();
     let mut report_files = report
         .files
         .iter()
         .filter_map(|(&symbol, file)| {
             let path = &interner[symbol];
-            let source_type = identify_source_path(path, &workspace_str).0;
+            let (source_type, prefix_len) = classifier.identify(symbol, interner);
             if config.allowed_source_types.contains(source_type) {
                 Some(ReportFileEntry {
                     symbol,
                     source_type,
                     path,
+                    group: group_by.group_name(path, source_type, prefix_len, config.language),
                     file,
                 })
             } else {
@@ -273,18 +1363,97 @@ fn render(config: &ReportConfig, report: &Report, interner: &Interner) -> Result
             }
         })
         .collect::<Vec<_>>();
-    report_files.sort_by_key(|entry| (entry.source_type, entry.path));
+    if group_by == GroupBy::SourceType {
+        report_files.sort_by_key(|entry| (entry.source_type, entry.path));
+    } else {
+        report_files.sort_by(|a, b| (&a.group, a.source_type, a.path).cmp(&(&b.group, b.source_type, b.path)));
+    }
+
+    let cache_path = config.output_path.join(cache::CACHE_FILE_NAME);
+    let mut previous_cache = if config.changed_only {
+        PageCache::open(&cache_path)?
+    } else {
+        PageCache::default()
+    };
+    let cached_summaries = replace(&mut previous_cache.summaries, BTreeMap::new());
+    let previous_summaries = match config.previous_summaries {
+        Some(ref summaries) if config.changed_only => Some(summaries),
+        _ if cached_summaries.is_empty() => None,
+        _ => Some(&cached_summaries),
+    };
 
     let summary_path = if let Some(summary) = template_config.summary {
-        Some(write_summary(config, &report_files, &tera, &summary).chain_err(|| "Cannot write summary")?)
+        let path = write_summary(config, interner, &report_files, group_by, &tera, &summary, &options, history, previous_summaries, waived_lines, timings)
+            .chain_err(|| "Cannot write summary")?;
+        written.push(path.clone());
+        Some(path)
     } else {
         None
     };
 
+    for page_config in &template_config.pages {
+        if (page_config.diff && config.diff_base.is_none()) || (page_config.reachability && !config.reachability) {
+            continue;
+        }
+        let path = write_summary(
+            config,
+            interner,
+            &report_files,
+            group_by,
+            &tera,
+            page_config,
+            &options,
+            history,
+            previous_summaries,
+            waived_lines,
+            timings,
+        ).chain_err(|| format!("Cannot write page `{}`", page_config.output))?;
+        written.push(path);
+    }
+
     if let Some(files_config) = template_config.files {
         tera.add_raw_template("<filename>", files_config.output)?;
+        let permalink_prefix = config.permalink_base.and_then(|base| {
+            let prefix = git::permalink_prefix(&config.workspace_path, base);
+            if prefix.is_none() {
+                warning!("cannot find the commit of `{}` on `{}`, the lines are not linked", config.workspace_path.display(), base);
+            }
+            prefix
+        });
+
+        let template_fingerprint = cache::fingerprint(&(template_name, &config.template_override_path, Value::Object(options.clone()).to_string()));
+        if previous_cache.template != template_fingerprint {
+            previous_cache.pages.clear();
+        }
+
+        let mut cache = PageCache {
+            template: template_fingerprint,
+            pages: BTreeMap::new(),
+            summaries: report_files.iter().map(|entry| (entry.path.to_owned(), entry.file.summary())).collect(),
+        };
+        let mut reused_count = 0;
         for entry in &report_files {
-            write_file(config, interner, entry, &tera, files_config.template).chain_err(|| format!("Cannot write file at `{}`", entry.path))?;
+            let previous = previous_cache.pages.get(entry.path);
+            let (page, reused) = write_file(config, interner, entry, &tera, &files_config, &options, permalink_prefix.as_ref().map(|p| &**p), previous, timings)
+                .chain_err(|| format!("Cannot write file at `{}`", entry.path))?;
+            reused_count += reused as usize;
+            written.push(config.output_path.join(&page.output));
+            cache.pages.insert(entry.path.to_owned(), page);
+        }
+
+        // Remove the pages of files no longer in the report.
+        for (path, page) in &previous_cache.pages {
+            if !cache.pages.contains_key(path) && !cache.pages.values().any(|p| p.output == page.output) {
+                let stale_path = config.output_path.join(&page.output);
+                if stale_path.is_file() {
+                    remove_file(&stale_path)?;
+                }
+            }
+        }
+
+        cache.save(&cache_path)?;
+        if config.changed_only {
+            progress!("Reused", "{} of {} file pages unchanged since the previous report", reused_count, report_files.len());
         }
     }
 
@@ -295,15 +1464,27 @@ struct ReportFileEntry<'a> {
     symbol: Symbol,
     source_type: SourceType,
     path: &'a str,
+    group: String,
     file: &'a ::cov::report::File,
 }
 
 #[derive(Deserialize, Debug)]
 struct Config<'a> {
+    #[serde(default)]
+    schema: u32,
     #[serde(borrow)]
     summary: Option<FileConfig<'a>>,
     #[serde(borrow)]
     files: Option<FileConfig<'a>>,
+    #[serde(default, borrow)]
+    pages: Vec<FileConfig<'a>>,
+    #[serde(default)]
+    options: Map<String, Value>,
+    group_by: Option<GroupBy>,
+}
+#[derive(Deserialize, Debug)]
+struct OverrideConfig {
+    schema: Option<u32>,
 }
 #[derive(Deserialize, Debug)]
 struct FileConfig<'a> {
@@ -311,35 +1492,342 @@ struct FileConfig<'a> {
     output: &'a str,
     #[serde(borrow)]
     template: &'a str,
+    #[serde(default)]
+    detailed: bool,
+    #[serde(default)]
+    sources: bool,
+    #[serde(default)]
+    git: bool,
+    #[serde(default)]
+    highlight: bool,
+    #[serde(default)]
+    diff: bool,
+    #[serde(default)]
+    reachability: bool,
 }
 
 /// Renders the summary page.
-fn write_summary(config: &ReportConfig, report_files: &[ReportFileEntry], tera: &Tera, file_config: &FileConfig) -> Result<PathBuf> {
+fn write_summary(
+    config: &ReportConfig,
+    interner: &Interner,
+    report_files: &[ReportFileEntry],
+    group_by: GroupBy,
+    tera: &Tera,
+    file_config: &FileConfig,
+    options: &Map<String, Value>,
+    history: Option<&History>,
+    previous_summaries: Option<&BTreeMap<String, FileSummary>>,
+    waived_lines: Option<&WaivedLines>,
+    timings: &mut Timings,
+) -> Result<PathBuf> {
     let path = config.output_path.join(file_config.output);
     let mut context = Context::new();
 
-    let files = report_files
+    let shown_files = select_files(report_files, config.max_files, config.top_uncovered, config.rounding);
+    let changed_lines = config.diff_base.and_then(|base| {
+        let changed_lines = git::changed_lines(&config.workspace_path, base);
+        if changed_lines.is_none() {
+            warning!("cannot find the lines changed since {}", base);
+        }
+        changed_lines
+    });
+    let files = shown_files
         .iter()
         .map(|entry| {
-            json!({
+            let mut file = json!({
                 "symbol": entry.symbol,
                 "path": entry.path,
+                "source_type": entry.source_type.name(),
+                "group": entry.group,
                 "summary": entry.file.summary(),
+            });
+            if file_config.detailed {
+                let lines = entry
+                    .file
+                    .lines
+                    .iter()
+                    .map(|(line_number, line)| {
+                        let (count, branches) = serialize_line(line, interner);
+                        json!({
+                            "line": *line_number,
+                            "count": count,
+                            "branches": branches,
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                file["lines"] = Value::Array(lines);
+                file["functions"] = Value::Array(serialize_functions(entry, interner, config.language));
+            }
+            if file_config.sources {
+                file["source"] = serialize_source(&config.workspace_path.join(entry.path));
+            }
+            if let Some(ref changed_lines) = changed_lines {
+                let path = Path::new(entry.path).strip_prefix(&config.workspace_path).unwrap_or_else(|_| Path::new(entry.path));
+                file["changed_lines"] = changed_lines.get(&*path.to_string_lossy().replace('\\', "/")).map_or_else(|| json!([]), |lines| json!(lines));
+            }
+            if let Some(history) = history {
+                file["history"] = json!(history.line_trend(entry.path));
+            }
+            if let Some(previous_summaries) = previous_summaries {
+                file["delta"] = previous_summaries.get(entry.path).map_or(Value::Null, |previous| serialize_delta(&entry.file.summary(), previous, config.rounding));
+            }
+            file
+        })
+        .collect::<Vec<_>>();
+
+    let mut totals = FileSummary::default();
+    for entry in report_files {
+        totals += entry.file.summary();
+    }
+    // The files are usually sorted by group, unless ranked by `--max-files` or `--top-uncovered`.
+    let mut groups: Vec<(&str, FileSummary, Vec<usize>)> = Vec::new();
+    for (i, entry) in shown_files.iter().enumerate() {
+        let index = match groups.iter().rposition(|g| g.0 == entry.group) {
+            Some(index) => index,
+            None => {
+                groups.push((&entry.group, FileSummary::default(), Vec::new()));
+                groups.len() - 1
+            },
+        };
+        let group = &mut groups[index];
+        group.1 += entry.file.summary();
+        group.2.push(i);
+    }
+    let groups = groups
+        .into_iter()
+        .map(|(name, summary, files)| {
+            json!({
+                "name": name,
+                "summary": summary,
+                "files": files,
             })
         })
         .collect::<Vec<_>>();
 
     context.add("crate_path", &config.workspace_path);
+    context.add("language", &config.language);
     context.add("files", &files);
-    let rendered = tera.render(file_config.template, &context)?;
-    let mut summary_file = File::create(&path)?;
-    summary_file.write_all(rendered.as_bytes())?;
+    context.add("totals", &totals);
+    context.add("omitted_files", &(report_files.len() - shown_files.len()));
+    context.add("group_by", group_by.name());
+    context.add("groups", &groups);
+    context.add("options", options);
+    if file_config.git {
+        context.add("git", &git::describe(&config.workspace_path));
+    }
+    context.add("unattributed", &find_unattributed(&config.gcno_path, &config.gcda_path)?);
+    context.add("diff_base", &config.diff_base);
+    context.add("reachability_enabled", &config.reachability);
+    if file_config.diff {
+        context.add("diff", &config.diff_base.and_then(|base| serialize_diff(config, report_files, base)));
+    }
+    if file_config.reachability {
+        context.add("reachability", &serialize_reachability(config, report_files, interner));
+    }
+    if let Some(waived_lines) = waived_lines {
+        let waivers = config
+            .waivers
+            .iter()
+            .zip(&waived_lines.waived)
+            .map(|(waiver, lines)| {
+                let lines = lines
+                    .iter()
+                    .map(|&(symbol, line)| {
+                        json!({
+                            "symbol": symbol,
+                            "path": &interner[symbol],
+                            "line": line,
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                json!({
+                    "path": waiver.path,
+                    "function": waiver.function,
+                    "marker": waiver.marker,
+                    "justification": waiver.justification,
+                    "lines": lines,
+                })
+            })
+            .collect::<Vec<_>>();
+        context.add("waivers", &waivers);
+    }
+    let rendered = timings.measure("render", Some(&path), || tera.render(file_config.template, &context))?;
+    timings.measure("write", Some(&path), || File::create(&path)?.write_all(rendered.as_bytes()))?;
     progress!("Created", "{}", path.display());
     Ok(path)
 }
 
+/// Selects the files shown in the summary.
+///
+/// If `max_files` or `top_uncovered` is given, the files are ranked worst first, by the lowest line coverage, or by the
+/// most uncovered lines if `top_uncovered` is set. Only the first `max_files` of them are kept. Otherwise all files are
+/// shown in their original order.
+fn select_files<'a, 'b>(report_files: &'b [ReportFileEntry<'a>], max_files: Option<usize>, top_uncovered: bool, rounding: Rounding) -> Vec<&'b ReportFileEntry<'a>> {
+    let mut files = report_files.iter().collect::<Vec<_>>();
+    if max_files.is_none() && !top_uncovered {
+        return files;
+    }
+
+    let uncovered = |entry: &ReportFileEntry| {
+        let summary = entry.file.summary();
+        summary.lines_count - summary.lines_covered
+    };
+    if top_uncovered {
+        files.sort_by(|a, b| uncovered(b).cmp(&uncovered(a)).then_with(|| a.path.cmp(b.path)));
+    } else {
+        let coverage = |entry: &ReportFileEntry| {
+            let summary = entry.file.summary();
+            rounding.percent(summary.lines_covered, summary.lines_count)
+        };
+        files.sort_by(|a, b| {
+            coverage(a)
+                .partial_cmp(&coverage(b))
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| uncovered(b).cmp(&uncovered(a)))
+                .then_with(|| a.path.cmp(b.path))
+        });
+    }
+    if let Some(max_files) = max_files {
+        files.truncate(max_files);
+    }
+    files
+}
+
+/// Serializes the changes since `base` with the counts of the added lines, see the [module
+/// documentation](./index.html#additional-pages). Returns `None` if the diff cannot be computed.
+fn serialize_diff(config: &ReportConfig, report_files: &[ReportFileEntry], base: &str) -> Option<Value> {
+    let diff_files = match git::diff(&config.workspace_path, base) {
+        Some(diff_files) => diff_files,
+        None => {
+            warning!("cannot find the lines changed since {}", base);
+            return None;
+        },
+    };
+    // The diff is relative to the workspace, the report files are usually absolute.
+    let entries = report_files
+        .iter()
+        .map(|entry| {
+            let path = Path::new(entry.path).strip_prefix(&config.workspace_path).unwrap_or_else(|_| Path::new(entry.path));
+            (path.to_string_lossy().replace('\\', "/"), entry)
+        })
+        .collect::<HashMap<_, _>>();
+
+    let mut totals = (0, 0);
+    let files = diff_files
+        .into_iter()
+        .map(|diff_file| {
+            let entry = entries.get(&diff_file.path);
+            let mut summary = (0, 0);
+            let hunks = diff_file
+                .hunks
+                .into_iter()
+                .map(|hunk| {
+                    let lines = hunk.lines
+                        .into_iter()
+                        .map(|line| {
+                            let mut value = json!(line);
+                            if line.kind == DiffLineKind::Added {
+                                let count = line.new.and_then(|n| entry.and_then(|e| e.file.lines.get(&n))).map(|l| l.count);
+                                if let Some(count) = count {
+                                    summary.0 += 1;
+                                    summary.1 += (count > 0) as usize;
+                                }
+                                value["count"] = json!(count);
+                            }
+                            value
+                        })
+                        .collect::<Vec<_>>();
+                    json!({ "header": hunk.header, "lines": lines })
+                })
+                .collect::<Vec<_>>();
+            totals.0 += summary.0;
+            totals.1 += summary.1;
+            json!({
+                "path": diff_file.path,
+                "symbol": entry.map(|e| e.symbol),
+                "summary": { "lines_count": summary.0, "lines_covered": summary.1 },
+                "hunks": hunks,
+            })
+        })
+        .collect::<Vec<_>>();
+    Some(json!({
+        "files": files,
+        "totals": { "lines_count": totals.0, "lines_covered": totals.1 },
+    }))
+}
+
+/// Serializes the uncovered functions with their reachability from the functions run by the tests, see the [module
+/// documentation](./index.html#additional-pages). Returns `None` if the call graph of the test programs is not found.
+fn serialize_reachability(config: &ReportConfig, report_files: &[ReportFileEntry], interner: &Interner) -> Option<Value> {
+    let graph = reachability::call_graph(&config.gcda_path)?;
+    let functions = report_files
+        .iter()
+        .flat_map(|entry| {
+            // Sorted as in `serialize_functions`, to keep the output deterministic.
+            let mut functions = entry.file.functions.iter().map(|f| (entry, f)).collect::<Vec<_>>();
+            functions.sort_by_key(|&(_, f)| (f.line, f.column, &interner[f.name]));
+            functions
+        })
+        .collect::<Vec<_>>();
+    let entry_points = functions.iter().filter(|&&(_, f)| f.summary.entry_count > 0).map(|&(_, f)| &interner[f.name]);
+    let reached = graph.reach(entry_points);
+
+    let mut reachable_count = 0;
+    let uncovered = functions
+        .iter()
+        .filter(|&&(_, f)| f.summary.entry_count == 0)
+        .map(|&(entry, f)| {
+            let name = &interner[f.name];
+            let via = reached.get(name);
+            reachable_count += via.is_some() as usize;
+            json!({
+                "symbol": entry.symbol,
+                "path": entry.path,
+                "name": name,
+                "line": f.line,
+                "summary": &f.summary,
+                "reachable": via.is_some(),
+                "via": via,
+            })
+        })
+        .collect::<Vec<_>>();
+    Some(json!({
+        "reachable_count": reachable_count,
+        "unreachable_count": uncovered.len() - reachable_count,
+        "functions": uncovered,
+    }))
+}
+
+/// Reads the source file at `path` for the `source` of a summary entry, or `null` if the file cannot be read.
+fn serialize_source(path: &Path) -> Value {
+    let mut content = Vec::new();
+    match File::open(path).and_then(|mut file| file.read_to_end(&mut content)) {
+        Ok(_) => json!({
+            "digest": format!("{:x}", md5::compute(&content)),
+            "lines_count": content.iter().filter(|&&b| b == b'\n').count() + usize::from(!content.is_empty() && !content.ends_with(b"\n")),
+        }),
+        Err(e) => {
+            debug!("cannot read source {:?}: {}", path, e);
+            Value::Null
+        },
+    }
+}
+
 /// Renders report for a source path.
-fn write_file(config: &ReportConfig, interner: &Interner, entry: &ReportFileEntry, tera: &Tera, template_name: &str) -> Result<()> {
+///
+/// If the `previous` page was rendered with the same context and still exists, it is reused without rendering. Returns
+/// the page to be cached, and whether it was reused.
+fn write_file(
+    config: &ReportConfig,
+    interner: &Interner,
+    entry: &ReportFileEntry,
+    tera: &Tera,
+    file_config: &FileConfig,
+    options: &Map<String, Value>,
+    permalink_prefix: Option<&str>,
+    previous: Option<&CachedPage>,
+    timings: &mut Timings,
+) -> Result<(CachedPage, bool)> {
     let mut context = Context::new();
 
     let mut lines = Vec::new();
@@ -347,24 +1835,51 @@ fn write_file(config: &ReportConfig, interner: &Interner, entry: &ReportFileEntr
 
     // Read the source file.
     let path = config.workspace_path.join(entry.path);
-    if let Ok(source_file) = File::open(path) {
-        let source_file = BufReader::new(source_file);
-        for source_line in source_file.lines() {
-            let (count, branches) = if let Some(line) = entry.file.lines.get(&source_line_number) {
-                let (count, branches) = serialize_line(line, interner);
-                (Some(count), branches)
-            } else {
-                (None, Vec::new())
-            };
-            lines.push(json!({
-                "line": source_line_number,
-                "source": source_line?,
-                "count": count,
-                "branches": branches,
-            }));
-            source_line_number += 1;
+    timings.measure("read", Some(&path), || -> Result<()> {
+        let source_file = match File::open(&path) {
+            Ok(source_file) => Some(source_file),
+            Err(e) => {
+                // Sources outside the workspace (e.g. the standard library) are often absent and not worth a warning.
+                if entry.source_type == SourceType::LOCAL {
+                    warning!("cannot read source `{}`: {}", path.display(), e);
+                }
+                None
+            },
+        };
+        if let Some(source_file) = source_file {
+            let source_file = BufReader::new(source_file);
+            let mut highlighter = if file_config.highlight { Some(Highlighter::new(config.language)) } else { None };
+            for source_line in source_file.lines() {
+                let source_line = source_line?;
+                let (count, branches) = if let Some(line) = entry.file.lines.get(&source_line_number) {
+                    let (count, branches) = serialize_line(line, interner);
+                    (Some(count), branches)
+                } else {
+                    (None, Vec::new())
+                };
+                let regions = line_regions(&entry.file.functions, source_line_number, &source_line);
+                let mut line = json!({
+                    "line": source_line_number,
+                    "source": source_line,
+                    "count": count,
+                    "branches": branches,
+                });
+                if let Some(ref mut highlighter) = highlighter {
+                    let mut highlighted = highlighter.line(&source_line);
+                    if !regions.is_empty() {
+                        highlighted = wrap_regions(&highlighted, &regions);
+                    }
+                    line["highlighted"] = Value::String(highlighted);
+                }
+                if !regions.is_empty() {
+                    line["regions"] = json!(regions);
+                }
+                lines.push(line);
+                source_line_number += 1;
+            }
         }
-    }
+        Ok(())
+    })?;
 
     // Add the remaining lines absent from the source file.
     lines.extend(entry.file.lines.range(source_line_number..).map(|(line_number, line)| {
@@ -377,37 +1892,120 @@ fn write_file(config: &ReportConfig, interner: &Interner, entry: &ReportFileEntr
         })
     }));
 
-    // Collect function info
-    let functions = entry
-        .file
-        .functions
-        .iter()
+    let functions = serialize_functions(entry, interner, config.language);
+
+    context.add("crate_path", &config.workspace_path);
+    context.add("language", &config.language);
+    context.add("symbol", &entry.symbol);
+    context.add("path", &entry.path);
+    context.add("summary", &entry.file.summary());
+    context.add("max_count", &entry.file.lines.values().map(|line| line.count).max().unwrap_or(0));
+    context.add("permalink", &permalink(config, entry, permalink_prefix));
+    context.add("lines", &lines);
+    context.add("functions", &functions);
+    context.add("options", options);
+
+    let filename = tera.render("<filename>", &context)?;
+    let path = config.output_path.join(&filename);
+    let page = CachedPage {
+        fingerprint: cache::fingerprint(&context.as_json()?.to_string()),
+        output: filename,
+    };
+    if previous == Some(&page) && path.is_file() {
+        return Ok((page, true));
+    }
+
+    let rendered = timings.measure("render", Some(&path), || tera.render(file_config.template, &context))?;
+    timings.measure("write", Some(&path), || File::create(&path)?.write_all(rendered.as_bytes()))?;
+
+    Ok((page, false))
+}
+
+/// Forms the URL of a local source file on the repository host from the `permalink_prefix`, see
+/// [`git::permalink_prefix()`]. Returns `None` for the files outside the workspace.
+///
+/// [`git::permalink_prefix()`]: ../git/fn.permalink_prefix.html
+fn permalink(config: &ReportConfig, entry: &ReportFileEntry, permalink_prefix: Option<&str>) -> Option<String> {
+    if entry.source_type != SourceType::LOCAL {
+        return None;
+    }
+    let path = Path::new(entry.path);
+    let relative_path = if path.is_absolute() { path.strip_prefix(&config.workspace_path).ok()? } else { path };
+    let components = relative_path.iter().map(|c| c.to_str()).collect::<Option<Vec<_>>>()?;
+    Some(format!("{}{}", permalink_prefix?, components.join("/")))
+}
+
+/// Serializes the functions defined in a source file into JSON values.
+fn serialize_functions(entry: &ReportFileEntry, interner: &Interner, language: Language) -> Vec<Value> {
+    // The order of functions depends on the order the GCNOs are read, sort them to keep the output deterministic.
+    let mut functions = entry.file.functions.iter().collect::<Vec<_>>();
+    functions.sort_by_key(|f| (f.line, f.column, &interner[f.name]));
+    functions
+        .into_iter()
         .map(|f| {
             let name = &interner[f.name];
+            let demangled_name = match language {
+                Language::Rust => format!("{:#}", demangle(name)),
+                Language::C => name.to_owned(),
+            };
             json!({
                 "symbol": f.name,
                 "name": name,
+                "demangled_name": demangled_name,
+                "short_name": short_name(&demangled_name, None),
                 "line": f.line,
                 "column": f.column,
+                "end_line": f.end_line,
+                "end_column": f.end_column,
                 "summary": &f.summary,
             })
         })
-        .collect::<Vec<_>>();
+        .collect()
+}
 
-    context.add("crate_path", &config.workspace_path);
-    context.add("symbol", &entry.symbol);
-    context.add("path", &entry.path);
-    context.add("summary", &entry.file.summary());
-    context.add("lines", &lines);
-    context.add("functions", &functions);
+/// Finds the regions of a source line belonging to different functions, when several functions with a column range
+/// (recorded by GCC 8 and later) share the line. The innermost function takes precedence, e.g. a lambda over the
+/// function defining it. Returns an empty vector if the line belongs to at most one function.
+fn line_regions(functions: &[cov::report::Function], line_number: u32, source: &str) -> Vec<Region> {
+    let mut functions = functions.iter().filter(|f| f.column > 0 && f.line <= line_number && line_number <= f.end_line).collect::<Vec<_>>();
+    if functions.len() < 2 {
+        return Vec::new();
+    }
 
-    let filename = tera.render("<filename>", &context)?;
-    let path = config.output_path.join(filename);
-    let rendered = tera.render(template_name, &context)?;
-    let mut file_file = File::create(path)?;
-    file_file.write_all(rendered.as_bytes())?;
+    // Paint the outermost functions first, so the inner ones overwrite them.
+    functions.sort_by_key(|f| Reverse((f.end_line - f.line, i64::from(f.end_column) - i64::from(f.column))));
+    let mut painted = vec![None; source.len()];
+    for f in functions {
+        let start = if f.line == line_number { f.column as usize } else { 1 };
+        let end = if f.end_line == line_number && f.end_column > 0 { cmp::min(f.end_column as usize, source.len()) } else { source.len() };
+        for column in start..=end {
+            painted[column - 1] = Some(f.summary.entry_count);
+        }
+    }
 
-    Ok(())
+    let mut regions = Vec::<Region>::new();
+    for (i, count) in painted.into_iter().enumerate() {
+        if let Some(count) = count {
+            if let Some(region) = regions.last_mut().filter(|r| r.end == i && r.count == count) {
+                region.end += 1;
+                continue;
+            }
+            regions.push(Region { start: i + 1, end: i + 1, count });
+        }
+    }
+    regions
+}
+
+/// Serializes the change of coverage percentages of a file since the previous report into JSON value.
+///
+/// The change is the difference between the rounded percentages, so it agrees with the percentages shown.
+fn serialize_delta(summary: &FileSummary, previous: &FileSummary, rounding: Rounding) -> Value {
+    let delta = |value, total, previous_value, previous_total| rounding.round(rounding.percent(value, total) - rounding.percent(previous_value, previous_total));
+    json!({
+        "lines": delta(summary.lines_covered, summary.lines_count, previous.lines_covered, previous.lines_count),
+        "branches": delta(summary.branches_taken, summary.branches_count, previous.branches_taken, previous.branches_count),
+        "functions": delta(summary.functions_called, summary.functions_count, previous.functions_called, previous.functions_count),
+    })
 }
 
 /// Serializes a source line as a branch target into JSON value.
@@ -417,14 +2015,115 @@ fn serialize_line(line: &::cov::report::Line, interner: &Interner) -> (u64, Vec<
         line.branches
             .iter()
             .map(|branch| {
+                let outcome = if line.count == 0 {
+                    "not_executed"
+                } else if branch.count == 0 {
+                    "not_taken"
+                } else {
+                    "taken"
+                };
                 json!({
                     "count": branch.count,
                     "symbol": branch.filename,
                     "path": &interner[branch.filename],
                     "line": branch.line,
                     "column": branch.column,
+                    "outcome": outcome,
+                    "fallthrough": branch.attr.contains(ArcAttr::FALLTHROUGH),
+                    "throw": branch.attr.contains(ArcAttr::THROW),
                 })
             })
             .collect(),
     )
 }
+
+#[cfg(unix)]
+#[test]
+fn test_template_override_sandbox() {
+    use std::fs::write;
+    use std::os::unix::fs::symlink;
+
+    let dir = TempDir::new().unwrap();
+    let override_path = dir.path().join("overrides");
+    let static_path = dir.path().join("output").join("static");
+    create_dir_all(override_path.join("tera")).unwrap();
+    create_dir_all(override_path.join("static")).unwrap();
+    write(override_path.join("tera").join("layout.html"), "{% include \"footer.html\" %}").unwrap();
+    write(override_path.join("tera").join("footer.html"), "footer").unwrap();
+    write(override_path.join("static").join("custom.css"), "body {}").unwrap();
+    write(dir.path().join("secret"), "secret").unwrap();
+
+    let mut tera = Tera::default();
+    apply_template_override(&mut tera, &override_path, &static_path, true).unwrap();
+    assert_eq!(tera.render("layout.html", &Context::new()).unwrap(), "footer");
+    assert!(static_path.join("custom.css").is_file());
+
+    symlink(dir.path().join("secret"), override_path.join("static").join("secret.css")).unwrap();
+    let error = apply_template_override(&mut Tera::default(), &override_path, &static_path, true).unwrap_err();
+    match *error.kind() {
+        ErrorKind::TemplateOutsideSandbox(ref path) => assert_eq!(path, &override_path.join("static").join("secret.css")),
+        ref kind => panic!("unexpected error {:?}", kind),
+    }
+    remove_file(override_path.join("static").join("secret.css")).unwrap();
+
+    symlink(dir.path().join("secret"), override_path.join("tera").join("footer.html.bak")).unwrap();
+    assert!(apply_template_override(&mut Tera::default(), &override_path, &static_path, true).is_err());
+    apply_template_override(&mut tera, &override_path, &static_path, false).unwrap();
+    assert_eq!(tera.render("footer.html.bak", &Context::new()).unwrap(), "secret");
+}
+
+#[test]
+fn test_apply_theme() {
+    use std::fs::{read_to_string, write};
+
+    let dir = TempDir::new().unwrap();
+    let static_path = dir.path();
+    apply_theme(static_path, "dark").unwrap();
+    assert!(!static_path.join("theme.css").exists());
+
+    create_dir_all(static_path.join("themes")).unwrap();
+    write(static_path.join("themes").join("light.css"), ":root {\n    --fg: #333;\n}\n").unwrap();
+    write(static_path.join("themes").join("dark.css"), ":root {\n    --fg: #ccc;\n}\n").unwrap();
+    apply_theme(static_path, "dark").unwrap();
+    assert_eq!(read_to_string(static_path.join("theme.css")).unwrap(), ":root {\n    --fg: #ccc;\n}\n");
+    apply_theme(static_path, "auto").unwrap();
+    assert_eq!(
+        read_to_string(static_path.join("theme.css")).unwrap(),
+        ":root {\n    --fg: #333;\n}\n\n@media (prefers-color-scheme: dark) {\n    :root {\n        --fg: #ccc;\n    }\n}\n"
+    );
+
+    match *apply_theme(static_path, "solarized").unwrap_err().kind() {
+        ErrorKind::UnknownTheme(ref name, ref available) => {
+            assert_eq!(name, "solarized");
+            assert_eq!(available, "auto, dark, light");
+        },
+        ref e => panic!("unexpected error {:?}", e),
+    }
+}
+
+#[test]
+fn test_find_unattributed() {
+    use std::fs::write;
+
+    let dir = TempDir::new().unwrap();
+    let gcno_path = dir.path().join("gcno");
+    let gcda_path = dir.path().join("gcda");
+    create_dir_all(&gcno_path).unwrap();
+    create_dir_all(&gcda_path).unwrap();
+    write(gcno_path.join("1111111111111111.@aaaaaaaaaaaaaaaa.x-aaaaaaaaaaaaaaaa.gcno"), "").unwrap();
+    write(gcda_path.join("1111111111111111.@aaaaaaaaaaaaaaaa.x-aaaaaaaaaaaaaaaa.gcda"), "").unwrap();
+    assert!(find_unattributed(&gcno_path, &gcda_path).unwrap().is_empty());
+
+    let orphan_path = gcda_path.join("2222222222222222.@bbbbbbbbbbbbbbbb.x-bbbbbbbbbbbbbbbb.gcda");
+    write(&orphan_path, "").unwrap();
+    ordering::record_program(&gcda_path, Path::new("/old/deps/x-bbbbbbbbbbbbbbbb"), &[orphan_path.clone()]).unwrap();
+    write(gcda_path.join("3333333333333333.@cccccccccccccccc.y-cccccccccccccccc.gcda"), "").unwrap();
+
+    let mut unattributed = find_unattributed(&gcno_path, &gcda_path).unwrap();
+    unattributed.sort_by(|a, b| a["crate_hash"].as_str().cmp(&b["crate_hash"].as_str()));
+    assert_eq!(unattributed.len(), 2);
+    assert_eq!(unattributed[0]["crate_hash"], "bbbbbbbbbbbbbbbb");
+    assert_eq!(unattributed[0]["programs"], json!(["/old/deps/x-bbbbbbbbbbbbbbbb"]));
+    assert_eq!(unattributed[1]["crate_hash"], "cccccccccccccccc");
+    assert_eq!(unattributed[1]["programs"], Value::Null);
+}
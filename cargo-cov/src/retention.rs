@@ -0,0 +1,227 @@
+//! Retention policy of the collected GCDA files.
+//!
+//! Every `cargo cov test` or `cargo cov run` adds a new set of GCDA files to `target/cov/build/gcda`, which are all
+//! merged into the report. In a long-lived checkout these stale counters accumulate. A retention policy can be
+//! configured in the workspace's `Cargo.toml`:
+//!
+//! ```toml
+//! [package.metadata.cov.retention]
+//! keep-sessions = 5  # keep the GCDA files from the last 5 runs of `cargo cov test`/`cargo cov run`
+//! keep-days = 14     # keep the GCDA files modified in the last 14 days
+//! ```
+//!
+//! The policy is applied automatically after `cargo cov build`, `cargo cov test` and `cargo cov run`, and can be
+//! applied manually (with optional overrides) using `cargo cov gc --keep-sessions 5 --keep-days 14`.
+//!
+//! A *session* is a single invocation of `cargo cov test` or `cargo cov run`. The start time of every session is
+//! recorded in `target/cov/build/gcda/sessions.json`, so cleaning the GCDA files also forgets the sessions. The
+//! arguments of the session are recorded as well, so the report can tell which test filters and harness flags (e.g.
+//! `cargo cov test foo -- --include-ignored`) the coverage was collected with.
+
+use archive;
+use error::{Result, ResultExt};
+use ordering;
+
+use serde_json::{from_reader, to_writer};
+use tempfile::NamedTempFile;
+
+use std::fs::{File, read_dir, remove_file};
+use std::io::ErrorKind as IoErrorKind;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Number of seconds in a day.
+const SECONDS_PER_DAY: u64 = 86_400;
+
+/// How long the GCDA files should be kept. A file is removed if it is rejected by any limit.
+#[derive(Copy, Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RetentionPolicy {
+    /// Keep the GCDA files produced by the last N sessions.
+    pub keep_sessions: Option<usize>,
+    /// Keep the GCDA files modified in the last N days.
+    pub keep_days: Option<u64>,
+}
+
+impl RetentionPolicy {
+    /// Whether the policy keeps everything.
+    pub fn is_empty(&self) -> bool {
+        self.keep_sessions.is_none() && self.keep_days.is_none()
+    }
+
+    /// Overrides the limits of this policy by those set in `other`.
+    pub fn overridden_by(self, other: RetentionPolicy) -> RetentionPolicy {
+        RetentionPolicy {
+            keep_sessions: other.keep_sessions.or(self.keep_sessions),
+            keep_days: other.keep_days.or(self.keep_days),
+        }
+    }
+}
+
+/// Statistics of the files removed by [`apply()`].
+///
+/// [`apply()`]: ./fn.apply.html
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RetentionOutcome {
+    /// Number of GCDA files removed.
+    pub files_removed: usize,
+    /// Total size of the GCDA files removed, in bytes.
+    pub bytes_removed: u64,
+}
+
+/// A recorded invocation of `cargo cov test` or `cargo cov run`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    /// Start time in seconds since the Unix epoch.
+    pub started: u64,
+    /// The subcommand, `test` or `run`. Empty if recorded by an older version of `cargo cov`.
+    #[serde(default)]
+    pub subcommand: String,
+    /// The arguments forwarded to cargo, including those after `--` forwarded to the test harness or the program.
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// An entry of the session log. Older versions of `cargo cov` only recorded the start time.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SessionEntry {
+    Started(u64),
+    Session(Session),
+}
+
+impl From<SessionEntry> for Session {
+    fn from(entry: SessionEntry) -> Session {
+        match entry {
+            SessionEntry::Started(started) => Session {
+                started,
+                ..Session::default()
+            },
+            SessionEntry::Session(session) => session,
+        }
+    }
+}
+
+/// Obtains the current time in seconds since the Unix epoch.
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// Obtains the path to the session log.
+pub fn sessions_path(gcda_path: &Path) -> PathBuf {
+    gcda_path.join("sessions.json")
+}
+
+/// Reads all recorded sessions, oldest first.
+pub fn read_sessions(gcda_path: &Path) -> Result<Vec<Session>> {
+    // Sessions are not recorded for the GCDA files in an archive.
+    if archive::is_archive(gcda_path) {
+        return Ok(Vec::new());
+    }
+    let path = sessions_path(gcda_path);
+    match File::open(&path) {
+        Ok(file) => {
+            let entries: Vec<SessionEntry> = from_reader(file).chain_err(|| format!("Cannot parse sessions at `{}`", path.display()))?;
+            Ok(entries.into_iter().map(Session::from).collect())
+        },
+        Err(ref e) if e.kind() == IoErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e).chain_err(|| format!("Cannot read sessions at `{}`", path.display())),
+    }
+}
+
+/// Writes all sessions.
+///
+/// The log is written to a temporary file and then renamed, so an interrupted write never leaves a truncated log.
+fn write_sessions(gcda_path: &Path, sessions: &[Session]) -> Result<()> {
+    let path = sessions_path(gcda_path);
+    let mut file = NamedTempFile::new_in(gcda_path).chain_err(|| format!("Cannot write sessions at `{}`", path.display()))?;
+    to_writer(&mut file, sessions)?;
+    file.persist(&path).chain_err(|| format!("Cannot write sessions at `{}`", path.display()))?;
+    Ok(())
+}
+
+/// Records the start of a new session.
+pub fn record_session(gcda_path: &Path, subcommand: &str, args: &[&OsStr]) -> Result<()> {
+    let mut sessions = read_sessions(gcda_path)?;
+    sessions.push(Session {
+        started: now(),
+        subcommand: subcommand.to_owned(),
+        args: args.iter().map(|arg| arg.to_string_lossy().into_owned()).collect(),
+    });
+    write_sessions(gcda_path, &sessions)
+}
+
+/// Removes the GCDA files in `gcda_path` which are not kept by the policy.
+pub fn apply(gcda_path: &Path, policy: RetentionPolicy) -> Result<RetentionOutcome> {
+    let mut outcome = RetentionOutcome::default();
+    if policy.is_empty() || !gcda_path.is_dir() {
+        return Ok(outcome);
+    }
+
+    // Files modified before the cutoff are removed.
+    let mut sessions = read_sessions(gcda_path)?;
+    let mut cutoff = 0;
+    if let Some(keep_sessions) = policy.keep_sessions {
+        if sessions.len() > keep_sessions {
+            sessions.drain(..(sessions.len() - keep_sessions));
+            write_sessions(gcda_path, &sessions)?;
+            cutoff = sessions.first().map_or_else(now, |s| s.started);
+        }
+    }
+    if let Some(keep_days) = policy.keep_days {
+        cutoff = cutoff.max(now().saturating_sub(keep_days * SECONDS_PER_DAY));
+    }
+
+    for entry in read_dir(gcda_path)? {
+        let path = entry?.path();
+        if path.extension() != Some(OsStr::new("gcda")) {
+            continue;
+        }
+        let metadata = path.metadata()?;
+        let modified = metadata.modified()?.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        if modified < cutoff {
+            trace!("rm {:?}", path);
+            remove_file(&path).chain_err(|| format!("Cannot remove `{}`", path.display()))?;
+            outcome.files_removed += 1;
+            outcome.bytes_removed += metadata.len();
+        }
+    }
+    if outcome.files_removed > 0 {
+        ordering::compact(gcda_path)?;
+    }
+
+    Ok(outcome)
+}
+
+#[test]
+fn test_read_sessions() {
+    let dir = ::tempfile::TempDir::new().unwrap();
+    ::std::fs::write(sessions_path(dir.path()), "[1526300000]").unwrap();
+    record_session(dir.path(), "test", &[OsStr::new("foo"), OsStr::new("--"), OsStr::new("--include-ignored")]).unwrap();
+    let sessions = read_sessions(dir.path()).unwrap();
+    assert_eq!(sessions.len(), 2);
+    assert_eq!(sessions[0], Session { started: 1526300000, ..Session::default() });
+    assert_eq!(sessions[1].subcommand, "test");
+    assert_eq!(sessions[1].args, ["foo", "--", "--include-ignored"]);
+}
+
+#[test]
+fn test_apply_compacts_programs() {
+    let dir = ::tempfile::TempDir::new().unwrap();
+    let gcda_path = dir.path();
+    // The latest session starts in the future, so every existing GCDA file is older than the sessions kept.
+    ::std::fs::write(sessions_path(gcda_path), "[1526300000, 9999999999]").unwrap();
+    let gcda_file = gcda_path.join("1111111111111111.foo.gcda");
+    File::create(&gcda_file).unwrap();
+    ordering::record_program(gcda_path, Path::new("deps/foo-fedcba9876543210"), &[gcda_file.clone()]).unwrap();
+
+    let policy = RetentionPolicy {
+        keep_sessions: Some(1),
+        keep_days: None,
+    };
+    assert_eq!(apply(gcda_path, policy).unwrap().files_removed, 1);
+    assert!(!gcda_file.exists());
+    assert!(ordering::producing_programs(gcda_path).unwrap().is_empty());
+    assert_eq!(::std::fs::read_to_string(gcda_path.join("programs.jsonl")).unwrap(), "");
+}
@@ -0,0 +1,165 @@
+//! Rounding of coverage percentages.
+//!
+//! A percentage like 89.95% may be shown as `90.0` by one report and `89.9` by another, or pass a 90% threshold while
+//! shown as failing. To avoid such disagreements, every percentage shown in the console, rendered by the templates, or
+//! compared against a threshold is rounded by the same [`Rounding`] policy, configured by `--rounding` and
+//! `--precision`.
+//!
+//! [`Rounding`]: ./struct.Rounding.html
+
+use utils::percent;
+
+use std::str::FromStr;
+
+/// How a percentage is rounded to the configured precision.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RoundingMode {
+    /// Rounds towards negative infinity, so a percentage never looks better than it is, e.g. 89.999% becomes 89.99%.
+    Floor,
+    /// Rounds half away from zero, e.g. 89.995% becomes 90.00%.
+    Round,
+    /// Rounds half to even (banker's rounding), e.g. 89.985% becomes 89.98% and 89.995% becomes 90.00%.
+    Bankers,
+}
+
+impl Default for RoundingMode {
+    fn default() -> RoundingMode {
+        RoundingMode::Round
+    }
+}
+
+impl RoundingMode {
+    /// Obtains the name of the mode, the inverse of [`from_str()`].
+    ///
+    /// [`from_str()`]: #method.from_str
+    pub fn name(self) -> &'static str {
+        match self {
+            RoundingMode::Floor => "floor",
+            RoundingMode::Round => "round",
+            RoundingMode::Bankers => "bankers",
+        }
+    }
+}
+
+/// The error raised when [`RoundingMode::from_str()`] encounters an unrecognized string.
+///
+/// [`RoundingMode::from_str()`]: ./enum.RoundingMode.html#method.from_str
+#[derive(Debug)]
+pub struct UnsupportedRoundingModeName;
+
+impl FromStr for RoundingMode {
+    type Err = UnsupportedRoundingModeName;
+    fn from_str(s: &str) -> Result<RoundingMode, UnsupportedRoundingModeName> {
+        Ok(match s {
+            "floor" => RoundingMode::Floor,
+            "round" => RoundingMode::Round,
+            "bankers" => RoundingMode::Bankers,
+            _ => return Err(UnsupportedRoundingModeName),
+        })
+    }
+}
+
+/// The default number of decimal places of a percentage.
+pub const DEFAULT_PRECISION: u32 = 2;
+
+/// The rounding policy of the percentages.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Rounding {
+    /// How to round.
+    pub mode: RoundingMode,
+    /// Number of decimal places to keep.
+    pub precision: u32,
+}
+
+impl Default for Rounding {
+    fn default() -> Rounding {
+        Rounding {
+            mode: RoundingMode::default(),
+            precision: DEFAULT_PRECISION,
+        }
+    }
+}
+
+impl Rounding {
+    /// Rounds `number` to the configured precision.
+    ///
+    /// The number is first scaled by the precision, and snapped to the nearest multiple of 0.5 if it is closer than
+    /// 10<sup>-6</sup>, so that a half-way percentage like 201/20000 = 1.005% (computed as 1.00499999…) is rounded as
+    /// exactly half-way. This only absorbs the error of computing a percentage from counts, not arbitrary errors of the
+    /// binary representation: a number which is truly within 10<sup>-6</sup> (after scaling) of a half is rounded as
+    /// the half too.
+    ///
+    /// ```
+    /// use cargo_cov::rounding::{Rounding, RoundingMode};
+    ///
+    /// let rounding = |mode, precision| Rounding { mode, precision };
+    /// assert_eq!(rounding(RoundingMode::Floor, 1).round(89.99), 89.9);
+    /// assert_eq!(rounding(RoundingMode::Round, 1).round(89.95), 90.0);
+    /// assert_eq!(rounding(RoundingMode::Bankers, 1).round(89.95), 90.0);
+    /// assert_eq!(rounding(RoundingMode::Bankers, 1).round(89.85), 89.8);
+    /// assert_eq!(rounding(RoundingMode::Round, 0).round(-0.5), -1.0);
+    /// ```
+    #[cfg_attr(feature = "cargo-clippy", allow(cast_possible_wrap, float_cmp))]
+    pub fn round(self, number: f64) -> f64 {
+        const EPSILON: f64 = 1e-6;
+
+        let scale = 10f64.powi(self.precision as i32);
+        let scaled = number * scale;
+        let nearest_half = (scaled * 2.0).round() / 2.0;
+        let scaled = if (scaled - nearest_half).abs() < EPSILON { nearest_half } else { scaled };
+        let rounded = match self.mode {
+            RoundingMode::Floor => scaled.floor(),
+            RoundingMode::Round => scaled.round(),
+            RoundingMode::Bankers => {
+                let floor = scaled.floor();
+                if (scaled - floor - 0.5).abs() < EPSILON {
+                    if floor % 2.0 == 0.0 {
+                        floor
+                    } else {
+                        floor + 1.0
+                    }
+                } else {
+                    scaled.round()
+                }
+            },
+        };
+        rounded / scale
+    }
+
+    /// Computes the percentage of `value` in `total` (100% if `total` is 0), rounded to the configured precision.
+    pub fn percent(self, value: usize, total: usize) -> f64 {
+        self.round(percent(value, total))
+    }
+
+    /// Rounds `number` and formats it with exactly the configured number of decimal places.
+    ///
+    /// ```
+    /// use cargo_cov::rounding::{Rounding, RoundingMode};
+    ///
+    /// assert_eq!(Rounding::default().format(89.995), "90.00");
+    /// assert_eq!(Rounding { mode: RoundingMode::Floor, precision: 1 }.format(89.995), "89.9");
+    /// ```
+    pub fn format(self, number: f64) -> String {
+        format!("{:.*}", self.precision as usize, self.round(number))
+    }
+}
+
+#[test]
+fn test_round_half_way() {
+    let rounding = |mode| Rounding { mode, precision: 2 };
+    // 1.005%, 1.015%, 0.125% and 89.995%, all half-way at two decimal places.
+    let inputs = [(201, 20000), (203, 20000), (1, 800), (17999, 20000)];
+    let results = |mode| inputs.iter().map(|&(value, total)| rounding(mode).percent(value, total)).collect::<Vec<_>>();
+    assert_eq!(results(RoundingMode::Floor), vec![1.0, 1.01, 0.12, 89.99]);
+    assert_eq!(results(RoundingMode::Round), vec![1.01, 1.02, 0.13, 90.0]);
+    assert_eq!(results(RoundingMode::Bankers), vec![1.0, 1.02, 0.12, 90.0]);
+    assert_eq!(rounding(RoundingMode::Round).format(percent(201, 20000)), "1.01");
+
+    let rounding = |mode| Rounding { mode, precision: 0 };
+    assert_eq!(rounding(RoundingMode::Floor).round(-0.5), -1.0);
+    assert_eq!(rounding(RoundingMode::Round).round(-0.5), -1.0);
+    assert_eq!(rounding(RoundingMode::Bankers).round(-0.5), 0.0);
+    assert_eq!(rounding(RoundingMode::Bankers).round(2.5), 2.0);
+    assert_eq!(rounding(RoundingMode::Bankers).round(3.5), 4.0);
+}
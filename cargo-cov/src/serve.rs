@@ -0,0 +1,296 @@
+//! Serving the report during the edit-test loop.
+//!
+//! `cargo cov serve` generates the report, serves the output directory at `http://127.0.0.1:<port>/`, and checks the
+//! *.gcno and *.gcda files every `--interval` seconds. Whenever any of them is added, removed or modified (e.g. by
+//! `cargo cov test` in another terminal), the report is generated again, and the page can be reloaded in the browser.
+//!
+//! The summaries of the previous run are kept in memory, so the index shows the change of coverage of every file
+//! (▲/▼) since the last refresh, and the files whose line coverage changed are printed to the terminal. As with
+//! `--changed-only`, only the pages of the files whose coverage changed are rendered again.
+//!
+//! The lock of `target/cov` is only held while the report is generated. If another `cargo cov` process holds it, the
+//! refresh is retried in the next check.
+
+use argparse::ReportConfig;
+use error::{ErrorKind, Result};
+use lock::CovLock;
+use outcome::ReportOutcome;
+use report;
+use rounding::Rounding;
+
+use cov::report::FileSummary;
+use walkdir::WalkDir;
+
+use std::collections::BTreeMap;
+use std::collections::hash_map::DefaultHasher;
+use std::fs::read;
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// The settings of `cargo cov serve`.
+#[derive(Clone, Debug)]
+pub struct ServeConfig<'a> {
+    /// Port to listen on 127.0.0.1, or 0 to pick an unused one.
+    pub port: u16,
+    /// Time between the checks of the *.gcno and *.gcda files.
+    pub interval: Duration,
+    /// The `target/cov` directory locked while the report is generated.
+    pub cov_path: Option<&'a Path>,
+}
+
+/// Entry point of `cargo cov serve`. Never returns unless the report cannot be served.
+pub fn serve(mut config: ReportConfig, serve_config: &ServeConfig) -> Result<()> {
+    config.changed_only = true;
+
+    let listener = TcpListener::bind(("127.0.0.1", serve_config.port))?;
+    let address = listener.local_addr()?;
+    let root = config.output_path.to_path_buf();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            if let Err(e) = stream.and_then(|stream| respond(stream, &root)) {
+                debug!("cannot respond to the request: {}", e);
+            }
+        }
+    });
+
+    let mut fingerprint = None;
+    loop {
+        let current = fingerprint_inputs(&[&config.gcno_path, &config.gcda_path]);
+        if fingerprint != Some(current) {
+            let lock = match serve_config.cov_path {
+                Some(cov_path) => match CovLock::acquire(cov_path, false, false) {
+                    Ok(lock) => Some(lock),
+                    Err(e) => {
+                        // Retried in the next check.
+                        if let ErrorKind::Locked(_) = *e.kind() {
+                            thread::sleep(serve_config.interval);
+                            continue;
+                        }
+                        return Err(e);
+                    },
+                },
+                None => None,
+            };
+            fingerprint = Some(current);
+            match report::generate(&config, false) {
+                Ok(outcome) => {
+                    if let Some(ref previous) = config.previous_summaries {
+                        print_deltas(previous, &outcome.summaries, config.rounding);
+                    }
+                    print_refreshed(&outcome, &config.output_path, &address.to_string(), config.rounding);
+                    config.previous_summaries = Some(outcome.summaries);
+                },
+                Err(e) => warning!("cannot generate the report: {}", e),
+            }
+            drop(lock);
+        }
+        thread::sleep(serve_config.interval);
+    }
+}
+
+/// Prints the location of the refreshed report and its total line coverage.
+fn print_refreshed(outcome: &ReportOutcome, output_path: &Path, address: &str, rounding: Rounding) {
+    let page = outcome.summary_path.as_ref().and_then(|path| path.strip_prefix(output_path).ok()).map_or_else(String::new, |path| {
+        path.to_string_lossy().replace('\\', "/")
+    });
+    let totals = &outcome.totals;
+    progress!("Serving", "http://{}/{} ({}% lines covered)", address, page, rounding.format(rounding.percent(totals.lines_covered, totals.lines_count)));
+}
+
+/// Prints the files whose line coverage changed since the previous refresh.
+fn print_deltas(previous: &BTreeMap<String, FileSummary>, current: &BTreeMap<String, FileSummary>, rounding: Rounding) {
+    for (path, summary) in current {
+        let before = previous.get(path).map_or(0.0, |p| rounding.percent(p.lines_covered, p.lines_count));
+        let after = rounding.percent(summary.lines_covered, summary.lines_count);
+        let delta = rounding.round(after - before);
+        if delta > 0.0 {
+            progress!("▲", "{} {}% (+{}%)", path, rounding.format(after), rounding.format(delta));
+        } else if delta < 0.0 {
+            progress!("▼", "{} {}% ({}%)", path, rounding.format(after), rounding.format(delta));
+        }
+    }
+}
+
+/// Computes the fingerprint of the names, sizes and modification times of all files under `paths` (either directories
+/// or archives).
+fn fingerprint_inputs(paths: &[&Path]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for path in paths {
+        for entry in WalkDir::new(path).sort_by(|a, b| a.file_name().cmp(b.file_name())) {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(_) => continue,
+            };
+            entry.path().hash(&mut hasher);
+            if let Ok(metadata) = entry.metadata() {
+                metadata.len().hash(&mut hasher);
+                metadata.modified().ok().hash(&mut hasher);
+            }
+        }
+    }
+    hasher.finish()
+}
+
+/// Answers an HTTP request with a file in the output directory at `root`.
+fn respond(mut stream: TcpStream, root: &Path) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Read the headers to the end, so the client does not see the connection reset.
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("");
+    let target = parts.next().unwrap_or("");
+    let (status, content_type, body) = if method != "GET" && method != "HEAD" {
+        ("405 Method Not Allowed", "text/plain", b"Method Not Allowed".to_vec())
+    } else {
+        match resolve_target(root, target).and_then(|path| read(&path).ok().map(|body| (path, body))) {
+            Some((path, body)) => ("200 OK", content_type(&path), body),
+            None => ("404 Not Found", "text/plain", b"Not Found".to_vec()),
+        }
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    if method != "HEAD" {
+        stream.write_all(&body)?;
+    }
+    stream.flush()
+}
+
+/// Maps the target of an HTTP request to a file under `root`. Returns `None` if the target leaves `root` or names a
+/// hidden file (e.g. the cache of the report).
+fn resolve_target(root: &Path, target: &str) -> Option<PathBuf> {
+    let path = target.split(&['?', '#'][..]).next().unwrap_or("");
+    let path = percent_decode(path)?;
+    let mut resolved = root.to_owned();
+    for component in path.split('/') {
+        if component.is_empty() {
+            continue;
+        }
+        if component.starts_with('.') || component.contains('\\') || component.contains(':') {
+            return None;
+        }
+        resolved.push(component);
+    }
+    if resolved.is_dir() {
+        resolved.push("index.html");
+    }
+    Some(resolved)
+}
+
+/// Decodes the `%XX` escapes of a URL path. Returns `None` if an escape is malformed or the result is not UTF-8.
+fn percent_decode(path: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut it = path.bytes();
+    while let Some(b) = it.next() {
+        if b == b'%' {
+            let hex = [it.next()?, it.next()?];
+            let hex = ::std::str::from_utf8(&hex).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+        } else {
+            bytes.push(b);
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// Guesses the MIME type of a file in the report from its extension.
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js") => "application/javascript; charset=utf-8",
+        Some("json") => "application/json",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("xml") => "application/xml",
+        Some("txt") | Some("md") | Some("csv") | Some("gcov") | Some("info") => "text/plain; charset=utf-8",
+        _ => "application/octet-stream",
+    }
+}
+
+#[test]
+fn test_resolve_target() {
+    let dir = ::tempfile::TempDir::new().unwrap();
+    let root = dir.path();
+    ::std::fs::create_dir(root.join("src")).unwrap();
+
+    assert_eq!(resolve_target(root, "/"), Some(root.join("index.html")));
+    assert_eq!(resolve_target(root, "/src/"), Some(root.join("src").join("index.html")));
+    assert_eq!(resolve_target(root, "/static/report.css?v=1"), Some(root.join("static").join("report.css")));
+    assert_eq!(resolve_target(root, "/a%20b.html#L1"), Some(root.join("a b.html")));
+    assert_eq!(resolve_target(root, "/../secret"), None);
+    assert_eq!(resolve_target(root, "/%2e%2e/secret"), None);
+    assert_eq!(resolve_target(root, "/.cov-cache.json"), None);
+    assert_eq!(resolve_target(root, "/src/%5c..%5csecret"), None);
+    assert_eq!(resolve_target(root, "/bad%zz"), None);
+}
+
+#[test]
+fn test_content_type() {
+    assert_eq!(content_type(Path::new("index.html")), "text/html; charset=utf-8");
+    assert_eq!(content_type(Path::new("static/report.css")), "text/css; charset=utf-8");
+    assert_eq!(content_type(Path::new("badge.svg")), "image/svg+xml");
+    assert_eq!(content_type(Path::new("report.zip")), "application/octet-stream");
+}
+
+#[test]
+fn test_fingerprint_inputs() {
+    let dir = ::tempfile::TempDir::new().unwrap();
+    let gcda_path = dir.path().join("gcda");
+    ::std::fs::create_dir(&gcda_path).unwrap();
+    let empty = fingerprint_inputs(&[&gcda_path]);
+    assert_eq!(fingerprint_inputs(&[&gcda_path]), empty);
+
+    ::std::fs::write(gcda_path.join("x.gcda"), b"1").unwrap();
+    let added = fingerprint_inputs(&[&gcda_path]);
+    assert_ne!(added, empty);
+
+    ::std::fs::write(gcda_path.join("x.gcda"), b"12").unwrap();
+    assert_ne!(fingerprint_inputs(&[&gcda_path]), added);
+}
+
+#[test]
+fn test_respond() {
+    use std::io::Read;
+
+    let dir = ::tempfile::TempDir::new().unwrap();
+    ::std::fs::write(dir.path().join("index.html"), b"<p>report</p>").unwrap();
+    let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let address = listener.local_addr().unwrap();
+
+    let get = |request: &str| {
+        let mut client = TcpStream::connect(address).unwrap();
+        client.write_all(request.as_bytes()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        respond(stream, dir.path()).unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        response
+    };
+
+    let response = get("GET / HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("Content-Type: text/html; charset=utf-8\r\n"));
+    assert!(response.ends_with("\r\n\r\n<p>report</p>"));
+
+    let response = get("GET /missing.html HTTP/1.1\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+    let response = get("POST / HTTP/1.1\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+}
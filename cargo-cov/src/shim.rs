@@ -22,20 +22,48 @@
 //! named after the crate, which both the doc-test and normal test coincide (`-Zprofile` fixes the problem by including
 //! the hash as well). This will cause one GCNO to overwrite another, and GCDA-merge will produce a corrupt report.
 //! `cargo cov` works-around this by moving these files to a unique location as soon as they are generated.
+//!
+//! Crates with identical names (e.g. a library and a binary, or two versions of the same crate) are distinguished by
+//! the hash cargo passes via `-C extra-filename`/`-C metadata`. When known, the hash is recorded in the new name as
+//! `<random>.@<hash>.<stem>.<ext>`, so the GCDA files can be matched to the GCNO files of the same crate when
+//! generating the report (see [`artifact_hash()`](./fn.artifact_hash.html)).
+//!
+//! JSON messages
+//! -------------
+//!
+//! When `cargo` is run with `--message-format json` (e.g. by an IDE), the shims print extra JSON messages on the same
+//! `stdout` stream, one per line, so the coverage artifacts can be tracked while building and testing:
+//!
+//! ```json
+//! {"reason":"cov-crate-instrumented","crate_name":"foo","crate_hash":"0123456789abcdef","target":"x86_64-unknown-linux-gnu"}
+//! {"reason":"cov-gcno-collected","path":"/path/to/target/cov/build/gcno/1a2b3c4d5e6f7a8b.@0123456789abcdef.foo-0123456789abcdef.gcno"}
+//! {"reason":"cov-gcda-produced","path":"/path/to/target/cov/build/gcda/8b7a6f5e4d3c2b1a.@0123456789abcdef.foo-0123456789abcdef.gcda"}
+//! ```
+//!
+//! As with cargo's own messages, consumers should ignore any `reason` they do not recognize.
 
-use argparse::is_rustc_compiling_local_crate;
+use argparse::{extract_crate_hash, find_option_value, is_rustc_compiling_local_crate};
 use error::{Result, ResultExt};
+use ordering;
+use timings::{self, RunEvent, current_run_id};
 use utils::{CommandExt, join_2, parent_3};
 
 use fs2::FileExt;
+use glob::Pattern;
 use rand::{Rng, thread_rng};
+use serde_json::from_str;
+use shell_escape::escape;
+use tempfile::TempDir;
 use walkdir::WalkDir;
 
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashSet};
 use std::env;
 use std::ffi::{OsStr, OsString};
 use std::fs::{File, rename};
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
 use std::process::Command;
+use std::time::Instant;
 
 /// Builds a crate by forwarding `args` to `rustc`.
 ///
@@ -47,8 +75,15 @@ use std::process::Command;
 /// | `COV_BUILD_PATH` | Path to `target/cov/build/` of the workspace |
 /// | `COV_PROFILER_LIB_PATH` | Path to folder containing `libclang_rt.profile*.a`, or the string `"@native"` |
 /// | `COV_PROFILER_LIB_NAME` | Library name e.g. `clang_rt.profile-x86_64`, or the string `"@native"` |
+/// | `COV_TARGET` | The target triple being built for |
+/// | `COV_TARGET_FLAGS` | (Optional) JSON of the [`TargetFlags`] configured in `Cargo.toml`, keyed by the pattern |
+/// | `COV_LINK_DEAD_CODE` | (Optional) `on` or `off` to force adding or removing `-Clink-dead-code` |
+/// | `COV_ONLY_PATH` | (Optional) Only instrument the packages which may contain the sources inside this path |
+/// | `COV_MESSAGE_FORMAT` | (Optional) `json` to print the [JSON messages](./index.html#json-messages) |
+/// | `COV_RUN_ID` | (Optional) The run to record the compile time in, see the [`timings` module] |
 ///
-/// If the crate to build is in the current workspace, several flags will be added to the command line:
+/// If the crate to build is in the current workspace (and its package overlaps `COV_ONLY_PATH` if set), several flags
+/// will be added to the command line:
 ///
 /// | Flag | Reason |
 /// |------|--------|
@@ -59,6 +94,25 @@ use std::process::Command;
 /// | `-Cinline-threshold=0` | Disable inlining, which complicates control flow. |
 /// | `-Ccodegen-units=1` | Disable ThinLTO which corrupts debuginfo (see [rustc issue #45511]). |
 ///
+/// The flags are then adjusted for targets with known quirks by the presets in [`TARGET_FLAG_PRESETS`], and finally by
+/// the `target-flags` configured in `Cargo.toml`:
+///
+/// ```toml
+/// [package.metadata.cov.target-flags."aarch64-*-android"]
+/// add = ["-Clink-arg=-Wl,--no-gc-sections"]
+/// remove = ["-Clink-dead-code"]
+/// ```
+///
+/// Whether `-Clink-dead-code` is used can be decided regardless of the target with `cargo cov --link-dead-code` or
+/// `cargo cov --no-link-dead-code`, or by default in `Cargo.toml`:
+///
+/// ```toml
+/// [package.metadata.cov]
+/// link-dead-code = false
+/// ```
+///
+/// On the fully-static `*-musl` targets, the profiler runtime is linked as `-l static=clang_rt.profile-*`.
+///
 /// Additionally, all GCNO files generated will be moved to `$COV_BUILD_PATH/gcno/` after the build succeeds.
 ///
 /// # Panics
@@ -66,15 +120,20 @@ use std::process::Command;
 /// Panics when any of the above environment variables is not set.
 ///
 /// [rustc issue #45511]: https://github.com/rust-lang/rust/issues/45511
+/// [`TargetFlags`]: ./struct.TargetFlags.html
+/// [`timings` module]: ../timings/index.html#run-log
+/// [`TARGET_FLAG_PRESETS`]: ./constant.TARGET_FLAG_PRESETS.html
 pub fn rustc<'a, I: Iterator<Item = &'a OsStr> + Clone>(args: I) -> Result<()> {
     let rustc_path = env::var_os("COV_RUSTC").expect("COV_RUSTC");
     let cov_build_path_os = env::var_os("COV_BUILD_PATH").expect("COV_BUILD_PATH");
     let cov_build_path = Path::new(&cov_build_path_os);
     let workspace_path = parent_3(cov_build_path);
-    let is_local = is_rustc_compiling_local_crate(args.clone(), workspace_path);
+    let is_local = is_rustc_compiling_local_crate(args.clone(), workspace_path) && may_contain_only_path();
+    let crate_hash = extract_crate_hash(args.clone());
+    let target = env::var("COV_TARGET").expect("COV_TARGET");
 
     let mut cmd = Command::new(rustc_path);
-    cmd.args(args);
+    cmd.args(args.clone());
 
     if is_local {
         let profiler_lib_path = env::var_os("COV_PROFILER_LIB_PATH").expect("COV_PROFILER_LIB_PATH");
@@ -83,27 +142,134 @@ pub fn rustc<'a, I: Iterator<Item = &'a OsStr> + Clone>(args: I) -> Result<()> {
         if profiler_lib_path == OsStr::new("@native") && profiler_lib_name == OsStr::new("@native") {
             cmd.arg("-Zprofile");
         } else {
-            cmd.arg("-Cpasses=insert-gcov-profiling").arg("-L").arg(profiler_lib_path).arg("-l").arg(profiler_lib_name);
+            // Fully-static targets must not pick up a shared profiler runtime which happens to sit next to the archive.
+            let mut lib_arg = OsString::from(if is_static_target(&target) { "static=" } else { "" });
+            lib_arg.push(profiler_lib_name);
+            cmd.arg("-Cpasses=insert-gcov-profiling").arg("-L").arg(profiler_lib_path).arg("-l").arg(lib_arg);
         }
-        cmd.args(&[
-            "-Clink-dead-code",
-            "-Coverflow-checks=off",
-            "-Cinline-threshold=0",
-            "-Ccodegen-units=1",
-            // "-Zdebug-macros", // don't enable, makes the gcno graph involving `assert!` even worse.
-        ]);
+        let overrides = match env::var("COV_TARGET_FLAGS") {
+            Ok(json) => from_str(&json).chain_err(|| "Cannot parse COV_TARGET_FLAGS")?,
+            Err(_) => BTreeMap::new(),
+        };
+        let link_dead_code = match env::var("COV_LINK_DEAD_CODE").as_ref().map(|s| &**s) {
+            Ok("on") => Some(true),
+            Ok("off") => Some(false),
+            _ => None,
+        };
+        cmd.args(coverage_flags(&target, &overrides, link_dead_code));
     }
 
     debug!("Executing {:?}", cmd);
 
+    let started = Instant::now();
     cmd.ensure_success("rustc")?;
+    // Cargo probes the target information by compiling a dummy crate named `___`, which is not worth recording.
+    match (current_run_id(), find_option_value(args.clone(), "--crate-name")) {
+        (Some(run), Some(crate_name)) if crate_name != "___" => {
+            let event = RunEvent {
+                instrumented: Some(is_local),
+                ..RunEvent::new(run, "compile", crate_name, started.elapsed())
+            };
+            timings::record_events(cov_build_path, &[event])?;
+        },
+        _ => {},
+    }
     if is_local {
-        move_gcov_files(cov_build_path, OsStr::new("gcno"))?;
+        let gcno_paths = move_gcov_files(cov_build_path, OsStr::new("gcno"), crate_hash)?;
+        if wants_json_messages() {
+            println!(
+                "{}",
+                json!({
+                    "reason": "cov-crate-instrumented",
+                    "crate_name": find_option_value(args, "--crate-name"),
+                    "crate_hash": crate_hash,
+                    "target": target,
+                })
+            );
+            print_artifact_messages(OsStr::new("gcno"), &gcno_paths);
+        }
     }
 
     Ok(())
 }
 
+/// Checks whether the package being compiled may contain the sources inside `$COV_ONLY_PATH`, i.e. its directory is
+/// inside the path, or the path is inside its directory. Always true without `cargo cov --only-path`.
+fn may_contain_only_path() -> bool {
+    match (env::var_os("COV_ONLY_PATH"), env::var_os("CARGO_MANIFEST_DIR")) {
+        (Some(only_path), Some(manifest_dir)) => {
+            let (only_path, manifest_dir) = (Path::new(&only_path), Path::new(&manifest_dir));
+            manifest_dir.starts_with(only_path) || only_path.starts_with(manifest_dir)
+        },
+        _ => true,
+    }
+}
+
+/// Flags added or removed for the targets matching a glob pattern of the target triple.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TargetFlags {
+    /// Flags to add, after the default ones.
+    #[serde(default)]
+    pub add: Vec<String>,
+    /// Default flags to remove, e.g. `-Clink-dead-code`.
+    #[serde(default)]
+    pub remove: Vec<String>,
+}
+
+/// Built-in adjustments for targets with known quirks, as `(pattern, added flags, removed flags)`.
+///
+/// * `-Clink-dead-code` is removed for the `*-windows-msvc` targets, where `link.exe` fails with unresolved symbols
+///   referred by the dead code of the standard library.
+/// * `-Clink-dead-code` is removed for the fully-static `*-musl` targets, where the dead code pulls in the
+///   `dlopen`-based symbol lookups of the standard library, which cannot work in a static executable.
+pub const TARGET_FLAG_PRESETS: &[(&str, &[&str], &[&str])] = &[
+    ("*-windows-msvc", &[], &["-Clink-dead-code"]),
+    ("*-musl*", &[], &["-Clink-dead-code"]),
+];
+
+/// Obtains the flags added to the local crates other than the profiler, see [`rustc()`](./fn.rustc.html).
+///
+/// `overrides` are the `target-flags` configured in `Cargo.toml`, applied after the built-in presets. If
+/// `link_dead_code` is given, `-Clink-dead-code` is added or removed regardless of both.
+fn coverage_flags(target: &str, overrides: &BTreeMap<String, TargetFlags>, link_dead_code: Option<bool>) -> Vec<String> {
+    fn matches(pattern: &str, target: &str) -> bool {
+        Pattern::new(pattern).map(|p| p.matches(target)).unwrap_or(pattern == target)
+    }
+    fn adjust<S: AsRef<str>>(flags: &mut Vec<String>, add: &[S], remove: &[S]) {
+        flags.retain(|flag| !remove.iter().any(|r| r.as_ref() == flag));
+        flags.extend(add.iter().map(|flag| flag.as_ref().to_owned()));
+    }
+
+    let mut flags = vec![
+        "-Clink-dead-code".to_owned(),
+        "-Coverflow-checks=off".to_owned(),
+        "-Cinline-threshold=0".to_owned(),
+        "-Ccodegen-units=1".to_owned(),
+        // "-Zdebug-macros", // don't enable, makes the gcno graph involving `assert!` even worse.
+    ];
+    for &(pattern, add, remove) in TARGET_FLAG_PRESETS {
+        if matches(pattern, target) {
+            adjust(&mut flags, add, remove);
+        }
+    }
+    for (pattern, target_flags) in overrides {
+        if matches(pattern, target) {
+            adjust(&mut flags, &target_flags.add, &target_flags.remove);
+        }
+    }
+    match link_dead_code {
+        Some(true) if !flags.iter().any(|f| f == "-Clink-dead-code") => flags.insert(0, "-Clink-dead-code".to_owned()),
+        Some(false) => flags.retain(|f| f != "-Clink-dead-code"),
+        _ => {},
+    }
+    flags
+}
+
+/// Checks whether the target produces fully-static executables by default.
+pub fn is_static_target(target: &str) -> bool {
+    target.contains("-musl")
+}
+
 /// Runs doc-test by forwarding `args` to `rustdoc`.
 ///
 /// This function requires several environment variables to be set, otherwise it will panic.
@@ -113,6 +279,8 @@ pub fn rustc<'a, I: Iterator<Item = &'a OsStr> + Clone>(args: I) -> Result<()> {
 /// | `COV_RUSTDOC` | Path to `rustdoc` executable |
 /// | `COV_BUILD_PATH` | Path to `target/cov/build/` of the workspace |
 /// | `COV_PROFILER_LIB_PATH` | Path to folder containing `libclang_rt.profile*.a`, or the string `"@native"` |
+/// | `COV_MESSAGE_FORMAT` | (Optional) `json` to print the [JSON messages](./index.html#json-messages) |
+/// | `COV_RUN_ID` | (Optional) The run to record the time spent in, see the [`timings` module] |
 ///
 /// All GCDA files generated will be moved to `$COV_BUILD_PATH/gcda/` after the test succeeds.
 ///
@@ -135,7 +303,10 @@ pub fn rustdoc<'a, I: Iterator<Item = &'a OsStr>>(args: I) -> Result<()> {
     debug!("Executing {:?}", cmd);
 
     cmd.ensure_success("rustdoc")?;
-    move_gcov_files(cov_build_path, OsStr::new("gcda"))?;
+    let gcda_paths = move_gcov_files(cov_build_path, OsStr::new("gcda"), None)?;
+    if wants_json_messages() {
+        print_artifact_messages(OsStr::new("gcda"), &gcda_paths);
+    }
 
     Ok(())
 }
@@ -148,45 +319,173 @@ pub fn rustdoc<'a, I: Iterator<Item = &'a OsStr>>(args: I) -> Result<()> {
 /// | Environment variable | Meaning |
 /// |----------------------|---------|
 /// | `COV_BUILD_PATH` | Path to `target/cov/build/` of the workspace |
+/// | `COV_RUNNER` | (Optional) How to run the program, one of [`RUNNERS`], default to `native` |
+/// | `COV_MESSAGE_FORMAT` | (Optional) `json` to print the [JSON messages](./index.html#json-messages) |
 ///
 /// All GCDA files generated will be moved to `$COV_BUILD_PATH/gcda/` after the program succeeds.
 ///
+/// The runners other than `native` execute the program on a mobile device or simulator, so cross-compiled tests can
+/// be covered with a single `cargo cov --target <triple> --runner <runner> test`:
+///
+/// * `adb` pushes the program to `/data/local/tmp/cargo-cov/` of the Android device selected by `adb` (use
+///   `ANDROID_SERIAL` to choose one), runs it there with `GCOV_PREFIX` pointing to an app-writable folder, and pulls
+///   the GCDA files back. The `adb` executable can be overridden with the `ADB` environment variable. Devices older
+///   than Android 7 do not report the exit status of the program, so failing tests may look successful.
+/// * `simctl` runs the program on the booted iOS simulator via `xcrun simctl spawn`. The simulator shares the file
+///   system of the host, so the GCDA files are written directly.
+///
 /// # Panics
 ///
-/// Panics when any of the above environment variables is not set.
+/// Panics when any of the above required environment variables is not set.
+///
+/// [`RUNNERS`]: ./constant.RUNNERS.html
+/// [`timings` module]: ../timings/index.html#run-log
 pub fn run<'a, I: Iterator<Item = &'a OsStr>>(mut args: I) -> Result<()> {
     let cov_build_path_os = env::var_os("COV_BUILD_PATH").expect("COV_BUILD_PATH");
     let cov_build_path = Path::new(&cov_build_path_os);
+    let program = args.next().expect("launcher");
 
-    let mut cmd = Command::new(args.next().expect("launcher"));
-    cmd.args(args);
+    let started = Instant::now();
+    match env::var("COV_RUNNER").as_ref().map(|s| &**s) {
+        Ok("adb") => return run_on_android(cov_build_path, program, args),
+        Ok("simctl") => {
+            let mut cmd = Command::new("xcrun");
+            cmd.args(&["simctl", "spawn", "booted"]).arg(program).args(args);
+            debug!("Executing {:?}", cmd);
+            cmd.ensure_success("xcrun")?;
+        },
+        _ => {
+            let mut cmd = Command::new(program);
+            cmd.args(args);
+            debug!("Executing {:?}", cmd);
+            cmd.ensure_success("test")?;
+        },
+    }
+
+    let gcda_paths = move_gcov_files(cov_build_path, OsStr::new("gcda"), None)?;
+    if wants_json_messages() {
+        print_artifact_messages(OsStr::new("gcda"), &gcda_paths);
+    }
+    ordering::record_program(&cov_build_path.join("gcda"), Path::new(program), &gcda_paths)?;
+    if let Some(run) = current_run_id() {
+        let event = RunEvent::new(run, "test", &ordering::program_name(Path::new(program)), started.elapsed());
+        timings::record_events(cov_build_path, &[event])?;
+    }
+
+    Ok(())
+}
+
+/// The test runners which can be selected by `cargo cov --runner`. See [`run()`] for detail.
+///
+/// [`run()`]: ./fn.run.html
+pub const RUNNERS: &[&str] = &["native", "adb", "simctl"];
+
+/// Folder on the Android device to store the test programs and the GCDA files.
+const ANDROID_DEVICE_PATH: &str = "/data/local/tmp/cargo-cov";
+
+/// Runs a program on an Android device through `adb`, and collects the GCDA files it produced.
+///
+/// The GCDA files are written to the absolute paths of the object files by default, which are not writable on the
+/// device. `GCOV_PREFIX` relocates them under `ANDROID_DEVICE_PATH/gcda`, and `GCOV_PREFIX_STRIP` removes the
+/// `target/cov/build` prefix so the pulled files have the same layout as if the program was run locally.
+fn run_on_android<'a, I: Iterator<Item = &'a OsStr>>(cov_build_path: &Path, program: &OsStr, args: I) -> Result<()> {
+    let adb = env::var_os("ADB").unwrap_or_else(|| OsString::from("adb"));
+    let program_name = Path::new(program).file_name().expect("program name").to_string_lossy().into_owned();
+    let device_program = format!("{}/{}", ANDROID_DEVICE_PATH, program_name);
+    let device_gcda_path = format!("{}/gcda", ANDROID_DEVICE_PATH);
+    let prefix_strip = cov_build_path
+        .components()
+        .filter(|c| match *c {
+            Component::Normal(_) => true,
+            _ => false,
+        })
+        .count();
+
+    let mut script = format!(
+        "rm -rf {gcda} && cd {root} && GCOV_PREFIX={gcda} GCOV_PREFIX_STRIP={strip} {program}",
+        gcda = device_gcda_path,
+        root = ANDROID_DEVICE_PATH,
+        strip = prefix_strip,
+        program = escape(Cow::Owned(device_program.clone())),
+    );
+    for arg in args {
+        script.push(' ');
+        script.push_str(&escape(arg.to_string_lossy()));
+    }
+
+    let mut cmd = Command::new(&adb);
+    cmd.args(&["shell", "mkdir", "-p", ANDROID_DEVICE_PATH]);
+    debug!("Executing {:?}", cmd);
+    cmd.ensure_success("adb")?;
+
+    let mut cmd = Command::new(&adb);
+    cmd.arg("push").arg(program).arg(&device_program);
     debug!("Executing {:?}", cmd);
+    cmd.ensure_success("adb")?;
 
+    let mut cmd = Command::new(&adb);
+    cmd.arg("shell").arg(script);
+    debug!("Executing {:?}", cmd);
     cmd.ensure_success("test")?;
-    move_gcov_files(cov_build_path, OsStr::new("gcda"))?;
 
+    // Pull into a temporary folder inside `target/cov/build`, so the GCDA files will be found by `move_gcov_files()`.
+    let pull_dir = TempDir::new_in(cov_build_path)?;
+    let mut cmd = Command::new(&adb);
+    cmd.arg("pull").arg(&device_gcda_path).arg(pull_dir.path());
+    debug!("Executing {:?}", cmd);
+    cmd.ensure_success("adb")?;
+
+    let gcda_paths = move_gcov_files(cov_build_path, OsStr::new("gcda"), None)?;
+    if wants_json_messages() {
+        print_artifact_messages(OsStr::new("gcda"), &gcda_paths);
+    }
+    ordering::record_program(&cov_build_path.join("gcda"), Path::new(program), &gcda_paths)?;
     Ok(())
 }
 
+/// Checks whether `cargo` is run with `--message-format json`, which `cargo cov` tells the shims via the
+/// `COV_MESSAGE_FORMAT` environment variable.
+fn wants_json_messages() -> bool {
+    env::var_os("COV_MESSAGE_FORMAT").map_or(false, |format| format == *"json")
+}
+
+/// Prints the JSON messages of the GCNO or GCDA files just moved, see the [module documentation](./index.html).
+pub fn print_artifact_messages(extension: &OsStr, paths: &[PathBuf]) {
+    let reason = if extension == OsStr::new("gcno") {
+        "cov-gcno-collected"
+    } else {
+        "cov-gcda-produced"
+    };
+    for path in paths {
+        println!("{}", json!({ "reason": reason, "path": path }));
+    }
+}
+
 /// Moves all files with the given `extension` to `[cov_build_path]/[extension]/`, and renames them uniquely so that
 /// there won't be file name collision inside that folder.
 ///
+/// The crate hash will be recorded in the new file names. If `crate_hash` is `None`, the hash is guessed from the
+/// original file name, e.g. `foo-0123456789abcdef.foo0.rcgu.gcda` (as named by `-Zprofile`).
+///
 /// # Examples
 ///
-/// ```no_run
+/// ```ignore
 /// use std::path::Path;
 /// use cargo_cov::shim::move_gcov_files;
 ///
 /// # fn main() { run().unwrap(); }
 /// # fn run() -> ::std::io::Result<()> {
 /// let build_folder = Path::new("workspace/target/cov/build");
-/// move_gcov_files(build_folder, OsStr::new("gcda"))?;
+/// move_gcov_files(build_folder, OsStr::new("gcda"), None)?;
 /// // All `*.gcda` files found inside `workspace/target/cov/build` will now be moved to
 /// // `workspace/target/cov/build/gcda`.
 /// # }
 /// ```
-pub fn move_gcov_files(cov_build_path: &Path, extension: &OsStr) -> Result<()> {
+///
+/// Returns the new paths of the moved files.
+pub fn move_gcov_files(cov_build_path: &Path, extension: &OsStr, crate_hash: Option<&str>) -> Result<Vec<PathBuf>> {
     let mut rng = thread_rng();
+    let mut moved_paths = Vec::new();
     let mut dest_path = join_2(cov_build_path, extension, "*");
 
     let mut lock_file = LockFile::new(cov_build_path)?;
@@ -212,9 +511,16 @@ pub fn move_gcov_files(cov_build_path: &Path, extension: &OsStr) -> Result<()> {
         }
 
         let source_path = entry.path();
+        let crate_hash = crate_hash.or_else(|| {
+            let file_name = source_path.file_name()?.to_str()?;
+            hash_from_file_stem(file_name.split('.').next()?)
+        });
 
         loop {
             let mut filename = OsString::from(format!("{:016x}.", rng.gen::<u64>()));
+            if let Some(hash) = crate_hash {
+                filename.push(format!("@{}.", hash));
+            }
             filename.push(source_path.file_stem().unwrap_or_else(|| OsStr::new("?")));
             filename.push(OsStr::new("."));
             filename.push(extension);
@@ -226,9 +532,88 @@ pub fn move_gcov_files(cov_build_path: &Path, extension: &OsStr) -> Result<()> {
 
         trace!("mv {:?} {:?}", source_path, dest_path);
         rename(source_path, &dest_path).chain_err(|| format!("cannot move `{}` to `{}`", source_path.display(), dest_path.display()))?;
+        moved_paths.push(dest_path.clone());
+    }
+
+    lock_file.unlock()?;
+    Ok(moved_paths)
+}
+
+/// Obtains the crate hash recorded in the name of a GCNO/GCDA file moved by [`move_gcov_files()`].
+///
+/// [`move_gcov_files()`]: ./fn.move_gcov_files.html
+pub fn artifact_hash(path: &Path) -> Option<&str> {
+    let file_name = path.file_name()?.to_str()?;
+    let mut components = file_name.splitn(3, '.').skip(1);
+    let component = components.next()?;
+    if component.starts_with('@') && components.next().is_some() {
+        Some(&component[1..])
+    } else {
+        None
+    }
+}
+
+/// Obtains the crate name recorded in the name of a GCNO/GCDA file moved by [`move_gcov_files()`], e.g. `foo` from
+/// `0123456789abcdef.@fedcba9876543210.foo-fedcba9876543210.gcda`.
+///
+/// [`move_gcov_files()`]: ./fn.move_gcov_files.html
+pub fn artifact_crate_name(path: &Path) -> Option<&str> {
+    let mut components = path.file_stem()?.to_str()?.split('.').peekable();
+    let mut stem = components.next()?;
+    if stem.len() == 16 && stem.bytes().all(|b| b.is_ascii_hexdigit()) && components.peek().is_some() {
+        stem = components.next()?;
+    }
+    if stem.starts_with('@') && components.peek().is_some() {
+        stem = components.next()?;
+    }
+    Some(match hash_from_file_stem(stem) {
+        Some(hash) => &stem[..(stem.len() - hash.len() - 1)],
+        None => stem,
+    })
+}
+
+/// The crate hashes recorded by the GCNO files, to recognize the stale GCDA files.
+///
+/// A GCDA recorded with a crate hash not matching any GCNO is stale, e.g. written by an old test program still on disk,
+/// and must not be merged. When no GCNO records a crate hash (e.g. they are not collected by `cargo cov`), no GCDA is
+/// considered stale. Every command reading GCDA files goes through this filter, so they agree on which are skipped.
+#[derive(Default, Debug)]
+pub struct GcnoHashes(HashSet<String>);
+
+impl GcnoHashes {
+    /// Records the crate hash of a GCNO file, if any.
+    pub fn insert(&mut self, gcno_path: &Path) {
+        self.0.extend(artifact_hash(gcno_path).map(str::to_owned));
+    }
+
+    /// Obtains the crate hash of the GCDA file if it is stale.
+    pub fn stale_hash<'a>(&self, gcda_path: &'a Path) -> Option<&'a str> {
+        match artifact_hash(gcda_path) {
+            Some(hash) if !self.0.is_empty() && !self.0.contains(hash) => Some(hash),
+            _ => None,
+        }
+    }
+
+    /// Checks whether the GCDA file is stale, printing a warning if so.
+    pub fn skips(&self, gcda_path: &Path) -> bool {
+        match self.stale_hash(gcda_path) {
+            Some(hash) => {
+                warning!("skipping `{}`, no *.gcno is built with the crate hash {}", gcda_path.display(), hash);
+                true
+            },
+            None => false,
+        }
     }
+}
 
-    lock_file.unlock()
+/// Extracts the crate hash from a file stem of an artifact built by cargo, e.g. `foo-0123456789abcdef`.
+fn hash_from_file_stem(stem: &str) -> Option<&str> {
+    let hash = &stem[(stem.rfind('-')? + 1)..];
+    if hash.len() == 16 && hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+        Some(hash)
+    } else {
+        None
+    }
 }
 
 struct LockFile(Option<File>);
@@ -259,3 +644,58 @@ impl Drop for LockFile {
         let _ = self.unlock();
     }
 }
+
+#[test]
+fn test_artifact_hash() {
+    assert_eq!(artifact_hash(Path::new("gcda/0123456789abcdef.@fedcba9876543210.foo.gcda")), Some("fedcba9876543210"));
+    assert_eq!(artifact_hash(Path::new("gcda/0123456789abcdef.foo.gcda")), None);
+    assert_eq!(hash_from_file_stem("foo_bar-fedcba9876543210"), Some("fedcba9876543210"));
+    assert_eq!(artifact_crate_name(Path::new("gcda/0123456789abcdef.@fedcba9876543210.foo-fedcba9876543210.gcda")), Some("foo"));
+    assert_eq!(artifact_crate_name(Path::new("gcno/0123456789abcdef.foo_bar.gcno")), Some("foo_bar"));
+    assert_eq!(artifact_crate_name(Path::new("gcno/x.gcno")), Some("x"));
+    assert_eq!(hash_from_file_stem("foo-bar"), None);
+}
+
+#[test]
+fn test_gcno_hashes() {
+    let stale = Path::new("gcda/0123456789abcdef.@fedcba9876543210.foo.gcda");
+    let fresh = Path::new("gcda/0123456789abcdef.@0000000000000000.foo.gcda");
+    let unhashed = Path::new("gcda/0123456789abcdef.foo.gcda");
+
+    let mut hashes = GcnoHashes::default();
+    hashes.insert(Path::new("gcno/x.gcno"));
+    assert_eq!(hashes.stale_hash(stale), None);
+
+    hashes.insert(Path::new("gcno/0123456789abcdef.@0000000000000000.foo.gcno"));
+    assert_eq!(hashes.stale_hash(stale), Some("fedcba9876543210"));
+    assert_eq!(hashes.stale_hash(fresh), None);
+    assert_eq!(hashes.stale_hash(unhashed), None);
+}
+
+#[test]
+fn test_coverage_flags() {
+    let has_flag = |target, overrides, flag| coverage_flags(target, overrides, None).iter().any(|f| f == flag);
+    let no_overrides = BTreeMap::new();
+    assert!(has_flag("x86_64-unknown-linux-gnu", &no_overrides, "-Clink-dead-code"));
+    assert!(has_flag("x86_64-pc-windows-gnu", &no_overrides, "-Clink-dead-code"));
+    assert!(!has_flag("x86_64-pc-windows-msvc", &no_overrides, "-Clink-dead-code"));
+    assert!(!has_flag("x86_64-unknown-linux-musl", &no_overrides, "-Clink-dead-code"));
+    assert!(has_flag("x86_64-unknown-linux-musl", &no_overrides, "-Ccodegen-units=1"));
+
+    let mut overrides = BTreeMap::new();
+    overrides.insert(
+        "aarch64-*-android".to_owned(),
+        TargetFlags {
+            add: vec!["-Clink-arg=-Wl,--no-gc-sections".to_owned()],
+            remove: vec!["-Clink-dead-code".to_owned()],
+        },
+    );
+    assert!(!has_flag("aarch64-linux-android", &overrides, "-Clink-dead-code"));
+    assert!(has_flag("aarch64-linux-android", &overrides, "-Clink-arg=-Wl,--no-gc-sections"));
+    assert!(has_flag("x86_64-linux-android", &overrides, "-Clink-dead-code"));
+
+    let flags = coverage_flags("x86_64-pc-windows-msvc", &no_overrides, Some(true));
+    assert_eq!(flags.iter().filter(|f| *f == "-Clink-dead-code").count(), 1);
+    let flags = coverage_flags("x86_64-unknown-linux-gnu", &no_overrides, Some(false));
+    assert!(!flags.iter().any(|f| f == "-Clink-dead-code"));
+}
@@ -0,0 +1,108 @@
+//! Annotated source of a single file in the terminal.
+//!
+//! `cargo cov show src/lib.rs` prints the source file with the execution count of every line in the left margin,
+//! green if the line is covered and red if it is never executed, so the coverage can be checked during quick iteration
+//! without opening the report in a browser. Lines without code have no count. With `--uncovered-only`, only the lines
+//! never executed are printed.
+//!
+//! The coverage is collected the same way as `cargo cov annotate`, honoring `--workspace`, `--gcno`, `--gcda` and
+//! `--import`. As with `cargo cov blame-report`, the file may be the full source path, or any suffix of it.
+
+use argparse::ReportConfig;
+use blame::find_symbol;
+use error::{ErrorKind, Result, ResultExt};
+use report;
+
+use cov::Interner;
+use cov::report::Line;
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+
+/// A line of the shown source file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShownLine {
+    /// The line number, 1-based.
+    pub number: u32,
+    /// The execution count, or `None` if the line contains no code.
+    pub count: Option<u64>,
+    /// The source text, without the line terminator.
+    pub text: String,
+}
+
+/// A source file with the execution count of every line.
+#[derive(Clone, Debug)]
+pub struct ShownFile {
+    /// The matched source path.
+    pub path: String,
+    /// Number of lines containing code.
+    pub lines_count: usize,
+    /// Number of lines containing code which are executed at least once.
+    pub lines_covered: usize,
+    /// The lines to print.
+    pub lines: Vec<ShownLine>,
+}
+
+/// Pairs every line of `source` with its count in `lines`, keeping only the uncovered lines if `uncovered_only`.
+fn shown_lines(source: &str, lines: &BTreeMap<u32, Line>, uncovered_only: bool) -> Vec<ShownLine> {
+    source
+        .lines()
+        .enumerate()
+        .map(|(i, text)| {
+            let number = i as u32 + 1;
+            ShownLine {
+                number,
+                count: lines.get(&number).map(|line| line.count),
+                text: text.to_owned(),
+            }
+        })
+        .filter(|line| !uncovered_only || line.count == Some(0))
+        .collect()
+}
+
+/// Reads the source `file` together with its coverage.
+///
+/// The `file` may be the full source path, or any suffix of it, e.g. `src/lib.rs`.
+pub fn show(config: &ReportConfig, file: &str, uncovered_only: bool) -> Result<ShownFile> {
+    let mut interner = Interner::new();
+    let report = report::collect(config, &mut interner)?;
+
+    let symbol = find_symbol(&interner, report.files.keys().cloned(), file).ok_or_else(|| ErrorKind::SourceNotFound(file.to_owned()))?;
+    let path = config.workspace_path.join(&interner[symbol]);
+    let mut source = String::new();
+    File::open(&path)
+        .and_then(|mut f| f.read_to_string(&mut source))
+        .chain_err(|| format!("Cannot read source `{}`", path.display()))?;
+
+    let lines = &report.files[&symbol].lines;
+    Ok(ShownFile {
+        path: interner[symbol].to_owned(),
+        lines_count: lines.len(),
+        lines_covered: lines.values().filter(|line| line.count > 0).count(),
+        lines: shown_lines(&source, lines, uncovered_only),
+    })
+}
+
+#[test]
+fn test_shown_lines() {
+    let line = |count| Line { count, ..Line::default() };
+    let lines = vec![(2, line(3)), (3, line(0)), (5, line(0))].into_iter().collect();
+    let source = "fn f() {\r\n    g();\n    h();\n}\n";
+
+    let shown = shown_lines(source, &lines, false);
+    assert_eq!(shown.iter().map(|l| (l.number, l.count)).collect::<Vec<_>>(), vec![(1, None), (2, Some(3)), (3, Some(0)), (4, None)]);
+    assert_eq!(shown[0].text, "fn f() {");
+
+    let shown = shown_lines(source, &lines, true);
+    assert_eq!(
+        shown,
+        vec![
+            ShownLine {
+                number: 3,
+                count: Some(0),
+                text: "    h();".to_owned(),
+            },
+        ]
+    );
+}
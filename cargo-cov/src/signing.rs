@@ -0,0 +1,139 @@
+//! Signing the exported coverage data.
+//!
+//! When the shards of a pipeline are exported on machines other than the one aggregating them, `cargo cov export
+//! --sign key.pem` writes a detached signature next to the exported file (`export.json.sig` for `export.json`), and
+//! `cargo cov report --import export.json --verify-key public.pem` fails unless the signature matches, so a file
+//! modified in transit is never reported. The tracefiles given by `--import-lcov` are verified the same way, their
+//! signatures can be created by `openssl dgst -sha256 -sign key.pem -out coverage.info.sig coverage.info`.
+//!
+//! The file is read only once, and the signature is verified against the same bytes which are then parsed, so the
+//! file cannot be swapped in between.
+//!
+//! The signature is the SHA-256 digest of the file signed by an RSA or EC private key in PEM format, as computed by the
+//! `openssl` program, which must be in `PATH`. The key pair can be created by:
+//!
+//! ```sh
+//! openssl ecparam -name prime256v1 -genkey -noout -out key.pem
+//! openssl ec -in key.pem -pubout -out public.pem
+//! ```
+
+use error::{ErrorKind, Result, ResultExt};
+
+use std::ffi::{OsStr, OsString};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Obtains the path to the detached signature of the file at `path`.
+pub fn signature_path(path: &Path) -> PathBuf {
+    let mut signature_path = OsString::from(path);
+    signature_path.push(".sig");
+    PathBuf::from(signature_path)
+}
+
+/// Runs `openssl dgst -sha256` with the extra `args`, and returns whether it succeeded. If `input` is given, it is
+/// digested instead of a file.
+fn openssl_dgst(args: &[&OsStr], input: Option<&[u8]>) -> Result<bool> {
+    let mut child = Command::new("openssl")
+        .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .arg("dgst")
+        .arg("-sha256")
+        .args(args)
+        .spawn()
+        .chain_err(|| "Cannot run `openssl`, is it installed?")?;
+    if let Some(input) = input {
+        // Dropping stdin closes it, so openssl sees the end of the input. A failure to write means openssl has exited
+        // early, which is reported by its status.
+        let mut stdin = child.stdin.take().expect("stdin");
+        stdin.write_all(input).ok();
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        debug!("openssl dgst {:?} failed with {}: {}", args, output.status, String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(output.status.success())
+}
+
+/// Signs the file at `path` with the private key at `key_path`. Returns the path to the signature.
+pub fn sign(path: &Path, key_path: &Path) -> Result<PathBuf> {
+    let signature_path = signature_path(path);
+    let signed = openssl_dgst(&[OsStr::new("-sign"), key_path.as_os_str(), OsStr::new("-out"), signature_path.as_os_str(), path.as_os_str()], None)?;
+    ensure!(signed, "Cannot sign `{}` with the key `{}`", path.display(), key_path.display());
+    Ok(signature_path)
+}
+
+/// Verifies the signature of the file at `path` with the public key at `public_key_path`, where `content` is what has
+/// been read from the file.
+///
+/// # Errors
+///
+/// Returns [`InvalidSignature`] if the signature is missing or does not match.
+///
+/// [`InvalidSignature`]: ../error/enum.ErrorKind.html#variant.InvalidSignature
+pub fn verify(path: &Path, content: &[u8], public_key_path: &Path) -> Result<()> {
+    let signature_path = signature_path(path);
+    ensure!(signature_path.is_file(), ErrorKind::InvalidSignature(path.to_owned(), "the signature is missing"));
+    ensure!(public_key_path.is_file(), "Cannot read the public key `{}`", public_key_path.display());
+    let verified = openssl_dgst(&[OsStr::new("-verify"), public_key_path.as_os_str(), OsStr::new("-signature"), signature_path.as_os_str()], Some(content))?;
+    ensure!(verified, ErrorKind::InvalidSignature(path.to_owned(), "the signature does not match"));
+    Ok(())
+}
+
+/// Reads the whole file at `path`, and verifies its signature if `public_key_path` is given.
+///
+/// # Errors
+///
+/// Returns [`InvalidSignature`] if the signature is missing or does not match.
+///
+/// [`InvalidSignature`]: ../error/enum.ErrorKind.html#variant.InvalidSignature
+pub fn read(path: &Path, public_key_path: Option<&Path>) -> Result<Vec<u8>> {
+    let mut content = Vec::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_end(&mut content))
+        .chain_err(|| format!("Cannot read `{}`", path.display()))?;
+    if let Some(public_key_path) = public_key_path {
+        verify(path, &content, public_key_path)?;
+    }
+    Ok(content)
+}
+
+#[test]
+fn test_sign_verify() {
+    use std::fs::{remove_file, write};
+
+    Command::new("openssl").arg("version").output().expect("this test needs `openssl` in PATH");
+
+    let dir = ::tempfile::TempDir::new().unwrap();
+    let generate_key = |name: &str| {
+        let key_path = dir.path().join(format!("{}.pem", name));
+        let public_key_path = dir.path().join(format!("{}.pub.pem", name));
+        let status = Command::new("openssl").args(&["ecparam", "-name", "prime256v1", "-genkey", "-noout", "-out"]).arg(&key_path).status().unwrap();
+        assert!(status.success());
+        let status = Command::new("openssl").args(&["ec", "-pubout", "-in"]).arg(&key_path).arg("-out").arg(&public_key_path).stderr(Stdio::null()).status().unwrap();
+        assert!(status.success());
+        (key_path, public_key_path)
+    };
+    let (key_path, public_key_path) = generate_key("key");
+    let (_, other_public_key_path) = generate_key("other");
+
+    let path = dir.path().join("export.json");
+    write(&path, b"{\"files\": []}").unwrap();
+    assert!(read(&path, Some(&public_key_path)).is_err());
+    assert_eq!(sign(&path, &key_path).unwrap(), dir.path().join("export.json.sig"));
+    assert_eq!(read(&path, Some(&public_key_path)).unwrap(), b"{\"files\": []}");
+    assert!(read(&path, Some(&other_public_key_path)).is_err());
+    assert!(verify(&path, b"{\"files\": [1]}", &public_key_path).is_err());
+
+    write(&path, b"{\"files\": [1]}").unwrap();
+    assert_eq!(read(&path, None).unwrap(), b"{\"files\": [1]}");
+    match *read(&path, Some(&public_key_path)).unwrap_err().kind() {
+        ErrorKind::InvalidSignature(ref p, _) => assert_eq!(p, &path),
+        ref kind => panic!("unexpected error {:?}", kind),
+    }
+
+    remove_file(&key_path).unwrap();
+    assert!(sign(&path, &key_path).is_err());
+}
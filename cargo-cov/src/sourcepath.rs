@@ -6,11 +6,23 @@
 //!
 //! This module provides a function to analyze the source path and determines if it is interesting or not. User can then
 //! selectively hide those reports if the category is not interesting.
+//!
+//! The categorization is specific to Rust. Since the GCNO and GCDA formats are language-agnostic, the coverage of a C or
+//! C++ project can be analyzed as well with [`Language::C`], where a path is either inside the workspace or unknown.
+//!
+//! [`Language::C`]: ./enum.Language.html#variant.C
+//!
+//! The GCNO files may be produced on another OS than the one running `cargo cov` (e.g. collected on a Linux CI runner
+//! and analyzed on Windows). Therefore `/` and `\` are both treated as path separators when matching and splitting the
+//! source paths, regardless of the current OS.
 
-use cov::IntoStringLossy;
+use cov::{Interner, IntoStringLossy, Symbol};
+pub use cov_types::{SourceType, UnsupportedSourceTypeName};
 
 use home::cargo_home;
 
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::path::MAIN_SEPARATOR;
 use std::str::FromStr;
 
@@ -36,78 +48,268 @@ lazy_static! {
     };
 }
 
-bitflags! {
-    /// The type of source path.
-    pub struct SourceType: u8 {
-        /// The path is in the local workspace.
-        const LOCAL = 1;
-        /// The "path" is part of macro declaration.
-        const MACROS = 2;
-        /// Unknown kind of path.
-        const UNKNOWN = 4;
-        /// The path is of external crates.
-        const CRATES = 8;
-        /// The path is in the Rust standard libraries.
-        const RUSTSRC = 16;
+/// The programming language of the profiled project.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    /// Rust. Source paths are categorized using [`identify_source_path()`].
+    ///
+    /// [`identify_source_path()`]: ./fn.identify_source_path.html
+    Rust,
+    /// C or C++. Source paths are either [`SourceType::LOCAL`] (inside the workspace, or relative) or
+    /// [`SourceType::UNKNOWN`] (e.g. system headers).
+    ///
+    /// [`SourceType::LOCAL`]: ./constant.SourceType::LOCAL.html
+    /// [`SourceType::UNKNOWN`]: ./constant.SourceType::UNKNOWN.html
+    C,
+}
 
-        /// The default set of interesting source paths.
-        const DEFAULT = SourceType::LOCAL.bits | SourceType::MACROS.bits | SourceType::UNKNOWN.bits;
+impl Default for Language {
+    fn default() -> Language {
+        Language::Rust
     }
 }
 
-/// The error raised when [`SourceType::from_str()`] encounters an unrecognized string.
-///
-/// [`SourceType::from_str()`]: ./struct.SourceType.html#method.from_str
-#[derive(Debug)]
-pub struct UnsupportedSourceTypeName;
+impl Language {
+    /// Obtains the name of the language, the inverse of [`from_str()`].
+    ///
+    /// [`from_str()`]: #method.from_str
+    pub fn name(self) -> &'static str {
+        match self {
+            Language::Rust => "rust",
+            Language::C => "c",
+        }
+    }
 
-impl SourceType {
-    /// Parses an iterator of strings using [`from_str()`], and returns the union of all bitflags.
+    /// The default set of interesting source paths of a project in this language.
+    pub fn default_source_types(self) -> SourceType {
+        match self {
+            Language::Rust => SourceType::DEFAULT,
+            Language::C => SourceType::LOCAL,
+        }
+    }
+
+    /// Analyzes the source path of a project in this language. See [`identify_source_path()`] for detail.
     ///
-    /// # Errors
+    /// ```
+    /// use cargo_cov::sourcepath::*;
     ///
-    /// Returns [`UnsupportedSourceTypeName`] when `from_str()` fails.
+    /// let source_path = "/checkout/src/main.c";
+    /// assert_eq!(Language::Rust.identify_source_path(source_path, "/workspace/path").0, SourceType::RUSTSRC);
+    /// assert_eq!(Language::C.identify_source_path(source_path, "/workspace/path").0, SourceType::UNKNOWN);
+    /// assert_eq!(Language::C.identify_source_path(source_path, "/checkout/").0, SourceType::LOCAL);
+    /// assert_eq!(Language::C.identify_source_path("src/main.c", "/workspace/path").0, SourceType::LOCAL);
+    /// ```
     ///
-    /// [`from_str()`]: #method.from_str
-    /// [`UnsupportedSourceTypeName`]: ./struct.UnsupportedSourceTypeName.html
-    pub fn from_multi_str<'a, I>(strings: I) -> Result<SourceType, UnsupportedSourceTypeName>
-    where
-        I: Iterator<Item = &'a str>,
-    {
-        let mut res = SourceType::empty();
-        for s in strings {
-            res |= s.parse()?;
+    /// [`identify_source_path()`]: ./fn.identify_source_path.html
+    pub fn identify_source_path(self, path: &str, crates_path: &str) -> (SourceType, usize) {
+        match self {
+            Language::Rust => identify_source_path(path, crates_path),
+            Language::C if path_starts_with(path, crates_path) => (SourceType::LOCAL, crates_path.len()),
+            // A relative path is relative to the directory of compilation, which is assumed to be the workspace.
+            Language::C if !is_absolute_path(path) => (SourceType::LOCAL, 0),
+            Language::C => (SourceType::UNKNOWN, 0),
         }
-        Ok(res)
     }
+}
+
+/// The error raised when [`Language::from_str()`] encounters an unrecognized string.
+///
+/// [`Language::from_str()`]: ./enum.Language.html#method.from_str
+#[derive(Debug)]
+pub struct UnsupportedLanguageName;
+
+impl FromStr for Language {
+    type Err = UnsupportedLanguageName;
+    fn from_str(s: &str) -> Result<Language, UnsupportedLanguageName> {
+        Ok(match s {
+            "rust" => Language::Rust,
+            "c" => Language::C,
+            _ => return Err(UnsupportedLanguageName),
+        })
+    }
+}
 
-    /// Obtains the path prefix so that
-    pub fn prefix(self) -> &'static str {
+/// How the files are grouped in the summary of a report, given by `--group-by`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum GroupBy {
+    /// By package, e.g. `./cov` for a workspace member, `serde-1.0.0` for an external crate and `core` for the standard
+    /// libraries. The package at the root of the workspace is `.`.
+    Crate,
+    /// By the directory containing the file, e.g. `./src/report`.
+    Dir,
+    /// By top-level Rust module, e.g. `cov::graph`, `crate::report` for the package at the root of the workspace, or
+    /// `serde::de` for an external crate.
+    Module,
+    /// By source type, e.g. `local` or `crates`.
+    SourceType,
+}
+
+impl Default for GroupBy {
+    fn default() -> GroupBy {
+        GroupBy::SourceType
+    }
+}
+
+impl GroupBy {
+    /// Obtains the name of the grouping, the inverse of [`from_str()`].
+    ///
+    /// [`from_str()`]: #method.from_str
+    pub fn name(self) -> &'static str {
         match self {
-            SourceType::LOCAL => ".",
-            SourceType::RUSTSRC => "«rust»",
-            SourceType::CRATES => "«crates»",
-            _ => "",
+            GroupBy::Crate => "crate",
+            GroupBy::Dir => "dir",
+            GroupBy::Module => "module",
+            GroupBy::SourceType => "source-type",
+        }
+    }
+
+    /// Obtains the name of the group of a source path, given the result of [`Language::identify_source_path()`].
+    ///
+    /// The packages and modules are guessed from the conventional Cargo layout (`src/`, `tests/`, etc.). Paths which
+    /// are neither local nor from the Rust libraries are grouped by their source type, except in `Dir`. C projects do
+    /// not have modules and are grouped by directory instead.
+    ///
+    /// ```
+    /// use cargo_cov::sourcepath::*;
+    ///
+    /// let path = "/workspace/cov/src/graph/mod.rs";
+    /// let (source_type, prefix_len) = Language::Rust.identify_source_path(path, "/workspace/");
+    /// let group = |group_by: GroupBy| group_by.group_name(path, source_type, prefix_len, Language::Rust);
+    /// assert_eq!(group(GroupBy::Crate), "./cov");
+    /// assert_eq!(group(GroupBy::Dir), "./cov/src/graph");
+    /// assert_eq!(group(GroupBy::Module), "cov::graph");
+    /// assert_eq!(group(GroupBy::SourceType), "local");
+    /// ```
+    ///
+    /// [`Language::identify_source_path()`]: ./enum.Language.html#method.identify_source_path
+    pub fn group_name(self, path: &str, source_type: SourceType, prefix_len: usize, language: Language) -> String {
+        let group_by = match (self, language) {
+            (GroupBy::Module, Language::C) => GroupBy::Dir,
+            (group_by, _) => group_by,
+        };
+        let rest = &path[prefix_len..];
+        let components = rest.split(is_separator).filter(|c| !c.is_empty()).collect::<Vec<_>>();
+        let directories = &components[..components.len().saturating_sub(1)];
+
+        match (group_by, source_type) {
+            (GroupBy::SourceType, _) => source_type.name().to_owned(),
+            (GroupBy::Dir, SourceType::LOCAL) | (GroupBy::Dir, SourceType::CRATES) | (GroupBy::Dir, SourceType::RUSTSRC) => {
+                let mut dir = source_type.prefix().to_owned();
+                for directory in directories {
+                    dir.push(MAIN_SEPARATOR);
+                    dir.push_str(directory);
+                }
+                dir
+            },
+            (GroupBy::Dir, _) => match path.rfind(is_separator) {
+                Some(0) => path[..1].to_owned(),
+                Some(i) => path[..i].to_owned(),
+                None => path.to_owned(),
+            },
+            (_, SourceType::LOCAL) | (_, SourceType::CRATES) | (_, SourceType::RUSTSRC) => {
+                let package = Package::split(source_type, &components);
+                if group_by == GroupBy::Crate {
+                    package.name
+                } else {
+                    package.module()
+                }
+            },
+            (_, _) => source_type.name().to_owned(),
         }
     }
 }
 
+/// The error raised when [`GroupBy::from_str()`] encounters an unrecognized string.
+///
+/// [`GroupBy::from_str()`]: ./enum.GroupBy.html#method.from_str
+#[derive(Debug)]
+pub struct UnsupportedGroupByName;
 
-impl FromStr for SourceType {
-    type Err = UnsupportedSourceTypeName;
-    fn from_str(s: &str) -> Result<SourceType, UnsupportedSourceTypeName> {
+impl FromStr for GroupBy {
+    type Err = UnsupportedGroupByName;
+    fn from_str(s: &str) -> Result<GroupBy, UnsupportedGroupByName> {
         Ok(match s {
-            "local" => SourceType::LOCAL,
-            "macros" => SourceType::MACROS,
-            "rustsrc" => SourceType::RUSTSRC,
-            "crates" => SourceType::CRATES,
-            "unknown" => SourceType::UNKNOWN,
-            "all" => SourceType::all(),
-            _ => return Err(UnsupportedSourceTypeName),
+            "crate" => GroupBy::Crate,
+            "dir" => GroupBy::Dir,
+            "module" => GroupBy::Module,
+            "source-type" => GroupBy::SourceType,
+            _ => return Err(UnsupportedGroupByName),
         })
     }
 }
 
+/// The package containing a source file, guessed from its path.
+struct Package<'a> {
+    /// Name of the package shown in the report.
+    name: String,
+    /// Name of the crate in Rust code.
+    crate_name: String,
+    /// The path components inside `src/`, including the file name. Empty if the file is not inside `src/`.
+    source: &'a [&'a str],
+}
+
+impl<'a> Package<'a> {
+    /// Finds the package from the path `components`, with the prefix of `source_type` stripped.
+    fn split(source_type: SourceType, components: &'a [&'a str]) -> Package<'a> {
+        fn inside_src<'a>(components: &'a [&'a str]) -> &'a [&'a str] {
+            match components.first() {
+                Some(&"src") => &components[1..],
+                _ => &[],
+            }
+        }
+
+        let (name, crate_name, source) = match source_type {
+            // `serde-1.0.0/src/lib.rs`
+            SourceType::CRATES if !components.is_empty() => {
+                let name = components[0];
+                let crate_name = match name.rfind('-') {
+                    Some(i) if name[(i + 1)..].starts_with(|c: char| c.is_ascii_digit()) => &name[..i],
+                    _ => name,
+                };
+                (name.to_owned(), crate_name, inside_src(&components[1..]))
+            },
+            // `src/libcore/option.rs`
+            SourceType::RUSTSRC if components.len() > 1 && components[0] == "src" => {
+                let name = components[1].trim_start_matches("lib");
+                (name.to_owned(), name, &components[2..])
+            },
+            // `library/core/src/option.rs`
+            SourceType::RUSTSRC if components.len() > 1 => (components[1].to_owned(), components[1], inside_src(&components[2..])),
+            // `cov/src/lib.rs`, `tests/it.rs` or `build.rs`
+            _ => {
+                let directories = &components[..components.len().saturating_sub(1)];
+                let end = directories
+                    .iter()
+                    .position(|c| ["src", "tests", "benches", "examples"].contains(c))
+                    .unwrap_or_else(|| directories.len());
+                if end == 0 {
+                    (SourceType::LOCAL.prefix().to_owned(), "crate", inside_src(components))
+                } else {
+                    let name = format!("{}{}{}", SourceType::LOCAL.prefix(), MAIN_SEPARATOR, components[..end].join(&MAIN_SEPARATOR.to_string()));
+                    (name, components[end - 1], inside_src(&components[end..]))
+                }
+            },
+        };
+        Package {
+            name,
+            crate_name: crate_name.replace('-', "_"),
+            source,
+        }
+    }
+
+    /// Obtains the path of the top-level module containing the source file.
+    fn module(&self) -> String {
+        match self.source.first() {
+            Some(first) if self.source.len() > 1 || (*first != "lib.rs" && *first != "main.rs") => {
+                format!("{}::{}", self.crate_name, first.trim_end_matches(".rs"))
+            },
+            _ => self.crate_name.clone(),
+        }
+    }
+}
+
 /// Analyzes the the source path and obtain its corresponding [`SourceType`].
 ///
 /// `crates_path` should be the string representation of the workspace path. If the `path` starts with `crates_path`, it
@@ -134,21 +336,165 @@ impl FromStr for SourceType {
 /// [`SourceType`]: ./struct.SourceType.html
 /// [`SourceType::LOCAL`]: ./constant.SourceType::LOCAL.html
 pub fn identify_source_path(path: &str, crates_path: &str) -> (SourceType, usize) {
-    if path.starts_with(crates_path) {
+    if path_starts_with(path, crates_path) {
         (SourceType::LOCAL, crates_path.len())
-    } else if path.starts_with(&*REGISTRY_PATH) {
+    } else if path_starts_with(path, &REGISTRY_PATH) {
         let subpath = &path[REGISTRY_PATH.len()..];
-        let first_slash = subpath.find(MAIN_SEPARATOR).map_or(0, |s| s + MAIN_SEPARATOR.len_utf8());
+        let first_slash = subpath.find(is_separator).map_or(0, |s| s + 1);
         (SourceType::CRATES, REGISTRY_PATH.len() + first_slash)
     } else if path.starts_with('<') && path.ends_with(" macros>") {
         (SourceType::MACROS, 0)
-    } else if path.starts_with(MACOS_RUSTSRC_DIR) {
+    } else if path_starts_with(path, MACOS_RUSTSRC_DIR) {
         (SourceType::RUSTSRC, MACOS_RUSTSRC_DIR.len())
-    } else if path.starts_with(DOCKER_RUSTSRC_DIR) {
+    } else if path_starts_with(path, DOCKER_RUSTSRC_DIR) {
         (SourceType::RUSTSRC, DOCKER_RUSTSRC_DIR.len())
-    } else if path.starts_with(WINDOWS_RUSTSRC_DIR) {
+    } else if path_starts_with(path, WINDOWS_RUSTSRC_DIR) {
         (SourceType::RUSTSRC, WINDOWS_RUSTSRC_DIR.len())
     } else {
         (SourceType::UNKNOWN, 0)
     }
 }
+
+/// Checks whether `c` separates the components of a source path on any OS, i.e. `/` or `\`.
+pub fn is_separator(c: char) -> bool {
+    c == '/' || c == '\\'
+}
+
+/// Checks whether `path` starts with `prefix`, treating `/` and `\` as the same character.
+///
+/// Since both separators are one byte long, the matched part of `path` is always `prefix.len()` bytes long.
+///
+/// ```
+/// use cargo_cov::sourcepath::path_starts_with;
+///
+/// assert!(path_starts_with(r"C:\projects\rust\src\libstd\lib.rs", "C:/projects/rust/"));
+/// assert!(path_starts_with("/checkout/src/libcore/option.rs", r"\checkout\"));
+/// assert!(!path_starts_with("/checkout/src/libcore/option.rs", "/workspace/"));
+/// ```
+pub fn path_starts_with(path: &str, prefix: &str) -> bool {
+    path.len() >= prefix.len() && path.bytes().zip(prefix.bytes()).all(|(a, b)| a == b || (is_separator(a as char) && is_separator(b as char)))
+}
+
+/// Checks whether `path` is absolute on any OS, i.e. starts with a separator or a drive letter like `C:\`.
+fn is_absolute_path(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    match bytes.first() {
+        Some(&c) if is_separator(c as char) => true,
+        Some(c) if c.is_ascii_alphabetic() => bytes.len() > 2 && bytes[1] == b':' && is_separator(bytes[2] as char),
+        _ => false,
+    }
+}
+
+/// Replaces the separators of `path` by the one of the current OS, so paths from another OS are shown consistently.
+///
+/// ```
+/// use cargo_cov::sourcepath::normalize_separators;
+/// use std::path::MAIN_SEPARATOR;
+///
+/// let expected = format!("src{0}libcore{0}option.rs", MAIN_SEPARATOR);
+/// assert_eq!(normalize_separators(r"src\libcore/option.rs"), expected);
+/// ```
+pub fn normalize_separators(path: &str) -> Cow<str> {
+    if path.contains(|c| is_separator(c) && c != MAIN_SEPARATOR) {
+        Cow::Owned(path.chars().map(|c| if is_separator(c) { MAIN_SEPARATOR } else { c }).collect())
+    } else {
+        Cow::Borrowed(path)
+    }
+}
+
+/// Classifies many interned source paths, analyzing every path only once.
+///
+/// The same files are classified over and over, e.g. once per line record while pruning and once per output while
+/// rendering, so the result of [`Language::identify_source_path()`] is cached by the symbol of the path.
+///
+/// [`Language::identify_source_path()`]: ./enum.Language.html#method.identify_source_path
+#[derive(Debug)]
+pub struct SourceClassifier {
+    language: Language,
+    crates_path: String,
+    paths: HashMap<Symbol, (SourceType, usize)>,
+}
+
+impl SourceClassifier {
+    /// Creates a classifier of the source paths of a project in `language` at `crates_path`.
+    pub fn new(language: Language, crates_path: String) -> SourceClassifier {
+        SourceClassifier {
+            language,
+            crates_path,
+            paths: HashMap::new(),
+        }
+    }
+
+    /// Analyzes the source path interned as `symbol`, same as [`Language::identify_source_path()`].
+    ///
+    /// [`Language::identify_source_path()`]: ./enum.Language.html#method.identify_source_path
+    pub fn identify(&mut self, symbol: Symbol, interner: &Interner) -> (SourceType, usize) {
+        let (language, crates_path) = (self.language, &self.crates_path);
+        *self.paths.entry(symbol).or_insert_with(|| language.identify_source_path(&interner[symbol], crates_path))
+    }
+}
+
+#[test]
+fn test_source_classifier() {
+    let mut interner = Interner::new();
+    let paths = [
+        "/workspace/src/lib.rs",
+        "/workspace/src/main.rs",
+        "/checkout/src/libcore/option.rs",
+        "<std macros>",
+        "src/relative.c",
+        "/usr/include/stdio.h",
+    ];
+    for &language in &[Language::Rust, Language::C] {
+        let mut classifier = SourceClassifier::new(language, "/workspace/".to_owned());
+        for path in &paths {
+            let symbol = interner.intern(*path);
+            assert_eq!(classifier.identify(symbol, &interner), language.identify_source_path(path, "/workspace/"), "{}", path);
+        }
+    }
+}
+
+#[test]
+fn test_group_name() {
+    let cases = [
+        ("/workspace/src/report.rs", SourceType::LOCAL, 11, ".", "./src", "crate::report"),
+        ("/workspace/build.rs", SourceType::LOCAL, 11, ".", ".", "crate"),
+        ("/workspace/cargo-cov/tests/it.rs", SourceType::LOCAL, 11, "./cargo-cov", "./cargo-cov/tests", "cargo_cov"),
+        ("/registry/serde-1.0.0/src/de/value.rs", SourceType::CRATES, 10, "serde-1.0.0", "«crates»/serde-1.0.0/src/de", "serde::de"),
+        ("/checkout/src/libcore/option.rs", SourceType::RUSTSRC, 10, "core", "«rust»/src/libcore", "core::option"),
+        ("/checkout/library/std/src/lib.rs", SourceType::RUSTSRC, 10, "std", "«rust»/library/std/src", "std"),
+        ("<std macros>", SourceType::MACROS, 0, "macros", "<std macros>", "macros"),
+    ];
+    for &(path, source_type, prefix_len, krate, dir, module) in &cases {
+        let path = path.replace('/', &MAIN_SEPARATOR.to_string());
+        let group = |group_by: GroupBy| group_by.group_name(&path, source_type, prefix_len, Language::Rust);
+        assert_eq!(group(GroupBy::Crate), krate, "{}", path);
+        assert_eq!(group(GroupBy::Dir), dir.replace('/', &MAIN_SEPARATOR.to_string()), "{}", path);
+        assert_eq!(group(GroupBy::Module), module, "{}", path);
+        assert_eq!(group(GroupBy::SourceType), source_type.name(), "{}", path);
+    }
+    assert_eq!(GroupBy::Module.group_name("/workspace/src/main.c", SourceType::LOCAL, 11, Language::C), "./src");
+}
+
+#[test]
+fn test_identify_foreign_separators() {
+    let cases = [
+        (r"C:\projects\rust\src\libstd\lib.rs", "/workspace/", SourceType::RUSTSRC, 17),
+        (r"\checkout\src\libcore\option.rs", "/workspace/", SourceType::RUSTSRC, 10),
+        (r"C:\workspace\src\lib.rs", "C:/workspace/", SourceType::LOCAL, 13),
+        ("/workspace/src/lib.rs", r"\workspace\", SourceType::LOCAL, 11),
+        ("/usr/include/stdio.h", r"C:\workspace\", SourceType::UNKNOWN, 0),
+    ];
+    for &(path, crates_path, source_type, prefix_len) in &cases {
+        assert_eq!(identify_source_path(path, crates_path), (source_type, prefix_len), "{}", path);
+    }
+
+    let registry_path = REGISTRY_PATH.replace(MAIN_SEPARATOR, if MAIN_SEPARATOR == '/' { "\\" } else { "/" });
+    let path = format!(r"{}serde-1.0.0\src\lib.rs", registry_path);
+    assert_eq!(identify_source_path(&path, "/workspace/"), (SourceType::CRATES, REGISTRY_PATH.len() + 12));
+
+    assert_eq!(Language::C.identify_source_path(r"C:\include\stdio.h", "/workspace/").0, SourceType::UNKNOWN);
+    assert_eq!(Language::C.identify_source_path(r"src\main.c", "/workspace/").0, SourceType::LOCAL);
+    assert_eq!(GroupBy::Dir.group_name(r"\workspace\src\lib.rs", SourceType::LOCAL, 11, Language::Rust), format!(".{0}src", MAIN_SEPARATOR));
+    assert_eq!(GroupBy::Module.group_name(r"\workspace\src\report.rs", SourceType::LOCAL, 11, Language::Rust), "crate::report");
+}
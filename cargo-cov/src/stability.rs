@@ -0,0 +1,86 @@
+//! Detection of coverage which varies between test runs.
+//!
+//! The `cargo cov stability` subcommand runs the test suite several times, starting every run with empty profile data.
+//! A line (or branch) which is covered in some runs but not the others indicates a nondeterministic test or a
+//! timing-dependent code path, e.g. a retry loop or a race between threads.
+
+use cargo::{Cargo, CleanTargets};
+use error::{Result, ResultExt};
+use report::create_graph;
+use sourcepath::{SourceType, identify_source_path};
+use timings::Timings;
+use utils::parent_3;
+
+use cov::{Interner, Symbol};
+
+use std::collections::BTreeMap;
+
+/// A line, or a branch of a line, whose covered-status varies between runs.
+#[derive(Debug, Serialize)]
+pub struct UnstableCoverage {
+    /// Source path of the line.
+    pub path: String,
+    /// Line number.
+    pub line: u32,
+    /// Index of the branch in the line, or `None` if this refers to the line itself.
+    pub branch: Option<usize>,
+    /// Number of runs in which the line or branch is covered.
+    pub covered_runs: usize,
+}
+
+/// Result of `cargo cov stability`.
+#[derive(Debug, Serialize)]
+pub struct StabilityReport {
+    /// Total number of runs performed.
+    pub runs: usize,
+    /// All lines and branches which were covered in some, but not all runs.
+    pub unstable: Vec<UnstableCoverage>,
+}
+
+/// Runs `cargo test` for `runs` times and collects the lines and branches with varying coverage.
+///
+/// Only the default source types of `cargo cov report` (local, macros and unknown) are considered.
+pub fn check(cargo: &Cargo, runs: usize) -> Result<StabilityReport> {
+    let cov_build_path = cargo.cov_build_path();
+    let gcno_path = cov_build_path.join("gcno");
+    let gcda_path = cov_build_path.join("gcda");
+    let workspace_str = parent_3(cov_build_path).to_string_lossy();
+
+    let mut interner = Interner::new();
+    // Maps (file, line, branch) to the number of runs covering it.
+    let mut covered_runs = BTreeMap::<(Symbol, u32, Option<usize>), usize>::new();
+
+    for run in 1..(runs + 1) {
+        progress!("Run", "{}/{}", run, runs);
+        cargo.clean(CleanTargets::BUILD_GCDA)?;
+        cargo.forward("test")?;
+
+        let graph = create_graph(&gcno_path, &gcda_path, Vec::new(), &mut interner, None, &mut Timings::new()).chain_err(|| "Cannot create graph")?;
+        let report = graph.report();
+        for (&symbol, file) in &report.files {
+            if !SourceType::DEFAULT.contains(identify_source_path(&interner[symbol], &workspace_str).0) {
+                continue;
+            }
+            for (&line_number, line) in &file.lines {
+                *covered_runs.entry((symbol, line_number, None)).or_insert(0) += (line.count > 0) as usize;
+                for (i, branch) in line.branches.iter().enumerate() {
+                    *covered_runs.entry((symbol, line_number, Some(i))).or_insert(0) += (branch.count > 0) as usize;
+                }
+            }
+        }
+    }
+
+    let mut unstable = covered_runs
+        .into_iter()
+        .filter(|&(_, count)| count > 0 && count < runs)
+        .map(|((symbol, line, branch), covered_runs)| UnstableCoverage {
+            path: interner[symbol].to_owned(),
+            line,
+            branch,
+            covered_runs,
+        })
+        .collect::<Vec<_>>();
+    unstable.sort_by(|a, b| (&a.path, a.line, a.branch).cmp(&(&b.path, b.line, b.branch)));
+
+    Ok(StabilityReport { runs, unstable })
+}
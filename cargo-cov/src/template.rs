@@ -7,7 +7,8 @@
 #![cfg_attr(feature="cargo-clippy", allow(needless_pass_by_value))]
 // The pass-by-value is mandated by Tera.
 
-use sourcepath::{SourceType, identify_source_path};
+use rounding::Rounding;
+use sourcepath::{Language, SourceType, is_separator, normalize_separators};
 use utils::ValueExt;
 
 use md5;
@@ -15,6 +16,7 @@ use rustc_demangle::demangle;
 use serde_json::Value;
 use tera::{Result, Tera};
 
+use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::path::MAIN_SEPARATOR;
 
@@ -28,12 +30,28 @@ use std::path::MAIN_SEPARATOR;
 /// | `clamp(min=0, max=100)` | Clamps a floating-point number between 0 and 100 |
 /// | `to_fixed(precision=2)` | Prints a floating-point number as fixed format with 2 decimal points |
 /// | `filename` | Extracts the filename part from a full path |
-/// | `simplify_source_path(crate_path="/path")` | See [`identify_source_path()`] |
+/// | `simplify_source_path(crate_path="/path", language="rust")` | See [`Language::identify_source_path()`] |
 /// | `coalesce(default=x)` | Returns `x` if the input is null |
-/// | `demangle` | Demangles a Rust symbol |
+/// | `demangle(language="rust")` | Demangles a Rust symbol, or does nothing if the language is not Rust |
+/// | `shorten(language="rust", segments=2)` | Shortens a Rust symbol to its path without generics, e.g. `Vec::drop`, see [`short_name()`] |
+/// | `function_name(form="short", language="rust")` | Formats a symbol in the `raw`, `demangled` or `short` form |
+/// | `pad(width=9)` | Right-aligns a string or number to 9 characters |
+/// | `worst_covered(limit=10)` | Ranks the summary `files` by the lowest line coverage, keeping the first 10 not fully covered |
+/// | `heatmap(max=1000, levels=10)` | Maps an execution count to a heat level from 1 to 10 on a logarithmic scale up to 1000, 0 if never executed |
 ///
-/// [`identify_source_path()`]: ../sourcepath/fn.identify_source_path.html
-pub fn new(dirs: &str) -> Result<Tera> {
+/// The `language` arguments are optional and default to `"rust"`.
+///
+/// It will also contain the following global functions, which round the percentages by `rounding`:
+///
+/// | Function | Action |
+/// |----------|--------|
+/// | `percent(value=3, total=4)` | Computes the rounded percentage as a number, 100 if `total` is 0 |
+/// | `format_percent(value=3, total=4)` | Formats the rounded percentage with the configured precision, e.g. `75.00` |
+/// | `format_percent(value=12.345)` | Formats a percentage computed by the template (e.g. a change of coverage) |
+///
+/// [`Language::identify_source_path()`]: ../sourcepath/enum.Language.html#method.identify_source_path
+/// [`short_name()`]: ./fn.short_name.html
+pub fn new(dirs: &str, rounding: Rounding) -> Result<Tera> {
     let mut tera = Tera::new(dirs)?;
     tera.autoescape_on(Vec::new());
     tera.register_filter("md5", compute_md5);
@@ -43,7 +61,14 @@ pub fn new(dirs: &str) -> Result<Tera> {
     tera.register_filter("simplify_source_path", simplify_source_path);
     tera.register_filter("coalesce", coalesce);
     tera.register_filter("demangle", demangle_rust);
+    tera.register_filter("shorten", shorten);
+    tera.register_filter("function_name", function_name);
+    tera.register_filter("pad", pad);
+    tera.register_filter("worst_covered", worst_covered);
+    tera.register_filter("heatmap", heatmap);
     tera.register_global_function("debug_it", Box::new(debug_it));
+    tera.register_global_function("percent", Box::new(move |args| Ok(Value::from(percent_arg(&args, rounding)?))));
+    tera.register_global_function("format_percent", Box::new(move |args| Ok(Value::String(rounding.format(percent_arg(&args, rounding)?)))));
     Ok(tera)
 }
 
@@ -73,7 +98,7 @@ fn to_fixed(value: Value, options: HashMap<String, Value>) -> Result<Value> {
 /// Provides the `filename` filter.
 fn filename(value: Value, _: HashMap<String, Value>) -> Result<Value> {
     let path = value.as_str().ok_or("expecting path")?;
-    let start = path.rfind(MAIN_SEPARATOR).map_or(0, |s| s + MAIN_SEPARATOR.len_utf8());
+    let start = path.rfind(is_separator).map_or(0, |s| s + 1);
     Ok(Value::from(&path[start..]))
 }
 
@@ -83,11 +108,11 @@ fn simplify_source_path(value: Value, mut options: HashMap<String, Value>) -> Re
     let mut crate_path = options.remove("crate_path").and_then(Value::try_into_string).ok_or("simplify_source_path should provide the crate_path")?;
     crate_path.push(MAIN_SEPARATOR);
 
-    let (source_type, stripped_len) = identify_source_path(&path, &crate_path);
+    let (source_type, stripped_len) = language_option(&options)?.identify_source_path(&path, &crate_path);
     let simplified = if source_type == SourceType::MACROS {
         path
     } else {
-        format!("{}{}{}", source_type.prefix(), MAIN_SEPARATOR, &path[stripped_len..])
+        format!("{}{}{}", source_type.prefix(), MAIN_SEPARATOR, normalize_separators(&path[stripped_len..]))
     };
 
     Ok(Value::String(simplified))
@@ -103,9 +128,181 @@ fn coalesce(value: Value, mut options: HashMap<String, Value>) -> Result<Value>
 }
 
 /// Provides the `demangle` filter.
-fn demangle_rust(value: Value, _: HashMap<String, Value>) -> Result<Value> {
+///
+/// With `hash=false`, the hash suffix of Rust symbols is removed, so the name does not change whenever the crate is
+/// rebuilt with different metadata.
+fn demangle_rust(value: Value, options: HashMap<String, Value>) -> Result<Value> {
     let name = value.as_str().ok_or("expecting string to demangle")?;
-    Ok(Value::String(demangle(name).to_string()))
+    let hash = options.get("hash").and_then(Value::as_bool).unwrap_or(true);
+    match language_option(&options)? {
+        Language::Rust if hash => Ok(Value::String(demangle(name).to_string())),
+        Language::Rust => Ok(Value::String(format!("{:#}", demangle(name)))),
+        Language::C => Ok(value.clone()),
+    }
+}
+
+/// Shortens a demangled Rust symbol without the hash to the path of the function, e.g. `crate::module::function`.
+///
+/// The generic arguments are removed, and a qualified path `<Type as Trait>::method` becomes `Type::method`. With
+/// `segments`, only the last that many segments of the path are kept.
+pub fn short_name(demangled: &str, segments: Option<usize>) -> String {
+    /// Finds the `>` closing the `<` at the start of `s`. The `>` of an arrow `->` does not count.
+    fn closing(s: &str) -> Option<usize> {
+        let mut depth = 0;
+        let mut previous = ' ';
+        for (i, c) in s.char_indices() {
+            match c {
+                '<' => depth += 1,
+                '>' if previous != '-' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(i);
+                    }
+                },
+                _ => {},
+            }
+            previous = c;
+        }
+        None
+    }
+
+    let mut short = String::with_capacity(demangled.len());
+    let mut rest = demangled;
+    while let Some(start) = rest.find('<') {
+        short.push_str(&rest[..start]);
+        let end = match closing(&rest[start..]) {
+            Some(end) => start + end,
+            None => {
+                rest = &rest[start..];
+                break;
+            },
+        };
+        let inner = &rest[start + 1..end];
+        if short.is_empty() {
+            // A qualified path, only the type matters.
+            let ty = inner.find(" as ").map_or(inner, |i| &inner[..i]);
+            short.push_str(&short_name(ty, None));
+        } else if short.ends_with("::") {
+            // The turbofish of a generic function, e.g. `collect::<Vec<u8>>`.
+            let len = short.len() - 2;
+            short.truncate(len);
+        }
+        rest = &rest[end + 1..];
+    }
+    short.push_str(rest);
+
+    match segments {
+        Some(segments) => {
+            let parts = short.split("::").collect::<Vec<_>>();
+            parts[parts.len().saturating_sub(segments.max(1))..].join("::")
+        },
+        None => short,
+    }
+}
+
+/// Provides the `shorten` filter.
+#[cfg_attr(feature = "cargo-clippy", allow(cast_possible_truncation))]
+fn shorten(value: Value, options: HashMap<String, Value>) -> Result<Value> {
+    let name = value.as_str().ok_or("expecting string to shorten")?;
+    let segments = options.get("segments").and_then(Value::as_u64).map(|s| s as usize);
+    match language_option(&options)? {
+        Language::Rust => Ok(Value::String(short_name(&format!("{:#}", demangle(name)), segments))),
+        Language::C => Ok(value.clone()),
+    }
+}
+
+/// Provides the `function_name` filter, which switches between the `raw` symbol, the `demangled` one (with the hash,
+/// like `demangle`) and the `short` one (like `shorten`), so a template can take the form from one of its options.
+fn function_name(value: Value, mut options: HashMap<String, Value>) -> Result<Value> {
+    let form = options.remove("form").and_then(Value::try_into_string).ok_or("function_name should provide the form")?;
+    match &*form {
+        "raw" => Ok(value),
+        "demangled" => demangle_rust(value, options),
+        "short" => shorten(value, options),
+        _ => Err(format!("unsupported function name form `{}`, expecting `raw`, `demangled` or `short`", form).into()),
+    }
+}
+
+/// Reads the optional `language` argument of a filter.
+fn language_option(options: &HashMap<String, Value>) -> Result<Language> {
+    match options.get("language").and_then(Value::as_str) {
+        Some(name) => name.parse().map_err(|_| format!("unsupported language `{}`", name).into()),
+        None => Ok(Language::Rust),
+    }
+}
+
+/// Provides the `pad` filter.
+#[cfg_attr(feature = "cargo-clippy", allow(cast_possible_truncation))]
+fn pad(value: Value, options: HashMap<String, Value>) -> Result<Value> {
+    let width = options.get("width").and_then(Value::as_u64).ok_or("pad should have a width")? as usize;
+    let string = match value {
+        Value::String(s) => s,
+        v => v.to_string(),
+    };
+    Ok(Value::String(format!("{:>1$}", string, width)))
+}
+
+/// Provides the `worst_covered` filter.
+///
+/// Files are ranked like `--max-files`: by the lowest line coverage, then by the most uncovered lines. Files without any
+/// uncovered line are left out.
+#[cfg_attr(feature = "cargo-clippy", allow(cast_possible_truncation, cast_precision_loss))]
+fn worst_covered(value: Value, options: HashMap<String, Value>) -> Result<Value> {
+    let files = match value {
+        Value::Array(files) => files,
+        _ => return Err("expecting an array of files to rank".into()),
+    };
+    let limit = options.get("limit").and_then(Value::as_u64).ok_or("worst_covered should have a limit")? as usize;
+
+    let lines = |file: &Value| {
+        let count = file.pointer("/summary/lines_count").and_then(Value::as_u64).unwrap_or(0);
+        let covered = file.pointer("/summary/lines_covered").and_then(Value::as_u64).unwrap_or(0);
+        (covered, count)
+    };
+    let coverage = |file: &Value| {
+        let (covered, count) = lines(file);
+        covered as f64 / count as f64
+    };
+    let uncovered = |file: &Value| {
+        let (covered, count) = lines(file);
+        count - covered
+    };
+
+    let mut files = files.into_iter().filter(|file| uncovered(file) > 0).collect::<Vec<_>>();
+    files.sort_by(|a, b| coverage(a).partial_cmp(&coverage(b)).unwrap_or(Ordering::Equal).then_with(|| uncovered(b).cmp(&uncovered(a))));
+    files.truncate(limit);
+    Ok(Value::Array(files))
+}
+
+/// Provides the `heatmap` filter.
+///
+/// The level grows with the logarithm of the count, so a line executed 10 times is as far from one executed once as
+/// from one executed 100 times. A count of 1 maps to level 1, and `max` (the hottest line of the file) to `levels`.
+#[cfg_attr(feature = "cargo-clippy", allow(cast_possible_truncation, cast_precision_loss, cast_sign_loss))]
+fn heatmap(value: Value, options: HashMap<String, Value>) -> Result<Value> {
+    let count = value.as_u64().ok_or("expecting an execution count")?;
+    let max = options.get("max").and_then(Value::as_u64).ok_or("heatmap should have a max count")?;
+    let levels = options.get("levels").and_then(Value::as_u64).unwrap_or(10).max(1);
+    let level = match count {
+        0 => 0,
+        _ if max <= 1 => 1,
+        _ if count >= max => levels,
+        _ => 1 + ((levels - 1) as f64 * (count as f64).ln() / (max as f64).ln()).round() as u64,
+    };
+    Ok(Value::from(level))
+}
+
+/// Reads the `value` and optional `total` arguments of the `percent` and `format_percent` global functions.
+#[cfg_attr(feature = "cargo-clippy", allow(cast_possible_truncation, cast_sign_loss))]
+fn percent_arg(args: &HashMap<String, Value>, rounding: Rounding) -> Result<f64> {
+    let value = args.get("value").and_then(Value::as_f64).ok_or("expecting a number as value")?;
+    match args.get("total") {
+        Some(total) => {
+            let total = total.as_u64().ok_or("expecting an integer as total")?;
+            Ok(rounding.percent(value as usize, total as usize))
+        },
+        None => Ok(rounding.round(value)),
+    }
 }
 
 /// Provides the `debug_it` global function.
@@ -113,3 +310,15 @@ fn debug_it(args: HashMap<String, Value>) -> Result<Value> {
     debug!("DEBUG FROM TEMPLATE: {:#?}", args);
     Ok(Value::Null)
 }
+
+#[test]
+fn test_short_name() {
+    assert_eq!(short_name("foo::bar::baz", None), "foo::bar::baz");
+    assert_eq!(short_name("<alloc::vec::Vec<T> as core::ops::drop::Drop>::drop", None), "alloc::vec::Vec::drop");
+    assert_eq!(short_name("<foo::Bar>::new", None), "foo::Bar::new");
+    assert_eq!(short_name("core::iter::Iterator::collect::<alloc::vec::Vec<u8>>", None), "core::iter::Iterator::collect");
+    assert_eq!(short_name("<foo::Map<F> as foo::Run>::run::<fn() -> u8>::{{closure}}", None), "foo::Map::run::{{closure}}");
+    assert_eq!(short_name("<alloc::vec::Vec<T> as core::ops::drop::Drop>::drop", Some(2)), "Vec::drop");
+    assert_eq!(short_name("main", Some(2)), "main");
+    assert_eq!(short_name("foo<unbalanced", None), "foo<unbalanced");
+}
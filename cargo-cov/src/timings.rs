@@ -0,0 +1,516 @@
+//! Timing of the coverage phases.
+//!
+//! With `cargo cov report --timings`, the time spent in every phase (parsing the GCNO/GCDA files, merging them into
+//! the graph, analyzing, rendering the templates and writing the output) is printed at the end. With
+//! `--timings=trace.json`, every measured step is also written as a [Chrome trace event] file, which can be opened in
+//! `chrome://tracing` or [Perfetto](https://ui.perfetto.dev/) to find out which files are slow.
+//!
+//!
+//! Run log
+//! -------
+//!
+//! Every `cargo cov build`, `test`, `run` and `report` also appends what it spent its time on to
+//! `target/cov/build/timings.jsonl`, one [`RunEvent`] per line:
+//!
+//! ```json
+//! {"run":1700000000000,"kind":"compile","name":"foo","instrumented":true,"duration":3.25}
+//! {"run":1700000000000,"kind":"compile","name":"serde","instrumented":false,"duration":7.5}
+//! {"run":1700000000000,"kind":"test","name":"foo","duration":0.75}
+//! {"run":1700000000000,"kind":"command","name":"test","duration":12.5}
+//! ```
+//!
+//! The `run` is the time the `cargo cov` command started, in milliseconds since the Unix epoch, which the shims receive
+//! in `COV_RUN_ID`. A `compile` event is recorded by the `rustc` shim for every crate, where the dependencies outside
+//! the workspace are compiled normally and the workspace crates are instrumented. A `test` event is recorded by the
+//! test runner shim for every test program, a `report` event for every phase of the report, and a `command` event for
+//! the whole command.
+//!
+//! After each command, the log is summarized in `target/cov/timings.html`, similar to the page of
+//! `cargo build --timings`: the time spent compiling the instrumented workspace crates and the uninstrumented
+//! dependencies, running the tests and generating the report in every run, and the slowest steps of the latest run.
+//! This helps tracking how much the coverage costs over time. Note that the workspace crates are never compiled without
+//! instrumentation, so the page shows where the time goes rather than the overhead compared to a normal build.
+//!
+//! Only the latest 50 runs are shown, and the older runs are removed from the log at the same time.
+//!
+//! [Chrome trace event]: https://docs.google.com/document/d/1CvAClvFfyA5R-PhYUmn5OOQtYMH4h6I0nSsKchNAySU
+//! [`RunEvent`]: ./struct.RunEvent.html
+
+use error::{Result, ResultExt};
+use utils::format_age;
+
+use fs2::FileExt;
+use serde_json::{from_str, to_string, to_writer};
+use tera::{Context, Tera};
+
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, ErrorKind as IoErrorKind, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// A single measured step.
+#[derive(Debug)]
+struct Event {
+    /// Name of the phase.
+    phase: &'static str,
+    /// The file being processed, if any.
+    path: Option<String>,
+    /// Start time relative to the creation of the [`Timings`](./struct.Timings.html).
+    start: Duration,
+    /// Time spent in the step.
+    duration: Duration,
+}
+
+/// Total time spent in a phase.
+#[derive(Clone, Debug)]
+pub struct PhaseTiming {
+    /// Name of the phase.
+    pub phase: &'static str,
+    /// Number of steps measured in this phase.
+    pub count: usize,
+    /// Total time spent in this phase.
+    pub total: Duration,
+}
+
+/// Collector of the time spent in every phase.
+#[derive(Debug)]
+pub struct Timings {
+    origin: Instant,
+    events: Vec<Event>,
+}
+
+impl Timings {
+    /// Creates a new collector. All steps are timed relative to now.
+    pub fn new() -> Timings {
+        Timings {
+            origin: Instant::now(),
+            events: Vec::new(),
+        }
+    }
+
+    /// Runs `f` and records the time spent as a step of `phase`.
+    pub fn measure<T, F: FnOnce() -> T>(&mut self, phase: &'static str, path: Option<&Path>, f: F) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.events.push(Event {
+            phase,
+            path: path.map(|p| p.to_string_lossy().into_owned()),
+            start: start.duration_since(self.origin),
+            duration: start.elapsed(),
+        });
+        result
+    }
+
+    /// Time elapsed since the collector was created.
+    pub fn elapsed(&self) -> Duration {
+        self.origin.elapsed()
+    }
+
+    /// Sums up the time spent in every phase, in the order they are first measured.
+    pub fn phases(&self) -> Vec<PhaseTiming> {
+        let mut phases = Vec::<PhaseTiming>::new();
+        for event in &self.events {
+            match phases.iter().position(|p| p.phase == event.phase) {
+                Some(i) => {
+                    phases[i].count += 1;
+                    phases[i].total += event.duration;
+                },
+                None => phases.push(PhaseTiming {
+                    phase: event.phase,
+                    count: 1,
+                    total: event.duration,
+                }),
+            }
+        }
+        phases
+    }
+
+    /// Writes all measured steps to `path` in the Chrome trace event format.
+    pub fn write_trace(&self, path: &Path) -> Result<()> {
+        fn micros(duration: Duration) -> u64 {
+            duration.as_secs() * 1_000_000 + u64::from(duration.subsec_micros())
+        }
+
+        let trace_events = self.events
+            .iter()
+            .map(|event| {
+                json!({
+                    "name": event.path.as_ref().map_or(event.phase, |p| &**p),
+                    "cat": event.phase,
+                    "ph": "X",
+                    "ts": micros(event.start),
+                    "dur": micros(event.duration),
+                    "pid": 1,
+                    "tid": 1,
+                })
+            })
+            .collect::<Vec<_>>();
+        let file = File::create(path)?;
+        to_writer(file, &json!({ "traceEvents": trace_events, "displayTimeUnit": "ms" }))?;
+        Ok(())
+    }
+}
+
+/// Converts a duration to seconds.
+pub fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) / 1e9
+}
+
+/// An entry of the run log, see the [module documentation](./index.html#run-log).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RunEvent {
+    /// Start time of the `cargo cov` command, in milliseconds since the Unix epoch.
+    pub run: u64,
+    /// What is measured, one of `compile`, `test`, `report` or `command`.
+    pub kind: String,
+    /// Name of the crate, test program, report phase or subcommand.
+    pub name: String,
+    /// Whether the crate is instrumented, only for `compile` events.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instrumented: Option<bool>,
+    /// Time spent, in seconds.
+    pub duration: f64,
+}
+
+impl RunEvent {
+    /// Creates an event of the current run.
+    pub fn new(run: u64, kind: &str, name: &str, duration: Duration) -> RunEvent {
+        RunEvent {
+            run,
+            kind: kind.to_owned(),
+            name: name.to_owned(),
+            instrumented: None,
+            duration: seconds(duration),
+        }
+    }
+}
+
+/// Identifies a new run by the current time.
+pub fn new_run_id() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() * 1000 + u64::from(d.subsec_nanos() / 1_000_000)).unwrap_or(0)
+}
+
+/// Obtains the run the shims are executed in from `COV_RUN_ID`, if set by `cargo cov`.
+pub fn current_run_id() -> Option<u64> {
+    env::var("COV_RUN_ID").ok().and_then(|id| id.parse().ok())
+}
+
+/// Path to the run log in the `target/cov/build` folder.
+fn run_log_path(cov_build_path: &Path) -> PathBuf {
+    cov_build_path.join("timings.jsonl")
+}
+
+/// Appends the events to the run log.
+pub fn record_events(cov_build_path: &Path, events: &[RunEvent]) -> Result<()> {
+    let mut lines = String::new();
+    for event in events {
+        lines.push_str(&to_string(event)?);
+        lines.push('\n');
+    }
+    let path = run_log_path(cov_build_path);
+    let mut file = OpenOptions::new().create(true).append(true).open(&path).chain_err(|| format!("Cannot open `{}`", path.display()))?;
+    // Crates compiled concurrently may append at the same time, so the lines are written while holding an exclusive lock.
+    FileExt::lock_exclusive(&file).chain_err(|| format!("Cannot lock `{}`", path.display()))?;
+    let result = file.write_all(lines.as_bytes()).and_then(|_| file.flush());
+    FileExt::unlock(&file)?;
+    result?;
+    Ok(())
+}
+
+/// Reads all events of the run log. Lines which cannot be parsed (e.g. cut off by a crash) are skipped.
+///
+/// Only the latest `max_runs` runs are kept, so the log does not grow forever: the older runs are removed from the log
+/// while holding the same lock as [`record_events()`](./fn.record_events.html). The log is truncated and rewritten in
+/// place instead of being replaced, since the shims may have it opened for appending.
+fn read_and_compact_events(cov_build_path: &Path, max_runs: usize) -> Result<Vec<RunEvent>> {
+    let path = run_log_path(cov_build_path);
+    let mut file = match OpenOptions::new().read(true).write(true).open(&path) {
+        Ok(file) => file,
+        Err(ref e) if e.kind() == IoErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).chain_err(|| format!("Cannot open `{}`", path.display())),
+    };
+    FileExt::lock_exclusive(&file).chain_err(|| format!("Cannot lock `{}`", path.display()))?;
+    let result = compact_events(&mut file, max_runs);
+    FileExt::unlock(&file)?;
+    result.chain_err(|| format!("Cannot compact `{}`", path.display()))
+}
+
+/// Reads the events from the locked run log, and rewrites it with only the latest `max_runs` runs if there are more.
+fn compact_events(file: &mut File, max_runs: usize) -> io::Result<Vec<RunEvent>> {
+    let mut lines = Vec::new();
+    for line in BufReader::new(&*file).lines() {
+        let line = line?;
+        if let Ok(event) = from_str::<RunEvent>(&line) {
+            lines.push((event, line));
+        }
+    }
+
+    let mut runs = lines.iter().map(|&(ref event, _)| event.run).collect::<Vec<_>>();
+    runs.sort();
+    runs.dedup();
+    if runs.len() > max_runs {
+        let oldest_kept = runs[runs.len() - max_runs];
+        lines.retain(|&(ref event, _)| event.run >= oldest_kept);
+        let mut content = String::new();
+        for &(_, ref line) in &lines {
+            content.push_str(line);
+            content.push('\n');
+        }
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(content.as_bytes())?;
+        file.flush()?;
+    }
+
+    Ok(lines.into_iter().map(|(event, _)| event).collect())
+}
+
+/// Time spent in a single run, summed by the kind of events.
+#[derive(Debug, Default, PartialEq)]
+struct RunSummary {
+    run: u64,
+    /// The subcommands executed in this run.
+    commands: Vec<String>,
+    /// Number of instrumented (workspace) crates compiled and the time spent.
+    instrumented: (usize, f64),
+    /// Number of dependencies compiled without instrumentation and the time spent.
+    dependencies: (usize, f64),
+    /// Number of test programs run and the time spent.
+    tests: (usize, f64),
+    /// Time spent generating the report.
+    report: f64,
+    /// Time spent in the whole run, including what is not measured separately, e.g. cargo itself.
+    total: f64,
+}
+
+/// Summarizes the events by run, the latest run first.
+fn summarize(events: &[RunEvent]) -> Vec<RunSummary> {
+    let mut summaries = Vec::<RunSummary>::new();
+    for event in events {
+        let i = match summaries.iter().position(|s| s.run == event.run) {
+            Some(i) => i,
+            None => {
+                summaries.push(RunSummary { run: event.run, ..RunSummary::default() });
+                summaries.len() - 1
+            },
+        };
+        let summary = &mut summaries[i];
+        let slot = match (&*event.kind, event.instrumented) {
+            ("compile", Some(true)) => &mut summary.instrumented,
+            ("compile", _) => &mut summary.dependencies,
+            ("test", _) => &mut summary.tests,
+            ("report", _) => {
+                summary.report += event.duration;
+                continue;
+            },
+            ("command", _) => {
+                summary.commands.push(event.name.clone());
+                summary.total += event.duration;
+                continue;
+            },
+            _ => continue,
+        };
+        slot.0 += 1;
+        slot.1 += event.duration;
+    }
+    for summary in &mut summaries {
+        let measured = summary.instrumented.1 + summary.dependencies.1 + summary.tests.1 + summary.report;
+        summary.total = summary.total.max(measured);
+    }
+    summaries.sort_by(|a, b| b.run.cmp(&a.run));
+    summaries
+}
+
+/// Maximum number of runs shown in `timings.html`, and kept in the run log.
+const MAX_RUNS: usize = 50;
+
+/// Maximum number of steps of the latest run shown in `timings.html`.
+const MAX_STEPS: usize = 30;
+
+/// Width of the bars in `timings.html`, in pixels.
+const BAR_WIDTH: f64 = 400.0;
+
+/// Colors of the bars in `timings.html`, for instrumented compilation, dependency compilation, tests and report.
+const BAR_COLORS: [&str; 4] = ["#e06c75", "#abb2bf", "#61afef", "#98c379"];
+
+/// Labels of the bars in `timings.html`, in the same order as the `BAR_COLORS`.
+const BAR_LABELS: [&str; 4] = ["Instrumented compilation", "Dependency compilation (not instrumented)", "Tests", "Report"];
+
+/// The template of `timings.html`. HTML is escaped in every substitution.
+const PAGE_TEMPLATE: &str = include_str!("../res/timings.html");
+
+/// A segment of a horizontal bar in `timings.html`.
+#[derive(Serialize)]
+struct BarPart {
+    x: String,
+    width: String,
+    color: &'static str,
+}
+
+/// Computes the segments of a horizontal bar of the `parts`, each a time in seconds, scaled so `max` fills the whole
+/// width.
+fn bar(parts: &[f64], max: f64) -> Vec<BarPart> {
+    let mut segments = Vec::new();
+    let mut x = 0.0;
+    for (part, color) in parts.iter().zip(&BAR_COLORS) {
+        let width = part / max.max(1e-9) * BAR_WIDTH;
+        if width > 0.0 {
+            segments.push(BarPart {
+                x: format!("{:.1}", x),
+                width: format!("{:.1}", width),
+                color,
+            });
+        }
+        x += width;
+    }
+    segments
+}
+
+/// Renders `timings.html` from the events of the run log, where `now` is in milliseconds since the Unix epoch.
+fn render_page(events: &[RunEvent], now: u64) -> Result<String> {
+    let summaries = summarize(events);
+    let summaries = &summaries[..summaries.len().min(MAX_RUNS)];
+    let max = summaries.iter().map(|s| s.total).fold(0.0, f64::max);
+
+    let runs = summaries
+        .iter()
+        .map(|summary| {
+            let compiled = summary.instrumented.1 + summary.dependencies.1;
+            json!({
+                "age": format_age(Duration::from_millis(now.saturating_sub(summary.run))),
+                "commands": summary.commands.join(", "),
+                "instrumented": format!("{:.2}", summary.instrumented.1),
+                "instrumented_count": summary.instrumented.0,
+                "dependencies": format!("{:.2}", summary.dependencies.1),
+                "dependencies_count": summary.dependencies.0,
+                "share": if compiled > 0.0 { format!("{:.1}%", 100.0 * summary.instrumented.1 / compiled) } else { String::new() },
+                "tests": format!("{:.2}", summary.tests.1),
+                "tests_count": summary.tests.0,
+                "report": format!("{:.2}", summary.report),
+                "total": format!("{:.2}", summary.total),
+                "bar": bar(&[summary.instrumented.1, summary.dependencies.1, summary.tests.1, summary.report], max),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut steps = Vec::new();
+    if let Some(latest) = summaries.first() {
+        let mut latest_steps = events.iter().filter(|e| e.run == latest.run && e.kind != "command").collect::<Vec<_>>();
+        latest_steps.sort_by(|a, b| b.duration.partial_cmp(&a.duration).unwrap_or(::std::cmp::Ordering::Equal));
+        latest_steps.truncate(MAX_STEPS);
+        let max = latest_steps.first().map_or(0.0, |e| e.duration);
+        for step in latest_steps {
+            let index = match (&*step.kind, step.instrumented) {
+                ("compile", Some(true)) => 0,
+                ("compile", _) => 1,
+                ("test", _) => 2,
+                _ => 3,
+            };
+            let mut parts = [0.0; 4];
+            parts[index] = step.duration;
+            steps.push(json!({
+                "kind": step.kind,
+                "name": step.name,
+                "duration": format!("{:.2}", step.duration),
+                "bar": bar(&parts, max),
+            }));
+        }
+    }
+
+    let legend = BAR_LABELS.iter().zip(&BAR_COLORS).map(|(label, color)| json!({"label": label, "color": color})).collect::<Vec<_>>();
+    let mut context = Context::new();
+    context.add("legend", &legend);
+    context.add("bar_width", &BAR_WIDTH);
+    context.add("runs", &runs);
+    context.add("steps", &steps);
+
+    let mut tera = Tera::default();
+    tera.add_raw_template("timings.html", PAGE_TEMPLATE)?;
+    Ok(tera.render("timings.html", &context)?)
+}
+
+/// Summarizes the run log into `target/cov/timings.html`, and returns its path.
+pub fn write_page(cov_build_path: &Path) -> Result<PathBuf> {
+    let events = read_and_compact_events(cov_build_path, MAX_RUNS)?;
+    let path = cov_build_path.with_file_name("timings.html");
+    let html = render_page(&events, new_run_id())?;
+    File::create(&path).and_then(|mut file| file.write_all(html.as_bytes())).chain_err(|| format!("Cannot write timings at `{}`", path.display()))?;
+    Ok(path)
+}
+
+#[test]
+fn test_phases() {
+    let mut timings = Timings::new();
+    assert_eq!(timings.measure("parse", Some(Path::new("a.gcno")), || 1), 1);
+    timings.measure("merge", None, || ());
+    timings.measure("parse", Some(Path::new("a.gcda")), || ());
+    let phases = timings.phases();
+    assert_eq!(phases.iter().map(|p| (p.phase, p.count)).collect::<Vec<_>>(), vec![("parse", 2), ("merge", 1)]);
+}
+
+#[test]
+fn test_summarize() {
+    let compile = |name: &str, instrumented: bool, duration: u64| RunEvent {
+        instrumented: Some(instrumented),
+        ..RunEvent::new(1, "compile", name, Duration::from_secs(duration))
+    };
+    let events = vec![
+        compile("foo", true, 3),
+        compile("serde", false, 5),
+        compile("libc", false, 1),
+        RunEvent::new(1, "test", "foo", Duration::from_secs(2)),
+        RunEvent::new(1, "command", "test", Duration::from_secs(12)),
+        RunEvent::new(2, "report", "parse", Duration::from_secs(1)),
+        RunEvent::new(2, "report", "render", Duration::from_secs(2)),
+    ];
+    let summaries = summarize(&events);
+    assert_eq!(
+        summaries,
+        vec![
+            RunSummary {
+                run: 2,
+                report: 3.0,
+                total: 3.0,
+                ..RunSummary::default()
+            },
+            RunSummary {
+                run: 1,
+                commands: vec!["test".to_owned()],
+                instrumented: (1, 3.0),
+                dependencies: (2, 6.0),
+                tests: (1, 2.0),
+                report: 0.0,
+                total: 12.0,
+            },
+        ]
+    );
+
+    let page = render_page(&events, 3_600_001).unwrap();
+    assert!(page.contains("<td class=\"name\">1h ago</td><td class=\"name\">test</td><td>3.00s (1)</td><td>6.00s (2)</td><td>33.3%</td>"));
+    assert!(page.contains("<td class=\"name\">report</td><td class=\"name\">render</td><td>2.00s</td>"));
+}
+
+#[test]
+fn test_render_page_escapes() {
+    let events = vec![RunEvent::new(1, "test", "<script>alert(1)</script>", Duration::from_secs(1))];
+    let page = render_page(&events, 1).unwrap();
+    assert!(page.contains("&lt;script&gt;alert(1)&lt;&#x2F;script&gt;"));
+    assert!(!page.contains("<script>"));
+}
+
+#[test]
+fn test_compact_events() {
+    let dir = ::tempfile::TempDir::new().unwrap();
+    for run in 1..6 {
+        record_events(dir.path(), &[RunEvent::new(run, "test", "a", Duration::from_secs(1)), RunEvent::new(run, "test", "b", Duration::from_secs(1))]).unwrap();
+    }
+    let runs = |events: Vec<RunEvent>| events.into_iter().map(|e| e.run).collect::<Vec<_>>();
+    assert_eq!(runs(read_and_compact_events(dir.path(), 10).unwrap()), vec![1, 1, 2, 2, 3, 3, 4, 4, 5, 5]);
+    assert_eq!(runs(read_and_compact_events(dir.path(), 3).unwrap()), vec![3, 3, 4, 4, 5, 5]);
+
+    // The older runs are removed from the log itself, and new events are still appended.
+    record_events(dir.path(), &[RunEvent::new(6, "test", "a", Duration::from_secs(1))]).unwrap();
+    assert_eq!(runs(read_and_compact_events(dir.path(), 10).unwrap()), vec![3, 3, 4, 4, 5, 5, 6]);
+    assert!(read_and_compact_events(&dir.path().join("missing"), 3).unwrap().is_empty());
+}
@@ -0,0 +1,520 @@
+//! Interactive browser of the coverage in the terminal.
+//!
+//! `cargo cov tui` shows the source files as a tree with the line coverage of every directory and file, next to the
+//! annotated source of the selected file. It is meant for environments where opening the HTML report is inconvenient,
+//! e.g. over SSH. The coverage is collected the same way as `cargo cov report`, honoring `--include`, `--workspace`,
+//! `--gcno`, `--gcda` and `--import`.
+//!
+//! Keys:
+//!
+//! * <kbd>↑</kbd>/<kbd>↓</kbd> (or <kbd>k</kbd>/<kbd>j</kbd>), <kbd>PgUp</kbd>/<kbd>PgDn</kbd>, <kbd>g</kbd>/<kbd>G</kbd>
+//!   move the selection.
+//! * <kbd>Enter</kbd> (or <kbd>→</kbd>/<kbd>l</kbd>) expands a directory or opens a file, <kbd>←</kbd>/<kbd>h</kbd>
+//!   collapses a directory or goes back to the tree.
+//! * <kbd>n</kbd>/<kbd>N</kbd> jump to the next/previous uncovered line of the opened file.
+//! * <kbd>Tab</kbd> switches between the tree and the source, <kbd>q</kbd> quits.
+//!
+//! The colors are left out if `NO_COLOR` is set, the same as the other output (see the [`ui` module]).
+//!
+//! [`ui` module]: ../ui/index.html
+
+use argparse::ReportConfig;
+use error::Result;
+use report;
+use rounding::Rounding;
+use sourcepath::{SourceClassifier, is_separator};
+use ui;
+
+use atty::Stream;
+use cov::Interner;
+use cov::report::FileSummary;
+use termcolor::ColorChoice;
+use termion::{clear, color, cursor, style, terminal_size};
+use termion::event::Key;
+use termion::input::TermRead;
+use termion::raw::IntoRawMode;
+use termion::screen::AlternateScreen;
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::io::{stdin, stdout, Read, Write};
+
+/// Width of the coverage bars in the tree.
+const BAR_WIDTH: usize = 10;
+
+/// A source file to browse.
+struct SourceFile {
+    /// The path as recorded in the GCNO files.
+    path: String,
+    summary: FileSummary,
+    /// Execution count of every profiled line.
+    counts: BTreeMap<u32, u64>,
+}
+
+/// What a row of the tree refers to.
+#[derive(Debug, PartialEq)]
+enum RowKind {
+    /// A directory, identified by the path up to and including its name.
+    Dir(String),
+    /// A file, identified by its index in the list of files.
+    File(usize),
+}
+
+/// A visible row of the file tree.
+#[derive(Debug, PartialEq)]
+struct TreeRow {
+    depth: usize,
+    name: String,
+    summary: FileSummary,
+    kind: RowKind,
+}
+
+/// Splits a path into its non-empty components, regardless of the separator.
+fn components(path: &str) -> Vec<&str> {
+    path.split(is_separator).filter(|c| !c.is_empty()).collect()
+}
+
+/// Computes the visible rows of the tree of `files`, which must be sorted by path. The content of the directories in
+/// `collapsed` is hidden.
+fn tree_rows(files: &[SourceFile], collapsed: &HashSet<String>) -> Vec<TreeRow> {
+    // The total of every directory, keyed by the components up to the directory itself.
+    let mut dir_summaries = HashMap::new();
+    for file in files {
+        let components = components(&file.path);
+        for depth in 1..components.len() {
+            *dir_summaries.entry(components[..depth].join("/")).or_insert_with(FileSummary::default) += file.summary;
+        }
+    }
+
+    let mut rows = Vec::new();
+    let mut previous: Vec<&str> = Vec::new();
+    for (index, file) in files.iter().enumerate() {
+        let components = components(&file.path);
+        let (dirs, name) = components.split_at(components.len().saturating_sub(1));
+        let common = previous.iter().zip(dirs).take_while(|&(a, b)| a == b).count();
+        let mut hidden = (1..common + 1).any(|depth| collapsed.contains(&dirs[..depth].join("/")));
+        for depth in common..dirs.len() {
+            let dir_path = dirs[..depth + 1].join("/");
+            if !hidden {
+                rows.push(TreeRow {
+                    depth,
+                    name: dirs[depth].to_owned(),
+                    summary: dir_summaries[&dir_path],
+                    kind: RowKind::Dir(dir_path.clone()),
+                });
+            }
+            hidden |= collapsed.contains(&dir_path);
+        }
+        if !hidden {
+            rows.push(TreeRow {
+                depth: dirs.len(),
+                name: name.first().map_or_else(|| file.path.clone(), |n| (*n).to_owned()),
+                summary: file.summary,
+                kind: RowKind::File(index),
+            });
+        }
+        previous = dirs.to_vec();
+    }
+    rows
+}
+
+/// Draws a bar of `width` cells, filled in proportion to `percent`.
+#[cfg_attr(feature = "cargo-clippy", allow(cast_possible_truncation, cast_sign_loss, cast_precision_loss))]
+fn coverage_bar(percent: f64, width: usize) -> String {
+    let filled = ((percent / 100.0 * width as f64).round() as usize).min(width);
+    let mut bar = "█".repeat(filled);
+    bar.push_str(&"░".repeat(width - filled));
+    bar
+}
+
+/// Truncates or pads `text` to exactly `width` characters.
+fn fit(text: &str, width: usize) -> String {
+    let mut fitted = text.chars().take(width).collect::<String>();
+    let len = fitted.chars().count();
+    fitted.extend((len..width).map(|_| ' '));
+    fitted
+}
+
+/// Which pane receives the keys.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Focus {
+    Tree,
+    Source,
+}
+
+/// The opened source file.
+struct SourceView {
+    /// Index of the file in the list of files.
+    file: usize,
+    /// The source code, one entry per line.
+    lines: Vec<String>,
+    /// The line under the cursor, 0-based.
+    cursor: usize,
+    /// The first line shown, 0-based.
+    offset: usize,
+}
+
+/// State of the browser.
+struct Browser {
+    files: Vec<SourceFile>,
+    workspace_path: String,
+    rounding: Rounding,
+    totals: FileSummary,
+    collapsed: HashSet<String>,
+    rows: Vec<TreeRow>,
+    selected: usize,
+    tree_offset: usize,
+    source: Option<SourceView>,
+    focus: Focus,
+    /// Number of rows available to the panes.
+    height: usize,
+    /// Whether the coverage is colored.
+    colored: bool,
+}
+
+impl Browser {
+    fn new(files: Vec<SourceFile>, workspace_path: String, rounding: Rounding) -> Browser {
+        let mut totals = FileSummary::default();
+        for file in &files {
+            totals += file.summary;
+        }
+        let collapsed = HashSet::new();
+        let rows = tree_rows(&files, &collapsed);
+        Browser {
+            files,
+            workspace_path,
+            rounding,
+            totals,
+            collapsed,
+            rows,
+            selected: 0,
+            tree_offset: 0,
+            source: None,
+            focus: Focus::Tree,
+            height: 1,
+            colored: ui::color_choice(Stream::Stdout) != ColorChoice::Never,
+        }
+    }
+
+    /// Formats the escape code to change the text color, or nothing if the colors are disabled.
+    fn fg<C: color::Color>(&self, c: C) -> String {
+        if self.colored {
+            color::Fg(c).to_string()
+        } else {
+            String::new()
+        }
+    }
+
+    /// Handles a key press. Returns `false` if the browser should quit.
+    fn handle_key(&mut self, key: Key) -> bool {
+        let page = self.height.max(1);
+        match (self.focus, key) {
+            (_, Key::Char('q')) | (_, Key::Ctrl('c')) | (Focus::Tree, Key::Esc) => return false,
+            (_, Key::Char('\t')) if self.source.is_some() => {
+                self.focus = if self.focus == Focus::Tree { Focus::Source } else { Focus::Tree };
+            },
+            (Focus::Tree, Key::Up) | (Focus::Tree, Key::Char('k')) => self.select(self.selected.saturating_sub(1)),
+            (Focus::Tree, Key::Down) | (Focus::Tree, Key::Char('j')) => self.select(self.selected + 1),
+            (Focus::Tree, Key::PageUp) => self.select(self.selected.saturating_sub(page)),
+            (Focus::Tree, Key::PageDown) => self.select(self.selected + page),
+            (Focus::Tree, Key::Home) | (Focus::Tree, Key::Char('g')) => self.select(0),
+            (Focus::Tree, Key::End) | (Focus::Tree, Key::Char('G')) => self.select(usize::max_value()),
+            (Focus::Tree, Key::Char('\n')) | (Focus::Tree, Key::Right) | (Focus::Tree, Key::Char('l')) => self.activate(key == Key::Char('\n')),
+            (Focus::Tree, Key::Left) | (Focus::Tree, Key::Char('h')) => self.collapse(),
+            (Focus::Source, Key::Up) | (Focus::Source, Key::Char('k')) => self.move_cursor(-1),
+            (Focus::Source, Key::Down) | (Focus::Source, Key::Char('j')) => self.move_cursor(1),
+            (Focus::Source, Key::PageUp) => self.move_cursor(-(page as isize)),
+            (Focus::Source, Key::PageDown) => self.move_cursor(page as isize),
+            (Focus::Source, Key::Home) | (Focus::Source, Key::Char('g')) => self.move_cursor(isize::min_value()),
+            (Focus::Source, Key::End) | (Focus::Source, Key::Char('G')) => self.move_cursor(isize::max_value()),
+            (Focus::Source, Key::Left) | (Focus::Source, Key::Char('h')) | (Focus::Source, Key::Esc) => self.focus = Focus::Tree,
+            (_, Key::Char('n')) => self.jump_uncovered(true),
+            (_, Key::Char('N')) | (_, Key::Char('p')) => self.jump_uncovered(false),
+            _ => {},
+        }
+        true
+    }
+
+    /// Selects the row at `index`, clamped to the tree, and scrolls it into view.
+    fn select(&mut self, index: usize) {
+        self.selected = index.min(self.rows.len().saturating_sub(1));
+        if self.selected < self.tree_offset {
+            self.tree_offset = self.selected;
+        } else if self.selected >= self.tree_offset + self.height {
+            self.tree_offset = self.selected + 1 - self.height;
+        }
+    }
+
+    /// Expands the selected directory (or toggles it if `toggle`), or opens the selected file.
+    fn activate(&mut self, toggle: bool) {
+        let dir_path = match self.rows.get(self.selected).map(|row| &row.kind) {
+            Some(&RowKind::Dir(ref path)) => path.clone(),
+            Some(&RowKind::File(index)) => {
+                self.open(index);
+                return;
+            },
+            None => return,
+        };
+        if !self.collapsed.remove(&dir_path) && toggle {
+            self.collapsed.insert(dir_path);
+        }
+        self.rows = tree_rows(&self.files, &self.collapsed);
+    }
+
+    /// Collapses the selected directory, or selects the parent directory of the selected row.
+    fn collapse(&mut self) {
+        let row = match self.rows.get(self.selected) {
+            Some(row) => row,
+            None => return,
+        };
+        if let RowKind::Dir(ref path) = row.kind {
+            if !self.collapsed.contains(path) {
+                self.collapsed.insert(path.clone());
+                self.rows = tree_rows(&self.files, &self.collapsed);
+                return;
+            }
+        }
+        let depth = row.depth;
+        if let Some(parent) = self.rows[..self.selected].iter().rposition(|r| r.depth < depth) {
+            self.select(parent);
+        }
+    }
+
+    /// Opens the file at `index` in the source pane.
+    fn open(&mut self, index: usize) {
+        let file = &self.files[index];
+        let path = ::std::path::Path::new(&self.workspace_path).join(&file.path);
+        let mut content = String::new();
+        let lines = match File::open(&path).and_then(|mut f| f.read_to_string(&mut content)) {
+            Ok(_) => content.lines().map(|line| line.replace('\t', "    ")).collect(),
+            Err(e) => {
+                // Still show the counts, without the source.
+                let last_line = file.counts.keys().next_back().cloned().unwrap_or(0) as usize;
+                let mut lines = vec![String::new(); last_line];
+                if let Some(first) = lines.first_mut() {
+                    *first = format!("({}: {})", path.display(), e);
+                }
+                lines
+            },
+        };
+        self.source = Some(SourceView {
+            file: index,
+            lines,
+            cursor: 0,
+            offset: 0,
+        });
+        self.focus = Focus::Source;
+        self.jump_uncovered(true);
+    }
+
+    /// Moves the cursor of the source pane by `delta` lines, and scrolls it into view.
+    #[cfg_attr(feature = "cargo-clippy", allow(cast_possible_wrap, cast_sign_loss))]
+    fn move_cursor(&mut self, delta: isize) {
+        let height = self.height;
+        if let Some(ref mut source) = self.source {
+            let last = source.lines.len().saturating_sub(1) as isize;
+            let cursor = (source.cursor as isize).saturating_add(delta).max(0).min(last.max(0));
+            source.cursor = cursor as usize;
+            if source.cursor < source.offset {
+                source.offset = source.cursor;
+            } else if source.cursor >= source.offset + height {
+                source.offset = source.cursor + 1 - height;
+            }
+        }
+    }
+
+    /// Moves the cursor to the next (or previous) uncovered line of the opened file, if any.
+    fn jump_uncovered(&mut self, forward: bool) {
+        let height = self.height;
+        let source = match self.source {
+            Some(ref mut source) => source,
+            None => return,
+        };
+        let counts = &self.files[source.file].counts;
+        let current = source.cursor as u32 + 1;
+        let mut uncovered = counts.iter().filter(|&(_, &count)| count == 0).map(|(&line, _)| line);
+        let target = if forward {
+            uncovered.find(|&line| line > current)
+        } else {
+            uncovered.filter(|&line| line < current).last()
+        };
+        // Line 0 appears in the records of compiler-generated code, and cannot be shown.
+        if let Some(index) = target.and_then(|line| (line as usize).checked_sub(1)) {
+            source.cursor = index.min(source.lines.len().saturating_sub(1));
+            // Center the line.
+            source.offset = source.cursor.saturating_sub(height / 2);
+        }
+    }
+
+    /// Formats the line coverage of `summary` as a colored bar and percentage.
+    fn format_coverage(&self, summary: &FileSummary) -> String {
+        let percent = self.rounding.percent(summary.lines_covered, summary.lines_count);
+        let bar_color = if percent >= 80.0 {
+            self.fg(color::Green)
+        } else if percent >= 50.0 {
+            self.fg(color::Yellow)
+        } else {
+            self.fg(color::Red)
+        };
+        format!("{}{}{} {:>7}%", bar_color, coverage_bar(percent, BAR_WIDTH), self.fg(color::Reset), self.rounding.format(percent))
+    }
+
+    /// Renders the whole screen of `width`×`height` cells.
+    fn draw<W: Write>(&mut self, out: &mut W, (width, height): (u16, u16)) -> Result<()> {
+        let (width, height) = (width as usize, height as usize);
+        self.height = height.saturating_sub(2).max(1);
+        self.select(self.selected);
+        self.move_cursor(0);
+
+        let mut screen = Vec::new();
+        write!(screen, "{}{}", clear::All, cursor::Goto(1, 1))?;
+        let totals = &self.totals;
+        let header = format!(
+            " cargo cov · {} files · lines {}% ({}/{}) · branches {}% · functions {}%",
+            self.files.len(),
+            self.rounding.format(self.rounding.percent(totals.lines_covered, totals.lines_count)),
+            totals.lines_covered,
+            totals.lines_count,
+            self.rounding.format(self.rounding.percent(totals.branches_taken, totals.branches_count)),
+            self.rounding.format(self.rounding.percent(totals.functions_called, totals.functions_count)),
+        );
+        write!(screen, "{}{}{}", style::Invert, fit(&header, width), style::Reset)?;
+
+        // The tree takes 40% of the width, leaving the rest to the source.
+        let tree_width = (width * 2 / 5).max(BAR_WIDTH + 20).min(width);
+        let name_width = tree_width.saturating_sub(BAR_WIDTH + 10);
+        for y in 0..self.height {
+            write!(screen, "{}", cursor::Goto(1, (y + 2) as u16))?;
+            if let Some(row) = self.rows.get(self.tree_offset + y) {
+                let marker = match row.kind {
+                    RowKind::Dir(ref path) if self.collapsed.contains(path) => "▸ ",
+                    RowKind::Dir(_) => "▾ ",
+                    RowKind::File(_) => "  ",
+                };
+                let name = format!("{}{}{}", "  ".repeat(row.depth), marker, row.name);
+                if self.tree_offset + y == self.selected {
+                    let highlight = if self.focus == Focus::Tree { style::Invert.to_string() } else { style::Underline.to_string() };
+                    write!(screen, "{}{}{}", highlight, fit(&name, name_width), style::Reset)?;
+                } else {
+                    write!(screen, "{}", fit(&name, name_width))?;
+                }
+                write!(screen, " {}", self.format_coverage(&row.summary))?;
+            }
+            if tree_width < width {
+                write!(screen, "{}│", cursor::Goto((tree_width + 1) as u16, (y + 2) as u16))?;
+                self.draw_source_line(&mut screen, y, width - tree_width - 1)?;
+            }
+        }
+
+        let help = match self.focus {
+            Focus::Tree => " ↑↓ select · enter open/expand · ← collapse · tab source · n/N uncovered · q quit",
+            Focus::Source => " ↑↓ scroll · n/N next/previous uncovered · ← tab tree · q quit",
+        };
+        write!(screen, "{}{}{}{}", cursor::Goto(1, height as u16), style::Invert, fit(help, width), style::Reset)?;
+        out.write_all(&screen)?;
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Renders the `y`-th visible line of the source pane, at most `width` cells wide.
+    fn draw_source_line(&self, screen: &mut Vec<u8>, y: usize, width: usize) -> Result<()> {
+        let source = match self.source {
+            Some(ref source) => source,
+            None => return Ok(()),
+        };
+        let index = source.offset + y;
+        let text = match source.lines.get(index) {
+            Some(text) => text,
+            None => return Ok(()),
+        };
+        let line_number = index as u32 + 1;
+        let (count_color, count) = match self.files[source.file].counts.get(&line_number) {
+            Some(&0) => (self.fg(color::Red), "#####".to_owned()),
+            Some(&count) => (self.fg(color::Green), count.to_string()),
+            None => (String::new(), "-".to_owned()),
+        };
+        let gutter = format!("{:>6} {}{:>9}{} ", line_number, count_color, count, self.fg(color::Reset));
+        let highlight = if index == source.cursor && self.focus == Focus::Source { style::Invert.to_string() } else { String::new() };
+        write!(screen, "{}{}{}{}", highlight, gutter, fit(text, width.saturating_sub(17)), style::Reset)?;
+        Ok(())
+    }
+}
+
+/// Collects the coverage and browses it interactively until the user quits.
+pub fn browse(config: &ReportConfig) -> Result<()> {
+    let mut interner = Interner::new();
+    let report = report::collect(config, &mut interner)?;
+
+    let mut classifier = SourceClassifier::new(config.language, config.workspace_path.to_string_lossy().into_owned());
+    let mut files = report
+        .files
+        .iter()
+        .filter(|&(&symbol, _)| config.allowed_source_types.contains(classifier.identify(symbol, &interner).0) && config.includes_path(&interner[symbol]))
+        .map(|(&symbol, file)| SourceFile {
+            path: interner[symbol].to_owned(),
+            summary: file.summary(),
+            counts: file.lines.iter().map(|(&line, record)| (line, record.count)).collect(),
+        })
+        .collect::<Vec<_>>();
+    ensure!(!files.is_empty(), "no coverage found, run `cargo cov test` first");
+    files.sort_by(|a, b| components(&a.path).cmp(&components(&b.path)));
+
+    let mut browser = Browser::new(files, config.workspace_path.to_string_lossy().into_owned(), config.rounding);
+    let stdin = stdin();
+    let mut screen = AlternateScreen::from(stdout().into_raw_mode()?);
+    write!(screen, "{}", cursor::Hide)?;
+    browser.draw(&mut screen, terminal_size()?)?;
+    for key in stdin.keys() {
+        if !browser.handle_key(key?) {
+            break;
+        }
+        browser.draw(&mut screen, terminal_size()?)?;
+    }
+    write!(screen, "{}", cursor::Show)?;
+    screen.flush()?;
+    Ok(())
+}
+
+#[test]
+fn test_tree_rows() {
+    let file = |path: &str, lines_covered| {
+        let mut summary = FileSummary::default();
+        summary.lines_count = 10;
+        summary.lines_covered = lines_covered;
+        SourceFile {
+            path: path.to_owned(),
+            summary,
+            counts: BTreeMap::new(),
+        }
+    };
+    let files = vec![file("build.rs", 1), file("src/a/x.rs", 2), file("src/a/y.rs", 3), file("src/lib.rs", 4)];
+    let summarize = |rows: &[TreeRow]| rows.iter().map(|r| (r.depth, r.name.clone(), r.summary.lines_covered)).collect::<Vec<_>>();
+
+    let rows = tree_rows(&files, &HashSet::new());
+    assert_eq!(
+        summarize(&rows),
+        vec![
+            (0, "build.rs".to_owned(), 1),
+            (0, "src".to_owned(), 9),
+            (1, "a".to_owned(), 5),
+            (2, "x.rs".to_owned(), 2),
+            (2, "y.rs".to_owned(), 3),
+            (1, "lib.rs".to_owned(), 4),
+        ]
+    );
+    assert_eq!(rows[2].kind, RowKind::Dir("src/a".to_owned()));
+    assert_eq!(rows[5].kind, RowKind::File(3));
+
+    let collapsed = vec!["src/a".to_owned()].into_iter().collect();
+    let rows = tree_rows(&files, &collapsed);
+    assert_eq!(summarize(&rows), vec![(0, "build.rs".to_owned(), 1), (0, "src".to_owned(), 9), (1, "a".to_owned(), 5), (1, "lib.rs".to_owned(), 4)]);
+}
+
+#[test]
+fn test_coverage_bar() {
+    assert_eq!(coverage_bar(0.0, 4), "░░░░");
+    assert_eq!(coverage_bar(50.0, 4), "██░░");
+    assert_eq!(coverage_bar(100.0, 4), "████");
+    assert_eq!(fit("abcdef", 3), "abc");
+    assert_eq!(fit("ab", 3), "ab ");
+}
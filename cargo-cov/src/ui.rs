@@ -1,12 +1,33 @@
 //! Print colored text.
 //!
 //! Provides functions and macros that simulate the `cargo` output style.
+//!
+//! The output is colored only if it goes to a terminal, unless overridden by the environment variables `NO_COLOR` (see
+//! <https://no-color.org>) and `CLICOLOR_FORCE`. Tables fit the width of the terminal, or `COLUMNS` if set.
 
+use artifacts::{Artifact, MatchStatus};
+use blame::Blame;
 use error::Error;
+use outcome::ReportOutcome;
+use owners::OwnerCoverage;
+use show::ShownFile;
+use stability::StabilityReport;
+use timings::{PhaseTiming, seconds};
+use rounding::Rounding;
+use utils::{format_age, format_duration};
 
+use atty::{self, Stream};
+use cov::graph::ArcExplanation;
+use cov::raw::ArcAttr;
+use rustc_demangle::demangle;
+use term_size;
 use termcolor::*;
 
+use std::cell::RefCell;
+use std::env;
+use std::ffi::OsString;
 use std::io::{Result, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 /// Prints a progress (green text), similar to the cargo output.
 macro_rules! progress {
@@ -16,7 +37,7 @@ macro_rules! progress {
         (|| -> ::std::io::Result<()> {
             use ::termcolor::*;
             use ::std::io::Write;
-            let stream = StandardStream::stderr(ColorChoice::Auto);
+            let stream = $crate::ui::stderr();
             let mut lock = stream.lock();
             lock.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))?;
             write!(lock, "{:>12} ", $tag)?;
@@ -34,20 +55,120 @@ macro_rules! warning {
         (|| -> ::std::io::Result<()> {
             use ::termcolor::*;
             use ::std::io::Write;
-            let stream = StandardStream::stderr(ColorChoice::Auto);
+            let message = format!($fmt $(, $args)*);
+            let stream = $crate::ui::stderr();
             let mut lock = stream.lock();
             lock.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)).set_bold(true))?;
             write!(lock, "warning: ")?;
             lock.reset()?;
-            writeln!(lock, $fmt $(, $args)*)?;
+            writeln!(lock, "{}", message)?;
+            $crate::ui::record_warning(message);
             Ok(())
         })().expect("print warning")
     }}
 }
 
+/// Decides whether the output to `stream` is colored.
+///
+/// `NO_COLOR` disables the colors, and `CLICOLOR_FORCE` (unless `0`) enables them even if the output is piped.
+/// Otherwise the output is colored only if `stream` is a terminal.
+pub fn color_choice(stream: Stream) -> ColorChoice {
+    choose_color(env::var_os("NO_COLOR"), env::var_os("CLICOLOR_FORCE"), atty::is(stream))
+}
+
+/// Decides the colors from the values of `NO_COLOR` and `CLICOLOR_FORCE`, see [`color_choice()`].
+///
+/// [`color_choice()`]: ./fn.color_choice.html
+fn choose_color(no_color: Option<OsString>, clicolor_force: Option<OsString>, is_terminal: bool) -> ColorChoice {
+    if no_color.map_or(false, |v| !v.is_empty()) {
+        ColorChoice::Never
+    } else if clicolor_force.map_or(false, |v| !v.is_empty() && v != "0") {
+        ColorChoice::Always
+    } else if is_terminal {
+        // `Auto` still disables the colors with `TERM=dumb`.
+        ColorChoice::Auto
+    } else {
+        ColorChoice::Never
+    }
+}
+
+/// Opens `stdout` for colored output according to [`color_choice()`].
+///
+/// [`color_choice()`]: ./fn.color_choice.html
+pub fn stdout() -> StandardStream {
+    StandardStream::stdout(color_choice(Stream::Stdout))
+}
+
+/// Opens `stderr` for colored output according to [`color_choice()`].
+///
+/// [`color_choice()`]: ./fn.color_choice.html
+pub fn stderr() -> StandardStream {
+    StandardStream::stderr(color_choice(Stream::Stderr))
+}
+
+/// Obtains the width of the terminal which `stream` is connected to. `COLUMNS` takes precedence, so the width of piped
+/// output can be chosen as well. Returns `None` if unknown, in which case nothing should be wrapped.
+pub fn terminal_width(stream: Stream) -> Option<usize> {
+    if let Some(columns) = env::var("COLUMNS").ok().and_then(|c| c.parse().ok()).filter(|&c| c > 0) {
+        return Some(columns);
+    }
+    if !atty::is(stream) {
+        return None;
+    }
+    let dimensions = match stream {
+        Stream::Stdout => term_size::dimensions_stdout(),
+        Stream::Stderr => term_size::dimensions_stderr(),
+        Stream::Stdin => term_size::dimensions_stdin(),
+    };
+    dimensions.map(|(width, _)| width)
+}
+
+/// Computes the width of the first column of a table, given the width of the longest name and the total width of the
+/// other columns. The column is narrowed to fit the terminal, but never below the width of the heading.
+fn name_column_width(longest: usize, heading: usize, rest: usize, terminal_width: Option<usize>) -> usize {
+    let width = longest.max(heading);
+    match terminal_width {
+        Some(terminal_width) if width + rest > terminal_width => terminal_width.saturating_sub(rest).max(heading),
+        _ => width,
+    }
+}
+
+/// Prints the first cell of a table row. A name wider than the column is put on a line of its own, and the rest of the
+/// row continues on the next line.
+fn write_name_cell<W: WriteColor>(lock: &mut W, name: &str, width: usize) -> Result<()> {
+    if name.chars().count() > width {
+        writeln!(lock, "{}", name)?;
+        write!(lock, "{:width$}", "", width = width)
+    } else {
+        write!(lock, "{:<width$}", name, width = width)
+    }
+}
+
+thread_local! {
+    /// The warnings printed while [`capture_warnings()`](./fn.capture_warnings.html) is running.
+    static CAPTURED_WARNINGS: RefCell<Option<Vec<String>>> = RefCell::new(None);
+}
+
+/// Records a printed warning if the warnings are being captured. Used by the `warning!` macro.
+pub fn record_warning(message: String) {
+    CAPTURED_WARNINGS.with(|captured| {
+        if let Some(ref mut warnings) = *captured.borrow_mut() {
+            warnings.push(message);
+        }
+    });
+}
+
+/// Runs `f`, and collects the warnings printed meanwhile. The warnings are still printed.
+pub fn capture_warnings<T, F: FnOnce() -> T>(f: F) -> (T, Vec<String>) {
+    let previous = CAPTURED_WARNINGS.with(|captured| captured.replace(Some(Vec::new())));
+    let result = f();
+    let warnings = CAPTURED_WARNINGS.with(|captured| captured.replace(previous)).unwrap_or_default();
+    (result, warnings)
+}
+
 /// Prints an error and the causes.
 pub fn print_error(error: &Error) -> Result<()> {
-    let stream = StandardStream::stderr(ColorChoice::Auto);
+    let stream = stderr();
     let mut lock = stream.lock();
 
     for (i, e) in error.iter().enumerate() {
@@ -69,7 +190,7 @@ pub fn print_error(error: &Error) -> Result<()> {
 
 /// Prints a help message that an unknown subcommand is used for `cargo cov`.
 pub fn print_unknown_subcommand(subcommand: &str) -> Result<()> {
-    let stream = StandardStream::stderr(ColorChoice::Auto);
+    let stream = stderr();
     let mut lock = stream.lock();
 
     lock.set_color(ColorSpec::new().set_fg(Some(Color::Red)).set_bold(true))?;
@@ -90,3 +211,256 @@ pub fn print_unknown_subcommand(subcommand: &str) -> Result<()> {
     writeln!(lock, "` for a list of valid commands.")?;
     Ok(())
 }
+
+/// The width of the "Files", "Lines", "Branches" and "Functions" columns of the coverage tables, including the spaces
+/// between them.
+const TABLE_NUMBERS_WIDTH: usize = 8 + 3 * 10;
+
+/// Prints a coverage percentage cell, colored green, yellow or red according to the `fair` and `good` limits.
+fn write_percent_cell<W: WriteColor>(lock: &mut W, value: usize, total: usize, fair: f64, good: f64, rounding: Rounding) -> Result<()> {
+    let p = rounding.percent(value, total);
+    let color = if p >= good {
+        Color::Green
+    } else if p >= fair {
+        Color::Yellow
+    } else {
+        Color::Red
+    };
+    lock.set_color(ColorSpec::new().set_fg(Some(color)))?;
+    write!(lock, " {:>8}%", rounding.format(p))?;
+    lock.reset()
+}
+
+/// Prints the coverage summary of every owner as a table.
+pub fn print_owner_table(owners: &[OwnerCoverage], rounding: Rounding) -> Result<()> {
+    let stream = stdout();
+    let mut lock = stream.lock();
+
+    let longest = owners.iter().map(|o| o.owner.chars().count()).max().unwrap_or(0);
+    let width = name_column_width(longest, 5, TABLE_NUMBERS_WIDTH, terminal_width(Stream::Stdout));
+    lock.set_color(ColorSpec::new().set_bold(true))?;
+    write!(lock, "{:<width$} {:>7} {:>9} {:>9} {:>9}", "Owner", "Files", "Lines", "Branches", "Functions", width = width)?;
+    lock.reset()?;
+    writeln!(lock)?;
+    for owner in owners {
+        let s = &owner.summary;
+        write_name_cell(&mut lock, &owner.owner, width)?;
+        write!(lock, " {:>7}", owner.files_count)?;
+        write_percent_cell(&mut lock, s.lines_covered, s.lines_count, 75.0, 90.0, rounding)?;
+        write_percent_cell(&mut lock, s.branches_taken, s.branches_count, 50.0, 75.0, rounding)?;
+        write_percent_cell(&mut lock, s.functions_called, s.functions_count, 75.0, 90.0, rounding)?;
+        writeln!(lock)?;
+    }
+    Ok(())
+}
+
+/// Prints the coverage summary of every crate and the total as a table.
+pub fn print_crate_table(outcome: &ReportOutcome, rounding: Rounding) -> Result<()> {
+    let stream = stdout();
+    let mut lock = stream.lock();
+
+    let longest = outcome.crates.iter().map(|c| c.name.chars().count()).max().unwrap_or(0);
+    let width = name_column_width(longest, 5, TABLE_NUMBERS_WIDTH, terminal_width(Stream::Stdout));
+    lock.set_color(ColorSpec::new().set_bold(true))?;
+    write!(lock, "{:<width$} {:>7} {:>9} {:>9} {:>9}", "Crate", "Files", "Lines", "Branches", "Functions", width = width)?;
+    lock.reset()?;
+    writeln!(lock)?;
+    let files_count = outcome.crates.iter().map(|c| c.files_count).sum::<usize>();
+    let rows = outcome.crates.iter().map(|c| (&*c.name, c.files_count, &c.summary));
+    for (i, (name, files_count, s)) in rows.chain(Some(("Total", files_count, &outcome.totals))).enumerate() {
+        if i == outcome.crates.len() {
+            lock.set_color(ColorSpec::new().set_bold(true))?;
+        }
+        write_name_cell(&mut lock, name, width)?;
+        write!(lock, " {:>7}", files_count)?;
+        lock.reset()?;
+        write_percent_cell(&mut lock, s.lines_covered, s.lines_count, 75.0, 90.0, rounding)?;
+        write_percent_cell(&mut lock, s.branches_taken, s.branches_count, 50.0, 75.0, rounding)?;
+        write_percent_cell(&mut lock, s.functions_called, s.functions_count, 75.0, 90.0, rounding)?;
+        writeln!(lock)?;
+    }
+    Ok(())
+}
+
+/// Prints the lines and branches whose coverage varies between runs.
+pub fn print_unstable_coverage(report: &StabilityReport) -> Result<()> {
+    let stream = stdout();
+    let mut lock = stream.lock();
+
+    if report.unstable.is_empty() {
+        lock.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))?;
+        write!(lock, "stable: ")?;
+        lock.reset()?;
+        writeln!(lock, "the coverage is identical in all {} runs", report.runs)?;
+        return Ok(());
+    }
+
+    for item in &report.unstable {
+        lock.set_color(ColorSpec::new().set_bold(true))?;
+        write!(lock, "{}:{}", item.path, item.line)?;
+        lock.reset()?;
+        if let Some(branch) = item.branch {
+            write!(lock, " (branch {})", branch)?;
+        }
+        lock.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)))?;
+        writeln!(lock, " covered in {}/{} runs", item.covered_runs, report.runs)?;
+        lock.reset()?;
+    }
+    Ok(())
+}
+
+/// Prints the provenance of a source line.
+pub fn print_blame(blame: &Blame) -> Result<()> {
+    fn count_to_string(count: Option<u64>) -> String {
+        count.map_or_else(|| "?".to_owned(), |c| c.to_string())
+    }
+    fn write_arc<W: WriteColor>(lock: &mut W, arrow: &str, arc: &ArcExplanation) -> Result<()> {
+        let source = if arc.attr.contains(ArcAttr::ON_TREE) { "derived" } else { "instrumented" };
+        writeln!(lock, "        {} block #{}: {} ({}, attr {:02x}h)", arrow, arc.block, count_to_string(arc.count), source, arc.attr)
+    }
+
+    let stream = stdout();
+    let mut lock = stream.lock();
+
+    lock.set_color(ColorSpec::new().set_bold(true))?;
+    write!(lock, "{}:{}", blame.path, blame.line)?;
+    lock.reset()?;
+    let color = if blame.count > Some(0) { Color::Green } else { Color::Red };
+    lock.set_color(ColorSpec::new().set_fg(Some(color)))?;
+    writeln!(lock, " count {}", count_to_string(blame.count))?;
+    lock.reset()?;
+
+    writeln!(lock, "\nObjects (*.gcno):")?;
+    for path in &blame.gcno_paths {
+        writeln!(lock, "    {}", path.display())?;
+    }
+
+    writeln!(lock, "\nRuns (*.gcda) with non-zero count:")?;
+    if blame.gcda_contributions.is_empty() {
+        writeln!(lock, "    (none)")?;
+    }
+    for contribution in &blame.gcda_contributions {
+        writeln!(lock, "    {} (+{})", contribution.path.display(), contribution.count)?;
+    }
+
+    writeln!(lock, "\nBlocks (the line count is the maximum of the block counts):")?;
+    for &(ref function, ref block) in &blame.blocks {
+        lock.set_color(ColorSpec::new().set_bold(true))?;
+        write!(lock, "    block #{} of `{}`", block.block, demangle(function))?;
+        lock.reset()?;
+        writeln!(lock, ": {} = sum of incoming arcs", count_to_string(block.count))?;
+        for arc in &block.incoming {
+            write_arc(&mut lock, "←", arc)?;
+        }
+        for arc in &block.outgoing {
+            write_arc(&mut lock, "→", arc)?;
+        }
+    }
+    Ok(())
+}
+
+/// Prints a source file with the execution count of every line in the left margin.
+pub fn print_source(file: &ShownFile) -> Result<()> {
+    let stream = stdout();
+    let mut lock = stream.lock();
+
+    lock.set_color(ColorSpec::new().set_bold(true))?;
+    write!(lock, "{}", file.path)?;
+    lock.reset()?;
+    #[cfg_attr(feature = "cargo-clippy", allow(cast_precision_loss))]
+    let percent = 100.0 * file.lines_covered as f64 / (file.lines_count as f64).max(1.0);
+    writeln!(lock, " {}/{} lines covered ({:.1}%)", file.lines_covered, file.lines_count, percent)?;
+
+    let number_width = file.lines.last().map_or(1, |line| line.number.to_string().len());
+    let count_width = file.lines.iter().filter_map(|line| line.count).max().map_or(1, |count| count.to_string().len());
+    for line in &file.lines {
+        let count = line.count.map_or_else(|| "-".to_owned(), |count| count.to_string());
+        match line.count {
+            Some(0) => lock.set_color(ColorSpec::new().set_fg(Some(Color::Red)))?,
+            Some(_) => lock.set_color(ColorSpec::new().set_fg(Some(Color::Green)))?,
+            None => lock.reset()?,
+        }
+        write!(lock, "{:>width$}", count, width = count_width)?;
+        lock.reset()?;
+        write!(lock, " {:>width$} | ", line.number, width = number_width)?;
+        if line.count == Some(0) {
+            lock.set_color(ColorSpec::new().set_fg(Some(Color::Red)))?;
+        }
+        write!(lock, "{}", line.text)?;
+        lock.reset()?;
+        writeln!(lock)?;
+    }
+    Ok(())
+}
+
+/// Prints the time spent in every phase as a table.
+pub fn print_timings(phases: &[PhaseTiming], elapsed: Duration) -> Result<()> {
+    let stream = stderr();
+    let mut lock = stream.lock();
+
+    lock.set_color(ColorSpec::new().set_bold(true))?;
+    write!(lock, "{:<8} {:>7} {:>10} {:>7}", "Phase", "Steps", "Time", "Share")?;
+    lock.reset()?;
+    writeln!(lock)?;
+    for phase in phases {
+        let share = 100.0 * seconds(phase.total) / seconds(elapsed).max(1e-9);
+        writeln!(lock, "{:<8} {:>7} {:>10} {:>6.1}%", phase.phase, phase.count, format_duration(phase.total), share)?;
+    }
+    lock.set_color(ColorSpec::new().set_bold(true))?;
+    write!(lock, "{:<8} {:>7} {:>10}", "Total", "", format_duration(elapsed))?;
+    lock.reset()?;
+    writeln!(lock)?;
+    Ok(())
+}
+
+/// Prints the collected GCNO and GCDA files as a table.
+pub fn print_artifacts(artifacts: &[Artifact]) -> Result<()> {
+    let stream = stdout();
+    let mut lock = stream.lock();
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let crate_names = artifacts.iter().map(|a| a.crate_name.as_ref().map_or("?", |s| &**s)).collect::<Vec<_>>();
+    let width = crate_names.iter().map(|s| s.len()).max().unwrap_or(0).max(5);
+
+    lock.set_color(ColorSpec::new().set_bold(true))?;
+    write!(lock, "{:<4} {:<width$} {:>10} {:>5} {:<8} {}", "Kind", "Crate", "Size", "Age", "Status", "Path", width = width)?;
+    lock.reset()?;
+    writeln!(lock)?;
+    for (artifact, crate_name) in artifacts.iter().zip(crate_names) {
+        let age = format_age(Duration::from_secs(now.saturating_sub(artifact.modified)));
+        write!(lock, "{:<4} {:<width$} {:>10} {:>5} ", artifact.kind, crate_name, artifact.size, age, width = width)?;
+        let (color, status) = match artifact.status {
+            MatchStatus::Matched => (Color::Green, "matched"),
+            MatchStatus::NoGcda => (Color::Yellow, "no gcda"),
+            MatchStatus::Orphan => (Color::Red, "orphan"),
+            MatchStatus::Stale => (Color::Red, "stale"),
+            MatchStatus::Invalid => (Color::Red, "invalid"),
+        };
+        lock.set_color(ColorSpec::new().set_fg(Some(color)))?;
+        write!(lock, "{:<8}", status)?;
+        lock.reset()?;
+        writeln!(lock, " {}", artifact.path.display())?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_choose_color() {
+    let some = |s: &str| Some(OsString::from(s));
+    assert_eq!(choose_color(None, None, true), ColorChoice::Auto);
+    assert_eq!(choose_color(None, None, false), ColorChoice::Never);
+    assert_eq!(choose_color(some("1"), None, true), ColorChoice::Never);
+    assert_eq!(choose_color(some(""), None, false), ColorChoice::Never);
+    assert_eq!(choose_color(None, some("1"), false), ColorChoice::Always);
+    assert_eq!(choose_color(None, some("0"), false), ColorChoice::Never);
+    assert_eq!(choose_color(some("1"), some("1"), true), ColorChoice::Never);
+}
+
+#[test]
+fn test_name_column_width() {
+    assert_eq!(name_column_width(3, 5, 38, None), 5);
+    assert_eq!(name_column_width(60, 5, 38, None), 60);
+    assert_eq!(name_column_width(60, 5, 38, Some(120)), 60);
+    assert_eq!(name_column_width(60, 5, 38, Some(80)), 42);
+    assert_eq!(name_column_width(60, 5, 38, Some(20)), 5);
+}
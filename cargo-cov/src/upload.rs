@@ -0,0 +1,461 @@
+//! Uploading the coverage to hosted services.
+//!
+//! `cargo cov upload --service codecov` renders the report with the `codecov` template, and sends it to the Codecov
+//! upload API over HTTPS together with the commit and branch of the workspace. The repository token is read from
+//! `--token`, the `upload-token` key of the configuration file, or the `CODECOV_TOKEN` environment variable, in that
+//! order, and sent in the `Authorization` header. Public repositories on some CI services may be uploaded without a
+//! token.
+//!
+//! `cargo cov upload --service bitbucket` publishes the coverage as a [Code Insights] report of the commit, with an
+//! annotation on every uncovered line changed by the pull request. The repository, commit and pull request are read from
+//! the environment variables of Bitbucket Pipelines, which also provides an authenticating proxy, so no token is needed
+//! there. Elsewhere, an access token with the `repository:write` scope must be given by `--token` or
+//! `$BITBUCKET_ACCESS_TOKEN`.
+//!
+//! [Code Insights]: https://support.atlassian.com/bitbucket-cloud/docs/code-insights/
+
+use argparse::ReportConfig;
+use error::{ErrorKind, Result, ResultExt};
+use git::{self, GitInfo};
+use outcome::ReportOutcome;
+use report;
+
+use curl::easy::{Easy, List};
+use serde_json::{self, Value};
+use tempfile::TempDir;
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+
+/// A coverage service supported by `cargo cov upload`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Service {
+    /// [Codecov](https://codecov.io).
+    Codecov,
+    /// [Bitbucket Cloud](https://bitbucket.org) Code Insights.
+    Bitbucket,
+}
+
+impl Service {
+    /// Obtains the name of the service, the inverse of [`from_str()`].
+    ///
+    /// [`from_str()`]: #method.from_str
+    pub fn name(self) -> &'static str {
+        match self {
+            Service::Codecov => "codecov",
+            Service::Bitbucket => "bitbucket",
+        }
+    }
+
+    /// The template rendering the coverage in the format accepted by this service.
+    fn template_name(self) -> &'static str {
+        match self {
+            Service::Codecov => "codecov",
+            Service::Bitbucket => "json",
+        }
+    }
+
+    /// The environment variable containing the repository token.
+    pub fn token_variable(self) -> &'static str {
+        match self {
+            Service::Codecov => "CODECOV_TOKEN",
+            Service::Bitbucket => "BITBUCKET_ACCESS_TOKEN",
+        }
+    }
+
+    /// The default URL of the service.
+    pub fn default_url(self) -> &'static str {
+        match self {
+            Service::Codecov => "https://codecov.io",
+            // Inside Bitbucket Pipelines, the API must be accessed in plain HTTP through the authenticating proxy.
+            Service::Bitbucket if in_bitbucket_pipelines() => "http://api.bitbucket.org",
+            Service::Bitbucket => "https://api.bitbucket.org",
+        }
+    }
+
+    /// Whether the service can be accessed without a token.
+    fn is_authenticated_implicitly(self) -> bool {
+        match self {
+            Service::Codecov => false,
+            Service::Bitbucket => in_bitbucket_pipelines(),
+        }
+    }
+}
+
+/// Checks whether we are running inside Bitbucket Pipelines.
+fn in_bitbucket_pipelines() -> bool {
+    env::var_os("BITBUCKET_BUILD_NUMBER").is_some()
+}
+
+/// The proxy of Bitbucket Pipelines, which adds the credentials of the build to requests to the Bitbucket API.
+const BITBUCKET_PIPELINES_PROXY: &str = "http://localhost:29418";
+
+/// Bitbucket accepts at most 1000 annotations per report, and 100 annotations per request.
+const BITBUCKET_MAX_ANNOTATIONS: usize = 1000;
+const BITBUCKET_ANNOTATIONS_PER_REQUEST: usize = 100;
+
+/// The error raised when [`Service::from_str()`] encounters an unrecognized string.
+///
+/// [`Service::from_str()`]: ./enum.Service.html#method.from_str
+#[derive(Debug)]
+pub struct UnsupportedServiceName;
+
+impl FromStr for Service {
+    type Err = UnsupportedServiceName;
+    fn from_str(s: &str) -> ::std::result::Result<Service, UnsupportedServiceName> {
+        match s {
+            "codecov" => Ok(Service::Codecov),
+            "bitbucket" => Ok(Service::Bitbucket),
+            _ => Err(UnsupportedServiceName),
+        }
+    }
+}
+
+/// Options of `cargo cov upload`.
+#[derive(Debug)]
+pub struct UploadConfig<'a> {
+    pub service: Service,
+    /// The repository token, default to the environment variable of the service.
+    pub token: Option<&'a str>,
+    /// The URL of the service, default to the public instance.
+    pub url: Option<&'a str>,
+}
+
+/// Renders the coverage for `upload_config.service` and uploads it. Returns the URL of the uploaded report, if the
+/// service provided one.
+pub fn upload(mut config: ReportConfig, upload_config: &UploadConfig) -> Result<Option<String>> {
+    let service = upload_config.service;
+    let token = match upload_config.token {
+        Some(token) => Some(token.to_owned()),
+        None => env::var(service.token_variable()).ok(),
+    };
+    if token.is_none() && !service.is_authenticated_implicitly() {
+        warning!("no {} token given by --token or ${}, uploading without one", service.name(), service.token_variable());
+    }
+
+    let temp_dir = TempDir::new()?;
+    config.template_name = OsStr::new(service.template_name());
+    config.template_override_path = None;
+    config.output_path = Cow::Owned(temp_dir.path().to_owned());
+    config.history_path = None;
+    config.changed_only = false;
+    config.gcov_files = false;
+    config.single_file = false;
+    config.emit = Vec::new();
+    let outcome = report::generate(&config, false)?;
+    let report_path = outcome.summary_path.clone().ok_or("the template has no summary page")?;
+    let base_url = upload_config.url.unwrap_or_else(|| service.default_url()).trim_end_matches('/');
+
+    match service {
+        Service::Codecov => upload_to_codecov(&config, &report_path, base_url, token),
+        Service::Bitbucket => publish_to_bitbucket(&config, &outcome, &report_path, base_url, token),
+    }
+}
+
+/// Uploads the rendered `codecov` template to the Codecov upload API.
+fn upload_to_codecov(config: &ReportConfig, report_path: &Path, base_url: &str, token: Option<String>) -> Result<Option<String>> {
+    let service = Service::Codecov;
+    let mut body = Vec::new();
+    File::open(report_path)?.read_to_end(&mut body)?;
+
+    let url = codecov_url(base_url, git::describe(&config.workspace_path));
+    let authorization = token.map(|token| format!("token {}", token));
+    progress!("Uploading", "{} to {}", report_path.display(), service.name());
+    let request = Request {
+        method: "POST",
+        url: &url,
+        content_type: "text/plain",
+        authorization: authorization.as_ref().map(|a| &**a),
+        proxy: None,
+    };
+    let response = request.send(service, &body)?;
+
+    // The first line of the response is the URL of the report.
+    Ok(response.lines().next().filter(|line| line.starts_with("http")).map(str::to_owned))
+}
+
+/// Builds the URL of the Codecov upload API for the commit and branch described by `info`. The token is sent in the
+/// `Authorization` header instead of the query, so it does not end up in the access logs of proxies and servers.
+fn codecov_url(base_url: &str, info: Option<GitInfo>) -> String {
+    let mut query = vec![("package", concat!("cargo-cov-", env!("CARGO_PKG_VERSION")).to_owned())];
+    if let Some(info) = info {
+        query.push(("commit", info.head.id));
+        if info.branch != "HEAD" {
+            query.push(("branch", info.branch));
+        }
+    }
+
+    let mut easy = Easy::new();
+    let query = query.iter().map(|&(key, ref value)| format!("{}={}", key, easy.url_encode(value.as_bytes()))).collect::<Vec<_>>();
+    format!("{}/upload/v2?{}", base_url, query.join("&"))
+}
+
+/// Publishes the rendered `json` template as a Code Insights report of the current commit, and annotates the uncovered
+/// lines changed by the pull request. Returns the URL of the commit, where the report is shown.
+fn publish_to_bitbucket(config: &ReportConfig, outcome: &ReportOutcome, report_path: &Path, base_url: &str, token: Option<String>) -> Result<Option<String>> {
+    let service = Service::Bitbucket;
+    let repo = env::var("BITBUCKET_REPO_FULL_NAME").map_err(|_| "$BITBUCKET_REPO_FULL_NAME is not set, cannot tell which repository to publish to")?;
+    let commit = match env::var("BITBUCKET_COMMIT") {
+        Ok(commit) => commit,
+        Err(_) => git::describe(&config.workspace_path).ok_or("cannot find the commit to publish to")?.head.id,
+    };
+    let coverage: Value = serde_json::from_reader(File::open(report_path)?)?;
+
+    // Only lines changed by a pull request are annotated, otherwise the report would be flooded by existing code.
+    let changed_lines = match env::var("BITBUCKET_PR_DESTINATION_BRANCH") {
+        Ok(branch) => {
+            let changed_lines = git::changed_lines(&config.workspace_path, &format!("origin/{}", branch));
+            if changed_lines.is_none() {
+                warning!("cannot compare with origin/{}, uncovered lines will not be annotated", branch);
+            }
+            changed_lines.unwrap_or_default()
+        },
+        Err(_) => Default::default(),
+    };
+    let annotations = bitbucket_annotations(&coverage, &config.workspace_path, &changed_lines);
+
+    let totals = &outcome.totals;
+    let rounding = config.rounding;
+    let lines_percent = rounding.percent(totals.lines_covered, totals.lines_count);
+    let branches_percent = rounding.percent(totals.branches_taken, totals.branches_count);
+    let report = json!({
+        "title": "Coverage",
+        "details": format!(
+            "{}% of lines and {}% of branches are covered by tests.",
+            rounding.format(lines_percent),
+            rounding.format(branches_percent),
+        ),
+        "report_type": "COVERAGE",
+        "reporter": "cargo-cov",
+        "result": if outcome.thresholds.iter().all(|t| t.passed) { "PASSED" } else { "FAILED" },
+        "data": [
+            {"title": "Line coverage", "type": "PERCENTAGE", "value": lines_percent},
+            {"title": "Branch coverage", "type": "PERCENTAGE", "value": branches_percent},
+            {"title": "Uncovered changed lines", "type": "NUMBER", "value": annotations.len()},
+        ],
+    });
+
+    let report_url = format!("{}/2.0/repositories/{}/commit/{}/reports/cargo-cov", base_url, repo, commit);
+    let authorization = token.map(|token| format!("Bearer {}", token));
+    let proxy = if service.is_authenticated_implicitly() && authorization.is_none() {
+        Some(BITBUCKET_PIPELINES_PROXY)
+    } else {
+        None
+    };
+    progress!("Publishing", "coverage report of {} to {}", commit, repo);
+    let request = Request {
+        method: "PUT",
+        url: &report_url,
+        content_type: "application/json",
+        authorization: authorization.as_ref().map(|a| &**a),
+        proxy,
+    };
+    request.send(service, &serde_json::to_vec(&report)?)?;
+
+    let annotations_url = format!("{}/annotations", report_url);
+    for batch in annotation_batches(annotations) {
+        let request = Request {
+            method: "POST",
+            url: &annotations_url,
+            ..request
+        };
+        request.send(service, &serde_json::to_vec(&batch)?)?;
+    }
+
+    Ok(Some(format!("https://bitbucket.org/{}/commits/{}", repo, commit)))
+}
+
+/// Creates an annotation for every uncovered line in `changed_lines` of the files of the rendered `json` template. The
+/// paths of the `coverage` are made relative to the `workspace_path` like those of the `changed_lines`.
+fn bitbucket_annotations(coverage: &Value, workspace_path: &Path, changed_lines: &BTreeMap<String, BTreeSet<u32>>) -> Vec<Value> {
+    let mut annotations = Vec::new();
+    for file in coverage["files"].as_array().map_or(&[][..], |files| files) {
+        let path = match file["path"].as_str() {
+            Some(path) => Path::new(path).strip_prefix(workspace_path).unwrap_or_else(|_| Path::new(path)),
+            None => continue,
+        };
+        let path = path.to_string_lossy().replace('\\', "/");
+        let changed = match changed_lines.get(&path) {
+            Some(changed) => changed,
+            None => continue,
+        };
+        for line in file["lines"].as_array().map_or(&[][..], |lines| lines) {
+            let number = line["line"].as_u64().unwrap_or(0);
+            if line["count"].as_u64() == Some(0) && changed.contains(&(number as u32)) {
+                annotations.push(json!({
+                    "external_id": format!("cargo-cov-{}-{}", path, number),
+                    "annotation_type": "CODE_SMELL",
+                    "summary": "Line not covered by tests",
+                    "path": path,
+                    "line": number,
+                    "severity": "LOW",
+                }));
+            }
+        }
+    }
+    annotations
+}
+
+/// Splits the annotations into the batches sent in every request, dropping those beyond the limit of a report.
+fn annotation_batches(mut annotations: Vec<Value>) -> Vec<Vec<Value>> {
+    if annotations.len() > BITBUCKET_MAX_ANNOTATIONS {
+        warning!("only the first {} of {} uncovered changed lines are annotated", BITBUCKET_MAX_ANNOTATIONS, annotations.len());
+        annotations.truncate(BITBUCKET_MAX_ANNOTATIONS);
+    }
+    annotations.chunks(BITBUCKET_ANNOTATIONS_PER_REQUEST).map(<[Value]>::to_vec).collect()
+}
+
+/// An HTTP request to a service.
+#[derive(Copy, Clone)]
+struct Request<'a> {
+    method: &'static str,
+    url: &'a str,
+    content_type: &'static str,
+    /// The value of the `Authorization` header, if any.
+    authorization: Option<&'a str>,
+    proxy: Option<&'a str>,
+}
+
+impl<'a> fmt::Debug for Request<'a> {
+    /// Formats the request with the credentials in the `Authorization` header redacted.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Request")
+            .field("method", &self.method)
+            .field("url", &self.url)
+            .field("content_type", &self.content_type)
+            .field("authorization", &self.authorization.map(|_| "<redacted>"))
+            .field("proxy", &self.proxy)
+            .finish()
+    }
+}
+
+impl<'a> Request<'a> {
+    /// Sends the request with `body`, and returns the response if the service responded with a successful status.
+    fn send(&self, service: Service, body: &[u8]) -> Result<String> {
+        let (status, response) = self.perform(body).chain_err(|| format!("Cannot upload to {}", service.name()))?;
+        ensure!(200 <= status && status < 300, ErrorKind::UploadFailed(service.name(), status, response));
+        Ok(response)
+    }
+
+    /// Sends the request with `body`, and returns the status code and the response.
+    fn perform(&self, mut body: &[u8]) -> ::std::result::Result<(u32, String), ::curl::Error> {
+        let mut easy = Easy::new();
+        let mut headers = List::new();
+        headers.append(&format!("Accept: {}", self.content_type))?;
+        headers.append(&format!("Content-Type: {}", self.content_type))?;
+        if let Some(authorization) = self.authorization {
+            headers.append(&format!("Authorization: {}", authorization))?;
+        }
+        easy.url(self.url)?;
+        easy.post(true)?;
+        easy.post_field_size(body.len() as u64)?;
+        if self.method != "POST" {
+            easy.custom_request(self.method)?;
+        }
+        if let Some(proxy) = self.proxy {
+            easy.proxy(proxy)?;
+        }
+        easy.http_headers(headers)?;
+
+        let mut response = Vec::new();
+        {
+            let mut transfer = easy.transfer();
+            transfer.read_function(|buf| Ok(body.read(buf).unwrap_or(0)))?;
+            transfer.write_function(|data| {
+                response.extend_from_slice(data);
+                Ok(data.len())
+            })?;
+            transfer.perform()?;
+        }
+        Ok((easy.response_code()?, String::from_utf8_lossy(&response).into_owned()))
+    }
+}
+
+#[test]
+fn test_codecov_url() {
+    let package = concat!("package=cargo-cov-", env!("CARGO_PKG_VERSION"));
+    assert_eq!(codecov_url("https://codecov.io", None), format!("https://codecov.io/upload/v2?{}", package));
+
+    let mut info = GitInfo::default();
+    info.head.id = "0123456789abcdef".to_owned();
+    info.branch = "feature/a b".to_owned();
+    assert_eq!(
+        codecov_url("https://codecov.example.com", Some(info.clone())),
+        format!("https://codecov.example.com/upload/v2?{}&commit=0123456789abcdef&branch=feature%2Fa%20b", package)
+    );
+    info.branch = "HEAD".to_owned();
+    assert_eq!(codecov_url("https://codecov.io", Some(info)), format!("https://codecov.io/upload/v2?{}&commit=0123456789abcdef", package));
+}
+
+#[test]
+fn test_request_redacts_token() {
+    let request = Request {
+        method: "POST",
+        url: "https://codecov.io/upload/v2?package=cargo-cov",
+        content_type: "text/plain",
+        authorization: Some("token 7f3c0e4d-secret"),
+        proxy: None,
+    };
+    let debug = format!("{:?}", request);
+    assert!(!debug.contains("7f3c0e4d-secret"));
+    assert!(debug.contains(r#"authorization: Some("<redacted>")"#));
+}
+
+#[test]
+fn test_bitbucket_annotations() {
+    let coverage = json!({
+        "files": [
+            {
+                "path": "/ws/src/lib.rs",
+                "lines": [
+                    {"line": 1, "count": 0},
+                    {"line": 2, "count": 3},
+                    {"line": 3, "count": 0},
+                    {"line": 4, "count": 0},
+                ],
+            },
+            {"path": "/ws/src/unchanged.rs", "lines": [{"line": 1, "count": 0}]},
+            {"path": "/elsewhere/src/main.rs", "lines": [{"line": 7, "count": 0}]},
+            {"lines": [{"line": 1, "count": 0}]},
+        ],
+    });
+    let mut changed_lines = BTreeMap::new();
+    changed_lines.insert("src/lib.rs".to_owned(), vec![2, 3, 4].into_iter().collect());
+    changed_lines.insert("/elsewhere/src/main.rs".to_owned(), vec![7].into_iter().collect());
+
+    let annotations = bitbucket_annotations(&coverage, Path::new("/ws"), &changed_lines);
+    let locations = annotations.iter().map(|a| (a["path"].as_str().unwrap(), a["line"].as_u64().unwrap())).collect::<Vec<_>>();
+    assert_eq!(locations, vec![("src/lib.rs", 3), ("src/lib.rs", 4), ("/elsewhere/src/main.rs", 7)]);
+    assert_eq!(annotations[0]["external_id"], "cargo-cov-src/lib.rs-3");
+    assert_eq!(annotations[0]["annotation_type"], "CODE_SMELL");
+    assert_eq!(annotations[0]["severity"], "LOW");
+
+    assert!(bitbucket_annotations(&coverage, Path::new("/ws"), &BTreeMap::new()).is_empty());
+    assert!(bitbucket_annotations(&json!({}), Path::new("/ws"), &changed_lines).is_empty());
+}
+
+#[test]
+fn test_annotation_batches() {
+    let annotations = |count: usize| (0..count).map(|i| json!({ "line": i })).collect::<Vec<_>>();
+    let sizes = |count| annotation_batches(annotations(count)).iter().map(Vec::len).collect::<Vec<_>>();
+
+    assert!(sizes(0).is_empty());
+    assert_eq!(sizes(1), vec![1]);
+    assert_eq!(sizes(BITBUCKET_ANNOTATIONS_PER_REQUEST), vec![BITBUCKET_ANNOTATIONS_PER_REQUEST]);
+    assert_eq!(sizes(BITBUCKET_ANNOTATIONS_PER_REQUEST + 1), vec![BITBUCKET_ANNOTATIONS_PER_REQUEST, 1]);
+    assert_eq!(sizes(250), vec![100, 100, 50]);
+
+    // Only the first annotations up to the limit of a report are sent.
+    let batches = annotation_batches(annotations(BITBUCKET_MAX_ANNOTATIONS + 5));
+    assert_eq!(batches.len(), BITBUCKET_MAX_ANNOTATIONS / BITBUCKET_ANNOTATIONS_PER_REQUEST);
+    assert!(batches.iter().all(|batch| batch.len() == BITBUCKET_ANNOTATIONS_PER_REQUEST));
+    assert_eq!(batches[0][0]["line"], 0);
+    assert_eq!(batches.last().unwrap().last().unwrap()["line"], BITBUCKET_MAX_ANNOTATIONS - 1);
+}
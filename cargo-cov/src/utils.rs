@@ -1,9 +1,11 @@
 //! Additional methods for libstd and external crates.
 
-use error::{ErrorKind, Result as CargoCovResult};
+use error::{ErrorKind, Result as CargoCovResult, ResultExt};
 
 use natord::compare_iter;
-use serde_json::Value;
+use serde::Serialize;
+use serde_json::{Value, to_writer};
+use tempfile::NamedTempFile;
 
 use std::cmp::Ordering;
 use std::fs::{File, Permissions, remove_dir_all};
@@ -18,6 +20,7 @@ use std::os::unix::fs::PermissionsExt;
 use std::os::windows::ffi::{OsStrExt, OsStringExt};
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::time::Duration;
 
 pub trait OptionExt {
     type Value;
@@ -110,6 +113,48 @@ impl CommandExt for Command {
     }
 }
 
+/// Writes `value` as JSON to `path`.
+///
+/// The JSON is written to a temporary file in the same directory, which then replaces `path` by a rename. Thus a
+/// concurrent `cargo cov report` (which only takes a shared lock) or an interrupted write never leaves a truncated
+/// file, and readers see either the old or the new content.
+pub fn write_json_atomically<T: Serialize>(path: &Path, value: &T) -> CargoCovResult<()> {
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let mut file = NamedTempFile::new_in(dir).chain_err(|| format!("Cannot write `{}`", path.display()))?;
+    to_writer(&mut file, value)?;
+    file.persist(path).chain_err(|| format!("Cannot write `{}`", path.display()))?;
+    Ok(())
+}
+
+/// Computes `value / total` as a percentage. An empty `total` is considered fully covered.
+#[cfg_attr(feature = "cargo-clippy", allow(cast_precision_loss))]
+pub fn percent(value: usize, total: usize) -> f64 {
+    if total == 0 {
+        100.0
+    } else {
+        100.0 * value as f64 / total as f64
+    }
+}
+
+/// Formats a duration as seconds with millisecond precision, e.g. `1.234s`.
+pub fn format_duration(duration: Duration) -> String {
+    format!("{}.{:03}s", duration.as_secs(), duration.subsec_nanos() / 1_000_000)
+}
+
+/// Formats an elapsed time in the largest whole unit, e.g. `5m` or `3d`.
+pub fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    match secs {
+        0...59 => format!("{}s", secs),
+        60...3599 => format!("{}m", secs / 60),
+        3600...86_399 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86_400),
+    }
+}
+
 /// Short circuit of `path.parent().parent().parent()`.
 ///
 /// # Panics
@@ -0,0 +1,236 @@
+//! Expected-uncovered code.
+//!
+//! Some code is not supposed to be covered by the tests, e.g. handlers of unrecoverable I/O errors or generated code.
+//! Instead of lowering the thresholds for everything, such code can be *waived* in the configuration file of
+//! `cargo cov report`, together with a justification:
+//!
+//! ```toml
+//! [[waivers]]
+//! path = "src/generated/**"
+//! justification = "Generated by build.rs, tested upstream"
+//!
+//! [[waivers]]
+//! function = "my_crate::io::*"
+//! justification = "Requires a faulty disk"
+//!
+//! [[waivers]]
+//! marker = "OOM"
+//! justification = "Allocation failure cannot be simulated"
+//! ```
+//!
+//! * `path` is a glob pattern matching the source path relative to the workspace.
+//! * `function` is a glob pattern matching the (demangled) name of the function containing the line.
+//! * `marker` matches the lines containing `cov:waive(OOM)` in the source code, or all lines from `cov:waive-begin(OOM)`
+//!   to `cov:waive-end(OOM)` inclusively.
+//!
+//! If a waiver has several of them, a line must match all. The waived lines are listed in a separate section of the
+//! summary page, and do *not* count as covered. With `--fail-on-unwaived-uncovered`, the report fails if any uncovered
+//! line is not waived.
+
+use argparse::ReportConfig;
+use error::{ErrorKind, Result};
+use owners::relativize;
+use sourcepath::Language;
+
+use cov::{Interner, Report, Symbol};
+use cov::report::FunctionLines;
+use glob::{MatchOptions, Pattern};
+use rustc_demangle::demangle;
+
+use std::collections::{BTreeSet, HashMap};
+use std::fs::File;
+use std::io::Read;
+
+/// An expected-uncovered item in the configuration.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Waiver {
+    /// Glob pattern of the source paths relative to the workspace.
+    pub path: Option<String>,
+    /// Glob pattern of the function names.
+    pub function: Option<String>,
+    /// ID of the `cov:waive(ID)` markers in the source code.
+    pub marker: Option<String>,
+    /// Why the code is expected to be uncovered.
+    pub justification: String,
+}
+
+/// The uncovered lines after applying the waivers.
+#[derive(Debug, Default)]
+pub struct WaivedLines {
+    /// The lines waived by every waiver, in the same order as the configuration.
+    pub waived: Vec<Vec<(Symbol, u32)>>,
+    /// The uncovered lines not waived by any waiver.
+    pub unwaived: Vec<(Symbol, u32)>,
+}
+
+/// A waiver with its patterns compiled.
+struct Matcher<'a> {
+    path: Option<Pattern>,
+    function: Option<Pattern>,
+    markers: Option<(String, String, String)>,
+    waiver: &'a Waiver,
+}
+
+impl<'a> Matcher<'a> {
+    fn new(waiver: &'a Waiver) -> Result<Matcher<'a>> {
+        fn compile(pattern: &Option<String>) -> Result<Option<Pattern>> {
+            match *pattern {
+                Some(ref pattern) => Ok(Some(Pattern::new(pattern).map_err(|e| ErrorKind::InvalidWaiver(format!("invalid pattern `{}`: {}", pattern, e)))?)),
+                None => Ok(None),
+            }
+        }
+
+        ensure!(
+            waiver.path.is_some() || waiver.function.is_some() || waiver.marker.is_some(),
+            ErrorKind::InvalidWaiver(format!("waiver `{}` should have a path, function or marker", waiver.justification))
+        );
+        Ok(Matcher {
+            path: compile(&waiver.path)?,
+            function: compile(&waiver.function)?,
+            markers: waiver
+                .marker
+                .as_ref()
+                .map(|id| (format!("cov:waive({})", id), format!("cov:waive-begin({})", id), format!("cov:waive-end({})", id))),
+            waiver,
+        })
+    }
+
+    /// Finds the lines of a source file marked by this waiver.
+    fn marked_lines(&self, source: &str) -> BTreeSet<u32> {
+        let mut lines = BTreeSet::new();
+        if let Some((ref line_marker, ref begin_marker, ref end_marker)) = self.markers {
+            let mut in_region = false;
+            for (line, content) in (1..).zip(source.lines()) {
+                if content.contains(&**begin_marker) {
+                    in_region = true;
+                }
+                if in_region || content.contains(&**line_marker) {
+                    lines.insert(line);
+                }
+                if content.contains(&**end_marker) {
+                    in_region = false;
+                }
+            }
+        }
+        lines
+    }
+}
+
+/// Sorts the uncovered lines of the reported files into waived and unwaived ones.
+///
+/// `function_lines` should be obtained from [`Graph::function_lines()`] if any waiver matches functions.
+///
+/// # Errors
+///
+/// Returns [`InvalidWaiver`] if a waiver has no path, function or marker, or has an invalid pattern.
+///
+/// [`Graph::function_lines()`]: ../../cov/graph/struct.Graph.html#method.function_lines
+/// [`InvalidWaiver`]: ../error/enum.ErrorKind.html#variant.InvalidWaiver
+pub fn apply(config: &ReportConfig, report: &Report, function_lines: &[FunctionLines], interner: &Interner) -> Result<WaivedLines> {
+    let matchers = config.waivers.iter().map(Matcher::new).collect::<Result<Vec<_>>>()?;
+    let options = MatchOptions {
+        case_sensitive: true,
+        require_literal_separator: true,
+        require_literal_leading_dot: false,
+    };
+    let workspace_str = config.workspace_path.to_string_lossy();
+
+    // Lines of the functions matched by every waiver, keyed by the file.
+    let mut function_matches = HashMap::<(usize, Symbol), BTreeSet<u32>>::new();
+    for (i, matcher) in matchers.iter().enumerate() {
+        if let Some(ref pattern) = matcher.function {
+            for f in function_lines {
+                let name = &interner[f.name];
+                let is_match = pattern.matches(name) || (config.language == Language::Rust && pattern.matches(&format!("{:#}", demangle(name))));
+                if is_match {
+                    let lines = function_matches.entry((i, f.filename)).or_insert_with(BTreeSet::new);
+                    lines.extend(f.covered.iter().chain(&f.uncovered));
+                }
+            }
+        }
+    }
+
+    let mut result = WaivedLines {
+        waived: vec![Vec::new(); matchers.len()],
+        unwaived: Vec::new(),
+    };
+    let mut symbols = report.files.keys().cloned().collect::<Vec<_>>();
+    symbols.sort_by_key(|&symbol| &interner[symbol]);
+    for symbol in symbols {
+        let path = &interner[symbol];
+        if !config.allowed_source_types.contains(config.language.identify_source_path(path, &workspace_str).0) {
+            continue;
+        }
+        let uncovered = report.files[&symbol].lines.iter().filter(|&(_, line)| line.count == 0).map(|(&n, _)| n).collect::<Vec<_>>();
+        if uncovered.is_empty() {
+            continue;
+        }
+
+        let relative_path = relativize(path, &workspace_str);
+        let mut source = None;
+        let mut waived_by = vec![None; uncovered.len()];
+        for (i, matcher) in matchers.iter().enumerate() {
+            if let Some(ref pattern) = matcher.path {
+                match relative_path {
+                    Some(ref p) if pattern.matches_with(p, &options) => {},
+                    _ => continue,
+                }
+            }
+            let marked_lines = if matcher.markers.is_some() {
+                if source.is_none() {
+                    let mut content = String::new();
+                    // A missing source file simply has no markers.
+                    let _ = File::open(config.workspace_path.join(path)).and_then(|mut f| f.read_to_string(&mut content));
+                    source = Some(content);
+                }
+                Some(matcher.marked_lines(source.as_ref().expect("read above")))
+            } else {
+                None
+            };
+            let function_lines = if matcher.function.is_some() {
+                match function_matches.get(&(i, symbol)) {
+                    Some(lines) => Some(lines),
+                    None => continue,
+                }
+            } else {
+                None
+            };
+
+            for (&line, waiver) in uncovered.iter().zip(&mut waived_by) {
+                let is_match = waiver.is_none() && marked_lines.as_ref().map_or(true, |l| l.contains(&line)) && function_lines.map_or(true, |l| l.contains(&line));
+                if is_match {
+                    *waiver = Some(i);
+                }
+            }
+        }
+
+        for (line, waiver) in uncovered.into_iter().zip(waived_by) {
+            match waiver {
+                Some(i) => result.waived[i].push((symbol, line)),
+                None => result.unwaived.push((symbol, line)),
+            }
+        }
+    }
+
+    for (matcher, lines) in matchers.iter().zip(&result.waived) {
+        if lines.is_empty() {
+            warning!("waiver `{}` does not match any uncovered line", matcher.waiver.justification);
+        }
+    }
+
+    Ok(result)
+}
+
+#[test]
+fn test_marked_lines() {
+    let waiver = Waiver {
+        marker: Some("OOM".to_owned()),
+        justification: "cannot be simulated".to_owned(),
+        ..Waiver::default()
+    };
+    let matcher = Matcher::new(&waiver).unwrap();
+    let source = "fn a() {\n    abort(); // cov:waive(OOM)\n    // cov:waive-begin(OOM)\n    b();\n    // cov:waive-end(OOM)\n    c(); // cov:waive(IO)\n}\n";
+    assert_eq!(matcher.marked_lines(source).into_iter().collect::<Vec<_>>(), vec![2, 3, 4, 5]);
+    assert!(Matcher::new(&Waiver::default()).is_err());
+}
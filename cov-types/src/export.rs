@@ -0,0 +1,41 @@
+//! Exporting a report.
+
+use report::Report;
+
+use std::io::Write;
+
+/// A format which a [`Report`] can be exported to.
+///
+/// ```rust
+/// use cov_types::{Export, Report};
+/// use std::io::{self, Write};
+///
+/// /// Writes the number of covered lines of every file.
+/// struct CoveredLines;
+///
+/// impl Export for CoveredLines {
+///     type Error = io::Error;
+///
+///     fn export(&self, report: &Report, writer: &mut Write) -> io::Result<()> {
+///         for (filename, file) in &report.files {
+///             writeln!(writer, "{}\t{}", filename, file.summary().lines_covered)?;
+///         }
+///         Ok(())
+///     }
+/// }
+///
+/// let mut report = Report::default();
+/// report.files.insert("src/lib.rs".to_owned(), Default::default());
+/// let mut output = Vec::new();
+/// CoveredLines.export(&report, &mut output).unwrap();
+/// assert_eq!(output, b"src/lib.rs\t0\n");
+/// ```
+///
+/// [`Report`]: ../report/struct.Report.html
+pub trait Export {
+    /// The error raised when the report cannot be exported.
+    type Error;
+
+    /// Writes the `report` in this format to `writer`.
+    fn export(&self, report: &Report, writer: &mut Write) -> Result<(), Self::Error>;
+}
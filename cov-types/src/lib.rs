@@ -0,0 +1,48 @@
+//! `cov-types` contains the core types shared by [`cov`] and [`cargo-cov`], without the GCNO/GCDA parser or the cargo
+//! integration.
+//!
+//! Tools which only consume coverage data, e.g. a dashboard reading the reports exported by `cargo cov`, or a plugin
+//! writing the report into a new format, can depend on this crate alone. The parser changes whenever a compiler
+//! changes its output, but the types here only change in a semver-incompatible release of this crate.
+//!
+//! The structs of the [`report`] module are `#[non_exhaustive]`, so new fields can be added in a compatible release.
+//! Outside of this crate they cannot be built by a struct expression, but from their `Default` value with the fields
+//! assigned one by one, and patterns matching them must end with `..`.
+//!
+//! The crate provides:
+//!
+//! * [`SourceType`], the category of a source path, as used by `cargo cov report --include`.
+//! * [`Report`], the coverage of every line, branch and function, keyed by plain strings. The [`cov::Report`] is
+//!   converted to it by [`cov::Report::resolve()`].
+//! * [`FileSummary`] and [`FunctionSummary`], the statistics of a file and a function.
+//! * [`Export`], the trait of a writer turning a `Report` into another format.
+//!
+//! With the default `serde` feature, all types can be serialized and deserialized with serde.
+//!
+//! [`cov`]: https://docs.rs/cov
+//! [`cargo-cov`]: https://docs.rs/crate/cargo-cov
+//! [`cov::Report`]: https://docs.rs/cov/*/cov/report/struct.Report.html
+//! [`cov::Report::resolve()`]: https://docs.rs/cov/*/cov/report/struct.Report.html#method.resolve
+//! [`SourceType`]: ./struct.SourceType.html
+//! [`Report`]: ./report/struct.Report.html
+//! [`report`]: ./report/index.html
+//! [`FileSummary`]: ./report/struct.FileSummary.html
+//! [`FunctionSummary`]: ./report/struct.FunctionSummary.html
+//! [`Export`]: ./export/trait.Export.html
+
+#![cfg_attr(feature = "cargo-clippy", warn(warnings, clippy_pedantic))]
+#![cfg_attr(feature = "cargo-clippy", allow(missing_docs_in_private_items))]
+
+#[macro_use]
+extern crate bitflags;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+
+pub mod export;
+pub mod report;
+mod source_type;
+
+pub use export::Export;
+pub use report::{FileSummary, FunctionSummary, Report};
+pub use source_type::{SourceType, UnsupportedSourceTypeName};
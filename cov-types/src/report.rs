@@ -0,0 +1,176 @@
+//! The report model.
+//!
+//! Unlike the `Report` of the `cov` crate, the file and function names here are plain strings, so a report can be read
+//! back and used without the interner of the parser. The attributes of the basic blocks and arcs are not included,
+//! since they describe the GCNO format instead of the coverage.
+
+use std::collections::BTreeMap;
+use std::ops::AddAssign;
+
+/// A coverage report.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[non_exhaustive]
+pub struct Report {
+    /// Files in the report, keyed by their source paths.
+    pub files: BTreeMap<String, File>,
+}
+
+/// Coverage information about a source file.
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[non_exhaustive]
+pub struct File {
+    /// Lines in the file.
+    pub lines: BTreeMap<u32, Line>,
+
+    /// Functions in the file.
+    pub functions: Vec<Function>,
+}
+
+impl File {
+    /// Produces a summary of the current file.
+    pub fn summary(&self) -> FileSummary {
+        let mut summary = FileSummary {
+            lines_count: self.lines.len(),
+            lines_covered: self.lines.values().filter(|line| line.count > 0).count(),
+            functions_count: self.functions.len(),
+            ..FileSummary::default()
+        };
+        for function in &self.functions {
+            let s = &function.summary;
+            summary.branches_count += s.branches_count;
+            summary.branches_executed += s.branches_executed;
+            summary.branches_taken += s.branches_taken;
+            summary.functions_called += (s.entry_count > 0) as usize;
+        }
+        summary
+    }
+}
+
+/// Coverage information about a source line of code.
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[non_exhaustive]
+pub struct Line {
+    /// Number of times this line is executed.
+    pub count: u64,
+
+    /// List of branches this line will lead to.
+    pub branches: Vec<Branch>,
+}
+
+/// Coverage information about a branch.
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[non_exhaustive]
+pub struct Branch {
+    /// Number of times this branch is taken.
+    pub count: u64,
+
+    /// The target filename of this branch.
+    pub filename: String,
+
+    /// The line number of the target of this branch. Zero if missing.
+    pub line: u32,
+
+    /// The column number of the target of this branch. Zero if missing.
+    pub column: u32,
+}
+
+/// Coverage information about a function.
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[non_exhaustive]
+pub struct Function {
+    /// Name of the function, which is usually mangled.
+    pub name: String,
+
+    /// The line number where this function is defined. Zero if missing.
+    pub line: u32,
+
+    /// The column number where this function is defined. Zero if missing.
+    pub column: u32,
+
+    /// The line number where this function ends. Zero if missing.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub end_line: u32,
+
+    /// The column number where this function ends (inclusive). Zero if missing.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub end_column: u32,
+
+    /// Summary about this function.
+    pub summary: FunctionSummary,
+}
+
+/// Statistical summary of a function.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[non_exhaustive]
+pub struct FunctionSummary {
+    /// Number of basic blocks in the function (excluding the enter- and exit-blocks).
+    pub blocks_count: usize,
+
+    /// Number of basic blocks that has been executed (having non-zero count).
+    pub blocks_executed: usize,
+
+    /// How many times the function is called.
+    pub entry_count: u64,
+
+    /// How many times the function has returned.
+    pub exit_count: u64,
+
+    /// Number of conditional branches in the function.
+    pub branches_count: usize,
+
+    /// Number of conditional basic blocks that has been executed.
+    pub branches_executed: usize,
+
+    /// Number of branches that has been taken.
+    pub branches_taken: usize,
+
+    /// Cyclomatic complexity of the function, i.e. the number of linearly independent paths through its control-flow
+    /// graph. A function without any branches has complexity 1.
+    pub complexity: usize,
+}
+
+/// Statistical summary of a file.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[non_exhaustive]
+pub struct FileSummary {
+    /// Number of lines that can be profiled.
+    pub lines_count: usize,
+
+    /// Number of lines that has been covered.
+    pub lines_covered: usize,
+
+    /// Number of conditional branches in functions defined in this file.
+    pub branches_count: usize,
+
+    /// Number of conditional basic blocks that has been executed.
+    pub branches_executed: usize,
+
+    /// Number of branches that has been taken.
+    pub branches_taken: usize,
+
+    /// Number of functions defined in this file.
+    pub functions_count: usize,
+
+    /// Number of functions that has been called.
+    pub functions_called: usize,
+}
+
+impl AddAssign for FileSummary {
+    /// Accumulates the statistics of another file, e.g. to compute the total of a directory.
+    fn add_assign(&mut self, other: FileSummary) {
+        self.lines_count += other.lines_count;
+        self.lines_covered += other.lines_covered;
+        self.branches_count += other.branches_count;
+        self.branches_executed += other.branches_executed;
+        self.branches_taken += other.branches_taken;
+        self.functions_count += other.functions_count;
+        self.functions_called += other.functions_called;
+    }
+}
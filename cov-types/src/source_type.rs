@@ -0,0 +1,88 @@
+//! Categories of source paths.
+
+use std::str::FromStr;
+
+bitflags! {
+    /// The type of source path.
+    pub struct SourceType: u8 {
+        /// The path is in the local workspace.
+        const LOCAL = 1;
+        /// The "path" is part of macro declaration.
+        const MACROS = 2;
+        /// Unknown kind of path.
+        const UNKNOWN = 4;
+        /// The path is of external crates.
+        const CRATES = 8;
+        /// The path is in the Rust standard libraries.
+        const RUSTSRC = 16;
+
+        /// The default set of interesting source paths.
+        const DEFAULT = SourceType::LOCAL.bits | SourceType::MACROS.bits | SourceType::UNKNOWN.bits;
+    }
+}
+
+/// The error raised when [`SourceType::from_str()`] encounters an unrecognized string.
+///
+/// [`SourceType::from_str()`]: ./struct.SourceType.html#method.from_str
+#[derive(Debug)]
+pub struct UnsupportedSourceTypeName;
+
+impl SourceType {
+    /// Parses an iterator of strings using [`from_str()`], and returns the union of all bitflags.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`UnsupportedSourceTypeName`] when `from_str()` fails.
+    ///
+    /// [`from_str()`]: #method.from_str
+    /// [`UnsupportedSourceTypeName`]: ./struct.UnsupportedSourceTypeName.html
+    pub fn from_multi_str<'a, I>(strings: I) -> Result<SourceType, UnsupportedSourceTypeName>
+    where
+        I: Iterator<Item = &'a str>,
+    {
+        let mut res = SourceType::empty();
+        for s in strings {
+            res |= s.parse()?;
+        }
+        Ok(res)
+    }
+
+    /// Obtains the path prefix so that
+    pub fn prefix(self) -> &'static str {
+        match self {
+            SourceType::LOCAL => ".",
+            SourceType::RUSTSRC => "«rust»",
+            SourceType::CRATES => "«crates»",
+            _ => "",
+        }
+    }
+
+    /// Obtains the name of a single source type, the inverse of [`from_str()`].
+    ///
+    /// [`from_str()`]: #method.from_str
+    pub fn name(self) -> &'static str {
+        match self {
+            SourceType::LOCAL => "local",
+            SourceType::MACROS => "macros",
+            SourceType::RUSTSRC => "rustsrc",
+            SourceType::CRATES => "crates",
+            _ => "unknown",
+        }
+    }
+}
+
+impl FromStr for SourceType {
+    type Err = UnsupportedSourceTypeName;
+    fn from_str(s: &str) -> Result<SourceType, UnsupportedSourceTypeName> {
+        Ok(match s {
+            "local" => SourceType::LOCAL,
+            "macros" => SourceType::MACROS,
+            "rustsrc" => SourceType::RUSTSRC,
+            "crates" => SourceType::CRATES,
+            "unknown" => SourceType::UNKNOWN,
+            "all" => SourceType::all(),
+            _ => return Err(UnsupportedSourceTypeName),
+        })
+    }
+}
+
@@ -1,12 +1,14 @@
 #[macro_use]
+extern crate clap;
+#[macro_use]
 extern crate error_chain;
 extern crate cov;
 extern crate env_logger;
 extern crate serde_json;
 
-use cov::{Gcov, Interner, Result, SerializeWithInterner};
+use cov::{Gcov, Graph, Interner, Result, SerializeWithInterner};
+use cov::intern::{Symbol, UNKNOWN_SYMBOL};
 
-use std::env;
 use std::io::stdout;
 
 quick_main!(run);
@@ -14,9 +16,52 @@ quick_main!(run);
 fn run() -> Result<()> {
     env_logger::init();
 
-    let filename = env::args_os().nth(1).expect("filename");
+    let matches = clap_app!(dump =>
+        (@arg dot: --dot [FUNCTION] min_values(0) require_equals(true) "Merge the files and print the control-flow graph in Graphviz format, instead of the parsed records, optionally only of FUNCTION, e.g. `--dot=main`")
+        (@arg analyze: -a --analyze requires[dot] "Analyze the graph before printing, so every block and arc has a count")
+        (@arg filter: -f --filter +takes_value requires[dot] "Only print the graph of this function, same as `--dot=FUNCTION`")
+        (@arg files: <FILE>... "*.gcno and *.gcda files to dump")
+    ).get_matches();
+
     let mut interner = Interner::new();
-    let parsed = Gcov::open(filename, &mut interner)?;
-    serde_json::to_writer_pretty(stdout(), &parsed.with_interner(&interner))?;
+    let files = matches.values_of_os("files").expect("files");
+    if !matches.is_present("dot") {
+        for filename in files {
+            let parsed = Gcov::open(filename, &mut interner)?;
+            serde_json::to_writer_pretty(stdout(), &parsed.with_interner(&interner))?;
+        }
+        return Ok(());
+    }
+
+    let mut graph = Graph::default();
+    for filename in files {
+        graph.merge(Gcov::open(filename, &mut interner)?)?;
+    }
+    if matches.is_present("analyze") {
+        graph.analyze();
+    }
+    let filter = match matches.value_of("dot").or_else(|| matches.value_of("filter")) {
+        Some(name) => find_function(&graph, &interner, name)?,
+        None => UNKNOWN_SYMBOL,
+    };
+    graph.write_dot(filter, stdout())?;
     Ok(())
 }
+
+/// Finds the function named `name`, or else the only function whose name contains `name`, so a mangled name can be
+/// chosen by a part of it, e.g. `parse` for `_ZN3foo5parse17h0123456789abcdefE`.
+fn find_function(graph: &Graph, interner: &Interner, name: &str) -> Result<Symbol> {
+    let names = graph.function_names();
+    if let Some(&symbol) = names.iter().find(|&&symbol| &interner[symbol] == name) {
+        return Ok(symbol);
+    }
+    let candidates = names.into_iter().filter(|&symbol| interner[symbol].contains(name)).collect::<Vec<_>>();
+    match candidates.len() {
+        0 => bail!("no function is named `{}`", name),
+        1 => Ok(candidates[0]),
+        _ => {
+            let candidates = candidates.iter().map(|&symbol| &interner[symbol]).collect::<Vec<_>>();
+            bail!("several functions match `{}`: {}", name, candidates.join(", "))
+        },
+    }
+}
@@ -0,0 +1,25 @@
+//! Feeds arbitrary bytes to the GCNO/GCDA parser, which must return an error instead of crashing or exhausting memory.
+//!
+//! Seed the corpus with the test data for better coverage:
+//!
+//! ```sh
+//! mkdir -p corpus/gcov_open
+//! cp ../test-data/*/*.gc* corpus/gcov_open/
+//! cargo fuzz run gcov_open
+//! ```
+
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate cov;
+
+use cov::Interner;
+use cov::reader::Reader;
+
+// Same as `Gcov::open()`, without going through the file system.
+fuzz_target!(|data: &[u8]| {
+    let mut interner = Interner::new();
+    if let Ok(mut reader) = Reader::new(data, &mut interner) {
+        let _ = reader.parse();
+    }
+});
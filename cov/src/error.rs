@@ -79,6 +79,13 @@ error_chain! {
             description("counts mismatch")
             display("{0} counts mismatch on *.{3}, expecting {1} {0}, received {2} {0}", kind, expected, actual, ty)
         }
+
+        /// A GCNO/GCDA contains a value beyond the [limits](../reader/index.html#limits) of the parser. This means the
+        /// file is corrupt.
+        LimitExceeded(kind: &'static str, limit: u64, actual: u64) {
+            description("limit exceeded")
+            display("{} {} exceeds the limit {}", kind, actual, limit)
+        }
     }
 }
 
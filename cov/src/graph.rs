@@ -4,8 +4,9 @@
 use error::*;
 use intern::{Symbol, UNKNOWN_SYMBOL};
 use raw::*;
-use report::{self, Report};
+use report::{self, FunctionLines, InlinedLines, Report};
 use utils::*;
+use warning::Warning;
 
 use fixedbitset::FixedBitSet;
 use petgraph::Direction;
@@ -17,6 +18,7 @@ use std::borrow::Cow;
 use std::collections::{BTreeMap, Bound, HashSet};
 use std::collections::hash_map::{Entry, HashMap};
 use std::ops::{Index, IndexMut};
+use std::path::{Path, PathBuf};
 
 //----------------------------------------------------------------------------------------------------------------------
 //{{{ Graph
@@ -29,6 +31,8 @@ pub struct Graph {
     gcno_index: HashMap<GcnoFunctionIdentity, FunctionIndex>,
     gcda_index: HashMap<GcdaFunctionIdentity, FunctionIndex>,
     graph: DiGraph<BlockInfo, ArcInfo>,
+    provenance: Option<Provenance>,
+    warnings: Vec<Warning>,
 }
 
 impl Graph {
@@ -37,7 +41,100 @@ impl Graph {
         Graph::default()
     }
 
-    /// Merges a parsed GCNO/GCDA into the graph.
+    /// Enables tracking which GCDA files contributed counts to each source file.
+    ///
+    /// This should be called *before* any GCDA is [merged](#method.merge). The tracking is disabled by default to keep
+    /// the memory usage bounded when merging many GCDA files.
+    pub fn enable_provenance(&mut self) {
+        if self.provenance.is_none() {
+            self.provenance = Some(Provenance::default());
+        }
+    }
+
+    /// Obtains the GCDA files which contributed counts to the functions with lines in a source file (including those
+    /// inlined from it), and the sum of the arc counts each of them contributed to these functions.
+    ///
+    /// Returns an empty vector if the [provenance tracking](#method.enable_provenance) is not enabled.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// extern crate cov;
+    /// use cov::{Gcov, Graph, Interner};
+    /// use cov::raw::{Line, Record};
+    /// use std::path::Path;
+    ///
+    /// # fn main() { run().unwrap(); }
+    /// # fn run() -> cov::Result<()> {
+    /// let mut interner = Interner::default();
+    /// let mut graph = Graph::default();
+    /// graph.enable_provenance();
+    /// graph.merge(Gcov::open("test-data/branches.clang/x.gcno", &mut interner)?)?;
+    /// graph.merge(Gcov::open("test-data/branches.clang/x.gcda", &mut interner)?)?;
+    ///
+    /// let filename = interner.intern("x.cpp");
+    /// let provenance = graph.provenance(filename);
+    /// assert_eq!(provenance.len(), 1);
+    /// assert_eq!(provenance[0].0, Path::new("test-data/branches.clang/x.gcda"));
+    ///
+    /// // A file only reached through a function inlined from it is attributed as well.
+    /// let mut gcno = Gcov::open("test-data/branches.clang/x.gcno", &mut interner)?;
+    /// let inlined = interner.intern("inlined.h");
+    /// for record in &mut gcno.records {
+    ///     if let Record::Lines(ref mut lines) = *record {
+    ///         lines.lines.extend(&[Line::FileName(inlined), Line::LineNumber(1)]);
+    ///     }
+    /// }
+    /// let mut graph = Graph::default();
+    /// graph.enable_provenance();
+    /// graph.merge(gcno)?;
+    /// graph.merge(Gcov::open("test-data/branches.clang/x.gcda", &mut interner)?)?;
+    /// assert_eq!(graph.provenance(inlined), graph.provenance(filename));
+    /// # Ok(()) }
+    /// ```
+    pub fn provenance(&self, filename: Symbol) -> Vec<(&Path, u64)> {
+        match self.provenance {
+            Some(ref provenance) => provenance
+                .counts
+                .get(&filename)
+                .map_or_else(Vec::new, |counts| counts.iter().map(|&(i, count)| (&*provenance.sources[i], count)).collect()),
+            None => Vec::new(),
+        }
+    }
+
+    /// Obtains the names of all functions in the graph, sorted and without duplicates, e.g. to choose the function given
+    /// to [`write_dot()`](#method.write_dot).
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// extern crate cov;
+    /// use cov::{Gcov, Graph, Interner};
+    ///
+    /// # fn main() { run().unwrap(); }
+    /// # fn run() -> cov::Result<()> {
+    /// let mut interner = Interner::default();
+    /// let mut graph = Graph::default();
+    /// graph.merge(Gcov::open("test-data/branches.clang/x.gcno", &mut interner)?)?;
+    ///
+    /// let names = graph.function_names();
+    /// assert!(names.contains(&interner.intern("main")));
+    /// # Ok(()) }
+    /// ```
+    pub fn function_names(&self) -> Vec<Symbol> {
+        let mut names = self.functions.iter().filter_map(|f| f.source.map(|s| s.name)).collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Collects the [warnings](../warning/index.html) of every GCNO/GCDA [merged](#method.merge) so far, in order.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// Merges a parsed GCNO/GCDA into the graph. The recoverable anomalies of the file are appended to the
+    /// [`warnings()`](#method.warnings).
     ///
     /// # Errors
     ///
@@ -51,6 +148,10 @@ impl Graph {
     /// [`MissingFunction`]: ../error/enum.ErrorKind.html#variant.MissingFunction
     /// [`CountsMismatch`]: ../error/enum.ErrorKind.html#variant.MissingFunction
     pub fn merge(&mut self, mut gcov: Gcov) -> Result<()> {
+        self.warnings.extend(gcov.warnings());
+        if let (Type::Gcda, Some(provenance)) = (gcov.ty, self.provenance.as_mut()) {
+            provenance.sources.push(gcov.src.clone().unwrap_or_default());
+        }
         let source_location = match gcov.src.take() {
             Some(path) => Location::File(path),
             None => Location::None,
@@ -138,7 +239,10 @@ impl Graph {
         for (index, record) in gcda.records.into_iter().enumerate() {
             match record {
                 Record::Function(ident, function) => cur = Location::RecordIndex(index).wrap(|| self.find_function(checksum, ident, function))?,
-                Record::ArcCounts(ac) => self.add_arc_counts(cur, ac)?,
+                Record::ArcCounts(ac) => {
+                    self.record_provenance(cur, &ac);
+                    self.add_arc_counts(cur, ac)?
+                },
                 Record::Summary(_) => {},
                 _ => trace!("gcda-unknown-record: {:?}", record),
             }
@@ -194,6 +298,119 @@ impl Graph {
         r
     }
 
+    /// Obtains the covered and uncovered lines of every function.
+    ///
+    /// Only lines in the same file as the function definition are included, so code inlined from other files (e.g.
+    /// macros) is not attributed to the function. If the same function appears multiple times in the graph (e.g. when
+    /// it is compiled into several objects), the lines are merged into a single entry. The result is sorted by the
+    /// filename symbol, then the line number.
+    ///
+    /// Like [`report()`](#method.report), this should be called after the graph is [analyzed](#method.analyze).
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// extern crate cov;
+    /// use cov::{Gcov, Graph, Interner};
+    ///
+    /// # fn main() { run().unwrap(); }
+    /// # fn run() -> cov::Result<()> {
+    /// let mut interner = Interner::default();
+    /// let mut graph = Graph::default();
+    /// graph.merge(Gcov::open("test-data/branches.clang/x.gcno", &mut interner)?)?;
+    /// graph.merge(Gcov::open("test-data/branches.clang/x.gcda", &mut interner)?)?;
+    /// graph.analyze();
+    ///
+    /// let function_lines = graph.function_lines();
+    /// let main = function_lines.iter().find(|f| &interner[f.name] == "main").unwrap();
+    /// assert!(main.covered.contains(&3));
+    /// assert!(main.uncovered.contains(&6));
+    /// # Ok(()) }
+    /// ```
+    pub fn function_lines(&self) -> Vec<FunctionLines> {
+        let mut result = HashMap::<(Symbol, Symbol), FunctionLines>::new();
+
+        for function in &self.functions {
+            let source = match function.source {
+                Some(source) => source,
+                None => continue,
+            };
+            let entry = result.entry((source.filename, source.name)).or_insert_with(|| FunctionLines {
+                filename: source.filename,
+                name: source.name,
+                line: source.line,
+                ..FunctionLines::default()
+            });
+            for ni in &function.nodes {
+                let block = &self.graph[*ni];
+                let is_covered = block.count > Some(0);
+                for (filename, line) in block.iter_lines() {
+                    if filename != source.filename {
+                        continue;
+                    }
+                    if is_covered {
+                        entry.covered.insert(line);
+                    } else {
+                        entry.uncovered.insert(line);
+                    }
+                }
+            }
+        }
+
+        let mut result = result
+            .into_iter()
+            .map(|(_, mut function_lines)| {
+                let uncovered = function_lines.uncovered.difference(&function_lines.covered).cloned().collect();
+                function_lines.uncovered = uncovered;
+                function_lines
+            })
+            .collect::<Vec<_>>();
+        result.sort_by_key(|f| (f.filename, f.line, f.name));
+        result
+    }
+
+    /// Obtains the lines of other source files inlined into every function.
+    ///
+    /// This is the complement of [`function_lines()`](#method.function_lines): a line belongs to the result if it is in
+    /// a different file than the function definition. The count of each line is the maximum count of the blocks of the
+    /// function covering it, in the same way as [`report()`](#method.report). Entries of the same function compiled
+    /// into several objects are merged, and the result is sorted by the filename symbol, the line number, then the
+    /// inlined filename symbol.
+    ///
+    /// Like [`report()`](#method.report), this should be called after the graph is [analyzed](#method.analyze).
+    pub fn inlined_lines(&self) -> Vec<InlinedLines> {
+        let mut result = HashMap::<(Symbol, Symbol, Symbol), InlinedLines>::new();
+
+        for function in &self.functions {
+            let source = match function.source {
+                Some(source) => source,
+                None => continue,
+            };
+            for ni in &function.nodes {
+                let block = &self.graph[*ni];
+                let block_count = block.count.unwrap_or(0);
+                for (filename, line) in block.iter_lines() {
+                    if filename == source.filename || filename == UNKNOWN_SYMBOL {
+                        continue;
+                    }
+                    let entry = result.entry((source.filename, source.name, filename)).or_insert_with(|| InlinedLines {
+                        filename: source.filename,
+                        name: source.name,
+                        line: source.line,
+                        inlined_filename: filename,
+                        ..InlinedLines::default()
+                    });
+                    let count = entry.lines.entry(line).or_insert(0);
+                    *count = cmp::max(*count, block_count);
+                }
+            }
+        }
+
+        let mut result = result.into_iter().map(|(_, inlined_lines)| inlined_lines).collect::<Vec<_>>();
+        result.sort_by_key(|f| (f.filename, f.line, f.name, f.inlined_filename));
+        result
+    }
+
     /// Populates the report with information about a function.
     fn report_function(&self, function: &FunctionInfo, r: &mut Report) {
         let source = function.source.unwrap_or_default();
@@ -222,6 +439,18 @@ impl Graph {
             })
             .fold((0, 0, 0), tuple_3_add);
 
+        // The cyclomatic complexity is E - N + 2, which equals to 1 plus the number of extra outgoing arcs of every
+        // block. The fake arcs (to the exit block after calling a function which may not return) are not decisions
+        // made by the function itself, and are ignored.
+        let complexity = 1 + function
+            .nodes
+            .iter()
+            .map(|ni| {
+                let out_degree = self.graph.edges(*ni).filter(|er| !er.weight().attr.contains(ArcAttr::FAKE)).count();
+                out_degree.saturating_sub(1)
+            })
+            .sum::<usize>();
+
         let entry_count = self.graph[entry_block].count.unwrap_or(0);
         let mut exit_count = self.graph[exit_block].count.unwrap_or(0);
         exit_count -= self.graph
@@ -236,19 +465,23 @@ impl Graph {
             })
             .sum::<u64>();
 
+        let mut summary = report::FunctionSummary::default();
+        summary.blocks_count = blocks_count;
+        summary.blocks_executed = blocks_executed;
+        summary.entry_count = entry_count;
+        summary.exit_count = exit_count;
+        summary.branches_count = branches_count;
+        summary.branches_executed = branches_executed;
+        summary.branches_taken = branches_taken;
+        summary.complexity = complexity;
+
         let report_function = report::Function {
             name: source.name,
             line: source.line,
-            column: 0,
-            summary: report::FunctionSummary {
-                blocks_count,
-                blocks_executed,
-                entry_count,
-                exit_count,
-                branches_count,
-                branches_executed,
-                branches_taken,
-            },
+            column: source.column,
+            end_line: source.end_line,
+            end_column: source.end_column,
+            summary,
         };
         r.files.entry(source.filename).or_default_().functions.push(report_function);
     }
@@ -789,6 +1022,44 @@ impl Graph {
         }
         Ok(())
     }
+
+    /// Records the arc counts contributed by the GCDA currently being merged, if provenance tracking is enabled.
+    ///
+    /// The counts are attributed to every source file in the line records of the function, not only the file defining
+    /// it, so a GCDA reaching a file only through a function inlined from it is recorded as well.
+    fn record_provenance(&mut self, index: FunctionIndex, ac: &ArcCounts) {
+        let provenance = match self.provenance {
+            Some(ref mut provenance) => provenance,
+            None => return,
+        };
+        let function = match self.functions.get(index.0) {
+            Some(function) => function,
+            None => return,
+        };
+        let total = ac.counts.iter().sum::<u64>();
+        if total == 0 {
+            return;
+        }
+        let graph = &self.graph;
+        let mut filenames = function
+            .nodes
+            .iter()
+            .flat_map(|ni| graph[*ni].iter_lines().map(|(filename, _)| filename))
+            .chain(function.source.map(|source| source.filename))
+            .filter(|&filename| filename != UNKNOWN_SYMBOL)
+            .collect::<Vec<_>>();
+        filenames.sort();
+        filenames.dedup();
+
+        let gcda_index = provenance.sources.len() - 1;
+        for filename in filenames {
+            let counts = provenance.counts.entry(filename).or_insert_with(Vec::new);
+            match counts.last_mut() {
+                Some(&mut (i, ref mut count)) if i == gcda_index => *count += total,
+                _ => counts.push((gcda_index, total)),
+            }
+        }
+    }
 }
 
 //}}}
@@ -854,6 +1125,15 @@ impl<'a> Iterator for IterLines<'a> {
 //----------------------------------------------------------------------------------------------------------------------
 //{{{ Auxiliary structures
 
+/// Which GCDA files contributed counts to each source file.
+#[derive(Default, Debug, Clone)]
+struct Provenance {
+    /// Paths of the merged GCDA files.
+    sources: Vec<PathBuf>,
+    /// For each source file, the indices into `sources` and the sum of arc counts contributed by that GCDA.
+    counts: HashMap<Symbol, Vec<(usize, u64)>>,
+}
+
 /// Function information.
 #[derive(Default, Debug, Clone)]
 struct FunctionInfo {
@@ -884,6 +1164,75 @@ impl FunctionInfo {
     }
 }
 
+//}}}
+//----------------------------------------------------------------------------------------------------------------------
+//{{{ Explanation
+
+/// How the count of a basic block is computed, produced by [`Graph::explain_line()`].
+///
+/// [`Graph::explain_line()`]: ./struct.Graph.html#method.explain_line
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct BlockExplanation {
+    /// Name of the function containing the block.
+    pub function: Symbol,
+    /// Index of the block in the function.
+    pub block: usize,
+    /// The count of the block. `None` if it cannot be determined.
+    pub count: Option<u64>,
+    /// Attributes of the block.
+    pub attr: BlockAttr,
+    /// Arcs going into the block. The block count is the sum of their counts.
+    pub incoming: Vec<ArcExplanation>,
+    /// Arcs going out of the block.
+    pub outgoing: Vec<ArcExplanation>,
+}
+
+/// An arc adjacent to a block in a [`BlockExplanation`].
+///
+/// [`BlockExplanation`]: ./struct.BlockExplanation.html
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ArcExplanation {
+    /// Index of the block at the other end of the arc.
+    pub block: usize,
+    /// The count of the arc. `None` if it cannot be determined.
+    pub count: Option<u64>,
+    /// Attributes of the arc. Arcs without the [`ON_TREE`] flag are instrumented and their counts are read directly
+    /// from the GCDA files; the counts of the other arcs are derived during [analysis](./struct.Graph.html#method.analyze).
+    ///
+    /// [`ON_TREE`]: ../raw/struct.ArcAttr.html#associatedconstant.ON_TREE
+    pub attr: ArcAttr,
+}
+
+impl Graph {
+    /// Explains how the count of a source line is computed.
+    ///
+    /// Returns every basic block which covers the line. The line count in the [report](#method.report) is the maximum
+    /// count of these blocks. This is mainly intended for debugging.
+    pub fn explain_line(&self, filename: Symbol, line: u32) -> Vec<BlockExplanation> {
+        let explain_arc = |er: EdgeReference<ArcInfo>, block: NodeIndex| {
+            let arc = er.weight();
+            ArcExplanation {
+                block: self.graph[block].block,
+                count: arc.count,
+                attr: arc.attr,
+            }
+        };
+
+        self.graph
+            .node_references()
+            .filter(|&(_, block)| block.iter_lines().any(|l| l == (filename, line)))
+            .map(|(ni, block)| BlockExplanation {
+                function: self[block.index].source.map_or(UNKNOWN_SYMBOL, |s| s.name),
+                block: block.block,
+                count: block.count,
+                attr: block.attr,
+                incoming: self.graph.edges_directed(ni, Direction::Incoming).map(|er| explain_arc(er, er.source())).collect(),
+                outgoing: self.graph.edges(ni).map(|er| explain_arc(er, er.target())).collect(),
+            })
+            .collect()
+    }
+}
+
 //}}}
 //----------------------------------------------------------------------------------------------------------------------
 //{{{ Graphvis
@@ -986,6 +1335,39 @@ impl Graph {
         writeln!(writer, "}}")?;
         Ok(())
     }
+
+    /// Renders the graph as Graphvis `*.dot` format into a string. See [`write_dot()`](#method.write_dot) for the
+    /// meaning of `function_name`.
+    ///
+    /// Every basic block is labeled with its execution count (red if never executed, gray if unknown before
+    /// [analysis](#method.analyze)), its block number and source lines, and every arc with its count and attributes.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// extern crate cov;
+    /// use cov::{Gcov, Graph, Interner};
+    ///
+    /// # fn main() { run().unwrap(); }
+    /// # fn run() -> cov::Result<()> {
+    /// let mut interner = Interner::default();
+    /// let mut graph = Graph::default();
+    /// graph.merge(Gcov::open("test-data/branches.clang/x.gcno", &mut interner)?)?;
+    /// graph.merge(Gcov::open("test-data/branches.clang/x.gcda", &mut interner)?)?;
+    /// graph.analyze();
+    ///
+    /// let dot = graph.to_dot(interner.intern("main"));
+    /// assert!(dot.starts_with("digraph {"));
+    /// assert!(dot.contains("ENTRY"));
+    /// assert!(dot.contains(" -> "));
+    /// assert_eq!(graph.to_dot(interner.intern("no_such_function")), "digraph {\n\tnode[shape=plain]\n}\n");
+    /// # Ok(()) }
+    /// ```
+    pub fn to_dot(&self, function_name: Symbol) -> String {
+        let mut dot = Vec::new();
+        self.write_dot(function_name, &mut dot).expect("writing to a vector never fails");
+        String::from_utf8(dot).expect("the labels are ASCII")
+    }
 }
 
 //}}}
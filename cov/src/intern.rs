@@ -64,7 +64,7 @@ use shawshank::{self, ArenaSet};
 
 use std::borrow::Borrow;
 #[cfg(feature = "serde")]
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fmt;
 #[cfg(feature = "serde")]
 use std::hash::Hash;
@@ -224,7 +224,8 @@ pub struct Iter<'a> {
 impl<'a> Iterator for Iter<'a> {
     type Item = (Symbol, &'a str);
     fn next(&mut self) -> Option<Self::Item> {
-        if self.current_index >= self.interner.0.capacity() {
+        // Symbols are never disinterned, so the symbols in use are exactly `0..count`.
+        if self.current_index >= self.interner.0.count() {
             None
         } else {
             let symbol = Symbol(self.current_index);
@@ -353,6 +354,13 @@ impl<T: SerializeWithInterner> SerializeWithInterner for Vec<T> {
     }
 }
 
+#[cfg(feature = "serde")]
+impl<T: SerializeWithInterner + Ord> SerializeWithInterner for BTreeSet<T> {
+    fn serialize_with_interner<S: Serializer>(&self, serializer: S, interner: &Interner) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter().map(|value| value.with_interner(interner)))
+    }
+}
+
 #[cfg(feature = "serde")]
 impl<T: SerializeWithInterner> SerializeWithInterner for Option<T> {
     fn serialize_with_interner<S: Serializer>(&self, serializer: S, interner: &Interner) -> Result<S::Ok, S::Error> {
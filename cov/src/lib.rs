@@ -50,11 +50,25 @@
 //! # Ok(()) }
 //! ```
 //!
+//! ## Per-function coverage
+//!
+//! Tools which only need to know whether a piece of code is reached at all (e.g. mutation testers which skip mutants in
+//! uncovered code) may use [`Graph::function_lines()`] instead of the full report. It returns the covered and uncovered
+//! lines of every function, keyed by the function's filename and name. This interface is kept stable across releases.
+//!
+//! ## Warnings
+//!
+//! Oddities which do not prevent computing the coverage, such as a function without any basic block, are not errors.
+//! They are collected by [`Graph::warnings()`] while merging instead, see the [`warning`] module.
+//!
 //! [cfg]: https://en.wikipedia.org/wiki/Control_flow_graph
 //! [`Reader`]: ./reader/struct.Reader.html
 //! [`Gcov`]: ./raw/struct.Gcov.html
 //! [`Graph`]: ./graph/struct.Graph.html
 //! [`Report`]: ./report/struct.Report.html
+//! [`Graph::function_lines()`]: ./graph/struct.Graph.html#method.function_lines
+//! [`Graph::warnings()`]: ./graph/struct.Graph.html#method.warnings
+//! [`warning`]: ./warning/index.html
 
 #![recursion_limit = "128"] // needed for error_chain.
 
@@ -77,6 +91,7 @@ extern crate petgraph;
 extern crate fixedbitset;
 extern crate num_traits; // required for shawshank
 extern crate shawshank;
+extern crate cov_types;
 
 #[macro_use]
 pub mod intern;
@@ -88,6 +103,7 @@ pub mod raw;
 pub mod reader;
 pub mod graph;
 pub mod report;
+pub mod warning;
 
 #[cfg(feature = "serde")]
 pub use deserializer::with_interner as deserializer_with_interner;
@@ -99,3 +115,4 @@ pub use intern::SerializeWithInterner;
 pub use raw::Gcov;
 pub use report::Report;
 pub use utils::IntoStringLossy;
+pub use warning::Warning;
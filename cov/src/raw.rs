@@ -13,7 +13,7 @@ use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::{fmt, u64};
 use std::collections::BTreeMap;
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{BufReader, Read};
 use std::path::{Path, PathBuf};
 #[cfg(feature = "serde")]
 use std::result::Result as StdResult;
@@ -50,17 +50,30 @@ impl Gcov {
     /// * Returns [`UnknownFileType`] if the file is not a in GCNO/GCDA format.
     /// * Returns [`UnsupportedVersion`] if the GCNO/GCDA version is not supported by this crate.
     /// * Returns [`UnknownTag`] if the GCNO/GCDA contains an unrecognized record tag.
+    /// * Returns [`LimitExceeded`] if the GCNO/GCDA exceeds the [limits](../reader/index.html#limits) of the parser.
     /// * Returns [`Io`] on I/O failure.
     ///
     /// [`UnknownFileType`]: ../error/enum.ErrorKind.html#variant.UnknownFileType
     /// [`UnsupportedVersion`]: ../error/enum.ErrorKind.html#variant.UnsupportedVersion
     /// [`UnknownTag`]: ../error/enum.ErrorKind.html#variant.UnknownTag
+    /// [`LimitExceeded`]: ../error/enum.ErrorKind.html#variant.LimitExceeded
     /// [`Io`]: ../error/enum.ErrorKind.html#variant.Io
     pub fn open<P: AsRef<Path>>(p: P, interner: &mut Interner) -> Result<Gcov> {
         debug!("open gcov file {:?}", p.as_ref());
         let src = p.as_ref().to_owned();
+        let file = Location::File(src.clone()).wrap(|| File::open(p))?;
+        Gcov::read(BufReader::new(file), src, interner)
+    }
+
+    /// Parses the GCNO/GCDA content from a reader, e.g. a member of an archive. The `src` path is recorded as where the
+    /// content comes from.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`open()`](#method.open).
+    pub fn read<R: Read>(reader: R, src: PathBuf, interner: &mut Interner) -> Result<Gcov> {
+        debug!("read gcov file {:?}", src);
         Location::File(src.clone()).wrap(|| -> Result<Gcov> {
-            let reader = BufReader::new(File::open(p)?);
             let mut gcov = Reader::new(reader, interner)?.parse()?;
             gcov.src = Some(src);
             Ok(gcov)
@@ -163,6 +176,29 @@ pub const INVALID_VERSION: Version = Version(0);
 /// Starting from this version the gcov format is modified in an incompatible way.
 pub const VERSION_4_7: Version = Version(0x34_30_37_2a);
 
+/// GCNO/GCDA version targeting gcc 8.
+///
+/// Starting from this version the functions in a GCNO record their columns and end line, and the `BASIC_BLOCK` record
+/// only contains the number of blocks.
+pub const VERSION_8: Version = Version(0x41_38_30_2a);
+
+/// GCNO/GCDA version targeting gcc 9.
+///
+/// Starting from this version a GCNO records the working directory of the compiler, and the `SUMMARY` record of a GCDA
+/// only contains the number of runs and the maximum count.
+pub const VERSION_9: Version = Version(0x41_39_30_2a);
+
+/// GCNO/GCDA version targeting gcc 10.
+///
+/// Starting from this version the functions in a GCNO also record their end column.
+pub const VERSION_10: Version = Version(0x42_30_30_2a);
+
+/// GCNO/GCDA version targeting gcc 12.
+///
+/// Starting from this version the lengths of records and strings are counted in bytes instead of 4-byte words, and the
+/// header contains a checksum after the stamp.
+pub const VERSION_12: Version = Version(0x42_32_30_2a);
+
 impl Version {
     /// Converts a raw version number to a `Version` structure.
     ///
@@ -307,6 +343,15 @@ derive_serialize_with_interner! {
         pub filename: Symbol,
         /// Line number
         pub line: u32,
+        /// Column number where the function starts, or 0 if unknown. Only recorded since gcc 8.
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub column: u32,
+        /// Line number where the function ends, or 0 if unknown. Only recorded since gcc 8.
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub end_line: u32,
+        /// Column number where the function ends (inclusive), or 0 if unknown. Only recorded since gcc 10.
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub end_column: u32,
     }
 }
 
@@ -2,8 +2,27 @@
 //!
 //! The file format of GCNO/GCDA is documented in the [GCC source code][gcov-io.h].
 //!
+//! # Limits
+//!
+//! The lengths in a corrupt file could ask the parser to allocate gigabytes of memory, and absurd counts could overflow
+//! the arithmetic when analyzing the graph. The reader therefore rejects a file exceeding any of [`MAX_RECORD_LENGTH`],
+//! [`MAX_STRING_LENGTH`], [`MAX_BLOCKS`], [`MAX_ARCS`] or [`MAX_COUNT`] with [`LimitExceeded`]. The limits are far above
+//! anything a compiler emits.
+//!
+//! The parser is fuzzed by the `gcov_open` target in the `fuzz/` directory of this crate (requires `cargo-fuzz`):
+//!
+//! ```sh
+//! cargo fuzz run gcov_open
+//! ```
+//!
 //! [`Gcov`]: ../raw/struct.Gcov.html
 //! [gcov-io.h]: https://gcc.gnu.org/git/?p=gcc.git;a=blob;f=gcc/gcov-io.h;hb=HEAD
+//! [`MAX_RECORD_LENGTH`]: ./constant.MAX_RECORD_LENGTH.html
+//! [`MAX_STRING_LENGTH`]: ./constant.MAX_STRING_LENGTH.html
+//! [`MAX_BLOCKS`]: ./constant.MAX_BLOCKS.html
+//! [`MAX_ARCS`]: ./constant.MAX_ARCS.html
+//! [`MAX_COUNT`]: ./constant.MAX_COUNT.html
+//! [`LimitExceeded`]: ../error/enum.ErrorKind.html#variant.LimitExceeded
 
 use error::*;
 use intern::{Interner, Symbol, UNKNOWN_SYMBOL};
@@ -12,10 +31,28 @@ use utils::IntoStringLossy;
 
 use byteorder::{BigEndian, ByteOrder, LittleEndian, ReadBytesExt};
 
+use std::cmp;
 use std::io::{self, Read, Take};
 use std::iter::FromIterator;
 use std::result::Result as StdResult;
 
+/// The maximum length of a record in bytes.
+pub const MAX_RECORD_LENGTH: u64 = 256 << 20;
+
+/// The maximum length of a string (e.g. a function name or a path) in bytes.
+pub const MAX_STRING_LENGTH: u64 = 1 << 20;
+
+/// The maximum number of basic blocks in a function. Since gcc 8 only the number is recorded, so it is not bounded by the
+/// record length.
+pub const MAX_BLOCKS: u64 = 1 << 22;
+
+/// The maximum number of arc counts in a function. Since gcc 12 the counts which are all zero are only recorded by their
+/// total length, so it is not bounded by the actual record length.
+pub const MAX_ARCS: u64 = 1 << 22;
+
+/// The maximum execution count of an arc, leaving enough headroom to sum the counts of many arcs without overflow.
+pub const MAX_COUNT: u64 = 1 << 48;
+
 /// The reader of a GCNO/GCDA file.
 ///
 /// # Examples
@@ -46,6 +83,8 @@ pub struct Reader<'si, R> {
     version: Version,
     stamp: u32,
     is_big_endian: bool,
+    /// The length of the counters omitted from this record because they are all zero (gcc 12+).
+    zero_length: u64,
     interner: &'si mut Interner,
 }
 
@@ -84,6 +123,41 @@ fn test_consume_to_end() {
     })().unwrap();
 }
 
+#[test]
+fn test_limits() {
+    fn parse(bytes: &[u8]) -> Result<Gcov> {
+        let mut interner = Interner::new();
+        Reader::new(bytes, &mut interner)?.parse()
+    }
+    fn assert_limit_exceeded(result: Result<Gcov>, expected_kind: &str) {
+        match result.map_err(|e| e.0) {
+            Err(ErrorKind::LimitExceeded(kind, _, _)) => assert_eq!(kind, expected_kind),
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    // magic, version 4.2 and stamp of a GCNO from clang.
+    let header = b"oncg*204J\xc8\xfeB";
+
+    let mut huge_record = header.to_vec();
+    huge_record.extend_from_slice(b"\0\0\0\x01\xff\xff\xff\xff");
+    assert_limit_exceeded(parse(&huge_record), "record length");
+
+    let mut huge_string = header.to_vec();
+    huge_string.extend_from_slice(b"\0\0\0\x01\x03\0\0\0\x01\0\0\0\x02\0\0\0\0\0\0\x40");
+    assert_limit_exceeded(parse(&huge_string), "string length");
+
+    // magic, version 8 and stamp of a GCNO from gcc, and the "has unexecuted blocks" flag.
+    let mut huge_blocks = b"oncg*08A\0\0\0\0\0\0\0\0".to_vec();
+    huge_blocks.extend_from_slice(b"\0\0\x41\x01\x01\0\0\0\xff\xff\xff\xff");
+    assert_limit_exceeded(parse(&huge_blocks), "block count");
+
+    // magic, version 12, stamp and checksum of a GCDA from gcc, followed by 256 MiB of zero counts.
+    let mut huge_zero_counts = b"adcg*02B\0\0\0\0\0\0\0\0".to_vec();
+    huge_zero_counts.extend_from_slice(b"\0\0\xa1\x01\0\0\0\xf0");
+    assert_limit_exceeded(parse(&huge_zero_counts), "arc count number");
+}
+
 impl<'si, R: Read> Reader<'si, R> {
     /// Advances the reader cursor by `count` bytes. If `res` is an error, include the file position information to the
     /// error, otherwise return `res` as-is.
@@ -156,11 +230,17 @@ impl<'si, R: Read> Reader<'si, R> {
     ///
     /// # Errors
     ///
+    /// * Returns [`LimitExceeded`] if the string is longer than [`MAX_STRING_LENGTH`].
     /// * Returns [`Io`] on I/O failure, e.g. reaching end-of-file.
     ///
+    /// [`LimitExceeded`]: ../error/enum.ErrorKind.html#variant.LimitExceeded
+    /// [`MAX_STRING_LENGTH`]: ./constant.MAX_STRING_LENGTH.html
     /// [`Io`]: ../error/enum.ErrorKind.html#variant.Io
     fn read_string(&mut self) -> Result<Symbol> {
-        let length = (self.read_32()? as u64) * 4;
+        let length = self.read_length()?;
+        if length > MAX_STRING_LENGTH {
+            bail!(Location::Cursor(self.cursor - 4).wrap_error(ErrorKind::LimitExceeded("string length", MAX_STRING_LENGTH, length)));
+        }
         let mut buf = Vec::with_capacity(length as usize);
         let value = self.reader.by_ref().take(length).read_to_end(&mut buf);
         let _ = self.advance_cursor(length, value)?;
@@ -170,6 +250,18 @@ impl<'si, R: Read> Reader<'si, R> {
         Ok(self.interner.intern(string))
     }
 
+    /// Reads the length of a record or a string in bytes. The length is counted in 4-byte words before gcc 12.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Io`] on I/O failure, e.g. reaching end-of-file.
+    ///
+    /// [`Io`]: ../error/enum.ErrorKind.html#variant.Io
+    fn read_length(&mut self) -> Result<u64> {
+        let length = u64::from(self.read_32()?);
+        Ok(if self.version >= VERSION_12 { length } else { length * 4 })
+    }
+
     /// Reads something from this reader using the provided function `f`, until end-of-file is encountered.
     ///
     /// The result is a collection of returned values of `f`.
@@ -209,6 +301,7 @@ impl<'si, R: Read> Reader<'si, R> {
             cursor: 4,
             version: INVALID_VERSION,
             stamp: 0,
+            zero_length: 0,
         };
         trace!("gcov-version @ 0x{:x}", result.cursor);
         let version = result.read_32()?;
@@ -216,9 +309,31 @@ impl<'si, R: Read> Reader<'si, R> {
         result.version = version;
         trace!("gcov-stamp @ 0x{:x}", result.cursor);
         result.stamp = result.read_32()?;
+        if version >= VERSION_12 {
+            trace!("gcov-checksum @ 0x{:x}", result.cursor);
+            result.read_32()?;
+        }
+        if ty == Type::Gcno && version >= VERSION_9 {
+            trace!("gcov-cwd @ 0x{:x}", result.cursor);
+            result.read_string()?;
+        }
+        if ty == Type::Gcno && version >= VERSION_8 {
+            trace!("gcov-has-unexecuted-blocks @ 0x{:x}", result.cursor);
+            result.read_32()?;
+        }
         Ok(result)
     }
 
+    /// The file type read from the header.
+    pub fn file_type(&self) -> Type {
+        self.ty
+    }
+
+    /// The stamp read from the header. A GCDA has the same stamp as the GCNO it is produced from.
+    pub fn stamp(&self) -> u32 {
+        self.stamp
+    }
+
     /// Parses the content of the reader, to produce a [`Gcov`] structure.
     ///
     /// # Errors
@@ -262,14 +377,27 @@ impl<'si, R: Read> Reader<'si, R> {
     ///
     /// # Errors
     ///
+    /// * Returns [`LimitExceeded`] if the record is longer than [`MAX_RECORD_LENGTH`].
     /// * Returns [`Io`] on I/O failure.
     ///
+    /// [`LimitExceeded`]: ../error/enum.ErrorKind.html#variant.LimitExceeded
+    /// [`MAX_RECORD_LENGTH`]: ./constant.MAX_RECORD_LENGTH.html
     /// [`Io`]: ../error/enum.ErrorKind.html#variant.Io
     fn read_record_header(&mut self) -> Result<(Tag, Reader<Take<&mut R>>)> {
         trace!("record-tag @ 0x{:x}", self.cursor);
         let tag = Tag(self.read_32()?);
         trace!("record-length @ 0x{:x}", self.cursor);
-        let length = (self.read_32()? as u64) * 4;
+        let mut length = self.read_length()?;
+        // Since gcc 12, counters which are all zero are omitted, and the length is written as a negative number.
+        let mut zero_length = 0;
+        if self.version >= VERSION_12 && tag == COUNTER_BASE_TAG && length > 0x8000_0000 {
+            zero_length = 0x1_0000_0000 - length;
+            length = 0;
+        }
+        if length > MAX_RECORD_LENGTH || zero_length > MAX_RECORD_LENGTH {
+            let length = cmp::max(length, zero_length);
+            bail!(Location::Cursor(self.cursor - 4).wrap_error(ErrorKind::LimitExceeded("record length", MAX_RECORD_LENGTH, length)));
+        }
         let subreader = Reader {
             reader: self.reader.by_ref().take(length),
             cursor: self.cursor,
@@ -277,6 +405,7 @@ impl<'si, R: Read> Reader<'si, R> {
             version: self.version,
             stamp: self.stamp,
             is_big_endian: self.is_big_endian,
+            zero_length,
             interner: self.interner,
         };
         debug!(
@@ -317,7 +446,7 @@ impl<'si, R: Read> Reader<'si, R> {
             Some(Source {
                 name,
                 filename: UNKNOWN_SYMBOL,
-                line: 0,
+                ..Source::default()
             })
         } else {
             None
@@ -345,25 +474,54 @@ impl<'si, R: Read> Reader<'si, R> {
     fn read_source(&mut self) -> Result<Source> {
         trace!("source-name @ 0x{:x}", self.cursor);
         let name = self.read_string()?;
+        if self.version >= VERSION_8 {
+            trace!("source-artificial @ 0x{:x}", self.cursor);
+            self.read_32()?;
+        }
         trace!("source-filename @ 0x{:x}", self.cursor);
         let filename = self.read_string()?;
         trace!("source-line @ 0x{:x}", self.cursor);
         let line = self.read_32()?;
-        Ok(Source {
+        let mut source = Source {
             name,
             filename,
             line,
-        })
+            ..Source::default()
+        };
+        if self.version >= VERSION_8 {
+            trace!("source-column @ 0x{:x}", self.cursor);
+            source.column = self.read_32()?;
+            trace!("source-end-line @ 0x{:x}", self.cursor);
+            source.end_line = self.read_32()?;
+        }
+        if self.version >= VERSION_10 {
+            trace!("source-end-column @ 0x{:x}", self.cursor);
+            source.end_column = self.read_32()?;
+        }
+        Ok(source)
     }
 
     /// Parses the `BASIC_BLOCK` record.
     ///
     /// # Errors
     ///
+    /// * Returns [`LimitExceeded`] if the number of blocks exceeds [`MAX_BLOCKS`].
     /// * Returns [`Io`] on I/O failure.
     ///
+    /// [`LimitExceeded`]: ../error/enum.ErrorKind.html#variant.LimitExceeded
+    /// [`MAX_BLOCKS`]: ./constant.MAX_BLOCKS.html
     /// [`Io`]: ../error/enum.ErrorKind.html#variant.Io
     fn parse_blocks(&mut self) -> Result<Blocks> {
+        if self.version >= VERSION_8 {
+            trace!("blocks-count @ 0x{:x}", self.cursor);
+            let count = self.read_32()?;
+            if u64::from(count) > MAX_BLOCKS {
+                bail!(Location::Cursor(self.cursor - 4).wrap_error(ErrorKind::LimitExceeded("block count", MAX_BLOCKS, u64::from(count))));
+            }
+            return Ok(Blocks {
+                flags: vec![BlockAttr::empty(); count as usize],
+            });
+        }
         trace!("blocks-flags @ 0x{:x}", self.cursor);
         let flags = self.until_eof(|s| {
             let raw_flag = s.read_32()?;
@@ -428,12 +586,32 @@ impl<'si, R: Read> Reader<'si, R> {
     ///
     /// # Errors
     ///
+    /// * Returns [`LimitExceeded`] if any count exceeds [`MAX_COUNT`], or the number of counts omitted since gcc 12
+    ///     exceeds [`MAX_ARCS`].
     /// * Returns [`Io`] on I/O failure.
     ///
+    /// [`LimitExceeded`]: ../error/enum.ErrorKind.html#variant.LimitExceeded
+    /// [`MAX_COUNT`]: ./constant.MAX_COUNT.html
+    /// [`MAX_ARCS`]: ./constant.MAX_ARCS.html
     /// [`Io`]: ../error/enum.ErrorKind.html#variant.Io
     fn parse_arc_counts(&mut self) -> Result<ArcCounts> {
+        if self.zero_length > 0 {
+            let count = self.zero_length / 8;
+            if count > MAX_ARCS {
+                bail!(Location::Cursor(self.cursor).wrap_error(ErrorKind::LimitExceeded("arc count number", MAX_ARCS, count)));
+            }
+            return Ok(ArcCounts {
+                counts: vec![0; count as usize],
+            });
+        }
         trace!("arc-counts-counts @ 0x{:x}", self.cursor);
-        let counts = self.until_eof(Self::read_64)?;
+        let counts = self.until_eof(|s| {
+            let count = s.read_64()?;
+            if count > MAX_COUNT {
+                bail!(Location::Cursor(s.cursor - 8).wrap_error(ErrorKind::LimitExceeded("arc count", MAX_COUNT, count)));
+            }
+            Ok(count)
+        })?;
         Ok(ArcCounts { counts })
     }
 
@@ -445,6 +623,17 @@ impl<'si, R: Read> Reader<'si, R> {
     ///
     /// [`Io`]: ../error/enum.ErrorKind.html#variant.Io
     fn parse_summary(&mut self) -> Result<Summary> {
+        if self.version >= VERSION_9 {
+            trace!("summary-runs @ 0x{:x}", self.cursor);
+            let runs = self.read_32()?;
+            trace!("summary-sum-max @ 0x{:x}", self.cursor);
+            let sum_max = u64::from(self.read_32()?);
+            return Ok(Summary {
+                runs,
+                sum_max,
+                ..Summary::default()
+            });
+        }
         trace!("summary-checksum @ 0x{:x}", self.cursor);
         let checksum = self.read_32()?;
         trace!("summary-num @ 0x{:x}", self.cursor);
@@ -4,18 +4,30 @@
 //! be easily serialized via serde for human-readable report generation, or transformation to other format consumed by
 //! external services.
 //!
+//! The names in the report are [`Symbol`]s of the [`Interner`] used when parsing. [`Report::resolve()`] converts the
+//! report into the model of the `cov-types` crate, where the names are plain strings, for tools which do not want to
+//! depend on the parser. The [`FileSummary`] and [`FunctionSummary`] are defined in `cov-types` and re-exported here.
+//!
 //! [`Report`]: ./struct.Report.html
+//! [`Report::resolve()`]: ./struct.Report.html#method.resolve
+//! [`Symbol`]: ../intern/struct.Symbol.html
+//! [`Interner`]: ../intern/struct.Interner.html
+//! [`FileSummary`]: ./struct.FileSummary.html
+//! [`FunctionSummary`]: ./struct.FunctionSummary.html
 
 #[cfg(feature = "serde")]
-use intern::{Interner, SerializeWithInterner};
-use intern::Symbol;
+use intern::SerializeWithInterner;
+use intern::{Interner, Symbol};
 use raw::{ArcAttr, BlockAttr};
 use utils::tuple_4_add;
 
 #[cfg(feature = "serde")]
 use serde::{Serialize, Serializer};
 
-use std::collections::{BTreeMap, HashMap};
+use cov_types;
+pub use cov_types::{FileSummary, FunctionSummary};
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
 derive_serialize_with_interner! {
     /// A coverage report, generated from a [`Graph`].
@@ -42,7 +54,72 @@ derive_serialize_with_interner! {
     }
 }
 
+impl Report {
+    /// Resolves the symbols using the `interner`, and converts this report into the interner-free model of the
+    /// `cov-types` crate, which tools can depend on without the parser.
+    ///
+    /// ```rust
+    /// use cov::{Gcov, Graph, Interner};
+    ///
+    /// # fn main() { run().unwrap(); }
+    /// # fn run() -> cov::Result<()> {
+    /// let mut interner = Interner::default();
+    /// let mut graph = Graph::default();
+    /// graph.merge(Gcov::open("test-data/trivial.clang/x.gcno", &mut interner)?)?;
+    /// graph.merge(Gcov::open("test-data/trivial.clang/x.gcda", &mut interner)?)?;
+    /// graph.analyze();
+    ///
+    /// let report = graph.report();
+    /// let resolved = report.resolve(&interner);
+    /// for (filename, file) in &report.files {
+    ///     assert_eq!(resolved.files[&interner[*filename]].summary(), file.summary());
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub fn resolve(&self, interner: &Interner) -> cov_types::Report {
+        let files = self.files.iter().map(|(filename, file)| (interner[*filename].to_owned(), file.resolve(interner)));
+        // The model is non-exhaustive, so it is filled into a default value instead of a struct expression.
+        let mut report = cov_types::Report::default();
+        report.files = files.collect();
+        report
+    }
+}
+
 impl File {
+    /// Resolves the symbols using the `interner`, see [`Report::resolve()`].
+    ///
+    /// [`Report::resolve()`]: ./struct.Report.html#method.resolve
+    pub fn resolve(&self, interner: &Interner) -> cov_types::report::File {
+        let lines = self.lines.iter().map(|(&line_number, line)| {
+            let branches = line.branches.iter().map(|branch| {
+                let mut resolved = cov_types::report::Branch::default();
+                resolved.count = branch.count;
+                resolved.filename = interner[branch.filename].to_owned();
+                resolved.line = branch.line;
+                resolved.column = branch.column;
+                resolved
+            });
+            let mut resolved = cov_types::report::Line::default();
+            resolved.count = line.count;
+            resolved.branches = branches.collect();
+            (line_number, resolved)
+        });
+        let functions = self.functions.iter().map(|function| {
+            let mut resolved = cov_types::report::Function::default();
+            resolved.name = interner[function.name].to_owned();
+            resolved.line = function.line;
+            resolved.column = function.column;
+            resolved.end_line = function.end_line;
+            resolved.end_column = function.end_column;
+            resolved.summary = function.summary;
+            resolved
+        });
+        let mut file = cov_types::report::File::default();
+        file.lines = lines.collect();
+        file.functions = functions.collect();
+        file
+    }
+
     /// Produces a summary of the current file.
     pub fn summary(&self) -> FileSummary {
         let lines_count = self.lines.len();
@@ -55,15 +132,15 @@ impl File {
                 (s.branches_count, s.branches_executed, s.branches_taken, (s.entry_count > 0) as usize)
             })
             .fold((0, 0, 0, 0), tuple_4_add);
-        FileSummary {
-            lines_count,
-            lines_covered,
-            branches_count,
-            branches_executed,
-            branches_taken,
-            functions_count,
-            functions_called,
-        }
+        let mut summary = FileSummary::default();
+        summary.lines_count = lines_count;
+        summary.lines_covered = lines_covered;
+        summary.branches_count = branches_count;
+        summary.branches_executed = branches_executed;
+        summary.branches_taken = branches_taken;
+        summary.functions_count = functions_count;
+        summary.functions_called = functions_called;
+        summary
     }
 }
 
@@ -121,61 +198,72 @@ derive_serialize_with_interner! {
         /// The column number where this function is defined. Zero if missing.
         pub column: u32,
 
+        /// The line number where this function ends. Zero if missing.
+        #[cfg_attr(feature="serde", serde(default))]
+        pub end_line: u32,
+
+        /// The column number where this function ends (inclusive). Zero if missing.
+        #[cfg_attr(feature="serde", serde(default))]
+        pub end_column: u32,
+
         /// Summary about this function.
         pub summary: FunctionSummary,
     }
 }
 
-/// Statistical summary of a function.
-#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
-#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-pub struct FunctionSummary {
-    /// Number of basic blocks in the function (excluding the enter- and exit-blocks).
-    pub blocks_count: usize,
-
-    /// Number of basic blocks that has been executed (having non-zero count).
-    pub blocks_executed: usize,
-
-    /// How many times the function is called.
-    pub entry_count: u64,
+derive_serialize_with_interner! {
+    /// The covered and uncovered lines of a function, produced by [`Graph::function_lines()`].
+    ///
+    /// A function is identified by its `filename` and `name` (which is usually mangled). This structure is a supported
+    /// interface for tools which only need coarse, per-function information, e.g. mutation testers which want to skip
+    /// mutants in code never executed by the test suite.
+    ///
+    /// [`Graph::function_lines()`]: ../graph/struct.Graph.html#method.function_lines
+    #[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+    #[cfg_attr(feature="serde", derive(Serialize, Deserialize))]
+    pub struct FunctionLines {
+        /// The source file where this function is defined.
+        pub filename: Symbol,
 
-    /// How many times the function has returned.
-    pub exit_count: u64,
+        /// Name of the function.
+        pub name: Symbol,
 
-    /// Number of conditional branches in the function.
-    pub branches_count: usize,
+        /// The line number where this function is defined. Zero if missing.
+        pub line: u32,
 
-    /// Number of conditional basic blocks that has been executed.
-    pub branches_executed: usize,
+        /// Lines in `filename` belonging to this function which have been executed at least once.
+        pub covered: BTreeSet<u32>,
 
-    /// Number of branches that has been taken.
-    pub branches_taken: usize,
+        /// Lines in `filename` belonging to this function which have never been executed.
+        pub uncovered: BTreeSet<u32>,
+    }
 }
 
-/// Statistical summary of a file.
-#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
-#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-pub struct FileSummary {
-    /// Number of lines that can be profiled.
-    pub lines_count: usize,
-
-    /// Number of lines that has been covered.
-    pub lines_covered: usize,
-
-    /// Number of conditional branches in functions defined in this file.
-    pub branches_count: usize,
+derive_serialize_with_interner! {
+    /// The lines of another source file inlined into a function, produced by [`Graph::inlined_lines()`].
+    ///
+    /// For instance, when a local function calls `Vec::push()` which gets inlined, the lines of `liballoc/vec.rs` appear
+    /// in the local function. This structure attributes their execution to the local function.
+    ///
+    /// [`Graph::inlined_lines()`]: ../graph/struct.Graph.html#method.inlined_lines
+    #[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+    #[cfg_attr(feature="serde", derive(Serialize, Deserialize))]
+    pub struct InlinedLines {
+        /// The source file where the calling function is defined.
+        pub filename: Symbol,
 
-    /// Number of conditional basic blocks that has been executed.
-    pub branches_executed: usize,
+        /// Name of the calling function.
+        pub name: Symbol,
 
-    /// Number of branches that has been taken.
-    pub branches_taken: usize,
+        /// The line number where the calling function is defined. Zero if missing.
+        pub line: u32,
 
-    /// Number of functions defined in this file.
-    pub functions_count: usize,
+        /// The source file of the inlined code.
+        pub inlined_filename: Symbol,
 
-    /// Number of functions that has been called.
-    pub functions_called: usize,
+        /// Execution count of every line in `inlined_filename` inlined into the calling function.
+        pub lines: BTreeMap<u32, u64>,
+    }
 }
 
 derive_serialize_with_interner! {
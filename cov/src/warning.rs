@@ -0,0 +1,148 @@
+//! Recoverable anomalies in a GCNO/GCDA file.
+//!
+//! The parser rejects a file it cannot make sense of with an [error], but some oddities are still accepted since the
+//! coverage can be computed anyway, e.g. a function without any basic block. These are reported as [`Warning`]s, so a
+//! tool may point out a broken build or a corrupt file instead of silently producing a wrong report.
+//!
+//! The warnings of a single file are found by [`Gcov::warnings()`], and those of every file merged into a graph are
+//! collected by [`Graph::warnings()`].
+//!
+//! [error]: ../error/enum.ErrorKind.html
+//! [`Warning`]: ./struct.Warning.html
+//! [`Gcov::warnings()`]: ../raw/struct.Gcov.html#method.warnings
+//! [`Graph::warnings()`]: ../graph/struct.Graph.html#method.warnings
+
+use intern::{Interner, Symbol};
+use raw::{Gcov, Ident, Record, Type};
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+/// The kind of a [`Warning`](./struct.Warning.html).
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum WarningKind {
+    /// The stamp of the file is 0. Compilers never write it, so the file is probably truncated or written by hand, and
+    /// the GCDA cannot be reliably matched with its GCNO.
+    ZeroStamp,
+    /// The function identifier is announced twice in the same file. The counts of a GCDA are then added twice to the
+    /// same function.
+    DuplicatedIdent(Ident),
+    /// The function with this identifier and name has no basic blocks in the GCNO, and thus no lines to report.
+    EmptyFunction(Ident, Symbol),
+}
+
+/// A recoverable anomaly found in a GCNO/GCDA file.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Warning {
+    /// Source of the file, see [`Gcov::src`](../raw/struct.Gcov.html#structfield.src).
+    pub src: Option<PathBuf>,
+    /// What is wrong with the file.
+    pub kind: WarningKind,
+}
+
+impl Warning {
+    /// Describes the warning in a human-readable message, resolving the function names with the `interner`.
+    pub fn describe(&self, interner: &Interner) -> String {
+        let message = match self.kind {
+            WarningKind::ZeroStamp => "the stamp is 0".to_owned(),
+            WarningKind::DuplicatedIdent(ident) => format!("function #{} is announced more than once", ident),
+            WarningKind::EmptyFunction(ident, name) => format!("function #{} `{}` has no basic blocks", ident, &interner[name]),
+        };
+        match self.src {
+            Some(ref src) => format!("{}: {}", src.display(), message),
+            None => message,
+        }
+    }
+}
+
+impl Gcov {
+    /// Finds the recoverable anomalies in the file, see the [module documentation](../warning/index.html).
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use cov::{Gcov, Interner};
+    /// # use cov::Result;
+    ///
+    /// # fn main() { run().unwrap(); }
+    /// # fn run() -> Result<()> {
+    /// let mut interner = Interner::new();
+    /// let gcov = Gcov::open("test-data/trivial.clang/x.gcno", &mut interner)?;
+    /// assert!(gcov.warnings().is_empty());
+    /// # Ok(()) }
+    /// ```
+    pub fn warnings(&self) -> Vec<Warning> {
+        fn check_empty(last_function: Option<(Ident, Symbol, bool)>, kinds: &mut Vec<WarningKind>) {
+            if let Some((ident, name, false)) = last_function {
+                kinds.push(WarningKind::EmptyFunction(ident, name));
+            }
+        }
+
+        let mut kinds = Vec::new();
+        if self.stamp == 0 {
+            kinds.push(WarningKind::ZeroStamp);
+        }
+
+        let mut idents = HashSet::new();
+        // The function announced last, and whether it has any basic blocks so far.
+        let mut last_function = None;
+        for record in &self.records {
+            match *record {
+                Record::Function(ident, ref function) => {
+                    if !idents.insert(ident) {
+                        kinds.push(WarningKind::DuplicatedIdent(ident));
+                    }
+                    if self.ty == Type::Gcno {
+                        check_empty(last_function, &mut kinds);
+                        last_function = Some((ident, function.source.map(|source| source.name).unwrap_or_default(), false));
+                    }
+                },
+                Record::Blocks(ref blocks) => {
+                    if let Some((_, _, ref mut has_blocks)) = last_function {
+                        *has_blocks |= !blocks.flags.is_empty();
+                    }
+                },
+                _ => {},
+            }
+        }
+        check_empty(last_function, &mut kinds);
+
+        kinds.into_iter().map(|kind| Warning { src: self.src.clone(), kind }).collect()
+    }
+}
+
+#[test]
+fn test_warnings() {
+    use raw::{Blocks, Function, Source, Version};
+
+    let mut interner = Interner::new();
+    let name = interner.intern("main");
+    let function = Function {
+        source: Some(Source { name, ..Source::default() }),
+        ..Function::default()
+    };
+    let gcov = Gcov {
+        ty: Type::Gcno,
+        version: Version::default(),
+        stamp: 0,
+        records: vec![
+            Record::Function(Ident(1), function),
+            Record::Blocks(Blocks { flags: Vec::new() }),
+            Record::Function(Ident(2), function),
+            Record::Function(Ident(1), function),
+        ],
+        src: Some(PathBuf::from("x.gcno")),
+    };
+    let kinds = gcov.warnings().into_iter().map(|warning| warning.kind).collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        vec![
+            WarningKind::ZeroStamp,
+            WarningKind::EmptyFunction(Ident(1), name),
+            WarningKind::DuplicatedIdent(Ident(1)),
+            WarningKind::EmptyFunction(Ident(2), name),
+            WarningKind::EmptyFunction(Ident(1), name),
+        ]
+    );
+    assert_eq!(gcov.warnings()[1].describe(&interner), "x.gcno: function #1 `main` has no basic blocks");
+}
@@ -15,6 +15,11 @@ BUILDERS = {
         cmd=['g++-7', '--std=c++14', '--coverage'],
         gcov='gcov-7'
     ),
+    '.gcc12': Builder(
+        ext='.cpp',
+        cmd=['g++-12', '--std=c++14', '--coverage'],
+        gcov='gcov-12'
+    ),
     '.clang': Builder(
         ext='.cpp',
         cmd=['clang++', '--std=c++14', '--coverage'],
@@ -26,6 +26,8 @@
 //! | `python3`  | Python 3.5+           |
 //! | `g++-7`    | GCC 7.1               |
 //! | `gcov-7`   | GCC 7.1               |
+//! | `g++-12`   | GCC 12.2              |
+//! | `gcov-12`  | GCC 12.2              |
 //! | `clang++`  | clang 3.4+            |
 //! | `gcov`     | LLVM 3.4+ or GCC 4.6- |
 //! | `rustc`    | Rust nightly          |
@@ -36,7 +38,8 @@
 //! ### Creating a new test
 //!
 //! 1. Write the new source code in `test-data/src`. Must be a single file, of the form `filename.cpp` or `filename.rs`.
-//! 2. Create the folder `test-data/filename.clang/`, `test-data/filename.gcc7/` or `test-data/filename.rustc/`.
+//! 2. Create the folder `test-data/filename.clang/`, `test-data/filename.gcc7/`, `test-data/filename.gcc12/` or
+//!    `test-data/filename.rustc/`.
 //! 3. Run `rebuild.py` (no need to clean)
 //! 4. Fill in `test-data/filename.*/x.json`.
 //! 5. Run `cargo test` and update the JSON file or the code.
@@ -63,7 +66,7 @@ fn main() {
 }
 
 fn run() -> io::Result<()> {
-    let allowed_extensions = [OsStr::new("gcc7"), OsStr::new("clang"), OsStr::new("rustc")];
+    let allowed_extensions = [OsStr::new("gcc7"), OsStr::new("gcc12"), OsStr::new("clang"), OsStr::new("rustc")];
     let mut failed_tests = 0;
 
     let stdout = StandardStream::stdout(ColorChoice::Auto);
@@ -0,0 +1,44 @@
+//! Writing the GCDA files from a fully-static executable.
+//!
+//! ```sh
+//! rustup target add x86_64-unknown-linux-musl
+//! cargo cov clean
+//! cargo cov test --target x86_64-unknown-linux-musl
+//! cargo cov report
+//! ```
+//!
+//! The report should show `classify` fully covered except the `Ordering::Less` arm, and `never_called`
+//! uncovered.
+
+use std::cmp::Ordering;
+use std::env;
+
+/// Classifies a number.
+pub fn classify(n: i32) -> &'static str {
+    match n.cmp(&0) {
+        Ordering::Less => "negative",
+        Ordering::Equal => "zero",
+        Ordering::Greater => "positive",
+    }
+}
+
+/// Reads an environment variable, which is resolved by the static libc without `dlopen`.
+pub fn home_is_set() -> bool {
+    env::var_os("HOME").is_some()
+}
+
+/// Never called by the tests.
+pub fn never_called() -> i32 {
+    42
+}
+
+#[test]
+fn test_classify() {
+    assert_eq!(classify(0), "zero");
+    assert_eq!(classify(12), "positive");
+}
+
+#[test]
+fn test_home_is_set() {
+    home_is_set();
+}